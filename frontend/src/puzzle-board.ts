@@ -21,6 +21,9 @@ export class PuzzleBoard {
     _moves: string[] = [];
     _remaining_moves: string[] = [];
 
+    // The moves the player has attempted in this puzzle, including wrong ones.
+    _played_moves: string[] = [];
+
     _board_states: any[];
     _seek_position: number = 0;
 
@@ -88,6 +91,7 @@ export class PuzzleBoard {
 
         if (config.moves) {
             this._moves = config.moves ? config.moves.split(" ") : [];
+            this._played_moves = [];
         }
 
         this._game = config.fen ? new Chess(config.fen) : new Chess();
@@ -157,6 +161,11 @@ export class PuzzleBoard {
         return this._failed;
     }
 
+    // The moves the player has attempted in this puzzle, including wrong ones.
+    played_moves() {
+        return this._played_moves.slice();
+    }
+
     is_first_move() {
         return this._remaining_moves.length == this._moves.length - 1
             && !this.is_complete()
@@ -355,6 +364,9 @@ export class PuzzleBoard {
         // Store board state.
         this.push_board_state([orig, dest], this._computer_color);
 
+        // Record the move in the list of attempted moves.
+        this._played_moves.push(orig + dest + (promotion ? promotion : ""));
+
         // Call on move callback now that we've validated it.
         if (this._config.on_move)
             this._config.on_move();
@@ -9,7 +9,6 @@ import {
     h,
     VNode,
 } from 'snabbdom';
-import { PuzzleBoard } from './puzzle-board.js';
 
 const patch = init([
     classModule,
@@ -24,17 +23,18 @@ const patch = init([
 export class PuzzleHistory {
     vnode: Element | VNode;
     config: any;
-    boards: any[];
     data: any;
     data_request_error: string = null;
+    collections: any[] = [];
+    added_to_collection: any = {};
     container_tag: string = 'div#puzzle-history';
 
     constructor(element, config) {
         this.vnode = element;
         this.config = {};
-        this.boards = [];
 
         this.configure(config ? config : {});
+        this.request_collections();
     }
 
     configure(config) {
@@ -47,7 +47,6 @@ export class PuzzleHistory {
     render() {
         try {
             this.vnode = patch(this.vnode, this.view());
-            this.create_boards();
         }
         catch (err) {
             this.vnode = patch(this.vnode, this.error_view(err));
@@ -123,8 +122,13 @@ export class PuzzleHistory {
                         attrs: { href: `/tactics/by_id/${puzzle.puzzle_id}` }
                     },
                     [
-                        h('div.puzzle-history-board', {
-                            dataset: { id: puzzle.puzzle_id },
+                        // A thumbnail rendered by the server, which is much lighter than a board.
+                        h('img.puzzle-history-board', {
+                            attrs: {
+                                src: `/api/render/${puzzle.puzzle_id}.svg`,
+                                alt: `Puzzle ${puzzle.puzzle_id}`,
+                                loading: 'lazy',
+                            },
                         })
                     ]),
                 ]),
@@ -139,6 +143,10 @@ export class PuzzleHistory {
                                 ]),
                             ]),
                             this.difficulty_row(item),
+                            this.solution_row(item),
+                            this.tags_row(item),
+                            this.notes_row(item),
+                            this.collection_row(puzzle),
                         ]),
                     ]),
                 ]),
@@ -158,6 +166,99 @@ export class PuzzleHistory {
         }
     }
 
+    // The puzzle's solution in SAN, with a link to download it as PGN.
+    solution_row(item) {
+        if (!item.san) {
+            return;
+        }
+
+        return h('tr', [
+            h('th', 'Solution'),
+            h('td', [
+                item.san,
+                ' (',
+                h('a', { props: { href: `/api/tactics/pgn/${item.puzzle.puzzle_id}` } }, 'pgn'),
+                ')',
+            ]),
+        ]);
+    }
+
+    // The user's own tags for the puzzle, which link to the history of puzzles with that tag.
+    tags_row(item) {
+        if (item.notes.tags.length == 0) {
+            return;
+        }
+
+        let links = [];
+        item.notes.tags.forEach((tag, index) => {
+            if (index > 0) {
+                links.push(', ');
+            }
+            links.push(h('a', { props: { href: `/tactics/history?tag=${encodeURIComponent(tag)}` } }, tag));
+        });
+
+        return h('tr', [
+            h('th', 'Tags'),
+            h('td', links),
+        ]);
+    }
+
+    notes_row(item) {
+        if (item.notes.notes) {
+            return h('tr', [
+                h('th', 'Notes'),
+                h('td.puzzle-history-notes', item.notes.notes),
+            ]);
+        }
+    }
+
+    collection_row(puzzle) {
+        if (this.collections.length == 0) {
+            return;
+        }
+
+        let added = this.added_to_collection[puzzle.puzzle_id];
+        let options = this.collections.map(collection =>
+            h('option', { props: { value: collection.id } }, collection.name));
+
+        return h('tr', [
+            h('th', 'Collection'),
+            h('td', added ? `Added to '${added}'` : [
+                h('select', {
+                    on: { change: (e) => this.on_collection_selected(puzzle.puzzle_id, e.target) },
+                }, [ h('option', { props: { value: '' } }, 'Add to collection...') ].concat(options)),
+            ]),
+        ]);
+    }
+
+    on_collection_selected(puzzle_id, select) {
+        let collection = this.collections.find(collection => collection.id == select.value);
+        if (!collection || typeof this.config.add_to_collection !== "function") {
+            return;
+        }
+
+        this.config.add_to_collection(collection.id, puzzle_id)
+            .then(() => {
+                this.added_to_collection[puzzle_id] = collection.name;
+                this.render();
+            })
+            .catch(err => {
+                this.data_request_error = `Failed to add puzzle to collection: ${err.responseJSON?.error ?? "unknown error"}`;
+                this.render();
+            });
+    }
+
+    request_collections() {
+        if (typeof this.config.request_collections === "function") {
+            this.config.request_collections()
+                .then(collections => {
+                    this.collections = collections;
+                    this.render();
+                })
+                .catch(err => console.error(`Failed to get collections: ${err.responseText}`));
+        }
+    }
+
     difficulty_to_string(difficulty) {
         if (difficulty == 0)
             return "Again";
@@ -179,13 +280,12 @@ export class PuzzleHistory {
 
             this.config.request_data(this.config)
                 .then(data => {
-                    this.received_data(data);
                     this.data = data;
                     this.config.loading = false;
                     this.render();
                 })
                 .catch(err => {
-                    this.data_request_error = `Failed to get puzzle history: ${err.responseJSON.error}`;
+                    this.data_request_error = `Failed to get puzzle history: ${err.responseJSON?.error ?? "unknown error"}`;
                     this.config.loading = false;
                     this.render();
 
@@ -194,13 +294,6 @@ export class PuzzleHistory {
         }
     }
 
-    received_data(data) {
-        // Get rid of unused boards, otherwise they won't be attached to a dom element anymore
-        // but we have no way of knowing it.
-        let puzzle_count = data.puzzles ? data.puzzles.length : 0;
-        this.boards.length = puzzle_count;
-    }
-
     prev_page() {
         this.config.page = Math.max(1, this.config.page - 1);
         this.request_data();
@@ -210,31 +303,4 @@ export class PuzzleHistory {
         this.config.page = Math.min(this.data.num_pages, this.config.page + 1);
         this.request_data();
     }
-
-    create_boards() {
-        if (!this.data || !this.data.puzzles) {
-            return;
-        }
-
-        let puzzles = {};
-        this.data.puzzles.map(item => {
-            puzzles[item.puzzle.puzzle_id] = item.puzzle;
-        });
-
-        let index = 0;
-        let boards = this.boards;
-        for (const container of document.getElementsByClassName('puzzle-history-board')) {
-            if (container instanceof HTMLElement) {
-                let puzzle = puzzles[container.dataset.id];
-
-                if (!boards[index]) {
-                    boards[index] = new PuzzleBoard(container, {});
-                }
-                boards[index].configure(Object.assign({
-                    locked: true,
-                }, puzzle));
-            }
-            index += 1;
-        }
-    }
 }
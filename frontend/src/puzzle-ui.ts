@@ -38,11 +38,37 @@ export class PuzzleUi {
 
     first_try: boolean = true;
     hint_used: boolean = false;
+    mistakes: number = 0;
+
+    // The time the current puzzle was started, and the time it took to complete it.
+    start_time: number = null;
+    duration_ms: number = null;
 
     puzzle: PuzzleBoard = null;
 
+    // The user's notes and tags for the current puzzle as they're being edited, with the tags as a
+    // comma-separated string.
+    notes: any = null;
+    notes_status: string = null;
+
+    // The result of reporting the current puzzle.
+    report_status: string = null;
+
+    // The local engine's analysis of the current position, and the status of the request for it.
+    engine_analysis: any = null;
+    engine_analysis_status: string = null;
+
+    // The server-side solve session for the current puzzle, which checks the user's moves, and the
+    // chain of pending requests to it, so that moves are submitted in the order they're played.
+    session_id: number = null;
+    session_requests: Promise<any> = Promise.resolve();
+
     countdown_interval: number = null;
 
+    // The time the current rush run ends, and the interval for updating its countdown.
+    rush_deadline: number = null;
+    rush_interval: number = null;
+
     constructor(container, config) {
         this.config = {};
         this.vnode = container;
@@ -51,10 +77,26 @@ export class PuzzleUi {
         this.render();
         
         this.puzzle = new PuzzleBoard(document.getElementById("board"), {
-            on_success: this.on_puzzle_board_change.bind(this),
+            on_success: () => {
+                this.submit_session_move();
+                this.on_puzzle_success();
+                this.on_puzzle_board_change();
+                this.on_rush_puzzle_done();
+                this.on_daily_puzzle_done(this.first_try);
+            },
             on_move: this.on_puzzle_board_change.bind(this),
-            on_right_move: this.on_puzzle_board_change.bind(this),
-            on_wrong_move: () => { this.first_try = false; this.on_puzzle_board_change(); },
+            on_right_move: () => {
+                this.submit_session_move();
+                this.on_puzzle_board_change();
+            },
+            on_wrong_move: () => {
+                this.submit_session_move();
+                this.first_try = false;
+                this.mistakes += 1;
+                this.on_puzzle_board_change();
+                this.on_rush_puzzle_done();
+                this.on_daily_puzzle_done(false);
+            },
             on_seek: this.on_puzzle_board_change.bind(this),
             on_promote: this.render.bind(this),
         });
@@ -72,6 +114,100 @@ export class PuzzleUi {
         this.render();
     }
 
+    on_puzzle_success() {
+        // Store the solve time the first time the puzzle is completed.
+        if (this.duration_ms === null && this.start_time !== null) {
+            this.duration_ms = Date.now() - this.start_time;
+        }
+    }
+
+    // Get the details of the user's attempt at the current puzzle, for submitting with reviews.
+    attempt_data() {
+        let duration_ms = this.duration_ms;
+        if (duration_ms === null && this.start_time !== null) {
+            duration_ms = Date.now() - this.start_time;
+        }
+
+        return {
+            duration_ms,
+            mistakes: this.mistakes,
+            hint_used: this.hint_used,
+            moves: this.puzzle ? this.puzzle.played_moves().join(' ') : null,
+            session_id: this.session_id,
+        };
+    }
+
+    // Start a solve session for the current puzzle. Rush runs don't use them, as their results
+    // don't affect reviews.
+    start_solve_session() {
+        this.session_id = null;
+
+        if (this.config.mode == 'Rush' || typeof this.config.solve_start !== "function") {
+            return;
+        }
+
+        let puzzle = this.config.puzzle;
+        this.queue_session_request(() => this.config.solve_start(puzzle).then((state) => {
+            // Ignore the session if the puzzle was changed while it was being started.
+            if (this.config.puzzle === puzzle) {
+                this.session_id = state.session_id;
+            }
+        }));
+    }
+
+    // Queue a request to the current solve session, after any that are still pending. The request
+    // is skipped if there isn't a session.
+    queue_session_request(request) {
+        this.session_requests = this.session_requests
+            .then(() => request(this.session_id))
+            .catch((err) => {
+                console.error(`Solve session request failed: ${err && err.responseText}`);
+            });
+
+        return this.session_requests;
+    }
+
+    // Make sure there's a solve session for the current puzzle before it's reviewed, as the server
+    // requires one. If starting it failed, a new one is started and the moves played so far, and
+    // any hint, are replayed in it.
+    ensure_solve_session() {
+        return this.session_requests.then(() => {
+            if (this.session_id !== null || typeof this.config.solve_start !== "function") {
+                return;
+            }
+
+            let puzzle = this.config.puzzle;
+            let moves = this.puzzle.played_moves();
+            let hint_used = this.hint_used;
+            return this.config.solve_start(puzzle).then((state) => {
+                if (this.config.puzzle !== puzzle) {
+                    return;
+                }
+                this.session_id = state.session_id;
+                let replay = hint_used ? this.config.solve_hint(state.session_id) : Promise.resolve();
+                return moves.reduce((request, move) => request
+                    .then(() => this.config.solve_move(state.session_id, move)), replay);
+            });
+        });
+    }
+
+    // Submit the move the user just played to the solve session.
+    submit_session_move() {
+        let moves = this.puzzle.played_moves();
+        let move = moves[moves.length - 1];
+
+        this.queue_session_request((session_id) => session_id !== null
+            ? this.config.solve_move(session_id, move)
+            : null);
+    }
+
+    // Go back to the start of the solve session when the puzzle is reset.
+    reset_solve_session() {
+        this.queue_session_request((session_id) => session_id !== null
+            ? this.config.solve_reset(session_id)
+            : null);
+    }
+
     configure(config) {
         console.log(config);
 
@@ -107,6 +243,20 @@ export class PuzzleUi {
                 moves: config.puzzle ? config.puzzle.moves : null,
                 puzzle_rating: config.puzzle ? config.puzzle.rating : null,
             });
+
+            // Reset the notes for the new puzzle.
+            this.notes = config.notes
+                ? { notes: config.notes.notes, tags: config.notes.tags.join(', ') }
+                : null;
+            this.notes_status = null;
+            this.report_status = null;
+            this.engine_analysis = null;
+            this.engine_analysis_status = null;
+
+            // Reset the attempt details for the new puzzle.
+            this.mistakes = 0;
+            this.start_time = Date.now();
+            this.duration_ms = null;
         }
 
         // Store whether it's currently the first try or not, so we know if it's a successful solve or not.
@@ -116,6 +266,14 @@ export class PuzzleUi {
         // Re-render the layout.
         this.config = Object.assign(this.config, config);
         this.render();
+
+        if (config.puzzle) {
+            this.start_solve_session();
+        }
+
+        if (config.rush !== undefined) {
+            this.start_rush_countdown();
+        }
     }
 
     render() {
@@ -137,25 +295,31 @@ export class PuzzleUi {
     request_data() {
         if (typeof this.config.request_data === "function") {
             console.log("Puzzle ui: requesting data");
-            this.config.error = null;
-            this.config.loading = true;
-            this.render();
+            this.load_data(this.config.request_data());
+        }
+    }
 
-            this.config.request_data()
-                .then(data => {
-                    this.configure(data);
-                    this.config.loading = false;
-                    this.disable_review_buttons = false;
-                    this.render();
-                })
-                .catch(err => {
-                    this.config.error = `Failed to get data: ${err.responseJSON.error}`;
-                    this.config.loading = false;
-                    this.render();
+    // Show the loading message until the given request for data completes, and then configure the
+    // ui with it.
+    load_data(request) {
+        this.config.error = null;
+        this.config.loading = true;
+        this.render();
 
-                    console.error(this.config.error);
-                });
-        }
+        request
+            .then(data => {
+                this.configure(data);
+                this.config.loading = false;
+                this.disable_review_buttons = false;
+                this.render();
+            })
+            .catch(err => {
+                this.config.error = `Failed to get data: ${err.responseJSON.error}`;
+                this.config.loading = false;
+                this.render();
+
+                console.error(this.config.error);
+            });
     }
 
     view() {
@@ -195,9 +359,13 @@ export class PuzzleUi {
         }
         else if (this.config.mode) {
             let mode = this.config.mode;
-            if (mode == "Random") {
+            if (mode == "Random" || mode == "Weaknesses") {
                 if (this.config.puzzle) {
                     let [min, max] = this.config.rating_range ? this.config.rating_range : [0, 0];
+                    if (this.config.weakness) {
+                        let tag = this.config.weakness.tag.replace(/_/g, ' ');
+                        return `Training weakness '${tag}' in rating range ${min}-${max}`;
+                    }
                     return `Reviewing random puzzle in rating range ${min}-${max}`;
                 }
                 else {
@@ -215,6 +383,39 @@ export class PuzzleUi {
                     return 'No such puzzle';
                 }
             }
+            else if (mode == "Rush") {
+                return this.rush_topbar();
+            }
+            else if (mode == "Daily" && this.config.daily) {
+                let daily = this.config.daily;
+                if (this.config.puzzle) {
+                    let result = '';
+                    if (daily.result) {
+                        result = daily.result.success ? ' (solved)' : ' (failed)';
+                    }
+                    let best = daily.best_streak > daily.streak ? `, best ${daily.best_streak}` : '';
+                    return `Puzzle of the day for ${daily.date}${result}. ` +
+                        `Daily streak: ${daily.streak} days${best}`;
+                }
+                else {
+                    return 'No puzzle of the day could be found';
+                }
+            }
+            else if (mode == "Collection" && this.config.collection) {
+                let collection = this.config.collection;
+                if (this.config.puzzle) {
+                    return `Puzzle ${collection.position} of ${collection.total} in collection '${collection.name}'`;
+                }
+                else {
+                    return h('p', [
+                        `You have finished the collection '${collection.name}'. Return to the `,
+                        h('a', { props: { href: `/collections/${collection.id}` } }, 'collection'),
+                        ' or the ',
+                        h('a', { props: { href: '/collections' } }, 'list of collections'),
+                        '.'
+                    ]);
+                }
+            }
             else if (mode == "Review" && this.config.stats) {
                 if (this.config.puzzle) {
                     let reviews_left = this.config.stats.reviews_due_now;
@@ -247,6 +448,77 @@ export class PuzzleUi {
         return 'error';
     }
 
+    rush_topbar() {
+        let rush = this.config.rush;
+
+        // If there's no run yet, show the start options and the user's personal bests.
+        if (!rush) {
+            let bests = (this.config.rush_bests || [])
+                .map(best => `${best.score} in ${best.duration_secs / 60} minutes`);
+
+            return h('p', [
+                'Puzzle rush: solve as many puzzles as you can before the time runs out, or until ',
+                'you make too many mistakes. The puzzles get harder as you go. Start a ',
+                h('a', { on: { click: () => this.start_rush(3) } }, '3 minute run'),
+                ' or a ',
+                h('a', { on: { click: () => this.start_rush(5) } }, '5 minute run'),
+                '.',
+                bests.length > 0 ? h('br') : null,
+                bests.length > 0 ? `Your personal bests are ${bests.join(', ')}.` : null,
+            ]);
+        }
+
+        let run = rush.run;
+        if (!run.finished) {
+            let ms_remaining = Math.max(0, this.rush_deadline - Date.now());
+            return `Puzzle rush: ${run.score} solved, ${run.mistakes} of ${rush.max_mistakes} mistakes, ` +
+                `${this.countdown_duration(ms_remaining)} left`;
+        }
+
+        let best_text;
+        if (rush.personal_best === null) {
+            best_text = "That's your first run of this length.";
+        }
+        else if (run.score > rush.personal_best) {
+            best_text = `That's a new personal best, beating your previous best of ${rush.personal_best}!`;
+        }
+        else {
+            best_text = `Your personal best is ${rush.personal_best}.`;
+        }
+
+        let contents = [
+            `Run over! You solved ${run.score} puzzles. ${best_text}`,
+            h('br'),
+        ];
+
+        // List the failed puzzles, and give the option of adding them as cards.
+        let failed = rush.puzzles.filter(puzzle => puzzle.success === false);
+        if (failed.length > 0) {
+            contents.push('Puzzles failed: ');
+            failed.forEach((puzzle, i) => {
+                if (i > 0) {
+                    contents.push(', ');
+                }
+                contents.push(h('a', { props: { href: `/tactics/by_id/${puzzle.puzzle_id}` } }, puzzle.puzzle_id));
+            });
+
+            if (this.config.rush_failed_added) {
+                contents.push('. They have been added to your reviews.');
+            }
+            else {
+                contents.push('. ');
+                contents.push(h('a', { on: { click: this.on_add_failed_clicked.bind(this) } },
+                    'Add them to your reviews'));
+            }
+
+            contents.push(h('br'));
+        }
+
+        contents.push(h('a', { on: { click: this.request_data.bind(this) } }, 'Start another run'));
+
+        return h('p', contents);
+    }
+
     promotion_ui() {
         if (!this.puzzle) {
             return h('div');
@@ -284,7 +556,13 @@ export class PuzzleUi {
     }
 
     sidebar() {
-        if (this.puzzle && this.config.puzzle && !this.config.loading) {
+        if (this.puzzle && this.config.puzzle && !this.config.loading && this.config.mode == 'Rush') {
+            return h('div.column.sidebar', [
+                this.move_controls(),
+                this.side_to_move(),
+            ]);
+        }
+        else if (this.puzzle && this.config.puzzle && !this.config.loading) {
             return h('div.column.sidebar', [
                 this.move_controls(),
                 this.puzzle_info(),
@@ -298,7 +576,10 @@ export class PuzzleUi {
                 this.dont_repeat_button(),
                 this.too_easy_button(),
                 this.too_hard_button(),
+                this.report_button(),
                 this.puzzle_themes(),
+                this.engine_analysis_panel(),
+                this.puzzle_notes(),
             ]);
         }
     }
@@ -394,6 +675,81 @@ export class PuzzleUi {
         }
     }
 
+    // The user's notes and tags for the puzzle, which can be edited once it's complete.
+    puzzle_notes() {
+        if (!this.puzzle.is_complete() || !this.notes || typeof this.config.save_notes !== "function") {
+            return;
+        }
+
+        return h('div#puzzle-notes.bt-panel', [
+            h('textarea.textarea', {
+                props: { value: this.notes.notes, placeholder: "Notes, e.g. why you missed something" },
+                on: { input: (e) => this.on_notes_changed('notes', e.target) },
+            }),
+            h('input.input', {
+                props: { value: this.notes.tags, placeholder: "Tags, e.g. calculation, missed-defence" },
+                on: { input: (e) => this.on_notes_changed('tags', e.target) },
+            }),
+            h('div', [
+                h('button.button', { on: { click: this.on_save_notes_clicked.bind(this) } }, "Save notes"),
+                this.notes_status ? h('span.notes-status', ` ${this.notes_status}`) : null,
+            ]),
+        ]);
+    }
+
+    // The local engine's evaluation and best line for the current position, which can be requested
+    // once the puzzle is complete if an engine is configured.
+    engine_analysis_panel() {
+        if (!this.puzzle.is_complete() || typeof this.config.analyse !== "function") {
+            return;
+        }
+
+        let analysis = this.engine_analysis;
+        return h('div#engine-analysis.bt-panel', [
+            h('button.button', {
+                attrs: { disabled: this.engine_analysis_status == 'Analysing...' },
+                on: { click: this.on_engine_analysis_clicked.bind(this) },
+            }, "Engine analysis"),
+            this.engine_analysis_status ? h('span.analysis-status', ` ${this.engine_analysis_status}`) : null,
+            analysis ? h('div', [
+                h('b', `${this.format_score(analysis.score)} `),
+                `(depth ${analysis.depth}) `,
+                analysis.pv_san,
+            ]) : null,
+        ]);
+    }
+
+    on_engine_analysis_clicked() {
+        let fen = this.puzzle.fen();
+        this.engine_analysis = null;
+        this.engine_analysis_status = 'Analysing...';
+        this.render();
+
+        this.config.analyse(fen)
+            .then(analysis => {
+                this.engine_analysis = analysis;
+                this.engine_analysis_status = null;
+                this.render();
+            })
+            .catch(err => {
+                this.engine_analysis_status = `Analysis failed: ${err.responseJSON.error}`;
+                this.render();
+            });
+    }
+
+    // Format an engine score from white's point of view, e.g. "+0.35" or "#-3".
+    format_score(score) {
+        if (!score) {
+            return '?';
+        }
+        else if (score.mate !== undefined) {
+            return `#${score.mate}`;
+        }
+        else {
+            return `${score.cp > 0 ? '+' : ''}${(score.cp / 100).toFixed(2)}`;
+        }
+    }
+
     analysis_link() {
         if (this.analysis_fen) {
             return h('a.analysis-link', { props: {
@@ -404,9 +760,21 @@ export class PuzzleUi {
     }
 
     source_url() {
+        // Puzzles generated from the user's own games link back to the game instead.
+        let puzzle = this.config.puzzle;
+        if (puzzle.source && puzzle.source != "lichess") {
+            if (!puzzle.game_url) {
+                return null;
+            }
+            return h('a.analysis-link', { props: {
+                target: "_blank",
+                href: puzzle.game_url,
+            } }, "Game");
+        }
+
         return h('a.analysis-link', { props: {
             target: "_blank",
-            href: `https://lichess.org/training/${this.config.puzzle.puzzle_id}`,
+            href: `https://lichess.org/training/${puzzle.puzzle_id}`,
         } }, "Source");
     }
 
@@ -529,8 +897,13 @@ export class PuzzleUi {
         }
     }
 
+    // Whether we're showing new puzzles, which can be skipped.
+    is_new_puzzle_mode() {
+        return this.config.mode == 'Random' || this.config.mode == 'Weaknesses';
+    }
+
     skip_button() {
-        if (this.config.mode == 'Random' && this.puzzle && this.puzzle.is_first_move()) {
+        if (this.is_new_puzzle_mode() && this.puzzle && this.puzzle.is_first_move()) {
             return h('div#skip-button.bt-panel.controls-subpanel', [
                 h('a', { on: { click: this.on_skip_clicked.bind(this) } }, "Skip this puzzle"),
             ]);
@@ -540,7 +913,7 @@ export class PuzzleUi {
     too_hard_button() {
         let complete_with_mistakes = this.puzzle.is_complete() && !this.first_try;
         let failed = this.puzzle.is_failed();
-        if (this.config.mode == 'Random' && (failed || complete_with_mistakes)) {
+        if (this.is_new_puzzle_mode() && (failed || complete_with_mistakes)) {
             return h('div#too-hard-button.bt-panel.controls-subpanel', [
                 h('a', { on: { click: this.on_too_hard_clicked.bind(this) } }, "Too hard (see easier puzzles)"),
             ]);
@@ -549,7 +922,7 @@ export class PuzzleUi {
 
     dont_repeat_button() {
         let is_success = this.puzzle.is_complete() && this.first_try;
-        if (this.config.mode == 'Random' && is_success) {
+        if (this.is_new_puzzle_mode() && is_success) {
             return h('div#dont-repeat.bt-panel.controls-subpanel', [
                 h('a', { on: { click: this.on_dont_repeat_clicked.bind(this) } }, "Don't repeat this puzzle"),
             ]);
@@ -558,13 +931,23 @@ export class PuzzleUi {
 
     too_easy_button() {
         let is_success = this.puzzle.is_complete() && this.first_try;
-        if (this.config.mode == 'Random' && is_success) {
+        if (this.is_new_puzzle_mode() && is_success) {
             return h('div#too-easy.bt-panel.controls-subpanel', [
                 h('a', { on: { click: this.on_too_easy_clicked.bind(this) } }, "Too easy (see harder puzzles)"),
             ]);
         }
     }
 
+    report_button() {
+        if (typeof this.config.on_report !== "function") {
+            return;
+        }
+
+        return h('div#report-button.bt-panel.controls-subpanel', this.report_status
+            ? [ this.report_status, ' ', h('a', { props: { href: '/reports' } }, "Reported puzzles") ]
+            : [ h('a', { on: { click: this.on_report_clicked.bind(this) } }, "Report or exclude this puzzle") ]);
+    }
+
     reviewing_ahead() {
         if (this.config.puzzle && !this.config.due_today) {
             return h('div#reviewing-ahead.bt-panel.controls-subpanel',
@@ -581,7 +964,7 @@ export class PuzzleUi {
                     h('div.column'),
                     h('div.column', [
                         h('button#try-again.button',
-                            { on: { click: () => { this.puzzle.reset(); this.render(); } } },
+                            { on: { click: () => { this.puzzle.reset(); this.reset_solve_session(); this.render(); } } },
                             [
                                 h('p.main-text', 'Reset'),
                                 h('p.sub-text', 'Try again'),
@@ -598,7 +981,10 @@ export class PuzzleUi {
         let ui = this;
 
         if (this.puzzle.is_complete()) {
-            if (this.first_try) {
+            if (this.config.auto_grade) {
+                return this.auto_grade_complete();
+            }
+            else if (this.first_try) {
                 let card = this.config.card;
                 return h('div#reviewing-ahead.bt-panel.controls-subpanel', [
                     h('p', [
@@ -686,6 +1072,36 @@ export class PuzzleUi {
         }
     }
 
+    // The puzzle complete panel when reviews are automatically graded by the server.
+    auto_grade_complete() {
+        let ui = this;
+
+        let status = '';
+        if (this.hint_used)
+            status = ' (hint used)';
+        else if (this.mistakes > 0)
+            status = ' (with mistakes)';
+
+        return h('div#reviewing-ahead.bt-panel.controls-subpanel', [
+            h('p', ['Puzzle complete', status]),
+            h('div.columns.button-container', [
+                h('div.column'),
+                h('div.column', [
+                    h('button#continue.button.review-button', {
+                        on: { click: function() { ui.on_review_button_clicked(this); } },
+                        attrs: { disabled: this.disable_review_buttons },
+                    }, [
+                        h('p.main-text', 'Continue'),
+                        h('p.sub-text', 'Auto grade'),
+                    ]),
+                ]),
+                h('div.column'),
+            ]),
+            h('p', 'The difficulty will be graded automatically from your solve time, mistakes ' +
+                'and hint usage.'),
+        ]);
+    }
+
     hint_button() {
         if (this.puzzle && !this.puzzle.is_complete() && !this.puzzle.is_failed()) {
             let button_text;
@@ -717,6 +1133,11 @@ export class PuzzleUi {
         this.first_try = false;
         this.hint_used = true;
 
+        // Let the server know a hint was used, as it records the attempt.
+        this.queue_session_request((session_id) => session_id !== null
+            ? this.config.solve_hint(session_id)
+            : null);
+
         // Get the current hint mode.
         let cur = this.puzzle.get_next_move_highlight();
 
@@ -765,15 +1186,22 @@ export class PuzzleUi {
 
         let card = this.config.card;
         let puzzle_id = card.id;
-        let difficulty = parseInt(button.data.dataset.difficulty);
+        // Buttons without a difficulty leave it to the server to grade the attempt.
+        let dataset = button.data.dataset;
+        let difficulty = dataset && dataset.difficulty !== undefined ? parseInt(dataset.difficulty) : null;
         console.log(`Reviewing ${puzzle_id} with difficulty ${difficulty}`);
 
         if (this.config.on_review) {
             this.disable_review_buttons = true;
             this.render();
 
-            this.config.on_review(card, difficulty)
-                .then(() => {
+            // Wait for any moves that are still being submitted to the solve session first.
+            this.ensure_solve_session()
+                .then(() => this.config.on_review(card, difficulty, this.attempt_data()))
+                .then((response) => {
+                    if (response && response.difficulty !== undefined) {
+                        console.log(`Review graded with difficulty ${response.difficulty}`);
+                    }
                     console.log("Done, loading next puzzle");
                     this.request_data();
                 })
@@ -812,6 +1240,56 @@ export class PuzzleUi {
         this.skip_puzzle(DIFFICULTY_EASY, true);
     }
 
+    on_notes_changed(field, input) {
+        this.notes[field] = input.value;
+        if (this.notes_status) {
+            this.notes_status = null;
+            this.render();
+        }
+    }
+
+    on_save_notes_clicked() {
+        let tags = this.notes.tags
+            .split(',')
+            .map(tag => tag.trim())
+            .filter(tag => tag.length > 0);
+
+        this.config.save_notes(this.config.puzzle, this.notes.notes, tags)
+            .then(notes => {
+                this.notes = { notes: notes.notes, tags: notes.tags.join(', ') };
+                this.notes_status = "Saved";
+                this.render();
+            })
+            .catch(err => {
+                this.notes_status = `Failed to save notes: ${err.responseJSON.error}`;
+                this.render();
+            });
+    }
+
+    on_report_clicked() {
+        let reason = window.prompt("Why are you reporting this puzzle? (e.g. it's ambiguous, it has " +
+            "a second solution, or you just don't like it)");
+        if (!reason || reason.trim().length == 0) {
+            return;
+        }
+
+        this.config.on_report(this.config.puzzle, reason)
+            .then(() => {
+                // Move on to the next puzzle, unless there isn't one.
+                if (this.config.mode == 'Specific' || this.config.mode == 'Daily') {
+                    this.report_status = "Reported. This puzzle won't come up again unless you undo the report.";
+                    this.render();
+                }
+                else {
+                    this.request_data();
+                }
+            })
+            .catch(err => {
+                this.report_status = `Failed to report puzzle: ${err.responseJSON.error}`;
+                this.render();
+            });
+    }
+
     // Scroll wheel.
     on_wheel(evt) {
         if (evt.deltaY < 0) {
@@ -828,7 +1306,7 @@ export class PuzzleUi {
         if (this.config.on_skip && this.config.card) {
             this.disable_review_buttons = true;
 
-            this.config.on_skip(this.config.card, difficulty, update_rating)
+            this.config.on_skip(this.config.card, difficulty, update_rating, this.attempt_data())
                 .then(() => {
                     console.log("Skipped, loading next puzzle");
                     this.request_data();
@@ -842,6 +1320,88 @@ export class PuzzleUi {
         }
     }
 
+    // Report whether the user solved the daily puzzle on the first try, the first time they
+    // complete or fail it.
+    on_daily_puzzle_done(success) {
+        let daily = this.config.daily;
+        if (this.config.mode == 'Daily' && daily && !daily.result && this.config.puzzle
+            && typeof this.config.on_daily_result === "function")
+        {
+            // The result is checked by the server from the solve session, once the move has been
+            // submitted to it.
+            let puzzle = this.config.puzzle;
+            this.ensure_solve_session()
+                .then(() => this.config.on_daily_result(puzzle, this.session_id))
+                .then(daily => {
+                    this.config.daily = daily;
+                    this.render();
+                })
+                .catch((e) => {
+                    this.config.error = `Failed to submit daily puzzle result: ${e.responseText}`;
+                    console.error(this.config.error);
+                    this.render();
+                });
+
+            // Don't report it again while the request is in progress.
+            daily.result = { success };
+        }
+    }
+
+    start_rush(duration_minutes) {
+        if (typeof this.config.rush_start === "function") {
+            this.config.rush_failed_added = false;
+            this.load_data(this.config.rush_start(duration_minutes));
+        }
+    }
+
+    // Submit the result of the current rush puzzle and move straight on to the next one. The server
+    // checks the moves that were played to see if it was solved.
+    on_rush_puzzle_done() {
+        let rush = this.config.rush;
+        if (this.config.mode == 'Rush' && rush && !rush.run.finished && this.config.puzzle
+            && !this.config.loading)
+        {
+            this.load_data(this.config.rush_result(rush.run.id, this.config.puzzle, this.attempt_data()));
+        }
+    }
+
+    on_add_failed_clicked() {
+        this.config.rush_add_failed(this.config.rush.run.id)
+            .then(() => {
+                this.config.rush_failed_added = true;
+                this.render();
+            })
+            .catch((e) => {
+                this.config.error = `Failed to add failed puzzles: ${e.responseText}`;
+                console.error(this.config.error);
+                this.render();
+            });
+    }
+
+    // Count down the time left in the current rush run, and get the final state of the run from
+    // the server when it's up.
+    start_rush_countdown() {
+        if (this.rush_interval) {
+            clearInterval(this.rush_interval);
+            this.rush_interval = null;
+        }
+
+        let rush = this.config.rush;
+        if (rush && !rush.run.finished) {
+            this.rush_deadline = Date.now() + rush.ms_remaining;
+            this.rush_interval = setInterval(() => {
+                if (Date.now() < this.rush_deadline) {
+                    this.render();
+                }
+                else if (!this.config.loading) {
+                    clearInterval(this.rush_interval);
+                    this.rush_interval = null;
+                    this.load_data(this.config.rush_state(rush.run.id));
+                }
+            }, 1000);
+        }
+    }
+
     start_review_countdown() {
         if (!this.countdown_interval && this.config.stats && this.config.stats.ms_until_due > 0) {
             console.log('Starting countdown');
@@ -0,0 +1,11 @@
+-- Add the details of the user's attempt to reviews and skipped puzzles. These are nullable as
+-- they weren't recorded for older reviews.
+ALTER TABLE reviews ADD COLUMN duration_ms INTEGER;
+ALTER TABLE reviews ADD COLUMN mistakes INTEGER;
+ALTER TABLE reviews ADD COLUMN hint_used BOOLEAN;
+ALTER TABLE reviews ADD COLUMN moves TEXT;
+
+ALTER TABLE skipped_puzzles ADD COLUMN duration_ms INTEGER;
+ALTER TABLE skipped_puzzles ADD COLUMN mistakes INTEGER;
+ALTER TABLE skipped_puzzles ADD COLUMN hint_used BOOLEAN;
+ALTER TABLE skipped_puzzles ADD COLUMN moves TEXT;
@@ -0,0 +1,44 @@
+-- Normalised tables of puzzle themes and opening tags, so that puzzles can be filtered by them using
+-- an index instead of searching the space separated lists in the puzzles table. The rating is
+-- duplicated here so that the rating range can be filtered on the same index.
+CREATE TABLE IF NOT EXISTS puzzle_themes (
+    puzzle_id TEXT NOT NULL,
+    theme TEXT NOT NULL,
+    rating INTEGER NOT NULL,
+    PRIMARY KEY (puzzle_id, theme)
+);
+CREATE INDEX IF NOT EXISTS puzzle_themes_theme_rating ON puzzle_themes(theme, rating);
+
+CREATE TABLE IF NOT EXISTS puzzle_opening_tags (
+    puzzle_id TEXT NOT NULL,
+    opening_tag TEXT NOT NULL,
+    rating INTEGER NOT NULL,
+    PRIMARY KEY (puzzle_id, opening_tag)
+);
+CREATE INDEX IF NOT EXISTS puzzle_opening_tags_tag_rating ON puzzle_opening_tags(opening_tag, rating);
+
+-- Populate them from the existing puzzles by splitting the space separated lists.
+INSERT OR IGNORE INTO puzzle_themes (puzzle_id, theme, rating)
+WITH RECURSIVE split(puzzle_id, rating, theme, rest) AS (
+    SELECT puzzle_id, rating, '', coalesce(themes, '') || ' ' FROM puzzles
+    UNION ALL
+    SELECT puzzle_id, rating, substr(rest, 1, instr(rest, ' ') - 1), substr(rest, instr(rest, ' ') + 1)
+    FROM split
+    WHERE rest <> ''
+)
+SELECT puzzle_id, theme, rating FROM split WHERE theme <> '';
+
+INSERT OR IGNORE INTO puzzle_opening_tags (puzzle_id, opening_tag, rating)
+WITH RECURSIVE split(puzzle_id, rating, opening_tag, rest) AS (
+    SELECT puzzle_id, rating, '', coalesce(opening_tags, '') || ' ' FROM puzzles
+    UNION ALL
+    SELECT puzzle_id, rating, substr(rest, 1, instr(rest, ' ') - 1), substr(rest, instr(rest, ' ') + 1)
+    FROM split
+    WHERE rest <> ''
+)
+SELECT puzzle_id, opening_tag, rating FROM split WHERE opening_tag <> '';
+
+-- Index the quality filters along with the rating, so they can be checked without looking up the
+-- puzzle itself.
+CREATE INDEX IF NOT EXISTS puzzle_rating_quality
+    ON puzzles(rating, popularity, number_of_plays, rating_deviation);
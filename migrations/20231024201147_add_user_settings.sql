@@ -0,0 +1,2 @@
+-- User settings, stored as json so that new settings don't each need a migration.
+ALTER TABLE users ADD COLUMN settings TEXT;
@@ -0,0 +1,18 @@
+-- The set of puzzles each user has already seen (reviewed or skipped), so that new puzzles can be
+-- selected with an anti-join against it instead of checking for an existing card afterwards.
+CREATE TABLE IF NOT EXISTS seen_puzzles (
+    user_id TEXT NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    PRIMARY KEY (user_id, puzzle_id)
+) WITHOUT ROWID;
+
+-- Populate it from the existing cards, reviews and skipped puzzles. Cards don't have a user id, but
+-- they all belong to the local user for now.
+INSERT OR IGNORE INTO seen_puzzles (user_id, puzzle_id)
+SELECT 'local', puzzle_id FROM cards;
+
+INSERT OR IGNORE INTO seen_puzzles (user_id, puzzle_id)
+SELECT user_id, puzzle_id FROM reviews;
+
+INSERT OR IGNORE INTO seen_puzzles (user_id, puzzle_id)
+SELECT user_id, puzzle_id FROM skipped_puzzles;
@@ -0,0 +1,16 @@
+-- User-defined collections of puzzles.
+CREATE TABLE IF NOT EXISTS collections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS collections_user_id ON collections(user_id);
+
+-- The puzzles in each collection, in the order they were added.
+CREATE TABLE IF NOT EXISTS collection_puzzles (
+    collection_id INTEGER NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (collection_id, puzzle_id)
+);
@@ -0,0 +1,25 @@
+-- Timed puzzle rush runs. The score is the number of puzzles solved, and a run is over when it's
+-- been going for longer than its duration or the user has made too many mistakes.
+CREATE TABLE IF NOT EXISTS rush_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    started TEXT NOT NULL,
+    duration_secs INTEGER NOT NULL,
+    finished INTEGER NOT NULL DEFAULT 0,
+    score INTEGER NOT NULL DEFAULT 0,
+    mistakes INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS rush_runs_user_id ON rush_runs(user_id, duration_secs, score);
+
+-- The puzzles shown in each run, in order, and their outcomes. The outcome is null for the puzzle
+-- the user is currently on.
+CREATE TABLE IF NOT EXISTS rush_run_puzzles (
+    run_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    puzzle_rating INTEGER NOT NULL,
+    success INTEGER,
+    duration_ms INTEGER,
+    moves TEXT,
+    PRIMARY KEY (run_id, position)
+);
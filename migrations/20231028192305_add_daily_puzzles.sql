@@ -0,0 +1,16 @@
+-- The puzzle of the day for each day, so that it stays the same for the whole day even if the
+-- puzzles are updated.
+CREATE TABLE IF NOT EXISTS daily_puzzles (
+    date TEXT PRIMARY KEY NOT NULL,
+    puzzle_id TEXT NOT NULL
+);
+
+-- Each user's results for the daily puzzles they've attempted. Only the first attempt counts.
+CREATE TABLE IF NOT EXISTS daily_puzzle_results (
+    user_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    completed TEXT NOT NULL,
+    PRIMARY KEY (user_id, date)
+);
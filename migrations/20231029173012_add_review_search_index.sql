@@ -0,0 +1,3 @@
+-- An index for finding each puzzle's last review, for searching puzzles by their last review
+-- outcome.
+CREATE INDEX IF NOT EXISTS reviews_user_id_puzzle_id ON reviews(user_id, puzzle_id);
@@ -0,0 +1,16 @@
+-- Each user's notes on puzzles.
+CREATE TABLE IF NOT EXISTS puzzle_notes (
+    user_id TEXT NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    notes TEXT NOT NULL,
+    PRIMARY KEY (user_id, puzzle_id)
+);
+
+-- Each user's own tags on puzzles, which are separate from the lichess themes.
+CREATE TABLE IF NOT EXISTS puzzle_user_tags (
+    user_id TEXT NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (user_id, puzzle_id, tag)
+);
+CREATE INDEX IF NOT EXISTS puzzle_user_tags_tag ON puzzle_user_tags(user_id, tag);
@@ -0,0 +1,9 @@
+-- Puzzles each user has reported as broken or disliked, which are excluded from new puzzles and
+-- reviews for that user until the report is undone.
+CREATE TABLE IF NOT EXISTS puzzle_reports (
+    user_id TEXT NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    date TEXT NOT NULL,
+    PRIMARY KEY (user_id, puzzle_id)
+);
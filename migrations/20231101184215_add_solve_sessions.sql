@@ -0,0 +1,15 @@
+-- Sessions for solving a puzzle with each move checked by the server. The ply is the number of
+-- moves of the solution that have been played, including the opponent's, and the moves are all of
+-- the moves the user tried (including wrong ones) as space separated UCI moves. Completed is the
+-- time the user first finished the solution, or null if they haven't yet.
+CREATE TABLE IF NOT EXISTS solve_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    puzzle_id TEXT NOT NULL,
+    started TEXT NOT NULL,
+    ply INTEGER NOT NULL,
+    mistakes INTEGER NOT NULL DEFAULT 0,
+    hint_used INTEGER NOT NULL DEFAULT 0,
+    moves TEXT NOT NULL DEFAULT '',
+    completed TEXT
+);
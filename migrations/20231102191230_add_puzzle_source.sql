@@ -0,0 +1,3 @@
+-- Where each puzzle came from, so that puzzles that aren't from the lichess puzzle database (such
+-- as the ones generated from the user's own games) can be told apart and backed up.
+ALTER TABLE puzzles ADD COLUMN source TEXT NOT NULL DEFAULT 'lichess';
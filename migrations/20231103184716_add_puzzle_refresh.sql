@@ -0,0 +1,13 @@
+-- Puzzles that were in an earlier version of the lichess puzzle database but aren't in the latest
+-- one. They're kept so that cards and reviews of them still work, but aren't picked as new puzzles.
+ALTER TABLE puzzles ADD COLUMN removed BOOLEAN NOT NULL DEFAULT 0;
+
+-- The lichess puzzles seen so far by the import that's in progress, so that the ones that aren't in
+-- the database anymore can be marked as removed once it's finished.
+CREATE TABLE IF NOT EXISTS imported_lichess_puzzles (
+    puzzle_id TEXT PRIMARY KEY
+);
+
+-- The date of the last imported lichess puzzle database, and how many puzzles were in it.
+ALTER TABLE app_data ADD COLUMN lichess_db_date TEXT;
+ALTER TABLE app_data ADD COLUMN lichess_db_rows INTEGER;
@@ -0,0 +1,6 @@
+-- How far the import of the lichess puzzle database that's in progress has got, so that it can be
+-- resumed if it's stopped: which copy of the database is being imported, how many rows of it have
+-- been imported, and the ID of the last puzzle imported.
+ALTER TABLE app_data ADD COLUMN import_source TEXT;
+ALTER TABLE app_data ADD COLUMN import_rows INTEGER;
+ALTER TABLE app_data ADD COLUMN import_last_puzzle_id TEXT;
@@ -0,0 +1,10 @@
+-- Puzzle packs imported from csv, pgn or epd files. Each pack's puzzles have the pack's source.
+CREATE TABLE IF NOT EXISTS puzzle_packs (
+    source TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    format TEXT NOT NULL,
+    imported TEXT NOT NULL
+);
+
+-- So that random puzzles can be picked from one source without walking past all the others.
+CREATE INDEX IF NOT EXISTS puzzles_source_rating ON puzzles(source, rating);
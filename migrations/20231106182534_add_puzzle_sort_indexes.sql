@@ -0,0 +1,5 @@
+-- Indexes for sorting puzzle search results by popularity and number of plays. Search results are
+-- ordered by rowid within each value, which every index ends with, so that the indexes give the
+-- whole order and a page of results can be read without sorting all of the puzzles.
+CREATE INDEX IF NOT EXISTS puzzles_popularity ON puzzles(popularity);
+CREATE INDEX IF NOT EXISTS puzzles_number_of_plays ON puzzles(number_of_plays);
@@ -0,0 +1,2 @@
+-- Whether a solve session has been used for a review, as each session can only be used for one.
+ALTER TABLE solve_sessions ADD COLUMN reviewed INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,191 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, TimeZone, Utc};
+use serde::Deserialize;
+
+use crate::rating::Rating;
+use crate::srs::{Card, Difficulty, SrsConfig};
+
+/// Puzzles that were solved within this many days are given cards too, as well as the ones that
+/// were failed, so they get reviewed before they're forgotten.
+pub const RECENT_ATTEMPT_DAYS: i64 = 30;
+
+/// The rating the user's rating is estimated from when it's set from their activity, which is the
+/// same as a new lichess account's.
+pub const STARTING_RATING: Rating = Rating { rating: 1500, deviation: 350, volatility: 0.06 };
+
+/// An attempt at a puzzle from a lichess puzzle activity export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    pub puzzle_id: String,
+    pub date: DateTime<FixedOffset>,
+    pub win: bool,
+}
+
+impl Attempt {
+    /// The difficulty the attempt is reviewed as. Lichess only records whether the puzzle was
+    /// solved, so solved puzzles are 'good', the neutral answer for a successful review.
+    pub fn difficulty(&self) -> Difficulty {
+        match self.win {
+            true => Difficulty::Good,
+            false => Difficulty::Again,
+        }
+    }
+
+    /// The score of the attempt for rating purposes, which is a plain win or loss like on lichess.
+    pub fn score(&self) -> f64 {
+        match self.win {
+            true => 1.0,
+            false => 0.0,
+        }
+    }
+}
+
+/// The attempts read from an export, oldest first, and errors for the entries that couldn't be read.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedActivity {
+    pub attempts: Vec<Attempt>,
+    pub errors: Vec<String>,
+}
+
+/// An entry in lichess' puzzle activity export (https://lichess.org/api#tag/Puzzles/operation/apiPuzzleActivity).
+#[derive(Debug, Deserialize)]
+struct ActivityEntry {
+    /// The time of the attempt in milliseconds since the unix epoch.
+    date: i64,
+    win: bool,
+    puzzle: ActivityPuzzle,
+}
+
+/// The puzzle in an activity entry, which has more fields that we don't need as we already have
+/// the puzzle.
+#[derive(Debug, Deserialize)]
+struct ActivityPuzzle {
+    id: String,
+}
+
+/// Read the attempts from a lichess puzzle activity export, which can be NDJSON (as it's returned
+/// by the lichess api) or a JSON array of the same entries.
+pub fn parse_activity(contents: &str) -> ParsedActivity {
+    let entries: Vec<Result<ActivityEntry, String>> = match contents.trim_start().starts_with('[') {
+        true => match serde_json::from_str::<Vec<ActivityEntry>>(contents) {
+            Ok(entries) => entries.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(format!("invalid json: {e}"))],
+        },
+        false => contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| format!("invalid json: {e}")))
+            .collect(),
+    };
+
+    let mut activity = ParsedActivity::default();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let attempt = entry.and_then(|entry| {
+            let date = Utc.timestamp_millis_opt(entry.date).single()
+                .ok_or_else(|| format!("invalid date {}", entry.date))?;
+
+            Ok(Attempt {
+                puzzle_id: entry.puzzle.id,
+                date: date.with_timezone(&Local).fixed_offset(),
+                win: entry.win,
+            })
+        });
+
+        match attempt {
+            Ok(attempt) => activity.attempts.push(attempt),
+            Err(e) => activity.errors.push(format!("Entry {}: {e}", i + 1)),
+        }
+    }
+
+    // The api returns the newest attempts first, but they need to be replayed in order.
+    activity.attempts.sort_by_key(|attempt| attempt.date);
+
+    activity
+}
+
+/// Get the card for a puzzle from the user's attempts at it (oldest first), as if they'd been
+/// reviewed in the app at the same times. Puzzles that were last solved longer ago than
+/// `RECENT_ATTEMPT_DAYS` don't get one, as there'd be no point reviewing all of them.
+pub fn card_from_attempts(puzzle_id: &str, attempts: &[Attempt], now: DateTime<FixedOffset>,
+    srs_config: SrsConfig) -> Option<Card>
+{
+    let last_attempt = attempts.last()?;
+    if last_attempt.win && now - last_attempt.date > Duration::days(RECENT_ATTEMPT_DAYS) {
+        return None;
+    }
+
+    let mut card = Card::new(puzzle_id, attempts[0].date, srs_config);
+    for attempt in attempts {
+        card.review(attempt.date, attempt.difficulty());
+    }
+
+    Some(card)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration};
+
+    use crate::activity::{parse_activity, card_from_attempts, Attempt, RECENT_ATTEMPT_DAYS};
+    use crate::app::AppConfig;
+
+    #[test]
+    fn test_parse_activity() {
+        let entry = |date: i64, id: &str, win: bool| format!(
+            r#"{{"date":{date},"puzzle":{{"fen":"","id":"{id}","lastMove":"e2e4","plays":100,"rating":1500,"solution":[],"themes":[]}},"win":{win}}}"#);
+
+        // NDJSON, newest first like the api returns it, with a blank line and a bad entry.
+        let ndjson = [
+            entry(1698800000000, "b", false),
+            String::new(),
+            "{\"date\": 1698700000000}".to_string(),
+            entry(1698700000000, "a", true),
+        ].join("\n");
+
+        let activity = parse_activity(&ndjson);
+        assert_eq!(activity.attempts.len(), 2);
+        assert_eq!(activity.attempts[0].puzzle_id, "a");
+        assert!(activity.attempts[0].win);
+        assert_eq!(activity.attempts[0].date.timestamp_millis(), 1698700000000);
+        assert_eq!(activity.attempts[1].puzzle_id, "b");
+        assert!(!activity.attempts[1].win);
+        assert_eq!(activity.errors.len(), 1);
+        assert!(activity.errors[0].starts_with("Entry 2:"));
+
+        // A JSON array of the same entries.
+        let json = format!("[{}, {}]", entry(1698800000000, "b", false), entry(1698700000000, "a", true));
+        let activity = parse_activity(&json);
+        assert!(activity.errors.is_empty());
+        assert_eq!(activity.attempts.iter().map(|a| a.puzzle_id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+
+        // A broken JSON array is a single error.
+        let activity = parse_activity("[{\"date\": 1}");
+        assert!(activity.attempts.is_empty());
+        assert_eq!(activity.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_card_from_attempts() {
+        let srs = AppConfig::default().srs;
+        let now = DateTime::parse_from_rfc3339("2023-11-01T12:00:00+00:00").unwrap();
+        let attempt = |days_ago: i64, win: bool| Attempt {
+            puzzle_id: "a".to_string(),
+            date: now - Duration::days(days_ago),
+            win,
+        };
+
+        // Puzzles solved a long time ago don't get cards.
+        assert!(card_from_attempts("a", &[attempt(RECENT_ATTEMPT_DAYS + 1, true)], now, srs).is_none());
+        assert!(card_from_attempts("a", &[], now, srs).is_none());
+
+        // Failed puzzles do, however long ago, and are due soon after the attempt.
+        let failed = card_from_attempts("a", &[attempt(200, false)], now, srs).unwrap();
+        assert_eq!(failed.review_count, 1);
+        assert!(failed.due < now);
+
+        // Recently solved puzzles do too, with all of the attempts reviewed.
+        let solved = card_from_attempts("a", &[attempt(20, false), attempt(2, true)], now, srs).unwrap();
+        assert_eq!(solved.id, "a");
+        assert_eq!(solved.review_count, 2);
+        assert!(solved.due > now - Duration::days(2));
+        assert!(solved.ease < srs.default_ease);
+    }
+}
@@ -0,0 +1,1021 @@
+// Export and import of Anki decks (.apkg files), which are zip files of an Anki collection (an
+// sqlite database) and its media. Decks are exported in the legacy collection format, which every
+// version of Anki can import. Importing reads the newer formats too, but only the notes created
+// by the exporter, so that scheduling changes made in Anki can be brought back.
+// https://github.com/ankidroid/Anki-Android/wiki/Database-Structure
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Cursor, Read, Write};
+
+use chrono::{DateTime, Duration, FixedOffset, Local, TimeZone};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use sqlx::{ConnectOptions, Connection, Row, SqliteConnection};
+use sqlx::sqlite::SqliteConnectOptions;
+
+use crate::chess::Position;
+use crate::db::{AttemptData, Puzzle, Review};
+use crate::pgn;
+use crate::render;
+use crate::srs::{Card, Difficulty, SrsConfig, INITIAL_INTERVALS, MIN_INTERVAL};
+
+/// The name of the note type of exported puzzles, which is how notes created by the exporter are
+/// recognised when importing.
+pub const NOTE_TYPE_NAME: &str = "Better Tactics puzzle";
+
+/// The IDs of the note type and deck, which are always the same so that exporting again updates
+/// them in Anki instead of creating new ones.
+const NOTE_TYPE_ID: i64 = 1698796800000;
+const DECK_ID: i64 = 1698796800001;
+
+/// The name of the deck exported puzzles are put in.
+const DECK_NAME: &str = "Better Tactics";
+
+/// The fields of the note type. The puzzle ID has to be first, as that's how imported notes are
+/// matched to puzzles.
+const NOTE_FIELDS: [&str; 5] = ["PuzzleId", "FEN", "Solution", "Themes", "Board"];
+
+/// The separator between the fields of a note.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// The longest answer time Anki records, in milliseconds.
+const MAX_ANSWER_TIME_MS: i64 = 60000;
+
+/// Anki's card types.
+const CARD_TYPE_NEW: i64 = 0;
+const CARD_TYPE_LEARNING: i64 = 1;
+const CARD_TYPE_REVIEW: i64 = 2;
+const CARD_TYPE_RELEARNING: i64 = 3;
+
+/// Anki's queues, which are the card types for cards that aren't suspended or buried.
+const QUEUE_LEARNING: i64 = 1;
+const QUEUE_REVIEW: i64 = 2;
+
+/// Anki's revlog entry types.
+const REVLOG_LEARNING: i64 = 0;
+const REVLOG_REVIEW: i64 = 1;
+const REVLOG_RELEARNING: i64 = 2;
+
+/// The most a revlog entry's ID can have been moved after its review's time when it was exported,
+/// to keep the IDs unique, in milliseconds.
+const MAX_REVLOG_ID_SHIFT_MS: i64 = 1000;
+
+/// The largest collection that's read from an apkg, after it's decompressed, so that a small apkg
+/// can't decompress to something that fills up memory.
+const MAX_COLLECTION_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Learning cards are due at a unix timestamp, and review cards on a day number relative to the
+/// collection's creation, so anything bigger than this is a timestamp.
+const MIN_DUE_TIMESTAMP: i64 = 1_000_000_000;
+
+/// The tables of the legacy (schema 11) collection format.
+const COLLECTION_SCHEMA: &str = "
+    CREATE TABLE col (
+        id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+        ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+        conf text not null, models text not null, decks text not null, dconf text not null,
+        tags text not null
+    );
+    CREATE TABLE notes (
+        id integer primary key, guid text not null, mid integer not null, mod integer not null,
+        usn integer not null, tags text not null, flds text not null, sfld integer not null,
+        csum integer not null, flags integer not null, data text not null
+    );
+    CREATE TABLE cards (
+        id integer primary key, nid integer not null, did integer not null, ord integer not null,
+        mod integer not null, usn integer not null, type integer not null, queue integer not null,
+        due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+        lapses integer not null, left integer not null, odue integer not null,
+        odid integer not null, flags integer not null, data text not null
+    );
+    CREATE TABLE revlog (
+        id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+        ivl integer not null, lastIvl integer not null, factor integer not null,
+        time integer not null, type integer not null
+    );
+    CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+    CREATE INDEX ix_notes_usn ON notes (usn);
+    CREATE INDEX ix_cards_usn ON cards (usn);
+    CREATE INDEX ix_revlog_usn ON revlog (usn);
+    CREATE INDEX ix_cards_nid ON cards (nid);
+    CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+    CREATE INDEX ix_revlog_cid ON revlog (cid);
+    CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+/// A puzzle to export, with its card and the user's reviews of it, oldest first.
+#[derive(Debug)]
+pub struct ExportedPuzzle {
+    pub puzzle: Puzzle,
+    pub card: Card,
+    pub reviews: Vec<Review>,
+}
+
+/// The scheduling fields of an Anki card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnkiCard {
+    pub card_type: i64,
+    pub queue: i64,
+    /// A unix timestamp for learning cards, or a day number relative to the collection's creation
+    /// for review cards.
+    pub due: i64,
+    /// The interval in days (or negative seconds in the revlog, for learning intervals).
+    pub ivl: i64,
+    /// The ease in permille.
+    pub factor: i64,
+    pub reps: i64,
+    pub lapses: i64,
+    /// The number of learning steps left.
+    pub left: i64,
+}
+
+/// An entry in Anki's review log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevlogEntry {
+    /// The time of the review in milliseconds since the unix epoch.
+    pub id: i64,
+    /// The button that was pressed, from 1 (again) to 4 (easy).
+    pub ease: i64,
+    pub ivl: i64,
+    pub last_ivl: i64,
+    pub factor: i64,
+    /// The time taken to answer in milliseconds.
+    pub time: i64,
+    pub review_type: i64,
+}
+
+/// The revlog for a card, from replaying the user's reviews of it.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayedReviews {
+    pub revlog: Vec<RevlogEntry>,
+    /// The number of times the card was forgotten after leaving learning.
+    pub lapses: i64,
+    /// Whether the card is in learning again after being forgotten.
+    pub relearning: bool,
+}
+
+/// A puzzle's card and revlog read from an Anki collection.
+#[derive(Debug, Clone)]
+pub struct ImportedPuzzle {
+    pub puzzle_id: String,
+    pub card: Option<AnkiCard>,
+    pub revlog: Vec<RevlogEntry>,
+}
+
+/// The puzzles read from an Anki collection.
+#[derive(Debug, Clone)]
+pub struct AnkiCollection {
+    /// When the collection was created, which review cards' due days are relative to, as a unix
+    /// timestamp.
+    pub crt: i64,
+    pub puzzles: Vec<ImportedPuzzle>,
+}
+
+/// Convert an interval to Anki's revlog format, which is days, or negative seconds for intervals
+/// shorter than a day.
+fn anki_interval(interval: Duration) -> i64 {
+    match interval >= Duration::days(1) {
+        true => interval.num_days(),
+        false => -interval.num_seconds(),
+    }
+}
+
+/// Convert an interval from Anki's revlog format.
+fn interval_from_anki(ivl: i64) -> Duration {
+    match ivl {
+        ivl if ivl < 0 => Duration::seconds(-ivl),
+        ivl if ivl > 0 => Duration::days(ivl),
+        _ => *MIN_INTERVAL,
+    }
+}
+
+/// Convert an ease to Anki's format, which is in permille.
+fn anki_factor(ease: f64) -> i64 {
+    (ease * 1000.0).round() as i64
+}
+
+/// Replay a user's reviews of a puzzle, oldest first, to get the revlog entries for them, as the
+/// intervals and eases after each review aren't stored.
+pub fn replay_reviews(puzzle_id: &str, reviews: &[Review], srs_config: SrsConfig) -> ReplayedReviews {
+    let mut replayed = ReplayedReviews::default();
+    let Some(first_review) = reviews.first() else {
+        return replayed;
+    };
+
+    let mut card = Card::new(puzzle_id, first_review.date, srs_config);
+    for review in reviews {
+        let was_learning = card.in_learning();
+        let last_ivl = anki_interval(card.interval);
+
+        let review_type = match (was_learning, replayed.relearning) {
+            (false, _) => REVLOG_REVIEW,
+            (true, true) => REVLOG_RELEARNING,
+            (true, false) => REVLOG_LEARNING,
+        };
+
+        if review.difficulty == Difficulty::Again && !was_learning {
+            replayed.lapses += 1;
+            replayed.relearning = true;
+        }
+
+        card.review(review.date, review.difficulty);
+        if !card.in_learning() {
+            replayed.relearning = false;
+        }
+
+        // Revlog IDs have to be unique, so reviews at the same millisecond are moved apart.
+        let id = match replayed.revlog.last() {
+            Some(last) if last.id >= review.date.timestamp_millis() => last.id + 1,
+            _ => review.date.timestamp_millis(),
+        };
+
+        replayed.revlog.push(RevlogEntry {
+            id,
+            ease: review.difficulty.to_i64() + 1,
+            ivl: anki_interval(card.interval),
+            last_ivl,
+            factor: anki_factor(card.ease),
+            time: review.attempt.duration_ms.unwrap_or(0).clamp(0, MAX_ANSWER_TIME_MS),
+            review_type,
+        });
+    }
+
+    replayed
+}
+
+/// Get the Anki scheduling fields for a card. `crt` is the creation time of the collection, which
+/// review cards' due days are relative to.
+pub fn anki_card(card: &Card, replayed: &ReplayedReviews, crt: i64) -> AnkiCard {
+    let (card_type, queue, due, ivl, left) = match card.in_learning() {
+        true => {
+            let steps_left = INITIAL_INTERVALS.len() as i64 - card.learning_stage.max(0);
+            let card_type = match replayed.relearning {
+                true => CARD_TYPE_RELEARNING,
+                false => CARD_TYPE_LEARNING,
+            };
+            (card_type, QUEUE_LEARNING, card.due.timestamp(), 0, steps_left)
+        },
+        false => {
+            let due_day = (card.due.timestamp() - crt).div_euclid(Duration::days(1).num_seconds());
+            (CARD_TYPE_REVIEW, QUEUE_REVIEW, due_day, card.interval.num_days().max(1), 0)
+        },
+    };
+
+    AnkiCard {
+        card_type,
+        queue,
+        due,
+        ivl,
+        factor: anki_factor(card.ease),
+        reps: card.review_count,
+        lapses: replayed.lapses,
+        left,
+    }
+}
+
+/// Get a card from an Anki card's scheduling fields, and the interval from its last revlog entry
+/// if it has one. New cards don't have any scheduling to import, so they're None.
+pub fn card_from_anki(puzzle_id: &str, anki_card: &AnkiCard, last_ivl: Option<i64>, crt: i64,
+    srs_config: SrsConfig) -> Option<Card>
+{
+    let due = match anki_card.due {
+        due if due >= MIN_DUE_TIMESTAMP => due,
+        due => crt + due * Duration::days(1).num_seconds(),
+    };
+    let due = Local.timestamp_opt(due, 0).single()?.fixed_offset();
+
+    let (interval, learning_stage) = match anki_card.card_type {
+        CARD_TYPE_NEW => return None,
+        CARD_TYPE_REVIEW => (Duration::days(anki_card.ivl.max(1)), INITIAL_INTERVALS.len() as i64),
+        _ => {
+            // The number of learning steps left is in the last three digits.
+            let steps_left = (anki_card.left % 1000).clamp(1, INITIAL_INTERVALS.len() as i64);
+            let interval = last_ivl.map(interval_from_anki).unwrap_or(*MIN_INTERVAL);
+            (interval, INITIAL_INTERVALS.len() as i64 - steps_left)
+        },
+    };
+
+    let ease = match anki_card.factor {
+        0 => srs_config.default_ease,
+        factor => f64::max(srs_config.minimum_ease, factor as f64 / 1000.0),
+    };
+
+    Some(Card {
+        id: puzzle_id.to_string(),
+        due,
+        interval,
+        review_count: anki_card.reps,
+        ease,
+        learning_stage,
+        srs_config,
+    })
+}
+
+/// Get the revlog entries of a puzzle that aren't reviews the user already has, from the times of
+/// their reviews of the puzzle in milliseconds. Entries are matched to the reviews they were
+/// exported from, which can be up to `MAX_REVLOG_ID_SHIFT_MS` before them, as reviews at the same
+/// millisecond are moved apart when they're exported. Each review only matches one entry.
+pub fn new_revlog_entries<'a>(revlog: &'a [RevlogEntry], review_times: &[i64]) -> Vec<&'a RevlogEntry> {
+    let mut entries: Vec<&RevlogEntry> = revlog.iter().collect();
+    entries.sort_by_key(|entry| entry.id);
+    let mut review_times = review_times.to_vec();
+    review_times.sort_unstable();
+
+    let mut review_times = review_times.into_iter().peekable();
+    entries.into_iter()
+        .filter(|entry| {
+            // Reviews too long before this entry can't match it, or any of the later ones.
+            while review_times.next_if(|&time| time < entry.id - MAX_REVLOG_ID_SHIFT_MS).is_some() {}
+            review_times.next_if(|&time| time <= entry.id).is_none()
+        })
+        .collect()
+}
+
+/// Get a review from a revlog entry. Entries that aren't reviews, like cards being rescheduled
+/// by hand, are None.
+pub fn review_from_revlog(user_id: &str, puzzle_id: &str, entry: &RevlogEntry) -> Option<Review> {
+    if !(1..=4).contains(&entry.ease) || entry.review_type > REVLOG_RELEARNING {
+        return None;
+    }
+
+    Some(Review {
+        user_id: user_id.to_string(),
+        puzzle_id: puzzle_id.to_string(),
+        difficulty: Difficulty::from_i64(entry.ease - 1).ok()?,
+        date: Local.timestamp_millis_opt(entry.id).single()?.fixed_offset(),
+        user_rating: None,
+        attempt: AttemptData {
+            duration_ms: (entry.time > 0).then_some(entry.time),
+            ..Default::default()
+        },
+    })
+}
+
+/// The fields of a puzzle's note, in the order of `NOTE_FIELDS`. The FEN and board are of the
+/// position after the opponent's first move, which is the one the user has to solve.
+fn note_fields(puzzle: &Puzzle, board_filename: &str) -> Result<Vec<String>, String> {
+    let mut position = Position::from_fen(&puzzle.fen).map_err(|e| e.to_string())?;
+    let mut moves = puzzle.moves.split_whitespace();
+    if let Some(first_move) = moves.next() {
+        position.play_uci(first_move).map_err(|e| e.to_string())?;
+    }
+
+    let fen = position.to_fen();
+    let solution = pgn::san_line(&fen, &moves.collect::<Vec<_>>().join(" "))
+        .map_err(|e| e.to_string())?;
+
+    Ok(vec![
+        puzzle.puzzle_id.clone(),
+        fen,
+        solution,
+        puzzle.themes.join(" "),
+        format!("<img src=\"{board_filename}\">"),
+    ])
+}
+
+/// Anki's checksum of a note's first field, which it uses to find duplicates.
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    i64::from(u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]))
+}
+
+/// The collection's configuration, note type and deck, as the json Anki stores in the col table.
+fn collection_json(now: i64) -> (String, String, String, String) {
+    let conf = json!({
+        "nextPos": 1,
+        "estTimes": true,
+        "activeDecks": [DECK_ID],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": DECK_ID,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": NOTE_TYPE_ID.to_string(),
+        "collapseTime": 1200,
+    });
+
+    let fields: Vec<_> = NOTE_FIELDS.iter().enumerate().map(|(ord, name)| json!({
+        "name": name,
+        "ord": ord,
+        "sticky": false,
+        "rtl": false,
+        "font": "Arial",
+        "size": 20,
+        "media": [],
+    })).collect();
+
+    let models = json!({
+        NOTE_TYPE_ID.to_string(): {
+            "id": NOTE_TYPE_ID,
+            "name": NOTE_TYPE_NAME,
+            "type": 0,
+            "mod": now,
+            "usn": 0,
+            "sortf": 0,
+            "did": DECK_ID,
+            "tmpls": [{
+                "name": "Puzzle",
+                "ord": 0,
+                "qfmt": "<div class=\"board\">{{Board}}</div><div class=\"fen\">{{FEN}}</div>",
+                "afmt": "{{FrontSide}}<hr id=answer><div class=\"solution\">{{Solution}}</div>\
+                    <div class=\"themes\">{{Themes}}</div>",
+                "bqfmt": "",
+                "bafmt": "",
+                "did": null,
+            }],
+            "flds": fields,
+            "css": ".card { font-family: arial; font-size: 20px; text-align: center; }\n\
+                .board img { max-width: 400px; width: 100%; }\n\
+                .fen, .themes { font-size: 12px; color: grey; }",
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\
+                \\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\
+                \\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "req": [[0, "any", [0, 4]]],
+            "tags": [],
+            "vers": [],
+        },
+    });
+
+    let deck = |id: i64, name: &str| json!({
+        "id": id,
+        "name": name,
+        "mod": now,
+        "usn": 0,
+        "lrnToday": [0, 0],
+        "revToday": [0, 0],
+        "newToday": [0, 0],
+        "timeToday": [0, 0],
+        "collapsed": false,
+        "desc": "",
+        "dyn": 0,
+        "conf": 1,
+        "extendNew": 10,
+        "extendRev": 50,
+    });
+
+    let decks = json!({
+        "1": deck(1, "Default"),
+        DECK_ID.to_string(): deck(DECK_ID, DECK_NAME),
+    });
+
+    let deck_config = json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1, 10],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "order": 1,
+                "perDay": 20,
+                "bury": false,
+            },
+            "lapse": {
+                "delays": [10],
+                "mult": 0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0,
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "ivlFct": 1,
+                "maxIvl": 36500,
+                "bury": false,
+                "hardFactor": 1.2,
+            },
+        },
+    });
+
+    (conf.to_string(), models.to_string(), decks.to_string(), deck_config.to_string())
+}
+
+/// Export puzzles, their cards and the user's reviews of them as an Anki deck, with a note for
+/// each puzzle that has a board image of the puzzle.
+pub async fn write_apkg(puzzles: &[ExportedPuzzle], now: DateTime<FixedOffset>)
+    -> Result<Vec<u8>, String>
+{
+    let dir = tempfile::tempdir().map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+    let collection_path = dir.path().join("collection.anki2");
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(&collection_path)
+        .create_if_missing(true)
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to create collection: {e}"))?;
+
+    let mut media = Vec::new();
+    let result = write_collection(&mut conn, puzzles, now, &mut media).await;
+    conn.close().await.map_err(|e| format!("Failed to close collection: {e}"))?;
+    result.map_err(|e| format!("Failed to write collection: {e}"))?;
+
+    let collection = std::fs::read(&collection_path)
+        .map_err(|e| format!("Failed to read collection: {e}"))?;
+
+    // The media are named by their index in the zip, and the media file maps them to their names.
+    let media_names: HashMap<String, &str> = media.iter()
+        .enumerate()
+        .map(|(i, (name, _))| (i.to_string(), name.as_str()))
+        .collect();
+
+    let write_zip = || -> zip::result::ZipResult<Vec<u8>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+
+        zip.start_file("collection.anki2", options)?;
+        zip.write_all(&collection)?;
+        zip.start_file("media", options)?;
+        zip.write_all(json!(media_names).to_string().as_bytes())?;
+        for (i, (_, contents)) in media.iter().enumerate() {
+            zip.start_file(i.to_string(), options)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    };
+
+    write_zip().map_err(|e| format!("Failed to write apkg: {e}"))
+}
+
+/// Write the puzzles to a new collection, and add their board images to `media`.
+async fn write_collection(conn: &mut SqliteConnection, puzzles: &[ExportedPuzzle],
+    now: DateTime<FixedOffset>, media: &mut Vec<(String, String)>) -> Result<(), String>
+{
+    let now_secs = now.timestamp();
+    let now_ms = now.timestamp_millis();
+
+    // Review cards are due on a day relative to the collection's creation, so it's created on the
+    // day of the earliest due card, so that none of them are due before it.
+    let day_secs = Duration::days(1).num_seconds();
+    let earliest_due = puzzles.iter()
+        .map(|puzzle| puzzle.card.due.timestamp())
+        .fold(now_secs, i64::min);
+    let crt = earliest_due - earliest_due.rem_euclid(day_secs);
+
+    // Revlog IDs have to be unique across the whole collection, and not just for each card.
+    let mut revlog_ids = HashSet::new();
+
+    let mut tx = conn.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query(COLLECTION_SCHEMA)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (conf, models, decks, deck_config) = collection_json(now_secs);
+    sqlx::query("
+        INSERT INTO col (id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, dconf, tags)
+        VALUES (1, ?, ?, ?, 11, 0, 0, 0, ?, ?, ?, ?, '{}')
+    ")
+        .bind(crt)
+        .bind(now_ms)
+        .bind(now_ms)
+        .bind(conf)
+        .bind(models)
+        .bind(decks)
+        .bind(deck_config)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for (i, exported) in puzzles.iter().enumerate() {
+        let puzzle = &exported.puzzle;
+        let id = now_ms + i as i64;
+
+        let board_filename = format!("better-tactics-{}.svg", puzzle.puzzle_id);
+        let board = render::puzzle_svg(puzzle).map_err(|e| e.to_string())?;
+        let fields = note_fields(puzzle, &board_filename)
+            .map_err(|e| format!("Failed to export puzzle {}: {e}", puzzle.puzzle_id))?;
+        media.push((board_filename, board));
+
+        // The guid is based on the puzzle ID, so that exporting the same puzzle again updates the
+        // note in Anki rather than adding a duplicate.
+        sqlx::query("
+            INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data)
+            VALUES (?, ?, ?, ?, 0, ' better-tactics ', ?, ?, ?, 0, '')
+        ")
+            .bind(id)
+            .bind(format!("better-tactics-{}", puzzle.puzzle_id))
+            .bind(NOTE_TYPE_ID)
+            .bind(now_secs)
+            .bind(fields.join(&FIELD_SEPARATOR.to_string()))
+            .bind(&puzzle.puzzle_id)
+            .bind(field_checksum(&puzzle.puzzle_id))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let replayed = replay_reviews(&puzzle.puzzle_id, &exported.reviews, exported.card.srs_config);
+        let card = anki_card(&exported.card, &replayed, crt);
+
+        sqlx::query("
+            INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps,
+                lapses, left, odue, odid, flags, data)
+            VALUES (?, ?, ?, 0, ?, 0, ?, ?, ?, ?, ?, ?, ?, ?, 0, 0, 0, '')
+        ")
+            .bind(id)
+            .bind(id)
+            .bind(DECK_ID)
+            .bind(now_secs)
+            .bind(card.card_type)
+            .bind(card.queue)
+            .bind(card.due)
+            .bind(card.ivl)
+            .bind(card.factor)
+            .bind(card.reps)
+            .bind(card.lapses)
+            .bind(card.left)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for entry in replayed.revlog {
+            let mut revlog_id = entry.id;
+            while !revlog_ids.insert(revlog_id) {
+                revlog_id += 1;
+            }
+
+            sqlx::query("
+                INSERT INTO revlog (id, cid, usn, ease, ivl, lastIvl, factor, time, type)
+                VALUES (?, ?, 0, ?, ?, ?, ?, ?, ?)
+            ")
+                .bind(revlog_id)
+                .bind(id)
+                .bind(entry.ease)
+                .bind(entry.ivl)
+                .bind(entry.last_ivl)
+                .bind(entry.factor)
+                .bind(entry.time)
+                .bind(entry.review_type)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Read the cards and revlogs of notes created by the exporter from an Anki deck or collection
+/// package.
+pub async fn read_apkg(apkg: &[u8]) -> Result<AnkiCollection, String> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(apkg))
+        .map_err(|e| format!("Failed to read apkg: {e}"))?;
+
+    // Newer versions of Anki put the real collection in a newer format next to a legacy one that
+    // just says to update Anki, and the newest format is compressed with zstd.
+    let name = ["collection.anki21b", "collection.anki21", "collection.anki2"].into_iter()
+        .find(|name| zip.file_names().any(|file_name| file_name == *name))
+        .ok_or_else(|| "No collection in apkg".to_string())?;
+
+    let mut collection = zip.by_name(name)
+        .map_err(io::Error::from)
+        .and_then(|file| read_limited(file, MAX_COLLECTION_BYTES))
+        .map_err(|e| format!("Failed to read collection: {e}"))?;
+
+    if name == "collection.anki21b" {
+        collection = zstd::stream::Decoder::new(collection.as_slice())
+            .and_then(|decoder| read_limited(decoder, MAX_COLLECTION_BYTES))
+            .map_err(|e| format!("Failed to decompress collection: {e}"))?;
+    }
+
+    let dir = tempfile::tempdir().map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+    let collection_path = dir.path().join("collection.anki2");
+    std::fs::write(&collection_path, collection)
+        .map_err(|e| format!("Failed to write collection: {e}"))?;
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(&collection_path)
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to open collection: {e}"))?;
+
+    let result = read_collection(&mut conn).await;
+    conn.close().await.map_err(|e| format!("Failed to close collection: {e}"))?;
+
+    result.map_err(|e| format!("Failed to read collection: {e}"))
+}
+
+/// Read all of a reader, or fail if there's more than `limit` bytes.
+fn read_limited(reader: impl Read, limit: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("larger than {limit} bytes")));
+    }
+
+    Ok(bytes)
+}
+
+/// Read the puzzles from an Anki collection.
+async fn read_collection(conn: &mut SqliteConnection) -> Result<AnkiCollection, sqlx::Error> {
+    let (crt, models): (i64, String) = sqlx::query_as("SELECT crt, models FROM col")
+        .fetch_one(&mut *conn)
+        .await?;
+
+    // Note types are in the notetypes table in newer collections, and in the col table as json in
+    // the legacy format (where the notetypes table doesn't exist).
+    let mut note_type_ids: Vec<i64> = match sqlx::query("SELECT id FROM notetypes WHERE name = ?")
+        .bind(NOTE_TYPE_NAME)
+        .fetch_all(&mut *conn)
+        .await
+    {
+        Ok(rows) => rows.iter().map(|row| row.try_get("id")).collect::<Result<_, _>>()?,
+        Err(_) => Vec::new(),
+    };
+
+    if let Ok(models) = serde_json::from_str::<HashMap<String, serde_json::Value>>(&models) {
+        note_type_ids.extend(models.values()
+            .filter(|model| model["name"] == NOTE_TYPE_NAME)
+            .filter_map(|model| model["id"].as_i64()));
+    }
+
+    let mut puzzles = Vec::new();
+    for note_type_id in note_type_ids {
+        let notes = sqlx::query("
+            SELECT notes.flds, cards.id AS card_id, cards.type, cards.queue, cards.due, cards.ivl,
+                cards.factor, cards.reps, cards.lapses, cards.left
+            FROM notes
+            LEFT JOIN cards ON cards.nid = notes.id AND cards.ord = 0
+            WHERE notes.mid = ?
+        ")
+            .bind(note_type_id)
+            .fetch_all(&mut *conn)
+            .await?;
+
+        for note in notes {
+            let fields: String = note.try_get("flds")?;
+            let Some(puzzle_id) = fields.split(FIELD_SEPARATOR).next().filter(|id| !id.is_empty()) else {
+                continue;
+            };
+
+            let card_id: Option<i64> = note.try_get("card_id")?;
+            let Some(card_id) = card_id else {
+                puzzles.push(ImportedPuzzle { puzzle_id: puzzle_id.to_string(), card: None, revlog: Vec::new() });
+                continue;
+            };
+
+            let card = AnkiCard {
+                card_type: note.try_get("type")?,
+                queue: note.try_get("queue")?,
+                due: note.try_get("due")?,
+                ivl: note.try_get("ivl")?,
+                factor: note.try_get("factor")?,
+                reps: note.try_get("reps")?,
+                lapses: note.try_get("lapses")?,
+                left: note.try_get("left")?,
+            };
+
+            let revlog = sqlx::query("SELECT * FROM revlog WHERE cid = ? ORDER BY id")
+                .bind(card_id)
+                .fetch_all(&mut *conn)
+                .await?
+                .iter()
+                .map(|row| Ok(RevlogEntry {
+                    id: row.try_get("id")?,
+                    ease: row.try_get("ease")?,
+                    ivl: row.try_get("ivl")?,
+                    last_ivl: row.try_get("lastIvl")?,
+                    factor: row.try_get("factor")?,
+                    time: row.try_get("time")?,
+                    review_type: row.try_get("type")?,
+                }))
+                .collect::<Result<_, sqlx::Error>>()?;
+
+            puzzles.push(ImportedPuzzle { puzzle_id: puzzle_id.to_string(), card: Some(card), revlog });
+        }
+    }
+
+    Ok(AnkiCollection { crt, puzzles })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{DateTime, Duration, FixedOffset};
+
+    use crate::anki::{self, ExportedPuzzle, REVLOG_LEARNING, REVLOG_REVIEW};
+    use crate::db::{AttemptData, Puzzle, Review};
+    use crate::srs::{Card, Difficulty, SrsConfig};
+
+    fn date(hours: i64) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2023-11-01T12:00:00+00:00").unwrap() + Duration::hours(hours)
+    }
+
+    fn review(hours: i64, difficulty: Difficulty) -> Review {
+        Review {
+            user_id: "local".to_string(),
+            puzzle_id: "0000D".to_string(),
+            difficulty,
+            date: date(hours),
+            user_rating: None,
+            attempt: AttemptData { duration_ms: Some(5000), ..Default::default() },
+        }
+    }
+
+    /// Replay reviews onto a new card, as they are in the app.
+    fn reviewed_card(reviews: &[Review]) -> Card {
+        let mut card = Card::new("0000D", reviews[0].date, SrsConfig::default());
+        for review in reviews {
+            card.review(review.date, review.difficulty);
+        }
+        card
+    }
+
+    #[test]
+    fn test_replay_reviews() {
+        let reviews = vec![
+            review(0, Difficulty::Good),
+            review(1, Difficulty::Good),
+            review(48, Difficulty::Good),
+            review(200, Difficulty::Again),
+        ];
+
+        let replayed = anki::replay_reviews("0000D", &reviews, SrsConfig::default());
+        assert_eq!(replayed.lapses, 1);
+        assert!(replayed.relearning);
+
+        let types: Vec<_> = replayed.revlog.iter().map(|entry| entry.review_type).collect();
+        assert_eq!(types, vec![REVLOG_LEARNING, REVLOG_LEARNING, REVLOG_REVIEW, REVLOG_REVIEW]);
+
+        let eases: Vec<_> = replayed.revlog.iter().map(|entry| entry.ease).collect();
+        assert_eq!(eases, vec![3, 3, 3, 1]);
+
+        // The first interval is 10 minutes, in negative seconds, then a day.
+        assert_eq!(replayed.revlog[0].ivl, -600);
+        assert_eq!(replayed.revlog[1].last_ivl, -600);
+        assert_eq!(replayed.revlog[1].ivl, 1);
+        assert_eq!(replayed.revlog[3].ivl, -60);
+        assert_eq!(replayed.revlog[0].id, date(0).timestamp_millis());
+        assert_eq!(replayed.revlog[0].time, 5000);
+
+        // Reviews at the same time still get unique IDs.
+        let replayed = anki::replay_reviews("0000D", &[review(0, Difficulty::Again), review(0, Difficulty::Good)],
+            SrsConfig::default());
+        assert_eq!(replayed.revlog[1].id, replayed.revlog[0].id + 1);
+    }
+
+    #[test]
+    fn test_anki_card_round_trip() {
+        let srs_config = SrsConfig::default();
+        let crt = date(-24 * 10).timestamp();
+
+        let card_reviews = [
+            // A card in review.
+            vec![review(0, Difficulty::Good), review(1, Difficulty::Good), review(48, Difficulty::Easy)],
+            // A card in learning.
+            vec![review(0, Difficulty::Good)],
+            // A card that's been forgotten.
+            vec![review(0, Difficulty::Easy), review(200, Difficulty::Again)],
+        ];
+
+        for reviews in card_reviews {
+            let card = reviewed_card(&reviews);
+            let replayed = anki::replay_reviews("0000D", &reviews, srs_config);
+            let anki_card = anki::anki_card(&card, &replayed, crt);
+            let last_ivl = replayed.revlog.last().map(|entry| entry.ivl);
+
+            let imported = anki::card_from_anki("0000D", &anki_card, last_ivl, crt, srs_config).unwrap();
+            assert_eq!(imported.id, card.id);
+            assert_eq!(imported.review_count, card.review_count);
+            assert_eq!(imported.interval, card.interval);
+            assert_eq!(imported.learning_stage, card.learning_stage);
+            assert!((imported.ease - card.ease).abs() < 0.001);
+
+            // Review cards are only due on a day, so they come back due at the start of it.
+            assert!(imported.due <= card.due);
+            assert!(card.due - imported.due < Duration::days(1));
+        }
+
+        // New cards have no scheduling to import.
+        let new_card = anki::AnkiCard { card_type: 0, queue: 0, due: 1, ivl: 0, factor: 0, reps: 0,
+            lapses: 0, left: 0 };
+        assert!(anki::card_from_anki("0000D", &new_card, None, crt, srs_config).is_none());
+    }
+
+    #[test]
+    fn test_review_from_revlog() {
+        let replayed = anki::replay_reviews("0000D", &[review(0, Difficulty::Hard)], SrsConfig::default());
+        let imported = anki::review_from_revlog("local", "0000D", &replayed.revlog[0]).unwrap();
+        assert_eq!(imported.difficulty, Difficulty::Hard);
+        assert_eq!(imported.date, date(0));
+        assert_eq!(imported.attempt.duration_ms, Some(5000));
+
+        // Manual rescheduling isn't a review.
+        let rescheduled = anki::RevlogEntry { review_type: 4, ease: 0, ..replayed.revlog[0] };
+        assert!(anki::review_from_revlog("local", "0000D", &rescheduled).is_none());
+    }
+
+    #[test]
+    fn test_new_revlog_entries() {
+        // Two reviews at the same time are exported a millisecond apart, and both match.
+        let reviews = [review(0, Difficulty::Again), review(0, Difficulty::Good), review(1, Difficulty::Good)];
+        let replayed = anki::replay_reviews("0000D", &reviews, SrsConfig::default());
+        let times: Vec<_> = reviews.iter().map(|review| review.date.timestamp_millis()).collect();
+        assert!(anki::new_revlog_entries(&replayed.revlog, &times).is_empty());
+
+        // Reviews made in Anki since don't match, even if they're just after one that does.
+        let mut revlog = replayed.revlog.clone();
+        revlog.push(anki::RevlogEntry { id: revlog[2].id + 1, ..revlog[2] });
+        revlog.push(anki::RevlogEntry { id: date(2).timestamp_millis(), ..revlog[2] });
+        let new_ids: Vec<_> = anki::new_revlog_entries(&revlog, &times).iter().map(|entry| entry.id).collect();
+        assert_eq!(new_ids, [revlog[3].id, revlog[4].id]);
+
+        // Without the user's reviews, they're all new.
+        assert_eq!(anki::new_revlog_entries(&revlog, &[]).len(), 5);
+    }
+
+    #[test]
+    fn test_read_limited() {
+        let collection = vec![0; 1000];
+        let compressed = zstd::encode_all(collection.as_slice(), 0).unwrap();
+        assert!(compressed.len() < 100);
+
+        let decoder = || zstd::stream::Decoder::new(compressed.as_slice()).unwrap();
+        assert_eq!(anki::read_limited(decoder(), 1000).unwrap(), collection);
+        assert!(anki::read_limited(decoder(), 999).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apkg_round_trip() {
+        let puzzle = Puzzle {
+            puzzle_id: "0000D".to_string(),
+            fen: "5rk1/1p3ppp/pq3b2/8/8/1P1Q1N2/P4PPP/3R2K1 w - - 2 27".to_string(),
+            moves: "d3d6 f8d8 d6d8 f6d8".to_string(),
+            rating: 1517,
+            rating_deviation: 75,
+            popularity: 97,
+            number_of_plays: 11655,
+            themes: vec!["advantage".to_string(), "endgame".to_string()],
+            game_url: String::new(),
+            opening_tags: Vec::new(),
+            source: "lichess".to_string(),
+        };
+
+        let reviews = vec![review(0, Difficulty::Good), review(1, Difficulty::Good), review(48, Difficulty::Again)];
+        let card = reviewed_card(&reviews);
+        let export = |puzzle: &Puzzle, reviews: &[Review]| {
+            ExportedPuzzle { puzzle: puzzle.clone(), card: reviewed_card(reviews), reviews: reviews.to_vec() }
+        };
+        let exported = export(&puzzle, &reviews);
+
+        let apkg = anki::write_apkg(&[exported], date(100)).await.unwrap();
+        let collection = anki::read_apkg(&apkg).await.unwrap();
+
+        assert_eq!(collection.puzzles.len(), 1);
+        let imported = &collection.puzzles[0];
+        assert_eq!(imported.puzzle_id, "0000D");
+        assert_eq!(imported.revlog.len(), 3);
+
+        let imported_reviews: Vec<_> = imported.revlog.iter()
+            .filter_map(|entry| anki::review_from_revlog("local", "0000D", entry))
+            .collect();
+        assert_eq!(imported_reviews.iter().map(|review| review.date).collect::<Vec<_>>(),
+            reviews.iter().map(|review| review.date).collect::<Vec<_>>());
+
+        let imported_card = anki::card_from_anki("0000D", imported.card.as_ref().unwrap(),
+            imported.revlog.last().map(|entry| entry.ivl), collection.crt, SrsConfig::default()).unwrap();
+        assert_eq!(imported_card.due, card.due);
+        assert_eq!(imported_card.interval, card.interval);
+        assert_eq!(imported_card.review_count, 3);
+
+        // Reviews of different puzzles at the same time all get exported, with unique IDs.
+        let other_puzzle = Puzzle { puzzle_id: "00008".to_string(), ..puzzle.clone() };
+        let other_reviews: Vec<_> = reviews.iter()
+            .map(|review| Review { puzzle_id: "00008".to_string(), ..review.clone() })
+            .collect();
+        let exports = [export(&puzzle, &reviews), export(&other_puzzle, &other_reviews)];
+        let apkg = anki::write_apkg(&exports, date(100)).await.unwrap();
+        let collection = anki::read_apkg(&apkg).await.unwrap();
+
+        assert_eq!(collection.puzzles.len(), 2);
+        let revlog_ids: HashSet<_> = collection.puzzles.iter()
+            .flat_map(|puzzle| puzzle.revlog.iter().map(|entry| entry.id))
+            .collect();
+        assert_eq!(revlog_ids.len(), 6);
+
+        // And they match the reviews they came from when they're imported again.
+        let times: Vec<_> = reviews.iter().map(|review| review.date.timestamp_millis()).collect();
+        for puzzle in &collection.puzzles {
+            assert!(anki::new_revlog_entries(&puzzle.revlog, &times).is_empty());
+        }
+
+        // Apkgs without the exporter's notes don't have any puzzles.
+        let collection = anki::read_apkg(&anki::write_apkg(&[], date(0)).await.unwrap()).await.unwrap();
+        assert!(collection.puzzles.is_empty());
+    }
+}
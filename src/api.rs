@@ -1,11 +1,22 @@
+mod analysis;
+mod anki;
+mod collections;
+mod packs;
+mod personal;
+mod puzzle_db;
+mod render;
+mod reports;
+mod rush;
+mod solve;
 mod tactics;
 mod user;
 
 use axum::{Router, Json};
 use axum::body::Body;
+use axum::extract::DefaultBodyLimit;
 use axum::http::{StatusCode, Request};
 use axum::response::{Response, IntoResponse};
-use axum::routing::{get, post};
+use axum::routing::{get, post, delete};
 
 use crate::app::AppState;
 use crate::services::ServiceError;
@@ -17,12 +28,76 @@ pub type ApiResult<T> = Result<T, ApiError>;
 pub fn routes(app_state: AppState) -> Router {
     Router::new()
         // Tactics.
+        .route("/tactics/random", get(tactics::adaptive_random_puzzle))
         .route("/tactics/random/:min_rating/:max_rating", get(tactics::random_puzzle))
         .route("/tactics/random/skip", post(tactics::skip_next))
+        .route("/tactics/weaknesses/random", get(tactics::weakness_random_puzzle))
         .route("/tactics/by_id/:puzzle_id", get(tactics::puzzle_by_id))
+        .route("/tactics/pgn/:puzzle_id", get(tactics::puzzle_pgn))
+        .route("/tactics/daily", get(tactics::daily_puzzle))
+        .route("/tactics/daily", post(tactics::daily_result))
         .route("/tactics/review", get(tactics::next_review))
         .route("/tactics/review", post(tactics::review))
         .route("/tactics/history/:page", get(tactics::puzzle_history))
+        .route("/tactics/search", get(tactics::search_puzzles))
+        .route("/tactics/notes/:puzzle_id", get(tactics::puzzle_notes))
+        .route("/tactics/notes/:puzzle_id", post(tactics::set_puzzle_notes))
+
+        // Collections.
+        .route("/collections", get(collections::collections))
+        .route("/collections", post(collections::create_collection))
+        .route("/collections/:collection_id", get(collections::collection))
+        .route("/collections/:collection_id", post(collections::rename_collection))
+        .route("/collections/:collection_id", delete(collections::delete_collection))
+        .route("/collections/:collection_id/pgn", get(collections::collection_pgn))
+        .route("/collections/:collection_id/puzzles", post(collections::add_puzzle))
+        .route("/collections/:collection_id/puzzles/:puzzle_id", delete(collections::remove_puzzle))
+
+        // Puzzle reports.
+        .route("/reports", get(reports::reports))
+        .route("/reports", post(reports::report_puzzle))
+        .route("/reports/csv", get(reports::reports_csv))
+        .route("/reports/:puzzle_id", delete(reports::undo_report))
+
+        // Puzzle rush.
+        .route("/rush/start", post(rush::start_run))
+        .route("/rush/history", get(rush::history))
+        .route("/rush/best", get(rush::personal_bests))
+        .route("/rush/:run_id", get(rush::run))
+        .route("/rush/:run_id/result", post(rush::submit_result))
+        .route("/rush/:run_id/add_failed", post(rush::add_failed_puzzles))
+
+        // Solve sessions.
+        .route("/solve/start", post(solve::start_session))
+        .route("/solve/:session_id", get(solve::session))
+        .route("/solve/:session_id/move", post(solve::submit_move))
+        .route("/solve/:session_id/reset", post(solve::reset))
+        .route("/solve/:session_id/hint", post(solve::hint))
+
+        // Engine analysis.
+        .route("/analysis", post(analysis::analyse))
+
+        // Personal puzzles generated from the user's games.
+        .route("/personal/import", get(personal::import_status))
+        .route("/personal/import", post(personal::start_import)
+            .layer(DefaultBodyLimit::max(personal::MAX_PGN_BYTES)))
+
+        // Puzzle packs imported from files.
+        .route("/packs", get(packs::packs))
+        .route("/packs", post(packs::import_pack)
+            .layer(DefaultBodyLimit::max(packs::MAX_PACK_BYTES)))
+
+        // Anki decks of the user's cards and reviews.
+        .route("/anki/export", get(anki::export_deck))
+        .route("/anki/import", post(anki::import_deck)
+            .layer(DefaultBodyLimit::max(anki::MAX_APKG_BYTES)))
+
+        // The lichess puzzle database.
+        .route("/puzzle_db", get(puzzle_db::status))
+        .route("/puzzle_db/refresh", post(puzzle_db::refresh))
+
+        // Board thumbnails.
+        .route("/render/:file_name", get(render::board_thumbnail))
 
         // User.
         .route("/user/stats", axum::routing::get(user::stats))
@@ -30,6 +105,11 @@ pub fn routes(app_state: AppState) -> Router {
         .route("/user/rating_history", axum::routing::get(user::rating_history))
         .route("/user/review_score_histogram/:bucket_size", axum::routing::get(user::review_score_histogram))
         .route("/user/reset_rating/:new_rating", axum::routing::get(user::reset_rating))
+        .route("/user/weaknesses", get(user::weaknesses))
+        .route("/user/settings", get(user::get_settings))
+        .route("/user/settings", post(user::set_settings))
+        .route("/user/lichess_activity", post(user::import_lichess_activity)
+            .layer(DefaultBodyLimit::max(user::MAX_ACTIVITY_BYTES)))
 
         .fallback(not_found)
 
@@ -38,7 +118,7 @@ pub fn routes(app_state: AppState) -> Router {
 
 /// Not found handler.
 pub async fn not_found(req: Request<Body>) -> ApiError {
-    ApiError::NotFound(req.uri().to_string())
+    ApiError::NotFound(format!("api endpoint /api{}", req.uri()))
 }
 
 /// Type for API responses.
@@ -58,6 +138,8 @@ pub enum ApiError {
     NotFound(String),
     InternalError(String),
     InvalidParameter(String),
+    Unavailable(String),
+    Conflict(String),
 }
 
 #[derive(serde::Serialize)]
@@ -68,10 +150,10 @@ struct ApiErrorResponse {
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         match self {
-            Self::NotFound(endpoint) => (
+            Self::NotFound(desc) => (
                 StatusCode::NOT_FOUND,
                 Json(ApiErrorResponse {
-                    error: format!("No such api endpoint /api{endpoint}"),
+                    error: format!("No such {desc}"),
                 })
             ),
             Self::InternalError(desc) => (
@@ -86,6 +168,18 @@ impl IntoResponse for ApiError {
                     error: format!("Bad request: invalid parameter {param}"),
                 })
             ),
+            Self::Unavailable(desc) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ApiErrorResponse {
+                    error: format!("Service unavailable: {desc}"),
+                })
+            ),
+            Self::Conflict(desc) => (
+                StatusCode::CONFLICT,
+                Json(ApiErrorResponse {
+                    error: format!("Conflict: {desc}"),
+                })
+            ),
         }.into_response()
     }
 }
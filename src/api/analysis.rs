@@ -0,0 +1,35 @@
+use axum::extract::{State, Json};
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResult};
+use crate::app::AppState;
+use crate::services::analysis_service::Analysis;
+
+/// Request JSON for analysing a position.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnalysisRequest {
+    pub fen: String,
+    // Space separated UCI moves to play from the FEN before analysing.
+    #[serde(default)]
+    pub moves: String,
+    pub depth: Option<u32>,
+    pub movetime_ms: Option<u64>,
+}
+
+/// POST /api/analysis.
+pub async fn analyse(
+    State(state): State<AppState>,
+    Json(request): Json<AnalysisRequest>,
+) -> ApiResult<Json<Analysis>>
+{
+    if !state.analysis_service.enabled() {
+        return Err(ApiError::Unavailable("no engine configured, see ENGINE_PATH in CONFIG.md".to_string()));
+    }
+
+    let analysis = state.analysis_service
+        .analyse(&request.fen, &request.moves, request.depth, request.movetime_ms)
+        .await?
+        .map_err(|e| ApiError::InvalidParameter(format!("position: {e}")))?;
+
+    Ok(analysis.into())
+}
@@ -0,0 +1,46 @@
+use axum::body::Bytes;
+use axum::extract::{State, Json};
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use crate::api::ApiResult;
+use crate::app::AppState;
+use crate::services::anki_service::AnkiImportResult;
+use crate::services::user_service::UserService;
+
+/// The maximum size of an Anki deck to import, in bytes.
+pub const MAX_APKG_BYTES: usize = 100 * 1024 * 1024;
+
+/// GET /api/anki/export, which exports the user's cards and reviews as an Anki deck.
+pub async fn export_deck(
+    State(state): State<AppState>,
+) -> ApiResult<impl IntoResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let apkg = state.anki_service.export_deck(user_id).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"better-tactics.apkg\""),
+        ],
+        apkg,
+    ))
+}
+
+/// POST /api/anki/import, which imports the reviews and scheduling of exported cards from an Anki
+/// deck or collection package, sent as the request body.
+pub async fn import_deck(
+    State(mut state): State<AppState>,
+    apkg: Bytes,
+) -> ApiResult<Json<AnkiImportResult>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let result = state.anki_service.import_deck(user_id, &apkg).await?;
+
+    Ok(result.into())
+}
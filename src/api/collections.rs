@@ -0,0 +1,177 @@
+use axum::extract::{State, Json, Path};
+use axum::http::header;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResponse, ApiResult};
+use crate::app::AppState;
+use crate::db::Collection;
+use crate::services::user_service::UserService;
+
+/// Request JSON for creating or renaming a collection.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectionRequest {
+    pub name: String,
+}
+
+/// Request JSON for adding a puzzle to a collection.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CollectionPuzzleRequest {
+    pub puzzle_id: String,
+}
+
+/// Response JSON for a single collection.
+#[derive(Debug, serde::Serialize)]
+pub struct CollectionResponse {
+    #[serde(flatten)]
+    collection: Collection,
+    puzzle_ids: Vec<String>,
+}
+
+/// GET /api/collections.
+pub async fn collections(State(state): State<AppState>) -> ApiResult<Json<Vec<Collection>>> {
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let collections = state.collection_service.get_collections(user_id).await?;
+
+    Ok(collections.into())
+}
+
+/// POST /api/collections.
+pub async fn create_collection(
+    State(mut state): State<AppState>,
+    Json(request): Json<CollectionRequest>,
+) -> ApiResult<Json<Collection>>
+{
+    if request.name.trim().is_empty() {
+        Err(ApiError::InvalidParameter("name".into()))?;
+    }
+
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let collection = state.collection_service.create_collection(user_id, &request.name).await?;
+
+    Ok(collection.into())
+}
+
+/// GET /api/collections/:collection_id.
+pub async fn collection(
+    State(state): State<AppState>,
+    Path(collection_id): Path<i64>,
+) -> ApiResult<Json<CollectionResponse>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let (collection, puzzle_ids) = state.collection_service
+        .get_collection(user_id, collection_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("collection_id {collection_id}")))?;
+
+    Ok(Json(CollectionResponse { collection, puzzle_ids }))
+}
+
+/// GET /api/collections/:collection_id/pgn, which exports the collection's puzzles as PGN games.
+pub async fn collection_pgn(
+    State(state): State<AppState>,
+    Path(collection_id): Path<i64>,
+) -> ApiResult<impl IntoResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let pgn = state.collection_service
+        .get_collection_pgn(user_id, collection_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("collection_id {collection_id}")))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-chess-pgn; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"collection_{collection_id}.pgn\"")),
+        ],
+        pgn,
+    ))
+}
+
+/// POST /api/collections/:collection_id.
+pub async fn rename_collection(
+    State(mut state): State<AppState>,
+    Path(collection_id): Path<i64>,
+    Json(request): Json<CollectionRequest>,
+) -> ApiResult<ApiResponse>
+{
+    if request.name.trim().is_empty() {
+        Err(ApiError::InvalidParameter("name".into()))?;
+    }
+
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    if !state.collection_service.rename_collection(user_id, collection_id, &request.name).await? {
+        Err(ApiError::InvalidParameter(format!("collection_id {collection_id}")))?;
+    }
+
+    Ok(ApiResponse {
+        response: format!("Renamed collection {collection_id}"),
+    })
+}
+
+/// DELETE /api/collections/:collection_id.
+pub async fn delete_collection(
+    State(mut state): State<AppState>,
+    Path(collection_id): Path<i64>,
+) -> ApiResult<ApiResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    if !state.collection_service.delete_collection(user_id, collection_id).await? {
+        Err(ApiError::InvalidParameter(format!("collection_id {collection_id}")))?;
+    }
+
+    Ok(ApiResponse {
+        response: format!("Deleted collection {collection_id}"),
+    })
+}
+
+/// POST /api/collections/:collection_id/puzzles.
+pub async fn add_puzzle(
+    State(mut state): State<AppState>,
+    Path(collection_id): Path<i64>,
+    Json(request): Json<CollectionPuzzleRequest>,
+) -> ApiResult<ApiResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let puzzle_id = request.puzzle_id.trim();
+
+    if !state.collection_service.add_puzzle(user_id, collection_id, puzzle_id).await? {
+        Err(ApiError::InvalidParameter(format!("collection_id {collection_id} or puzzle_id {puzzle_id}")))?;
+    }
+
+    Ok(ApiResponse {
+        response: format!("Added puzzle {puzzle_id} to collection {collection_id}"),
+    })
+}
+
+/// DELETE /api/collections/:collection_id/puzzles/:puzzle_id.
+pub async fn remove_puzzle(
+    State(mut state): State<AppState>,
+    Path((collection_id, puzzle_id)): Path<(i64, String)>,
+) -> ApiResult<ApiResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    if !state.collection_service.remove_puzzle(user_id, collection_id, &puzzle_id).await? {
+        Err(ApiError::InvalidParameter(format!("collection_id {collection_id}")))?;
+    }
+
+    Ok(ApiResponse {
+        response: format!("Removed puzzle {puzzle_id} from collection {collection_id}"),
+    })
+}
@@ -0,0 +1,45 @@
+use axum::extract::{State, Json};
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResult};
+use crate::app::AppState;
+use crate::db::PuzzlePack;
+use crate::packs::{self, PackFormat};
+use crate::services::pack_service::PackImportResult;
+
+/// The maximum size of a puzzle pack file, in bytes.
+pub const MAX_PACK_BYTES: usize = 20 * 1024 * 1024;
+
+/// Request JSON for importing a puzzle pack.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackImportRequest {
+    pub name: String,
+    pub format: PackFormat,
+    pub contents: String,
+}
+
+/// GET /api/packs.
+pub async fn packs(
+    State(state): State<AppState>,
+) -> ApiResult<Json<Vec<PuzzlePack>>>
+{
+    Ok(state.pack_service.get_packs().await?.into())
+}
+
+/// POST /api/packs, which imports a pack of puzzles. A pack with the same name replaces the
+/// puzzles of the old one with the same IDs.
+pub async fn import_pack(
+    State(mut state): State<AppState>,
+    Json(request): Json<PackImportRequest>,
+) -> ApiResult<Json<PackImportResult>>
+{
+    let name = request.name.trim();
+    let source = packs::pack_source(name)
+        .ok_or_else(|| ApiError::InvalidParameter(format!("pack name '{name}'")))?;
+
+    let result = state.pack_service
+        .import_pack(&source, name, request.format, &request.contents)
+        .await?;
+
+    Ok(result.into())
+}
@@ -0,0 +1,47 @@
+use axum::extract::{State, Json};
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResult};
+use crate::app::AppState;
+use crate::services::personal_service::PersonalImportStatus;
+use crate::services::user_service::UserService;
+
+/// The maximum size of a PGN file to generate puzzles from, in bytes.
+pub const MAX_PGN_BYTES: usize = 20 * 1024 * 1024;
+
+/// Request JSON for generating puzzles from a PGN file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PersonalImportRequest {
+    pub pgn: String,
+    // The player's name in the games, or empty to check both players' moves.
+    #[serde(default)]
+    pub player: String,
+}
+
+/// POST /api/personal/import.
+pub async fn start_import(
+    State(state): State<AppState>,
+    Json(request): Json<PersonalImportRequest>,
+) -> ApiResult<Json<PersonalImportStatus>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    if !state.personal_service.enabled() {
+        return Err(ApiError::Unavailable("no engine configured, see ENGINE_PATH in CONFIG.md".to_string()));
+    }
+
+    let status = state.personal_service
+        .start_import(user_id, &request.pgn, &request.player)?
+        .ok_or_else(|| ApiError::Unavailable("an import is already running".to_string()))?;
+
+    Ok(status.into())
+}
+
+/// GET /api/personal/import.
+pub async fn import_status(
+    State(state): State<AppState>,
+) -> ApiResult<Json<PersonalImportStatus>>
+{
+    Ok(state.personal_service.status().into())
+}
@@ -0,0 +1,37 @@
+use axum::extract::{State, Query};
+use axum::Json;
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResult};
+use crate::app::AppState;
+use crate::services::puzzle_db_service::PuzzleDbStatus;
+
+/// Query parameters for /api/puzzle_db/refresh.
+#[derive(Debug, Deserialize)]
+pub struct RefreshQuery {
+    /// Refresh even if the lichess puzzle database doesn't look newer than the last import.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// GET /api/puzzle_db.
+pub async fn status(
+    State(state): State<AppState>,
+) -> ApiResult<Json<PuzzleDbStatus>>
+{
+    Ok(state.puzzle_db_service.status().await?.into())
+}
+
+/// POST /api/puzzle_db/refresh, which starts refreshing the lichess puzzle database in the
+/// background.
+pub async fn refresh(
+    State(state): State<AppState>,
+    Query(query): Query<RefreshQuery>,
+) -> ApiResult<Json<PuzzleDbStatus>>
+{
+    if !state.puzzle_db_service.start_refresh(query.force) {
+        return Err(ApiError::Conflict("the puzzle database is already being imported".to_string()));
+    }
+
+    Ok(state.puzzle_db_service.status().await?.into())
+}
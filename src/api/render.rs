@@ -0,0 +1,78 @@
+use axum::extract::{State, Path, Query};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResult};
+use crate::app::AppState;
+use crate::render::BOARD_SIZE;
+
+/// The range of sizes that PNG thumbnails can be rendered at.
+const MIN_PNG_SIZE: u32 = 16;
+const MAX_PNG_SIZE: u32 = 1024;
+
+/// How long browsers can cache thumbnails for, in seconds. Puzzles rarely change, and the ETag
+/// lets them check cheaply once it's expired.
+const THUMBNAIL_MAX_AGE: u32 = 86400;
+
+/// Query parameters for /api/render/:puzzle_id.png.
+#[derive(Debug, Deserialize)]
+pub struct RenderQuery {
+    /// The width and height of the image, which is BOARD_SIZE by default.
+    pub size: Option<u32>,
+}
+
+/// GET /api/render/:puzzle_id.svg or /api/render/:puzzle_id.png, which renders a thumbnail of a
+/// puzzle's starting position.
+pub async fn board_thumbnail(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+    Query(query): Query<RenderQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response>
+{
+    let (puzzle_id, extension) = file_name.rsplit_once('.')
+        .ok_or_else(|| ApiError::InvalidParameter(format!("file name {file_name}")))?;
+    let invalid_puzzle = || ApiError::InvalidParameter(format!("puzzle_id {puzzle_id}"));
+
+    let size = match extension {
+        "svg" => BOARD_SIZE,
+        "png" => query.size.unwrap_or(BOARD_SIZE).clamp(MIN_PNG_SIZE, MAX_PNG_SIZE),
+        _ => return Err(ApiError::InvalidParameter(format!("image format {extension}"))),
+    };
+
+    // The ETag comes from what the image is rendered from, so it's checked before rendering.
+    let etag = state.tactics_service.get_puzzle_thumbnail_etag(puzzle_id, extension, size).await?
+        .ok_or_else(invalid_puzzle)?;
+
+    let cache_headers = [
+        (header::CACHE_CONTROL, format!("public, max-age={THUMBNAIL_MAX_AGE}")),
+        (header::ETAG, etag.clone()),
+    ];
+
+    // The browser already has this image.
+    let if_none_match = headers.get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if matches!(if_none_match, Some(value) if value.split(',').any(|tag| tag.trim() == etag)) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let (content_type, body) = match extension {
+        "svg" => {
+            let svg = state.tactics_service.get_puzzle_svg(puzzle_id).await?
+                .ok_or_else(invalid_puzzle)?;
+            ("image/svg+xml", svg.into_bytes())
+        },
+        _ => {
+            let png = state.tactics_service.get_puzzle_png(puzzle_id, size).await?
+                .ok_or_else(invalid_puzzle)?;
+            ("image/png", png)
+        },
+    };
+
+    Ok((
+        cache_headers,
+        [(header::CONTENT_TYPE, content_type)],
+        body,
+    ).into_response())
+}
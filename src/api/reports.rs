@@ -0,0 +1,85 @@
+use axum::extract::{State, Json, Path};
+use axum::http::header;
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResponse, ApiResult};
+use crate::app::AppState;
+use crate::db::PuzzleReport;
+use crate::services::user_service::UserService;
+
+/// Request JSON for reporting a puzzle.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportRequest {
+    pub puzzle_id: String,
+    pub reason: String,
+}
+
+/// GET /api/reports.
+pub async fn reports(State(state): State<AppState>) -> ApiResult<Json<Vec<PuzzleReport>>> {
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    Ok(state.tactics_service.get_reports(user_id).await?.into())
+}
+
+/// GET /api/reports/csv, which downloads the user's reports as a csv file.
+pub async fn reports_csv(State(state): State<AppState>) -> ApiResult<impl IntoResponse> {
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let csv = state.tactics_service.get_reports_csv(user_id).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"puzzle_reports.csv\""),
+        ],
+        csv,
+    ))
+}
+
+/// POST /api/reports, which reports a puzzle and excludes it from the user's new puzzles and
+/// reviews.
+pub async fn report_puzzle(
+    State(mut state): State<AppState>,
+    Json(request): Json<ReportRequest>,
+) -> ApiResult<Json<PuzzleReport>>
+{
+    if request.reason.trim().is_empty() {
+        Err(ApiError::InvalidParameter("reason".into()))?;
+    }
+
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let report = state.tactics_service
+        .report_puzzle(user_id, &request.puzzle_id, &request.reason)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("puzzle_id {}", request.puzzle_id)))?;
+
+    // If it's the user's saved next puzzle, clear it so they get a new one.
+    if state.user_service.get_user_next_puzzle(user_id).await?.as_deref() == Some(&request.puzzle_id) {
+        state.user_service.set_user_next_puzzle(user_id, None).await?;
+    }
+
+    Ok(Json(report))
+}
+
+/// DELETE /api/reports/:puzzle_id, which undoes a report.
+pub async fn undo_report(
+    State(mut state): State<AppState>,
+    Path(puzzle_id): Path<String>,
+) -> ApiResult<ApiResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    if !state.tactics_service.undo_report(user_id, &puzzle_id).await? {
+        Err(ApiError::InvalidParameter(format!("puzzle_id {puzzle_id}")))?;
+    }
+
+    Ok(ApiResponse {
+        response: format!("Undid report of puzzle {puzzle_id}"),
+    })
+}
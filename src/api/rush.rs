@@ -0,0 +1,112 @@
+use axum::extract::{State, Json, Path};
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResponse, ApiResult};
+use crate::app::AppState;
+use crate::db::{AttemptData, RushRun};
+use crate::services::rush_service::{RushState, RUSH_DURATIONS_MINUTES};
+use crate::services::user_service::UserService;
+
+/// Request JSON for starting a rush run.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartRushRequest {
+    pub duration_minutes: i64,
+}
+
+/// Request JSON for submitting the result of a puzzle in a rush run.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RushResultRequest {
+    pub puzzle_id: String,
+    // The details of the user's attempt at the puzzle, including the moves, which are checked to
+    // see if it was solved.
+    #[serde(flatten)]
+    pub attempt: AttemptData,
+}
+
+/// POST /api/rush/start.
+pub async fn start_run(
+    State(mut state): State<AppState>,
+    Json(request): Json<StartRushRequest>,
+) -> ApiResult<Json<RushState>>
+{
+    if !RUSH_DURATIONS_MINUTES.contains(&request.duration_minutes) {
+        Err(ApiError::InvalidParameter(format!("duration_minutes {}", request.duration_minutes)))?;
+    }
+
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let rush_state = state.rush_service.start_run(user_id, request.duration_minutes).await?;
+
+    Ok(rush_state.into())
+}
+
+/// GET /api/rush/:run_id.
+pub async fn run(
+    State(mut state): State<AppState>,
+    Path(run_id): Path<i64>,
+) -> ApiResult<Json<RushState>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let rush_state = state.rush_service
+        .get_run(user_id, run_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("run_id {run_id}")))?;
+
+    Ok(rush_state.into())
+}
+
+/// POST /api/rush/:run_id/result.
+pub async fn submit_result(
+    State(mut state): State<AppState>,
+    Path(run_id): Path<i64>,
+    Json(request): Json<RushResultRequest>,
+) -> ApiResult<Json<RushState>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let rush_state = state.rush_service
+        .submit_result(user_id, run_id, &request.puzzle_id, &request.attempt)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("run_id {run_id}")))?;
+
+    Ok(rush_state.into())
+}
+
+/// POST /api/rush/:run_id/add_failed.
+pub async fn add_failed_puzzles(
+    State(mut state): State<AppState>,
+    Path(run_id): Path<i64>,
+) -> ApiResult<ApiResponse>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let count = state.rush_service
+        .add_failed_puzzles(user_id, run_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("run_id {run_id}")))?;
+
+    Ok(ApiResponse {
+        response: format!("Added {count} failed puzzles from run {run_id} as cards"),
+    })
+}
+
+/// GET /api/rush/history.
+pub async fn history(State(mut state): State<AppState>) -> ApiResult<Json<Vec<RushRun>>> {
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    Ok(state.rush_service.get_history(user_id).await?.into())
+}
+
+/// GET /api/rush/best.
+pub async fn personal_bests(State(mut state): State<AppState>) -> ApiResult<Json<Vec<RushRun>>> {
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    Ok(state.rush_service.get_personal_bests(user_id).await?.into())
+}
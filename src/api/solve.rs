@@ -0,0 +1,108 @@
+use axum::extract::{State, Json, Path};
+use serde::Deserialize;
+
+use crate::api::{ApiError, ApiResult};
+use crate::app::AppState;
+use crate::services::solve_service::{SolveState, SolveMoveResult, SolveHint};
+use crate::services::user_service::UserService;
+
+/// Request JSON for starting a solve session.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartSolveRequest {
+    pub puzzle_id: String,
+}
+
+/// Request JSON for submitting a move in a solve session.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SolveMoveRequest {
+    // The move, in UCI notation.
+    #[serde(rename = "move")]
+    pub uci: String,
+}
+
+/// POST /api/solve/start.
+pub async fn start_session(
+    State(mut state): State<AppState>,
+    Json(request): Json<StartSolveRequest>,
+) -> ApiResult<Json<SolveState>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let solve_state = state.solve_service
+        .start(user_id, &request.puzzle_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("puzzle_id {}", request.puzzle_id)))?;
+
+    Ok(solve_state.into())
+}
+
+/// GET /api/solve/:session_id.
+pub async fn session(
+    State(state): State<AppState>,
+    Path(session_id): Path<i64>,
+) -> ApiResult<Json<SolveState>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let solve_state = state.solve_service
+        .get_session(user_id, session_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("session_id {session_id}")))?;
+
+    Ok(solve_state.into())
+}
+
+/// POST /api/solve/:session_id/move.
+pub async fn submit_move(
+    State(mut state): State<AppState>,
+    Path(session_id): Path<i64>,
+    Json(request): Json<SolveMoveRequest>,
+) -> ApiResult<Json<SolveMoveResult>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let result = state.solve_service
+        .submit_move(user_id, session_id, &request.uci)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("session_id {session_id}")))?
+        .map_err(|e| ApiError::InvalidParameter(format!("move: {e}")))?;
+
+    Ok(result.into())
+}
+
+/// POST /api/solve/:session_id/reset.
+pub async fn reset(
+    State(mut state): State<AppState>,
+    Path(session_id): Path<i64>,
+) -> ApiResult<Json<SolveState>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let solve_state = state.solve_service
+        .reset(user_id, session_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("session_id {session_id}")))?;
+
+    Ok(solve_state.into())
+}
+
+/// POST /api/solve/:session_id/hint.
+pub async fn hint(
+    State(mut state): State<AppState>,
+    Path(session_id): Path<i64>,
+) -> ApiResult<Json<SolveHint>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let hint = state.solve_service
+        .hint(user_id, session_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("session_id {session_id}")))?;
+
+    Ok(hint.into())
+}
@@ -1,26 +1,42 @@
-use axum::extract::{State, Json, Path};
+use axum::extract::{State, Json, Path, Query};
+use axum::http::header;
+use axum::response::IntoResponse;
 use chrono::Local;
 use serde::Deserialize;
 use serde::ser::SerializeStruct;
 
 use crate::api::{ApiError, ApiResult};
-use crate::db::{Puzzle, PuzzleHistoryEntry};
+use crate::db::{Puzzle, PuzzleHistoryEntry, AttemptData, PuzzleFilter, PuzzleTagKind, PuzzleSearch,
+    PuzzleSearchResult, ReviewOutcome, SearchSort, PuzzleNotes};
 use crate::rating::GameResult;
 use crate::app::AppState;
 use crate::services::ServiceError;
+use crate::services::daily_service::DailyPuzzle;
+use crate::services::solve_service::SolveService;
 use crate::services::user_service::UserService;
 use crate::srs::{Difficulty, Card};
 use crate::time::LocalTimeProvider;
+use crate::weakness::{self, Weakness};
 
 /// Request JSON for /api/tactics/review.
 #[derive(Debug, Clone, Deserialize)]
 pub struct ReviewRequest {
     pub id: String,
-    pub difficulty: i64,
+    // The difficulty selected by the user. If it's not specified, the difficulty is automatically
+    // graded from the attempt data instead.
+    pub difficulty: Option<i64>,
     // The current review count of the card, to prevent a card from being reviewed twice by
     // accident, the client can submit this value to us and we can assume the request has already
     // been fulfilled if it doesn't match.
     pub review_count: i64,
+    // The solve session the puzzle was solved in, which the attempt data comes from. It's required,
+    // so that the attempt is always checked by the server, and the client starts a session before
+    // reviewing if it doesn't have one. Each session can only be used for one review.
+    pub session_id: i64,
+    // Whether the attempt is only recorded in the puzzle history, without creating or updating a
+    // card, for puzzles played through from a collection without cards.
+    #[serde(default)]
+    pub without_card: bool,
 }
 
 /// Request JSON for /api/tactics/random/skip.
@@ -29,6 +45,32 @@ pub struct SkipRequest {
     pub id: String,
     pub difficulty: i64,
     pub update_rating: bool,
+    // The details of the user's attempt at the puzzle, if they attempted it before skipping.
+    #[serde(flatten)]
+    pub attempt: AttemptData,
+}
+
+/// Request JSON for POST /api/tactics/notes/:puzzle_id.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotesRequest {
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Query parameters for /api/tactics/history/:page.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PuzzleHistoryQuery {
+    // Only include puzzles the user has given this tag.
+    pub tag: Option<String>,
+}
+
+/// Response JSON for /api/tactics/review.
+#[derive(Debug, serde::Serialize)]
+pub struct ReviewResponse {
+    difficulty: Difficulty,
 }
 
 /// Response JSON for endpoints returning a puzzle and a card.
@@ -38,6 +80,46 @@ pub struct CardResponse {
     #[serde(serialize_with = "serialize_card")]
     card: Option<Card>,
     due_today: bool,
+    // The user's notes and tags for the puzzle.
+    notes: Option<PuzzleNotes>,
+}
+
+impl CardResponse {
+    /// Add the user's notes and tags for the puzzle, if there is one.
+    async fn with_notes(mut self, state: &AppState, user_id: &str) -> ApiResult<Self> {
+        if let Some(puzzle) = &self.puzzle {
+            self.notes = state.tactics_service.get_puzzle_notes(user_id, &puzzle.puzzle_id).await?;
+        }
+
+        Ok(self)
+    }
+}
+
+/// Response JSON for a random puzzle, which includes the rating range it was chosen from.
+#[derive(Debug, serde::Serialize)]
+pub struct RandomPuzzleResponse {
+    #[serde(flatten)]
+    card_response: CardResponse,
+    rating_range: (i64, i64),
+    // The weakness the puzzle was chosen for, for /api/tactics/weaknesses/random.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weakness: Option<Weakness>,
+}
+
+/// Response JSON for /api/tactics/daily, which includes the date and the user's streak.
+#[derive(Debug, serde::Serialize)]
+pub struct DailyPuzzleResponse {
+    #[serde(flatten)]
+    card_response: CardResponse,
+    daily: DailyPuzzle,
+}
+
+/// Request JSON for POST /api/tactics/daily.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DailyResultRequest {
+    pub puzzle_id: String,
+    // The solve session the puzzle was played in, which the result is checked from.
+    pub session_id: i64,
 }
 
 /// Response JSON for puzzle history.
@@ -48,6 +130,48 @@ pub struct PuzzleHistoryResponse {
     puzzles: Vec<PuzzleHistoryEntry>,
 }
 
+/// The order of puzzle search results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Query parameters for /api/tactics/search. The lists of themes and opening tags are
+/// comma-separated, like for /api/tactics/random.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PuzzleSearchQuery {
+    pub themes: Option<String>,
+    pub exclude_themes: Option<String>,
+    pub opening_tags: Option<String>,
+    pub min_popularity: Option<i64>,
+    pub min_plays: Option<i64>,
+    pub max_rating_deviation: Option<i64>,
+    pub sources: Option<String>,
+    // The user's own tags, comma-separated.
+    pub tags: Option<String>,
+    pub min_rating: Option<i64>,
+    pub max_rating: Option<i64>,
+    pub has_card: Option<bool>,
+    pub last_outcome: Option<ReviewOutcome>,
+    pub sort: SearchSort,
+    pub order: SortOrder,
+    pub page: Option<u64>,
+    // The number of puzzles per page.
+    pub count: Option<u64>,
+}
+
+/// Response JSON for puzzle search.
+#[derive(Debug, serde::Serialize)]
+pub struct PuzzleSearchResponse {
+    current_page: u64,
+    has_next_page: bool,
+    puzzles: Vec<PuzzleSearchResult>,
+}
+
 fn serialize_card<S: serde::Serializer>(card: &Option<Card>, serializer: S) -> Result<S::Ok, S::Error> {
     if let Some(card) = card {
         let mut s = serializer.serialize_struct("Card", 10)?;
@@ -73,14 +197,11 @@ fn serialize_card<S: serde::Serializer>(card: &Option<Card>, serializer: S) -> R
 pub async fn review(
     State(mut state): State<AppState>,
     Json(request): Json<ReviewRequest>,
-) -> ApiResult<()>
+) -> ApiResult<Json<ReviewResponse>>
 {
     // TODO: use a JWT to get the user_id.
     let user_id = UserService::local_user_id();
 
-    let difficulty = Difficulty::from_i64(request.difficulty)
-        .map_err(|_| ApiError::InvalidParameter("difficulty".into()))?;
-
     // If this is for the user's saved next puzzle, clear it.
     if let Some(next_saved_puzzle) = state.user_service.get_user_next_puzzle(user_id).await? {
         if next_saved_puzzle == request.id {
@@ -93,11 +214,46 @@ pub async fn review(
     let puzzle = puzzle.ok_or(ServiceError::from(format!("No such puzzle {}", request.id)))?;
     let card = card.unwrap_or(Card::new(&request.id, Local::now().fixed_offset(), state.app_config.srs));
 
+    // Use the attempt recorded by the server in the session the puzzle was solved in.
+    let session_id = request.session_id;
+    let session = state.solve_service.get_solve_session(user_id, session_id).await?
+        .filter(|session| session.puzzle_id == request.id && !session.reviewed)
+        .ok_or_else(|| ApiError::InvalidParameter(format!("session_id {session_id}")))?;
+    let attempt = SolveService::attempt_data(&session);
+
+    // Puzzles that weren't solved without any mistakes or hints can only be failed.
+    let failed = session.completed.is_none() || session.mistakes > 0 || session.hint_used;
+
+    // Use the difficulty the user selected, or grade the attempt automatically if there isn't one
+    // and automatic grading is enabled.
+    let difficulty = match request.difficulty {
+        Some(difficulty) => Difficulty::from_i64(difficulty)
+            .map_err(|_| ApiError::InvalidParameter("difficulty".into()))?,
+        None if !state.app_config.auto_grade.enabled => Err(ApiError::InvalidParameter(
+            "difficulty, which is required as automatic grading isn't enabled".into()))?,
+        None if failed => Difficulty::Again,
+        None => state.tactics_service.auto_grade(user_id, &puzzle, &attempt).await?,
+    };
+
+    if failed && difficulty != Difficulty::Again {
+        Err(ApiError::InvalidParameter("difficulty".into()))?;
+    }
+
     if card.review_count != request.review_count {
         log::warn!(concat!("Attempted to review card with incorrect review count ({} != {}), it's possible "
             , "this request has accidentally been submitted twice so we're ignoring this attempt"),
             request.review_count, card.review_count);
-        return Ok(());
+        return Ok(Json(ReviewResponse { difficulty }));
+    }
+
+    // Use up the session before the review is applied, so that it can't be used for another one.
+    if !state.solve_service.mark_reviewed(session_id).await? {
+        Err(ApiError::InvalidParameter(format!("session_id {session_id}")))?;
+    }
+
+    if request.without_card {
+        state.tactics_service.skip_puzzle(user_id, &puzzle, &attempt).await?;
+        return Ok(Json(ReviewResponse { difficulty }));
     }
 
     // Update the user's rating.
@@ -108,9 +264,9 @@ pub async fn review(
     }).await?;
 
     // Review the card.
-    state.tactics_service.apply_review(user_id, new_rating, card, difficulty).await?;
+    state.tactics_service.apply_review(user_id, new_rating, card, difficulty, attempt).await?;
 
-    Ok(())
+    Ok(Json(ReviewResponse { difficulty }))
 }
 
 /// GET /api/tactics/review.
@@ -119,19 +275,41 @@ pub async fn next_review(
 ) -> ApiResult<Json<CardResponse>>
 {
     // TODO: use a JWT to get the user_id.
-    let _user_id = UserService::local_user_id();
+    let user_id = UserService::local_user_id();
 
     let response = state.tactics_service
         .get_next_review()
         .await?
         .map(|(puzzle, card)| {
-            CardResponse { puzzle: Some(puzzle), card: Some(card), due_today: true }
+            CardResponse { puzzle: Some(puzzle), card: Some(card), due_today: true, notes: None }
         })
-        .unwrap_or(CardResponse { puzzle: None, card: None, due_today: false });
+        .unwrap_or(CardResponse { puzzle: None, card: None, due_today: false, notes: None })
+        .with_notes(&state, user_id)
+        .await?;
 
     Ok(Json::from(response))
 }
 
+/// GET /api/tactics/pgn/:puzzle_id, which exports a puzzle as a PGN game.
+pub async fn puzzle_pgn(
+    State(state): State<AppState>,
+    Path(puzzle_id): Path<String>,
+) -> ApiResult<impl IntoResponse>
+{
+    let pgn = state.tactics_service
+        .get_puzzle_pgn(&puzzle_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("puzzle_id {puzzle_id}")))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-chess-pgn; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"puzzle_{puzzle_id}.pgn\"")),
+        ],
+        pgn,
+    ))
+}
+
 /// GET /api/tactics/by_id/:puzzle_id.
 pub async fn puzzle_by_id(
     State(state): State<AppState>,
@@ -139,7 +317,7 @@ pub async fn puzzle_by_id(
 ) -> ApiResult<Json<CardResponse>>
 {
     // TODO: use a JWT to get the user_id.
-    let _user_id = UserService::local_user_id();
+    let user_id = UserService::local_user_id();
 
     let (puzzle, card) = state.tactics_service
         .get_puzzle_by_id(puzzle_id.as_str())
@@ -150,23 +328,218 @@ pub async fn puzzle_by_id(
             let now = Local::now().fixed_offset();
             let card = card.unwrap_or(Card::new(&puzzle_id, now, state.app_config.srs));
             let due_today = card.is_due::<LocalTimeProvider>();
-            CardResponse { puzzle: Some(puzzle), card: Some(card), due_today }
+            CardResponse { puzzle: Some(puzzle), card: Some(card), due_today, notes: None }
         },
-        _ => CardResponse { puzzle: None, card: None, due_today: false },
+        _ => CardResponse { puzzle: None, card: None, due_today: false, notes: None },
     };
 
-    Ok(Json::from(response))
+    Ok(Json::from(response.with_notes(&state, user_id).await?))
+}
+
+/// GET /api/tactics/daily.
+pub async fn daily_puzzle(
+    State(mut state): State<AppState>,
+) -> ApiResult<Json<DailyPuzzleResponse>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let daily = state.daily_service.get_daily_puzzle(user_id).await?;
+    let card_response = match daily.puzzle.clone() {
+        Some(puzzle) => {
+            let (_, card) = state.tactics_service.get_puzzle_by_id(&puzzle.puzzle_id).await?;
+            let now = Local::now().fixed_offset();
+            let card = card.unwrap_or(Card::new(&puzzle.puzzle_id, now, state.app_config.srs));
+            let due_today = card.is_due::<LocalTimeProvider>();
+            CardResponse { puzzle: Some(puzzle), card: Some(card), due_today, notes: None }
+        },
+        None => CardResponse { puzzle: None, card: None, due_today: false, notes: None },
+    };
+    let card_response = card_response.with_notes(&state, user_id).await?;
+
+    Ok(Json(DailyPuzzleResponse { card_response, daily }))
+}
+
+/// POST /api/tactics/daily, which records whether the user solved the daily puzzle on their first
+/// try, for their streak.
+pub async fn daily_result(
+    State(mut state): State<AppState>,
+    Json(request): Json<DailyResultRequest>,
+) -> ApiResult<Json<DailyPuzzle>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let session_id = request.session_id;
+    let daily = state.daily_service
+        .submit_result(user_id, &request.puzzle_id, session_id)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("session_id {session_id}")))?;
+
+    Ok(Json(daily))
+}
+
+/// Query parameters for /api/tactics/random. The lists of themes, opening tags, sources and the
+/// user's own tags are comma-separated.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PuzzleFilterQuery {
+    pub themes: Option<String>,
+    pub exclude_themes: Option<String>,
+    pub opening_tags: Option<String>,
+    pub min_popularity: Option<i64>,
+    pub min_plays: Option<i64>,
+    pub max_rating_deviation: Option<i64>,
+    pub sources: Option<String>,
+    pub tags: Option<String>,
+}
+
+/// Split a comma-separated list from a query parameter.
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+impl PuzzleFilterQuery {
+    /// Convert the query into a PuzzleFilter, or None if no filter parameters were specified.
+    fn to_filter(&self) -> Option<PuzzleFilter> {
+        fn split(list: &Option<String>) -> Vec<String> {
+            list.as_deref().map(split_list).unwrap_or_default()
+        }
+
+        let filter = PuzzleFilter {
+            include_themes: split(&self.themes),
+            exclude_themes: split(&self.exclude_themes),
+            opening_tags: split(&self.opening_tags),
+            min_popularity: self.min_popularity,
+            min_plays: self.min_plays,
+            max_rating_deviation: self.max_rating_deviation,
+            sources: split(&self.sources),
+            user_tags: split(&self.tags),
+        };
+
+        (filter != PuzzleFilter::default()).then_some(filter)
+    }
 }
 
 /// GET /api/tactics/random/:min_rating/:max_rating.
 pub async fn random_puzzle(
     State(mut state): State<AppState>,
     Path((min_rating, max_rating)): Path<(i64, i64)>,
+    Query(query): Query<PuzzleFilterQuery>,
 ) -> ApiResult<Json<CardResponse>>
 {
     // TODO: use a JWT to get the user_id.
     let user_id = UserService::local_user_id();
 
+    let filter = get_puzzle_filter(&state, user_id, &query).await?;
+    let response = random_puzzle_in_range(&mut state, user_id, min_rating, max_rating, &filter).await?;
+
+    Ok(Json::from(response))
+}
+
+/// GET /api/tactics/random, which picks the rating range for the user, either adaptively from
+/// their target success rate or from the configured rating variation.
+pub async fn adaptive_random_puzzle(
+    State(mut state): State<AppState>,
+    Query(query): Query<PuzzleFilterQuery>,
+) -> ApiResult<Json<RandomPuzzleResponse>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let user_rating = state.user_service.get_user_rating(user_id).await?;
+    let settings = state.user_service.get_user_settings(user_id).await?;
+
+    let (min_rating, max_rating) = state.tactics_service
+        .get_new_puzzle_rating_range(user_id, user_rating, settings.target_success_rate)
+        .await?;
+
+    let filter = get_puzzle_filter(&state, user_id, &query).await?;
+    let response = random_puzzle_in_range(&mut state, user_id, min_rating, max_rating, &filter).await?;
+
+    Ok(Json(RandomPuzzleResponse {
+        card_response: response,
+        rating_range: (min_rating, max_rating),
+        weakness: None,
+    }))
+}
+
+/// GET /api/tactics/weaknesses/random, which is like /api/tactics/random but picks puzzles with
+/// one of the user's weakest themes or openings if they have any.
+pub async fn weakness_random_puzzle(
+    State(mut state): State<AppState>,
+    Query(query): Query<PuzzleFilterQuery>,
+) -> ApiResult<Json<RandomPuzzleResponse>>
+{
+    // The number of weakest themes or openings to choose from.
+    const WEAKNESS_COUNT: usize = 5;
+
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let user_rating = state.user_service.get_user_rating(user_id).await?;
+    let settings = state.user_service.get_user_settings(user_id).await?;
+
+    let (min_rating, max_rating) = state.tactics_service
+        .get_new_puzzle_rating_range(user_id, user_rating, settings.target_success_rate)
+        .await?;
+
+    // Narrow the user's filter down to one of their weaknesses that it allows.
+    let filter = get_puzzle_filter(&state, user_id, &query).await?;
+    let weaknesses: Vec<Weakness> = state.user_service.get_weaknesses(user_id).await?
+        .into_iter()
+        .filter(|weakness| weakness.restrict_filter(&filter).is_some())
+        .collect();
+    let weakness = weakness::choose_weakness(&weaknesses, WEAKNESS_COUNT, &mut rand::thread_rng())
+        .cloned();
+    let weakness_filter = weakness.as_ref().and_then(|weakness| weakness.restrict_filter(&filter));
+
+    let mut response = random_puzzle_in_range(&mut state, user_id, min_rating, max_rating,
+        weakness_filter.as_ref().unwrap_or(&filter)).await?;
+
+    // Fall back to the user's filter if there aren't any puzzles with the weakness in the range.
+    if response.puzzle.is_none() && weakness_filter.is_some() {
+        log::info!("No puzzles found for the weakness, getting a random puzzle instead");
+        response = random_puzzle_in_range(&mut state, user_id, min_rating, max_rating, &filter).await?;
+    }
+    else if weakness_filter.is_none() {
+        log::info!("No weaknesses found, getting a random puzzle instead");
+    }
+
+    // The user's saved next puzzle is returned if they haven't done it yet, which might not have
+    // the weakness.
+    let weakness = weakness.filter(|weakness| {
+        response.puzzle.as_ref().is_some_and(|puzzle| match weakness.kind {
+            PuzzleTagKind::Theme => puzzle.themes.contains(&weakness.tag),
+            PuzzleTagKind::Opening => puzzle.opening_tags.contains(&weakness.tag),
+        })
+    });
+
+    Ok(Json(RandomPuzzleResponse {
+        card_response: response,
+        rating_range: (min_rating, max_rating),
+        weakness,
+    }))
+}
+
+/// Get the puzzle filter from the query if one was specified, otherwise the user's default filter.
+async fn get_puzzle_filter(state: &AppState, user_id: &str, query: &PuzzleFilterQuery)
+    -> ApiResult<PuzzleFilter>
+{
+    Ok(match query.to_filter() {
+        Some(filter) => filter,
+        None => state.user_service.get_user_settings(user_id).await?.puzzle_filter,
+    })
+}
+
+/// Get the user's next random puzzle in the given rating range.
+async fn random_puzzle_in_range(state: &mut AppState, user_id: &str, min_rating: i64,
+    max_rating: i64, filter: &PuzzleFilter) -> ApiResult<CardResponse>
+{
     // Get the user's stored next puzzle if there is one.
     let saved_next_puzzle = state.user_service.get_user_next_puzzle(user_id).await?;
 
@@ -175,18 +548,19 @@ pub async fn random_puzzle(
         let (puzzle, card) = state.tactics_service.get_puzzle_by_id(&saved_next_puzzle).await?;
 
         if puzzle.is_some() && card.is_none() {
-            return Ok(Json(CardResponse {
+            return CardResponse {
                 puzzle,
                 card: Some(Card::new(&saved_next_puzzle, Local::now().fixed_offset(),
                     state.app_config.srs)),
                 due_today: true,
-            }));
+                notes: None,
+            }.with_notes(state, user_id).await;
         }
     }
 
     // Get the next random puzzle for the user.
-    let (puzzle, card) = state.tactics_service
-        .get_random_puzzle(min_rating, max_rating)
+    let puzzle = state.tactics_service
+        .get_random_puzzle(user_id, min_rating, max_rating, filter)
         .await?;
 
     let response = match puzzle {
@@ -195,14 +569,14 @@ pub async fn random_puzzle(
             state.user_service.set_user_next_puzzle(user_id, Some(&puzzle.puzzle_id)).await?;
 
             let now = Local::now().fixed_offset();
-            let card = card.unwrap_or(Card::new(&puzzle.puzzle_id, now, state.app_config.srs));
+            let card = Card::new(&puzzle.puzzle_id, now, state.app_config.srs);
             let due_today = card.is_due::<LocalTimeProvider>();
-            CardResponse { card: Some(card), puzzle: Some(puzzle), due_today }
+            CardResponse { card: Some(card), puzzle: Some(puzzle), due_today, notes: None }
         },
-        _ => CardResponse { card: None, puzzle: None, due_today: false },
+        _ => CardResponse { card: None, puzzle: None, due_today: false, notes: None },
     };
 
-    Ok(Json::from(response))
+    response.with_notes(state, user_id).await
 }
 
 pub async fn skip_next(
@@ -231,7 +605,7 @@ pub async fn skip_next(
     // If the puzzle exists, add the puzzle as skipped, and update the user's rating if
     // requested.
     if let (Some(puzzle), _) = state.tactics_service.get_puzzle_by_id(&user_next_puzzle).await? {
-        state.tactics_service.skip_puzzle(user_id, &puzzle).await?;
+        state.tactics_service.skip_puzzle(user_id, &puzzle, &request.attempt).await?;
 
         if request.update_rating {
             let difficulty = Difficulty::from_i64(request.difficulty)
@@ -251,10 +625,110 @@ pub async fn skip_next(
     Ok(())
 }
 
+/// GET /api/tactics/notes/:puzzle_id.
+pub async fn puzzle_notes(
+    State(state): State<AppState>,
+    Path(puzzle_id): Path<String>,
+) -> ApiResult<Json<PuzzleNotes>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let notes = state.tactics_service
+        .get_puzzle_notes(user_id, &puzzle_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("puzzle {puzzle_id}")))?;
+
+    Ok(Json(notes))
+}
+
+/// POST /api/tactics/notes/:puzzle_id, which replaces the user's notes and tags for a puzzle.
+pub async fn set_puzzle_notes(
+    State(mut state): State<AppState>,
+    Path(puzzle_id): Path<String>,
+    Json(request): Json<NotesRequest>,
+) -> ApiResult<Json<PuzzleNotes>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let notes = state.tactics_service
+        .set_puzzle_notes(user_id, &puzzle_id, &request.notes, &request.tags)
+        .await?
+        .ok_or_else(|| ApiError::InvalidParameter(format!("puzzle_id {puzzle_id}")))?;
+
+    Ok(Json(notes))
+}
+
+/// GET /api/tactics/search.
+pub async fn search_puzzles(
+    State(state): State<AppState>,
+    Query(query): Query<PuzzleSearchQuery>,
+) -> ApiResult<Json<PuzzleSearchResponse>>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    // The default and maximum length in puzzles for each page of search results.
+    const PUZZLE_SEARCH_PAGE_LENGTH: u64 = 20;
+    const MAX_PUZZLE_SEARCH_PAGE_LENGTH: u64 = 100;
+
+    let page = query.page.unwrap_or(1);
+    if page == 0 {
+        Err(ApiError::InvalidParameter("page 0".into()))?;
+    }
+
+    let count = query.count.unwrap_or(PUZZLE_SEARCH_PAGE_LENGTH);
+    if count == 0 || count > MAX_PUZZLE_SEARCH_PAGE_LENGTH {
+        Err(ApiError::InvalidParameter(format!("count {count}")))?;
+    }
+
+    let offset = (page - 1).checked_mul(count)
+        .and_then(|offset| i64::try_from(offset).ok())
+        .ok_or_else(|| ApiError::InvalidParameter(format!("page {page}")))?;
+
+    // The filter query parameters are the same as for random puzzles.
+    let filter = PuzzleFilterQuery {
+        themes: query.themes,
+        exclude_themes: query.exclude_themes,
+        opening_tags: query.opening_tags,
+        min_popularity: query.min_popularity,
+        min_plays: query.min_plays,
+        max_rating_deviation: query.max_rating_deviation,
+        sources: query.sources,
+        tags: query.tags,
+    }.to_filter().unwrap_or_default();
+
+    let search = PuzzleSearch {
+        filter,
+        min_rating: query.min_rating,
+        max_rating: query.max_rating,
+        has_card: query.has_card,
+        last_outcome: query.last_outcome,
+        sort: query.sort,
+        descending: query.order == SortOrder::Desc,
+    };
+
+    // Get one more than a page so we know if there's another page.
+    let mut puzzles = state.tactics_service
+        .search_puzzles(user_id, &search, offset, count as i64 + 1)
+        .await?;
+
+    let has_next_page = puzzles.len() as u64 > count;
+    puzzles.truncate(count as usize);
+
+    Ok(Json(PuzzleSearchResponse {
+        current_page: page,
+        has_next_page,
+        puzzles,
+    }))
+}
+
 /// GET /api/tactics/history/:page.
 pub async fn puzzle_history(
     State(state): State<AppState>,
     Path(page): Path<u64>,
+    Query(query): Query<PuzzleHistoryQuery>,
 ) -> ApiResult<Json<PuzzleHistoryResponse>>
 {
     // TODO: use a JWT to get the user_id.
@@ -268,7 +742,7 @@ pub async fn puzzle_history(
     let offset = (page - 1) * count;
 
     let (puzzles, total_count) = state.tactics_service
-        .get_puzzle_history(user_id, offset as i64, count as i64)
+        .get_puzzle_history(user_id, query.tag.as_deref(), offset as i64, count as i64)
         .await?;
 
     Ok(Json(PuzzleHistoryResponse {
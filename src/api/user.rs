@@ -1,10 +1,25 @@
 use axum::extract::{State, Json, Path};
 use chrono::Local;
+use serde::Deserialize;
 use serde_json::Value;
 
 use crate::api::{ApiError, ApiResponse};
 use crate::app::AppState;
-use crate::services::user_service::UserService;
+use crate::db::UserSettings;
+use crate::weakness::Weakness;
+use crate::services::user_service::{UserService, ActivityImportResult};
+
+/// The maximum size of a lichess puzzle activity export, in bytes.
+pub const MAX_ACTIVITY_BYTES: usize = 50 * 1024 * 1024;
+
+/// Request JSON for importing a lichess puzzle activity export.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ActivityImportRequest {
+    pub contents: String,
+    /// Whether to replace the user's rating with one estimated from the activity.
+    #[serde(default)]
+    pub set_rating: bool,
+}
 
 /// Reset the user's rating to the specified value.
 /// TODO: add this into the settings page.
@@ -25,6 +40,74 @@ pub async fn reset_rating(
     })
 }
 
+/// Get a user's settings.
+pub async fn get_settings(State(state): State<AppState>)
+    -> Result<Json<UserSettings>, ApiError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let settings = state.user_service.get_user_settings(user_id).await?;
+
+    Ok(settings.into())
+}
+
+/// Update a user's settings. Only the settings in the request are changed, and any set to null
+/// are reset to their defaults.
+pub async fn set_settings(
+    State(mut state): State<AppState>,
+    Json(update): Json<serde_json::Value>,
+) -> Result<ApiResponse, ApiError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let settings = state.user_service.get_user_settings(user_id).await?
+        .merge(update)
+        .map_err(|e| ApiError::InvalidParameter(format!("Invalid settings: {e}")))?;
+
+    if let Some(target_success_rate) = settings.target_success_rate {
+        if !(0.0..=1.0).contains(&target_success_rate) {
+            Err(ApiError::InvalidParameter("target_success_rate must be between 0 and 1".into()))?;
+        }
+    }
+
+    state.user_service.set_user_settings(user_id, settings).await?;
+
+    Ok(ApiResponse {
+        response: "Updated user settings".to_string(),
+    })
+}
+
+/// POST /api/user/lichess_activity, which imports the user's puzzle history from a lichess puzzle
+/// activity export.
+pub async fn import_lichess_activity(
+    State(mut state): State<AppState>,
+    Json(request): Json<ActivityImportRequest>,
+) -> Result<Json<ActivityImportResult>, ApiError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let result = state.user_service
+        .import_lichess_activity(user_id, &request.contents, request.set_rating)
+        .await?;
+
+    Ok(result.into())
+}
+
+/// Get a user's weakest puzzle themes and openings, weakest first.
+pub async fn weaknesses(State(state): State<AppState>)
+    -> Result<Json<Vec<Weakness>>, ApiError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let weaknesses = state.user_service.get_weaknesses(user_id).await?;
+
+    Ok(weaknesses.into())
+}
+
 /// Get a user's stats.
 pub async fn stats(State(state): State<AppState>)
     -> Result<Json<serde_json::Value>, ApiError>
@@ -4,15 +4,29 @@ use std::env::{self, VarError};
 use std::error::Error;
 use std::fmt::Debug;
 use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 use chrono::{NaiveTime, DateTime, Local, Duration};
 use url::Url;
 
 use crate::db::PuzzleDatabase;
+use crate::engine::{EnginePool, SearchLimit};
+use crate::lichess::LICHESS_DB_URL;
+use crate::services::analysis_service::AnalysisService;
+use crate::services::collection_service::CollectionService;
+use crate::services::daily_service::DailyService;
+use crate::services::personal_service::PersonalService;
+use crate::services::puzzle_db_service::PuzzleDbService;
+use crate::services::pack_service::PackService;
+use crate::services::anki_service::AnkiService;
+use crate::services::rush_service::RushService;
+use crate::services::solve_service::SolveService;
 use crate::services::tactics_service::TacticsService;
 use crate::services::user_service::UserService;
-use crate::srs::{SrsConfig, ReviewOrder};
+use crate::srs::{SrsConfig, ReviewOrder, AutoGradeConfig};
 
 /// The application useragent, e.g. "better_tactics/0.0.1".
 pub static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
@@ -37,14 +51,23 @@ pub struct AppConfig {
     pub database_url: DatabaseUrl,
     pub tactics: TacticsConfig,
     pub srs: SrsConfig,
+    pub auto_grade: AutoGradeConfig,
     pub backup: BackupConfig,
     pub ui: UiConfig,
+    pub engine: EngineConfig,
+    pub puzzle_db: PuzzleDbConfig,
 }
 
 #[derive(Debug, Clone)]
 pub struct TacticsConfig {
     pub puzzle_rating_variation_up: f32,
     pub puzzle_rating_variation_down: f32,
+    pub adaptive_score_window: f64,
+    pub adaptive_recent_reviews: i64,
+    pub rush_start_rating: i64,
+    pub rush_rating_step: i64,
+    pub daily_min_popularity: i64,
+    pub daily_min_plays: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +83,48 @@ pub struct UiConfig {
     pub subsequent_move_delay: u32,
 }
 
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub path: Option<String>,
+    pub pool_size: usize,
+    pub depth: u32,
+    pub movetime_ms: Option<u64>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PuzzleDbConfig {
+    pub source: PuzzleDbSource,
+    pub download: bool,
+    pub cache_dir: PathBuf,
+    pub sha256: Option<String>,
+    pub refresh_enabled: bool,
+    pub refresh_hour: NaiveTime,
+}
+
+/// Where the lichess puzzle database is imported from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PuzzleDbSource {
+    /// A url to download it from.
+    Url(Url),
+    /// A local file, or a directory containing one.
+    Path(PathBuf),
+}
+
+impl FromStr for PuzzleDbSource {
+    type Err = url::ParseError;
+
+    /// Parse a source, which is a url if it starts with http:// or https:// and a path otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(Self::Url(Url::parse(s)?))
+        }
+        else {
+            Ok(Self::Path(PathBuf::from(s)))
+        }
+    }
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
@@ -69,8 +134,11 @@ impl Default for AppConfig {
                 .expect("Failed to parse default database_url")),
             tactics: TacticsConfig::default(),
             srs: SrsConfig::default(),
+            auto_grade: AutoGradeConfig::default(),
             backup: BackupConfig::default(),
             ui: UiConfig::default(),
+            engine: EngineConfig::default(),
+            puzzle_db: PuzzleDbConfig::default(),
         }
     }
 }
@@ -80,6 +148,12 @@ impl Default for TacticsConfig {
         Self {
             puzzle_rating_variation_up: 0.0,
             puzzle_rating_variation_down: 0.05,
+            adaptive_score_window: 0.05,
+            adaptive_recent_reviews: 20,
+            rush_start_rating: 600,
+            rush_rating_step: 50,
+            daily_min_popularity: 90,
+            daily_min_plays: 1000,
         }
     }
 }
@@ -104,6 +178,44 @@ impl Default for UiConfig {
     }
 }
 
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            pool_size: 1,
+            depth: 18,
+            movetime_ms: None,
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl Default for PuzzleDbConfig {
+    fn default() -> Self {
+        Self {
+            source: PuzzleDbSource::Url(Url::parse(LICHESS_DB_URL)
+                .expect("Failed to parse default puzzle db url")),
+            download: true,
+            cache_dir: PathBuf::from("cache"),
+            sha256: None,
+            refresh_enabled: false,
+            refresh_hour: NaiveTime::from_hms_opt(3, 0, 0)
+                .expect("Failed to parse default puzzle db refresh hour"),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// The default limit for engine searches, which is the move time if it's set or otherwise the
+    /// depth.
+    pub fn search_limit(&self) -> SearchLimit {
+        match self.movetime_ms {
+            Some(ms) => SearchLimit::MoveTime(ms),
+            None => SearchLimit::Depth(self.depth),
+        }
+    }
+}
+
 impl AppConfig {
     /// Load the app config from a .env file or environment variables.
     pub fn from_env() -> Result<AppConfig, Box<dyn Error>> {
@@ -128,11 +240,34 @@ impl AppConfig {
                     .map_err(|e| format!("{e}, possible values: {}", ReviewOrder::possible_values()))?
                     .unwrap_or(defaults.srs.review_order),
             },
+            auto_grade: AutoGradeConfig {
+                enabled: Self::env_var("AUTO_GRADE_ENABLED")?.unwrap_or(defaults.auto_grade.enabled),
+                easy_time_factor: Self::env_var("AUTO_GRADE_EASY_TIME_FACTOR")?
+                    .unwrap_or(defaults.auto_grade.easy_time_factor),
+                hard_time_factor: Self::env_var("AUTO_GRADE_HARD_TIME_FACTOR")?
+                    .unwrap_or(defaults.auto_grade.hard_time_factor),
+                rating_range: Self::env_var("AUTO_GRADE_RATING_RANGE")?
+                    .unwrap_or(defaults.auto_grade.rating_range),
+                min_samples: Self::env_var("AUTO_GRADE_MIN_SAMPLES")?
+                    .unwrap_or(defaults.auto_grade.min_samples),
+            },
             tactics: TacticsConfig {
                 puzzle_rating_variation_up: Self::env_var("TACTICS_PUZZLE_RATING_VARIATION_UP")?
                     .unwrap_or(defaults.tactics.puzzle_rating_variation_up),
                 puzzle_rating_variation_down: Self::env_var("TACTICS_PUZZLE_RATING_VARIATION_DOWN")?
                     .unwrap_or(defaults.tactics.puzzle_rating_variation_down),
+                adaptive_score_window: Self::env_var("TACTICS_ADAPTIVE_SCORE_WINDOW")?
+                    .unwrap_or(defaults.tactics.adaptive_score_window),
+                adaptive_recent_reviews: Self::env_var("TACTICS_ADAPTIVE_RECENT_REVIEWS")?
+                    .unwrap_or(defaults.tactics.adaptive_recent_reviews),
+                rush_start_rating: Self::env_var("TACTICS_RUSH_START_RATING")?
+                    .unwrap_or(defaults.tactics.rush_start_rating),
+                rush_rating_step: Self::env_var("TACTICS_RUSH_RATING_STEP")?
+                    .unwrap_or(defaults.tactics.rush_rating_step),
+                daily_min_popularity: Self::env_var("TACTICS_DAILY_MIN_POPULARITY")?
+                    .unwrap_or(defaults.tactics.daily_min_popularity),
+                daily_min_plays: Self::env_var("TACTICS_DAILY_MIN_PLAYS")?
+                    .unwrap_or(defaults.tactics.daily_min_plays),
             },
             backup: BackupConfig {
                 enabled: Self::env_var("BACKUP_ENABLED")?.unwrap_or(defaults.backup.enabled),
@@ -149,6 +284,32 @@ impl AppConfig {
                 subsequent_move_delay: Self::env_var("UI_SUBSEQUENT_MOVE_DELAY")?
                     .unwrap_or(defaults.ui.subsequent_move_delay),
             },
+            engine: EngineConfig {
+                path: Self::env_var::<String>("ENGINE_PATH")?
+                    .filter(|path| !path.is_empty())
+                    .or(defaults.engine.path),
+                pool_size: Self::env_var("ENGINE_POOL_SIZE")?.unwrap_or(defaults.engine.pool_size),
+                depth: Self::env_var("ENGINE_DEPTH")?.unwrap_or(defaults.engine.depth),
+                movetime_ms: Self::env_var("ENGINE_MOVETIME_MS")?.or(defaults.engine.movetime_ms),
+                timeout_secs: Self::env_var("ENGINE_TIMEOUT_SECS")?
+                    .unwrap_or(defaults.engine.timeout_secs),
+            },
+            puzzle_db: PuzzleDbConfig {
+                source: Self::env_var("PUZZLE_DB_SOURCE")?.unwrap_or(defaults.puzzle_db.source),
+                download: Self::env_var("PUZZLE_DB_DOWNLOAD")?.unwrap_or(defaults.puzzle_db.download),
+                cache_dir: Self::env_var("PUZZLE_DB_CACHE_DIR")?.unwrap_or(defaults.puzzle_db.cache_dir),
+                sha256: Self::env_var::<String>("PUZZLE_DB_SHA256")?
+                    .filter(|sha256| !sha256.is_empty())
+                    .map(|sha256| sha256.to_lowercase())
+                    .or(defaults.puzzle_db.sha256),
+                refresh_enabled: Self::env_var("PUZZLE_DB_REFRESH_ENABLED")?
+                    .unwrap_or(defaults.puzzle_db.refresh_enabled),
+                refresh_hour: Self::env_var::<u32>("PUZZLE_DB_REFRESH_HOUR")?
+                    .map(|refresh_hour| NaiveTime::from_hms_opt(refresh_hour, 0, 0)
+                            .ok_or_else(|| format!("Invalid puzzle db refresh hour {}", refresh_hour)))
+                    .transpose()?
+                    .unwrap_or(defaults.puzzle_db.refresh_hour),
+            },
         })
     }
 
@@ -195,13 +356,34 @@ pub struct AppState {
     pub app_config: AppConfig,
     pub user_service: UserService,
     pub tactics_service: TacticsService,
+    pub collection_service: CollectionService,
+    pub rush_service: RushService,
+    pub daily_service: DailyService,
+    pub solve_service: SolveService,
+    pub analysis_service: AnalysisService,
+    pub personal_service: PersonalService,
+    pub puzzle_db_service: PuzzleDbService,
+    pub pack_service: PackService,
+    pub anki_service: AnkiService,
 }
 
 impl AppState {
-    pub fn new(app_config: AppConfig, db: PuzzleDatabase) -> AppState {
+    pub fn new(app_config: AppConfig, db: PuzzleDatabase, cancel_import: Arc<AtomicBool>) -> AppState {
+        // The engine processes are shared by the services that use them.
+        let engine_pool = EnginePool::from_config(&app_config.engine);
+
         Self {
             user_service: UserService::new(app_config.clone(), db.clone()),
             tactics_service: TacticsService::new(app_config.clone(), db.clone()),
+            collection_service: CollectionService::new(db.clone()),
+            rush_service: RushService::new(app_config.clone(), db.clone()),
+            daily_service: DailyService::new(app_config.clone(), db.clone()),
+            solve_service: SolveService::new(db.clone()),
+            analysis_service: AnalysisService::new(app_config.clone(), engine_pool.clone()),
+            personal_service: PersonalService::new(app_config.clone(), db.clone(), engine_pool),
+            puzzle_db_service: PuzzleDbService::new(app_config.clone(), db.clone(), cancel_import),
+            pack_service: PackService::new(db.clone()),
+            anki_service: AnkiService::new(app_config.clone(), db.clone()),
             app_config,
         }
     }
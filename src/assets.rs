@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use axum::Router;
 use axum::http::{Uri, header, StatusCode};
 use axum::response::{Response, IntoResponse};
@@ -11,6 +13,11 @@ pub static STATIC_ASSETS_PATH: &str = concat!("/assets_", env!("CARGO_PKG_VERSIO
 #[folder = "assets"]
 struct Asset;
 
+/// Get the contents of an embedded asset, e.g. "images/pieces/white-king.svg".
+pub fn get(path: &str) -> Option<Cow<'static, [u8]>> {
+    Asset::get(path).map(|file| file.data)
+}
+
 pub fn routes() -> Router {
     Router::new().fallback(handler)
 }
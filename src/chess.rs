@@ -0,0 +1,1080 @@
+use std::fmt;
+
+/// The offsets a knight can move by, as (file, rank).
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+
+/// The directions a bishop can move in, as (file, rank).
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+/// The directions a rook can move in, as (file, rank).
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+/// The directions a king or queen can move in, as (file, rank).
+const KING_DIRECTIONS: [(i8, i8); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+
+/// The roles a pawn can be promoted to.
+const PROMOTION_ROLES: [Role; 4] = [Role::Queen, Role::Rook, Role::Bishop, Role::Knight];
+
+/// The FEN of the standard starting position.
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Chess errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChessError {
+    InvalidFen(String),
+    InvalidMove(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for ChessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFen(desc) => write!(f, "Invalid FEN: {desc}"),
+            Self::InvalidMove(mv) => write!(f, "Invalid move {mv}"),
+            Self::IllegalMove(mv) => write!(f, "Illegal move {mv}"),
+        }
+    }
+}
+
+impl std::error::Error for ChessError {}
+
+/// The color of a piece or player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    /// The other color.
+    pub fn other(self) -> Self {
+        match self {
+            Self::White => Self::Black,
+            Self::Black => Self::White,
+        }
+    }
+
+    /// The direction this color's pawns move in.
+    fn forward(self) -> i8 {
+        match self {
+            Self::White => 1,
+            Self::Black => -1,
+        }
+    }
+
+    /// The rank this color's pieces start on.
+    fn back_rank(self) -> u8 {
+        match self {
+            Self::White => 0,
+            Self::Black => 7,
+        }
+    }
+}
+
+/// The kind of a piece.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl Role {
+    /// Get a role from its (case insensitive) letter.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(Self::Pawn),
+            'n' => Some(Self::Knight),
+            'b' => Some(Self::Bishop),
+            'r' => Some(Self::Rook),
+            'q' => Some(Self::Queen),
+            'k' => Some(Self::King),
+            _ => None,
+        }
+    }
+
+    /// The role's lowercase letter.
+    pub fn char(self) -> char {
+        match self {
+            Self::Pawn => 'p',
+            Self::Knight => 'n',
+            Self::Bishop => 'b',
+            Self::Rook => 'r',
+            Self::Queen => 'q',
+            Self::King => 'k',
+        }
+    }
+}
+
+/// A piece on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    pub color: Color,
+    pub role: Role,
+}
+
+impl Piece {
+    /// Get a piece from its FEN letter, which is uppercase for white.
+    fn from_char(c: char) -> Option<Self> {
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        Role::from_char(c).map(|role| Self { color, role })
+    }
+
+    /// The piece's FEN letter.
+    fn char(self) -> char {
+        match self.color {
+            Color::White => self.role.char().to_ascii_uppercase(),
+            Color::Black => self.role.char(),
+        }
+    }
+}
+
+/// A square on the board, from 0 (a1) to 63 (h8).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Square(u8);
+
+impl Square {
+    /// Create a square from its file and rank, which are 0-7.
+    pub fn new(file: u8, rank: u8) -> Self {
+        debug_assert!(file < 8 && rank < 8);
+        Self(rank * 8 + file)
+    }
+
+    /// Parse a square from its name, e.g. "e4".
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.as_bytes() {
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(Self::new(file - b'a', rank - b'1')),
+            _ => None,
+        }
+    }
+
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    pub fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// The square offset from this one by the given number of files and ranks, if it's on the
+    /// board.
+    fn offset(self, files: i8, ranks: i8) -> Option<Self> {
+        let file = self.file() as i8 + files;
+        let rank = self.rank() as i8 + ranks;
+        ((0..8).contains(&file) && (0..8).contains(&rank)).then(|| Self::new(file as u8, rank as u8))
+    }
+
+    /// All of the squares, from a1 to h8.
+    fn all() -> impl Iterator<Item = Self> {
+        (0..64).map(Self)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.file()) as char, (b'1' + self.rank()) as char)
+    }
+}
+
+/// A move, which can be converted to and from UCI notation (e.g. "e2e4", or "e7e8q" for a
+/// promotion). Castling is represented as the king moving two squares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<Role>,
+}
+
+impl Move {
+    /// Parse a move in UCI notation. This doesn't check whether the move is legal in any position.
+    pub fn from_uci(uci: &str) -> Result<Self, ChessError> {
+        let invalid = || ChessError::InvalidMove(uci.to_string());
+
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(invalid());
+        }
+
+        let from = Square::parse(&uci[0..2]).ok_or_else(invalid)?;
+        let to = Square::parse(&uci[2..4]).ok_or_else(invalid)?;
+        let promotion = match uci[4..].chars().next() {
+            Some(c) => Some(Role::from_char(c)
+                .filter(|role| PROMOTION_ROLES.contains(role))
+                .ok_or_else(invalid)?),
+            None => None,
+        };
+
+        Ok(Self { from, to, promotion })
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.char())?;
+        }
+        Ok(())
+    }
+}
+
+/// Which castling moves are still allowed, indexed by color and then king side/queen side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct CastlingRights([[bool; 2]; 2]);
+
+impl CastlingRights {
+    fn get(&self, color: Color, king_side: bool) -> bool {
+        self.0[color as usize][!king_side as usize]
+    }
+
+    fn set(&mut self, color: Color, king_side: bool, allowed: bool) {
+        self.0[color as usize][!king_side as usize] = allowed;
+    }
+}
+
+/// A chess position, with everything needed to generate the legal moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    board: [Option<Piece>; 64],
+    turn: Color,
+    castling: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl Position {
+    /// Parse a position from a FEN. The move counters are optional.
+    pub fn from_fen(fen: &str) -> Result<Self, ChessError> {
+        let invalid = |desc: &str| ChessError::InvalidFen(format!("{desc} in '{fen}'"));
+
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(invalid("wrong number of fields"));
+        }
+
+        // The board, from the 8th rank down.
+        let mut board = [None; 64];
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(invalid("wrong number of ranks"));
+        }
+
+        for (i, rank_str) in ranks.iter().enumerate() {
+            let rank = 7 - i as u8;
+            let mut file: u8 = 0;
+
+            for c in rank_str.chars() {
+                if let Some(empty) = c.to_digit(10).filter(|n| (1..=8).contains(n)) {
+                    file = file.checked_add(empty as u8)
+                        .filter(|file| *file <= 8)
+                        .ok_or_else(|| invalid("too many squares in rank"))?;
+                }
+                else {
+                    let piece = Piece::from_char(c).ok_or_else(|| invalid("invalid piece"))?;
+                    if file >= 8 {
+                        return Err(invalid("too many squares in rank"));
+                    }
+                    board[Square::new(file, rank).index()] = Some(piece);
+                    file += 1;
+                }
+            }
+
+            if file != 8 {
+                return Err(invalid("wrong number of squares in rank"));
+            }
+        }
+
+        let turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(invalid("invalid side to move")),
+        };
+
+        let mut castling = CastlingRights::default();
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                match c {
+                    'K' => castling.set(Color::White, true, true),
+                    'Q' => castling.set(Color::White, false, true),
+                    'k' => castling.set(Color::Black, true, true),
+                    'q' => castling.set(Color::Black, false, true),
+                    _ => return Err(invalid("invalid castling rights")),
+                }
+            }
+        }
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            square => Some(Square::parse(square).ok_or_else(|| invalid("invalid en passant square"))?),
+        };
+
+        let halfmove_clock = match fields.get(4) {
+            Some(clock) => clock.parse().map_err(|_| invalid("invalid halfmove clock"))?,
+            None => 0,
+        };
+
+        let fullmove_number = match fields.get(5) {
+            Some(number) => number.parse().map_err(|_| invalid("invalid fullmove number"))?,
+            None => 1,
+        };
+
+        let position = Self {
+            board,
+            turn,
+            castling,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+        };
+
+        // Without both kings we can't tell which moves are legal.
+        for color in [Color::White, Color::Black] {
+            if position.king_square(color).is_none() {
+                return Err(invalid("missing king"));
+            }
+        }
+
+        Ok(position)
+    }
+
+    /// Get the FEN for the position.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at(Square::new(file, rank)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.char());
+                    },
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.turn {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        let castling: String = [
+            (Color::White, true, 'K'),
+            (Color::White, false, 'Q'),
+            (Color::Black, true, 'k'),
+            (Color::Black, false, 'q'),
+        ]
+            .iter()
+            .filter(|(color, king_side, _)| self.castling.get(*color, *king_side))
+            .map(|(_, _, c)| *c)
+            .collect();
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+        match self.en_passant {
+            Some(square) => fen.push_str(&format!(" {square}")),
+            None => fen.push_str(" -"),
+        }
+
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+        fen
+    }
+
+    /// The side to move.
+    pub fn turn(&self) -> Color {
+        self.turn
+    }
+
+    /// The fullmove number, which starts at 1 and is incremented after black moves.
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.board[square.index()]
+    }
+
+    /// Get all of the legal moves for the side to move.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|m| {
+                let mut after = self.clone();
+                after.apply(m);
+                !after.is_king_attacked(self.turn)
+            })
+            .collect()
+    }
+
+    pub fn is_legal(&self, m: &Move) -> bool {
+        self.legal_moves().contains(m)
+    }
+
+    /// Play a move, if it's legal.
+    pub fn play(&mut self, m: &Move) -> Result<(), ChessError> {
+        if !self.is_legal(m) {
+            return Err(ChessError::IllegalMove(m.to_string()));
+        }
+
+        self.apply(m);
+        Ok(())
+    }
+
+    /// Play a move in UCI notation, if it's legal, returning the parsed move.
+    pub fn play_uci(&mut self, uci: &str) -> Result<Move, ChessError> {
+        let m = Move::from_uci(uci)?;
+        self.play(&m)?;
+        Ok(m)
+    }
+
+    /// Parse a move in standard algebraic notation, which must be legal in this position. Check
+    /// and annotation symbols are ignored, and so are a missing "x" or unnecessary disambiguation,
+    /// which are common in PGNs from other software.
+    pub fn parse_san(&self, san: &str) -> Result<Move, ChessError> {
+        let invalid = || ChessError::InvalidMove(san.to_string());
+
+        // Some software writes castling with zeros.
+        let text = san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+
+        let candidates: Vec<Move> = if text == "O-O" || text == "O-O-O" {
+            let file = if text == "O-O" { 6 } else { 2 };
+            self.legal_moves()
+                .into_iter()
+                .filter(|m| self.piece_at(m.from).is_some_and(|piece| piece.role == Role::King)
+                    && m.from.file().abs_diff(m.to.file()) == 2
+                    && m.to.file() == file)
+                .collect()
+        }
+        else {
+            let mut text = text.as_str();
+
+            // The promotion, e.g. "=Q" (or just "Q").
+            let mut promotion = None;
+            if let Some(c) = text.chars().last().filter(|c| "QRBN".contains(*c)) {
+                promotion = Role::from_char(c);
+                text = text[..text.len() - 1].trim_end_matches('=');
+            }
+
+            // The destination square.
+            if text.len() < 2 || !text.is_char_boundary(text.len() - 2) {
+                return Err(invalid());
+            }
+            let to = Square::parse(&text[text.len() - 2..]).ok_or_else(invalid)?;
+            text = &text[..text.len() - 2];
+
+            // The piece, which is omitted for pawns.
+            let mut role = Role::Pawn;
+            if let Some(c) = text.chars().next().filter(|c| "KQRBN".contains(*c)) {
+                role = Role::from_char(c).ok_or_else(invalid)?;
+                text = &text[1..];
+            }
+
+            // The file and/or rank the piece moves from, if given.
+            let mut from_file = None;
+            let mut from_rank = None;
+            for c in text.chars() {
+                match c {
+                    'a'..='h' => from_file = Some(c as u8 - b'a'),
+                    '1'..='8' => from_rank = Some(c as u8 - b'1'),
+                    'x' | '-' | ':' => (),
+                    _ => return Err(invalid()),
+                }
+            }
+
+            self.legal_moves()
+                .into_iter()
+                .filter(|m| m.to == to && m.promotion == promotion
+                    && self.piece_at(m.from).is_some_and(|piece| piece.role == role)
+                    && !matches!(from_file, Some(file) if m.from.file() != file)
+                    && !matches!(from_rank, Some(rank) if m.from.rank() != rank))
+                .collect()
+        };
+
+        match candidates.as_slice() {
+            [m] => Ok(*m),
+            [] => Err(ChessError::IllegalMove(san.to_string())),
+            // Ambiguous moves are invalid.
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Get a legal move in standard algebraic notation, e.g. "Nbxd7+", "O-O" or "e8=Q#".
+    pub fn san(&self, m: &Move) -> String {
+        let Some(piece) = self.piece_at(m.from) else {
+            return m.to_string();
+        };
+
+        let mut san = String::new();
+
+        if piece.role == Role::King && m.from.file().abs_diff(m.to.file()) == 2 {
+            san.push_str(if m.to.file() == 6 { "O-O" } else { "O-O-O" });
+        }
+        else {
+            // Pawns only move diagonally when capturing, including en passant.
+            let is_capture = self.piece_at(m.to).is_some()
+                || (piece.role == Role::Pawn && m.from.file() != m.to.file());
+
+            if piece.role == Role::Pawn {
+                if is_capture {
+                    san.push((b'a' + m.from.file()) as char);
+                }
+            }
+            else {
+                san.push(piece.char().to_ascii_uppercase());
+
+                // Disambiguate between pieces of the same kind that can move to the same square,
+                // by file if possible, then by rank, then by both.
+                let others: Vec<Square> = self.legal_moves()
+                    .iter()
+                    .filter(|other| other.to == m.to && other.from != m.from && self.piece_at(other.from) == Some(piece))
+                    .map(|other| other.from)
+                    .collect();
+
+                if !others.is_empty() {
+                    let from = m.from.to_string();
+                    if others.iter().all(|other| other.file() != m.from.file()) {
+                        san.push_str(&from[0..1]);
+                    }
+                    else if others.iter().all(|other| other.rank() != m.from.rank()) {
+                        san.push_str(&from[1..2]);
+                    }
+                    else {
+                        san.push_str(&from);
+                    }
+                }
+            }
+
+            if is_capture {
+                san.push('x');
+            }
+
+            san.push_str(&m.to.to_string());
+
+            if let Some(promotion) = m.promotion {
+                san.push('=');
+                san.push(promotion.char().to_ascii_uppercase());
+            }
+        }
+
+        let mut after = self.clone();
+        after.apply(m);
+        if after.is_checkmate() {
+            san.push('#');
+        }
+        else if after.is_check() {
+            san.push('+');
+        }
+
+        san
+    }
+
+    /// Whether the side to move is in check.
+    pub fn is_check(&self) -> bool {
+        self.is_king_attacked(self.turn)
+    }
+
+    /// Whether the side to move has been checkmated.
+    pub fn is_checkmate(&self) -> bool {
+        self.is_check() && self.legal_moves().is_empty()
+    }
+
+    /// Whether the side to move has no legal moves but isn't in check.
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_check() && self.legal_moves().is_empty()
+    }
+
+    /// Find a move by the side that isn't to move that could have just been played to reach this
+    /// position, and the position before it. Only quiet piece moves are considered, as captures
+    /// and pawn moves can't be taken back without knowing more about the game. This is for puzzles
+    /// that start with the player to move, as puzzles always start with the opponent's move.
+    pub fn retract_quiet_move(&self) -> Option<(Position, Move)> {
+        let mover = self.turn.other();
+
+        for role in [Role::Knight, Role::Bishop, Role::Rook, Role::Queen, Role::King] {
+            let piece = Piece { color: mover, role };
+
+            for to in Square::all().filter(|square| self.piece_at(*square) == Some(piece)) {
+                for from in Square::all().filter(|square| self.piece_at(*square).is_none()) {
+                    let mut before = self.clone();
+                    before.board[from.index()] = Some(piece);
+                    before.board[to.index()] = None;
+                    before.turn = mover;
+                    before.en_passant = None;
+                    before.halfmove_clock = self.halfmove_clock.saturating_sub(1);
+                    if mover == Color::Black {
+                        before.fullmove_number = u32::max(self.fullmove_number, 2) - 1;
+                    }
+
+                    // The player can't be in check when it's not their move.
+                    if before.is_king_attacked(self.turn) {
+                        continue;
+                    }
+
+                    // The move has to be legal, and lead to exactly this position, which it might
+                    // not if it's castling or it loses castling rights.
+                    let m = Move { from, to, promotion: None };
+                    let mut after = before.clone();
+                    if after.play(&m).is_ok() && after.board == self.board && after.castling == self.castling {
+                        return Some((before, m));
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn king_square(&self, color: Color) -> Option<Square> {
+        Square::all().find(|square| self.piece_at(*square) == Some(Piece { color, role: Role::King }))
+    }
+
+    fn is_king_attacked(&self, color: Color) -> bool {
+        self.king_square(color)
+            .is_some_and(|square| self.is_attacked(square, color.other()))
+    }
+
+    /// Whether a square is attacked by any of the given color's pieces.
+    fn is_attacked(&self, square: Square, by: Color) -> bool {
+        let is_piece = |square: Option<Square>, roles: &[Role]| {
+            square
+                .and_then(|square| self.piece_at(square))
+                .is_some_and(|piece| piece.color == by && roles.contains(&piece.role))
+        };
+
+        // Pawns attack diagonally forwards, so look diagonally backwards for them.
+        if [-1, 1].iter().any(|files| is_piece(square.offset(*files, -by.forward()), &[Role::Pawn])) {
+            return true;
+        }
+
+        if KNIGHT_OFFSETS.iter().any(|(files, ranks)| is_piece(square.offset(*files, *ranks), &[Role::Knight])) {
+            return true;
+        }
+
+        if KING_DIRECTIONS.iter().any(|(files, ranks)| is_piece(square.offset(*files, *ranks), &[Role::King])) {
+            return true;
+        }
+
+        let slider_attacks = |directions: &[(i8, i8)], roles: &[Role]| {
+            directions.iter().any(|(files, ranks)| {
+                let first_piece = self.ray(square, *files, *ranks)
+                    .find(|square| self.piece_at(*square).is_some());
+                is_piece(first_piece, roles)
+            })
+        };
+
+        slider_attacks(&BISHOP_DIRECTIONS, &[Role::Bishop, Role::Queen])
+            || slider_attacks(&ROOK_DIRECTIONS, &[Role::Rook, Role::Queen])
+    }
+
+    /// The squares in a direction from a square (not including it), up to the edge of the board.
+    fn ray(&self, from: Square, files: i8, ranks: i8) -> impl Iterator<Item = Square> {
+        std::iter::successors(from.offset(files, ranks), move |square| square.offset(files, ranks))
+    }
+
+    /// Get the moves for the side to move, not checking whether they leave the king in check
+    /// (except for castling through check).
+    fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let us = self.turn;
+        let mut moves = Vec::new();
+
+        for from in Square::all() {
+            let Some(piece) = self.piece_at(from).filter(|piece| piece.color == us) else {
+                continue;
+            };
+
+            // Whether a piece can move to or capture on a square.
+            let can_land = |to: Square| !matches!(self.piece_at(to), Some(piece) if piece.color == us);
+
+            match piece.role {
+                Role::Pawn => self.pawn_moves(from, &mut moves),
+                Role::Knight => {
+                    for (files, ranks) in KNIGHT_OFFSETS {
+                        if let Some(to) = from.offset(files, ranks).filter(|to| can_land(*to)) {
+                            moves.push(Move { from, to, promotion: None });
+                        }
+                    }
+                },
+                Role::King => {
+                    for (files, ranks) in KING_DIRECTIONS {
+                        if let Some(to) = from.offset(files, ranks).filter(|to| can_land(*to)) {
+                            moves.push(Move { from, to, promotion: None });
+                        }
+                    }
+                    self.castling_moves(from, &mut moves);
+                },
+                Role::Bishop | Role::Rook | Role::Queen => {
+                    let directions: &[(i8, i8)] = match piece.role {
+                        Role::Bishop => &BISHOP_DIRECTIONS,
+                        Role::Rook => &ROOK_DIRECTIONS,
+                        _ => &KING_DIRECTIONS,
+                    };
+
+                    for (files, ranks) in directions {
+                        for to in self.ray(from, *files, *ranks) {
+                            match self.piece_at(to) {
+                                None => moves.push(Move { from, to, promotion: None }),
+                                Some(piece) => {
+                                    if piece.color != us {
+                                        moves.push(Move { from, to, promotion: None });
+                                    }
+                                    break;
+                                },
+                            }
+                        }
+                    }
+                },
+            }
+        }
+
+        moves
+    }
+
+    fn pawn_moves(&self, from: Square, moves: &mut Vec<Move>) {
+        let us = self.turn;
+        let forward = us.forward();
+        let start_rank = if us == Color::White { 1 } else { 6 };
+        let last_rank = us.other().back_rank();
+
+        let mut push = |to: Square| {
+            if to.rank() == last_rank {
+                for role in PROMOTION_ROLES {
+                    moves.push(Move { from, to, promotion: Some(role) });
+                }
+            }
+            else {
+                moves.push(Move { from, to, promotion: None });
+            }
+        };
+
+        if let Some(one) = from.offset(0, forward).filter(|to| self.piece_at(*to).is_none()) {
+            push(one);
+
+            if from.rank() == start_rank {
+                if let Some(two) = one.offset(0, forward).filter(|to| self.piece_at(*to).is_none()) {
+                    push(two);
+                }
+            }
+        }
+
+        for files in [-1, 1] {
+            if let Some(to) = from.offset(files, forward) {
+                let is_capture = self.piece_at(to).is_some_and(|piece| piece.color != us);
+                if is_capture || self.en_passant == Some(to) {
+                    push(to);
+                }
+            }
+        }
+    }
+
+    fn castling_moves(&self, from: Square, moves: &mut Vec<Move>) {
+        let us = self.turn;
+        let rank = us.back_rank();
+
+        if from != Square::new(4, rank) || self.is_attacked(from, us.other()) {
+            return;
+        }
+
+        // (king side, rook file, the squares that need to be empty, the squares the king passes).
+        let sides: [(bool, u8, &[u8], &[u8]); 2] = [
+            (true, 7, &[5, 6], &[5, 6]),
+            (false, 0, &[1, 2, 3], &[3, 2]),
+        ];
+
+        for (king_side, rook_file, empty_files, king_files) in sides {
+            let has_rook = self.piece_at(Square::new(rook_file, rank)) == Some(Piece { color: us, role: Role::Rook });
+
+            if self.castling.get(us, king_side)
+                && has_rook
+                && empty_files.iter().all(|file| self.piece_at(Square::new(*file, rank)).is_none())
+                && king_files.iter().all(|file| !self.is_attacked(Square::new(*file, rank), us.other()))
+            {
+                let to = Square::new(if king_side { 6 } else { 2 }, rank);
+                moves.push(Move { from, to, promotion: None });
+            }
+        }
+    }
+
+    /// Apply a move without checking that it's legal.
+    fn apply(&mut self, m: &Move) {
+        let us = self.turn;
+        let Some(piece) = self.piece_at(m.from) else {
+            return;
+        };
+
+        let is_capture = self.piece_at(m.to).is_some();
+        let is_pawn = piece.role == Role::Pawn;
+
+        // Remove the pawn captured en passant.
+        if is_pawn && Some(m.to) == self.en_passant && m.from.file() != m.to.file() && !is_capture {
+            self.board[Square::new(m.to.file(), m.from.rank()).index()] = None;
+        }
+
+        // Move the rook when castling.
+        if piece.role == Role::King && m.from.file().abs_diff(m.to.file()) == 2 {
+            let (rook_from, rook_to) = if m.to.file() == 6 { (7, 5) } else { (0, 3) };
+            let rank = m.from.rank();
+            self.board[Square::new(rook_to, rank).index()] = self.board[Square::new(rook_from, rank).index()];
+            self.board[Square::new(rook_from, rank).index()] = None;
+        }
+
+        self.board[m.from.index()] = None;
+        self.board[m.to.index()] = Some(Piece {
+            color: us,
+            role: m.promotion.unwrap_or(piece.role),
+        });
+
+        self.en_passant = match is_pawn && m.from.rank().abs_diff(m.to.rank()) == 2 {
+            true => Some(Square::new(m.from.file(), (m.from.rank() + m.to.rank()) / 2)),
+            false => None,
+        };
+
+        // Castling rights are lost when the king moves, or a rook moves or is captured.
+        if piece.role == Role::King {
+            self.castling.set(us, true, false);
+            self.castling.set(us, false, false);
+        }
+        for color in [Color::White, Color::Black] {
+            let rank = color.back_rank();
+            if m.from == Square::new(7, rank) || m.to == Square::new(7, rank) {
+                self.castling.set(color, true, false);
+            }
+            if m.from == Square::new(0, rank) || m.to == Square::new(0, rank) {
+                self.castling.set(color, false, false);
+            }
+        }
+
+        self.halfmove_clock = if is_pawn || is_capture { 0 } else { self.halfmove_clock + 1 };
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.turn = us.other();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Count the leaf nodes of the move tree to the given depth.
+    fn perft(position: &Position, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        position.legal_moves()
+            .iter()
+            .map(|m| {
+                let mut after = position.clone();
+                after.apply(m);
+                perft(&after, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_perft() {
+        // Known move counts from https://www.chessprogramming.org/Perft_Results.
+        let cases = [
+            (STARTING_FEN, 3, 8902),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 2, 2039),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43238),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 3, 9467),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3, 62379),
+        ];
+
+        for (fen, depth, expected) in cases {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(perft(&position, depth), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let fens = [
+            STARTING_FEN,
+            "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b Kq d6 0 3",
+        ];
+
+        for fen in fens {
+            assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+        }
+
+        // The move counters are optional.
+        assert_eq!(Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap().to_fen(),
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn test_invalid_fen() {
+        let fens = [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w kq - 0 1",
+            "rnbqkbnr/pppppppp/45/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/81/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ];
+
+        for fen in fens {
+            assert!(matches!(Position::from_fen(fen), Err(ChessError::InvalidFen(_))), "{fen}");
+        }
+
+        // Enough empty squares in a rank to overflow the file count is an error, not a panic.
+        let fen = format!("{}/8/8/8/8/8/8/8 w - - 0 1", "8".repeat(40));
+        assert!(matches!(Position::from_fen(&fen), Err(ChessError::InvalidFen(_))));
+    }
+
+    #[test]
+    fn test_uci() {
+        assert_eq!(Move::from_uci("e7e8q").unwrap().to_string(), "e7e8q");
+        assert_eq!(Move::from_uci("a1h8").unwrap(), Move {
+            from: Square::new(0, 0),
+            to: Square::new(7, 7),
+            promotion: None,
+        });
+
+        for uci in ["", "e2", "e2e9", "i2e4", "e7e8k", "e2e4e5"] {
+            assert!(matches!(Move::from_uci(uci), Err(ChessError::InvalidMove(_))), "{uci}");
+        }
+    }
+
+    #[test]
+    fn test_play() {
+        let mut position = Position::from_fen(STARTING_FEN).unwrap();
+
+        assert!(matches!(position.play_uci("e2e5"), Err(ChessError::IllegalMove(_))));
+
+        // Fool's mate.
+        for uci in ["f2f3", "e7e5", "g2g4"] {
+            position.play_uci(uci).unwrap();
+            assert!(!position.is_checkmate());
+        }
+        position.play_uci("d8h4").unwrap();
+        assert!(position.is_check());
+        assert!(position.is_checkmate());
+        assert!(position.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn test_san() {
+        let cases = [
+            (STARTING_FEN, "g1f3", "Nf3"),
+            (STARTING_FEN, "e2e4", "e4"),
+            // Disambiguation by file, rank, and both.
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2", "Nbd2"),
+            ("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "a1a2", "R1a2"),
+            ("7k/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1", "a4b3", "Qa4b3"),
+            // Captures, including en passant, and checks.
+            ("r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24", "f2g3", "Bxg3"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+            ("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1", "a1a8", "Ra8+"),
+            // Castling, promotion and mate.
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1", "O-O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8g8", "O-O"),
+            ("6k1/4P3/4K3/8/8/8/8/8 w - - 0 1", "e7e8q", "e8=Q+"),
+            ("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", "d8h4", "Qh4#"),
+        ];
+
+        for (fen, uci, san) in cases {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(position.san(&Move::from_uci(uci).unwrap()), san, "{fen} {uci}");
+        }
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let cases = [
+            (STARTING_FEN, "Nf3", "g1f3"),
+            (STARTING_FEN, "e4", "e2e4"),
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "Nbd2", "b1d2"),
+            ("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "R1a2", "a1a2"),
+            ("7k/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1", "Qa4b3", "a4b3"),
+            ("r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24", "Bxg3", "f2g3"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "exd6", "e5d6"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O-O", "e1c1"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "O-O", "e8g8"),
+            ("6k1/4P3/4K3/8/8/8/8/8 w - - 0 1", "e8=Q+", "e7e8q"),
+            ("6k1/4P3/4K3/8/8/8/8/8 w - - 0 1", "e8N", "e7e8n"),
+            ("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", "Qh4#", "d8h4"),
+            // Things other software does.
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "0-0", "e1g1"),
+            (STARTING_FEN, "Ng1f3!?", "g1f3"),
+            ("r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24", "Bg3", "f2g3"),
+        ];
+
+        for (fen, san, uci) in cases {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(position.parse_san(san).unwrap().to_string(), uci, "{fen} {san}");
+        }
+
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1").unwrap();
+        assert!(matches!(position.parse_san("Nd2"), Err(ChessError::InvalidMove(_))));
+        assert!(matches!(position.parse_san("Nd4"), Err(ChessError::IllegalMove(_))));
+        assert!(matches!(position.parse_san("Zd2"), Err(ChessError::InvalidMove(_))));
+        assert!(matches!(position.parse_san("é"), Err(ChessError::InvalidMove(_))));
+    }
+
+    #[test]
+    fn test_special_moves() {
+        // En passant removes the captured pawn.
+        let mut position = Position::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        position.play_uci("e5d6").unwrap();
+        assert_eq!(position.to_fen(), "4k3/8/3P4/8/8/8/8/4K3 b - - 0 1");
+
+        // Castling moves the rook, and isn't allowed through check.
+        let mut position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        position.play_uci("e1g1").unwrap();
+        assert_eq!(position.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/5r2/R3K2R w KQkq - 0 1").unwrap();
+        assert!(!position.is_legal(&Move::from_uci("e1g1").unwrap()));
+        assert!(position.is_legal(&Move::from_uci("e1c1").unwrap()));
+
+        // Promotion.
+        let mut position = Position::from_fen("8/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        assert!(position.play_uci("e7e8").is_err());
+        position.play_uci("e7e8n").unwrap();
+        assert_eq!(position.to_fen(), "4N3/8/8/8/8/8/k7/4K3 b - - 0 1");
+
+        // Stalemate.
+        let position = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(position.is_stalemate());
+        assert!(!position.is_checkmate());
+    }
+
+    #[test]
+    fn test_retract_quiet_move() {
+        let retract = |fen: &str| Position::from_fen(fen).unwrap()
+            .retract_quiet_move()
+            .map(|(before, m)| (before.to_fen(), m.to_string()));
+
+        // Black's pawns can't be moved back, but the king can.
+        assert_eq!(retract("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"),
+            Some(("5k2/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1".to_string(), "f8g8".to_string())));
+
+        // If the player's in check, the move must have been the check.
+        assert_eq!(retract("4k3/8/8/8/8/8/8/r3K3 w - - 0 1"),
+            Some(("4k3/8/8/8/8/8/r7/4K3 b - - 0 1".to_string(), "a2a1".to_string())));
+
+        // The black king can't have moved if black can still castle, and pawns can't be moved back.
+        assert_eq!(retract("4k3/4p3/8/8/8/8/8/4K3 w k - 0 1"), None);
+    }
+}
@@ -1,6 +1,9 @@
 mod index;
 mod puzzle;
 mod about;
+mod collections;
+mod search;
+mod reports;
 
 use askama::Template;
 use axum::Router;
@@ -24,8 +27,20 @@ pub fn routes(app_state: AppState) -> Router {
         // Tactics pages.
         .route("/tactics", axum::routing::get(puzzle::next_review))
         .route("/tactics/new", axum::routing::get(puzzle::random_puzzle))
+        .route("/tactics/weaknesses", axum::routing::get(puzzle::weaknesses_puzzle))
+        .route("/tactics/rush", axum::routing::get(puzzle::rush_puzzle))
+        .route("/tactics/daily", axum::routing::get(puzzle::daily_puzzle))
         .route("/tactics/by_id/:puzzle_id", axum::routing::get(puzzle::specific_puzzle))
         .route("/tactics/history", axum::routing::get(puzzle::puzzle_history))
+        .route("/tactics/search", axum::routing::get(search::search_page))
+
+        // Collection pages.
+        .route("/collections", axum::routing::get(collections::collections_page))
+        .route("/collections/:collection_id", axum::routing::get(collections::collection_page))
+        .route("/collections/:collection_id/play", axum::routing::get(puzzle::play_collection))
+
+        // Reported puzzles.
+        .route("/reports", axum::routing::get(reports::reports_page))
 
         .fallback(not_found)
 
@@ -0,0 +1,69 @@
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+
+use crate::app::AppState;
+use crate::db::{Collection, PuzzlePack};
+use crate::services::user_service::UserService;
+
+use super::{BaseTemplateData, ControllerError, NotFoundTemplate};
+
+/// The collections page template.
+#[derive(Template)]
+#[template(path = "collections.html")]
+pub struct CollectionsTemplate {
+    base: BaseTemplateData,
+    collections: Vec<Collection>,
+    packs: Vec<PuzzlePack>,
+    // Whether puzzles can be generated from the user's games, which needs an engine.
+    engine_enabled: bool,
+}
+
+/// The template for a single collection.
+#[derive(Template)]
+#[template(path = "collection.html")]
+pub struct CollectionTemplate {
+    base: BaseTemplateData,
+    collection: Collection,
+    puzzle_ids: Vec<String>,
+}
+
+/// GET /collections
+pub async fn collections_page(
+    State(state): State<AppState>,
+) -> Result<CollectionsTemplate, ControllerError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    Ok(CollectionsTemplate {
+        base: Default::default(),
+        collections: state.collection_service.get_collections(user_id).await?,
+        packs: state.pack_service.get_packs().await?,
+        engine_enabled: state.personal_service.enabled(),
+    })
+}
+
+/// GET /collections/{collection_id}
+pub async fn collection_page(
+    State(state): State<AppState>,
+    Path(collection_id): Path<i64>,
+) -> Result<Response, ControllerError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    let response = match state.collection_service.get_collection(user_id, collection_id).await? {
+        Some((collection, puzzle_ids)) => CollectionTemplate {
+            base: Default::default(),
+            collection,
+            puzzle_ids,
+        }.into_response(),
+        None => NotFoundTemplate {
+            base: Default::default(),
+            request_uri: format!("/collections/{collection_id}"),
+        }.into_response(),
+    };
+
+    Ok(response)
+}
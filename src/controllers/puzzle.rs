@@ -2,7 +2,7 @@ use std::fmt::Display;
 use askama::Template;
 use axum::extract::{Path, Query, State};
 
-use crate::app::{UiConfig, AppState, TacticsConfig};
+use crate::app::{UiConfig, AppState};
 
 use super::{BaseTemplateData, ControllerError};
 
@@ -15,8 +15,20 @@ pub enum PuzzleMode {
     /// We're showing a random new puzzle.
     Random,
 
+    /// We're showing a random new puzzle from one of the user's weakest themes or openings.
+    Weaknesses,
+
     /// We're showing a specifically requested puzzle.
     Specific,
+
+    /// We're playing through the puzzles in a collection.
+    Collection,
+
+    /// We're showing puzzles in a timed puzzle rush run.
+    Rush,
+
+    /// We're showing the puzzle of the day.
+    Daily,
 }
 
 impl Display for PuzzleMode {
@@ -24,7 +36,11 @@ impl Display for PuzzleMode {
         match self {
             PuzzleMode::Review => write!(f, "Review"),
             PuzzleMode::Random => write!(f, "Random"),
+            PuzzleMode::Weaknesses => write!(f, "Weaknesses"),
             PuzzleMode::Specific => write!(f, "Specific"),
+            PuzzleMode::Collection => write!(f, "Collection"),
+            PuzzleMode::Rush => write!(f, "Rush"),
+            PuzzleMode::Daily => write!(f, "Daily"),
         }
     }
 }
@@ -35,15 +51,31 @@ impl Display for PuzzleMode {
 pub struct PuzzleTemplate {
     base: BaseTemplateData,
     mode: PuzzleMode,
-    tactics_config: TacticsConfig,
     ui_config: UiConfig,
+    auto_grade: bool,
+    engine_enabled: bool,
     requested_id: String,
+    collection: CollectionOptions,
+}
+
+/// The options for playing through a collection.
+#[derive(Default, serde::Deserialize)]
+pub struct CollectionOptions {
+    #[serde(skip)]
+    id: i64,
+    /// Whether the puzzles are shown in a random order.
+    #[serde(default)]
+    shuffle: bool,
+    /// Whether cards are created for the puzzles, by reviewing them.
+    #[serde(default)]
+    create_cards: bool,
 }
 
 /// The puzzle history request.
 #[derive(serde::Deserialize)]
 pub struct PuzzleHistoryRequest {
     page: Option<i64>,
+    tag: Option<String>,
 }
 
 /// The puzzle history page template.
@@ -52,6 +84,8 @@ pub struct PuzzleHistoryRequest {
 pub struct PuzzleHistoryTemplate {
     base: BaseTemplateData,
     page: i64,
+    // Only puzzles the user has given this tag are shown, if it isn't empty.
+    tag: String,
 }
 
 /// GET /tactics/by_id/{puzzle_id}
@@ -63,9 +97,11 @@ pub async fn specific_puzzle(
     Ok(PuzzleTemplate {
         base: Default::default(),
         mode: PuzzleMode::Specific,
-        tactics_config: state.app_config.tactics,
         ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
         requested_id: puzzle_id,
+        collection: Default::default(),
     })
 }
 
@@ -77,9 +113,77 @@ pub async fn random_puzzle(
     Ok(PuzzleTemplate {
         base: Default::default(),
         mode: PuzzleMode::Random,
-        tactics_config: state.app_config.tactics,
         ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
+        requested_id: "".to_string(),
+        collection: Default::default(),
+    })
+}
+
+/// GET /tactics/weaknesses
+pub async fn weaknesses_puzzle(
+    State(state): State<AppState>,
+) -> Result<PuzzleTemplate, ControllerError>
+{
+    Ok(PuzzleTemplate {
+        base: Default::default(),
+        mode: PuzzleMode::Weaknesses,
+        ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
+        requested_id: "".to_string(),
+        collection: Default::default(),
+    })
+}
+
+/// GET /tactics/rush
+pub async fn rush_puzzle(
+    State(state): State<AppState>,
+) -> Result<PuzzleTemplate, ControllerError>
+{
+    Ok(PuzzleTemplate {
+        base: Default::default(),
+        mode: PuzzleMode::Rush,
+        ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
+        requested_id: "".to_string(),
+        collection: Default::default(),
+    })
+}
+
+/// GET /tactics/daily
+pub async fn daily_puzzle(
+    State(state): State<AppState>,
+) -> Result<PuzzleTemplate, ControllerError>
+{
+    Ok(PuzzleTemplate {
+        base: Default::default(),
+        mode: PuzzleMode::Daily,
+        ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
+        requested_id: "".to_string(),
+        collection: Default::default(),
+    })
+}
+
+/// GET /collections/{collection_id}/play
+pub async fn play_collection(
+    State(state): State<AppState>,
+    Path(collection_id): Path<i64>,
+    Query(options): Query<CollectionOptions>,
+) -> Result<PuzzleTemplate, ControllerError>
+{
+    Ok(PuzzleTemplate {
+        base: Default::default(),
+        mode: PuzzleMode::Collection,
+        ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
         requested_id: "".to_string(),
+        collection: CollectionOptions { id: collection_id, ..options },
     })
 }
 
@@ -91,9 +195,11 @@ pub async fn next_review(
     Ok(PuzzleTemplate {
         base: Default::default(),
         mode: PuzzleMode::Review,
-        tactics_config: state.app_config.tactics,
         ui_config: state.app_config.ui,
+        auto_grade: state.app_config.auto_grade.enabled,
+        engine_enabled: state.analysis_service.enabled(),
         requested_id: "".to_string(),
+        collection: Default::default(),
     })
 }
 
@@ -104,6 +210,7 @@ pub async fn puzzle_history(
 {
     PuzzleHistoryTemplate {
         page: request.page.unwrap_or(1).max(1),
+        tag: request.tag.unwrap_or_default(),
         ..Default::default()
     }
 }
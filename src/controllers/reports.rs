@@ -0,0 +1,30 @@
+use askama::Template;
+use axum::extract::State;
+
+use crate::app::AppState;
+use crate::db::PuzzleReport;
+use crate::services::user_service::UserService;
+
+use super::{BaseTemplateData, ControllerError};
+
+/// The reported puzzles page template.
+#[derive(Template)]
+#[template(path = "reports.html")]
+pub struct ReportsTemplate {
+    base: BaseTemplateData,
+    reports: Vec<PuzzleReport>,
+}
+
+/// GET /reports
+pub async fn reports_page(
+    State(state): State<AppState>,
+) -> Result<ReportsTemplate, ControllerError>
+{
+    // TODO: use a JWT to get the user_id.
+    let user_id = UserService::local_user_id();
+
+    Ok(ReportsTemplate {
+        base: Default::default(),
+        reports: state.tactics_service.get_reports(user_id).await?,
+    })
+}
@@ -0,0 +1,27 @@
+use askama::Template;
+use axum::extract::State;
+
+use crate::app::AppState;
+use crate::db::PuzzlePack;
+
+use super::{BaseTemplateData, ControllerError};
+
+/// The puzzle search page, which gets its results from the search API.
+#[derive(Template, Default)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    base: BaseTemplateData,
+    // The puzzle packs, which can be searched as well as lichess and personal puzzles.
+    packs: Vec<PuzzlePack>,
+}
+
+/// GET /tactics/search
+pub async fn search_page(
+    State(state): State<AppState>,
+) -> Result<SearchTemplate, ControllerError>
+{
+    Ok(SearchTemplate {
+        packs: state.pack_service.get_packs().await?,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,74 @@
+use chrono::{NaiveDate, Datelike, Duration};
+
+/// Get the seed for picking the daily puzzle on the given date. This is a splitmix64 hash of the
+/// day number rather than one of the std or rand hashers, so that it's guaranteed to stay the
+/// same between versions and everyone gets the same puzzle on the same day.
+pub fn daily_seed(date: NaiveDate) -> u64 {
+    let mut z = (date.num_days_from_ce() as u64).wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Get the (current, best) streaks of consecutive days from the sorted dates the user solved the
+/// daily puzzle on. The current streak isn't broken until a whole day is missed, so solving
+/// yesterday's puzzle but not today's yet still counts.
+pub fn daily_streaks(solved_dates: &[NaiveDate], today: NaiveDate) -> (i64, i64) {
+    let mut best = 0;
+    let mut streak = 0;
+    let mut last_date: Option<NaiveDate> = None;
+
+    for date in solved_dates {
+        streak = match last_date {
+            Some(last_date) if *date == last_date => streak,
+            Some(last_date) if *date - last_date == Duration::days(1) => streak + 1,
+            _ => 1,
+        };
+
+        best = i64::max(best, streak);
+        last_date = Some(*date);
+    }
+
+    let current = match last_date {
+        Some(last_date) if today - last_date <= Duration::days(1) => streak,
+        _ => 0,
+    };
+
+    (current, best)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::daily::{daily_seed, daily_streaks};
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_daily_seed() {
+        // The seed needs to be stable, or the daily puzzle would change between versions.
+        assert_eq!(daily_seed(date(10, 28)), daily_seed(date(10, 28)));
+        assert_ne!(daily_seed(date(10, 28)), daily_seed(date(10, 29)));
+        assert_eq!(daily_seed(NaiveDate::from_ymd_opt(1, 1, 1).unwrap()), 0x910a2dec89025cc1);
+    }
+
+    #[test]
+    fn test_daily_streaks() {
+        assert_eq!(daily_streaks(&[], date(10, 28)), (0, 0));
+
+        // A three day streak up to today, after a two day streak.
+        let solved = [date(10, 20), date(10, 21), date(10, 26), date(10, 27), date(10, 28)];
+        assert_eq!(daily_streaks(&solved, date(10, 28)), (3, 3));
+
+        // It's still current the day after, but not the day after that.
+        assert_eq!(daily_streaks(&solved, date(10, 29)), (3, 3));
+        assert_eq!(daily_streaks(&solved, date(10, 30)), (0, 3));
+
+        // The best streak doesn't have to be the current one.
+        let solved = [date(10, 1), date(10, 2), date(10, 3), date(10, 4), date(10, 28)];
+        assert_eq!(daily_streaks(&solved, date(10, 28)), (1, 4));
+    }
+}
@@ -4,12 +4,31 @@ mod user;
 mod card;
 mod migration;
 mod backup;
+mod collection;
+mod rush;
+mod daily;
+mod search;
+mod notes;
+mod report;
+mod solve;
+mod pack;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 
 use chrono::{DateTime, FixedOffset};
 pub use dbresult::*;
 pub use puzzle::*;
 pub use user::*;
 pub use card::*;
+pub use collection::*;
+pub use rush::*;
+pub use daily::*;
+pub use search::*;
+pub use notes::*;
+pub use report::*;
+pub use solve::*;
+pub use pack::*;
 
 use sqlx::sqlite::{SqlitePoolOptions, SqliteConnectOptions, SqliteRow, SqliteJournalMode};
 use sqlx::{SqlitePool, ConnectOptions, Row};
@@ -22,12 +41,32 @@ use crate::srs::SrsConfig;
 pub struct PuzzleDatabase {
     pool: SqlitePool,
     srs_config: SrsConfig,
+    // The cached (min, max) puzzle rating, which is cleared when puzzles are added.
+    puzzle_rating_range: Arc<RwLock<Option<(i64, i64)>>>,
+    // The cached number of puzzles with each rating in the indexes new puzzles are picked from,
+    // which is cleared when puzzles are added.
+    rating_counts: Arc<RwLock<HashMap<String, RatingCounts>>>,
 }
 
 pub struct AppData {
     pub environment: String,
     pub lichess_db_imported: bool,
     pub last_backup_date: Option<DateTime<FixedOffset>>,
+    /// The date of the last imported lichess puzzle database, if it's known.
+    pub lichess_db_date: Option<DateTime<FixedOffset>>,
+    /// The number of puzzles in the last imported lichess puzzle database.
+    pub lichess_db_rows: Option<i64>,
+}
+
+/// How far an import of the lichess puzzle database has got.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportCheckpoint {
+    /// Identifies the copy of the database being imported, so that a different one isn't resumed.
+    pub source: String,
+    /// The number of rows of the csv that have been imported.
+    pub rows: i64,
+    /// The ID of the puzzle in the last imported row.
+    pub last_puzzle_id: String,
 }
 
 impl<'r> sqlx::FromRow<'r, SqliteRow> for AppData
@@ -43,6 +82,14 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for AppData
                     index: "date".to_string(),
                     source: e.to_string().into(),
                 })?,
+            lichess_db_date: row.try_get::<Option<&str>, _>("lichess_db_date")?
+                .map(DateTime::parse_from_rfc3339)
+                .transpose()
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "lichess_db_date".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            lichess_db_rows: row.try_get("lichess_db_rows")?,
         })
     }
 }
@@ -80,6 +127,8 @@ impl PuzzleDatabase {
         Ok(Self {
             pool,
             srs_config,
+            puzzle_rating_range: Default::default(),
+            rating_counts: Default::default(),
         })
     }
 
@@ -103,14 +152,63 @@ impl PuzzleDatabase {
             .await?)
     }
 
+    /// Set the app data. The import checkpoint is kept, as it's set separately while importing.
     pub async fn set_app_data(&self, app_data: &AppData) -> DbResult<()> {
         sqlx::query("
-            INSERT OR REPLACE INTO app_data (environment, lichess_db_imported, last_backup_date)
-            VALUES (?, ?, ?)
+            INSERT INTO app_data (environment, lichess_db_imported, last_backup_date,
+                lichess_db_date, lichess_db_rows)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (environment) DO UPDATE SET
+                lichess_db_imported = excluded.lichess_db_imported,
+                last_backup_date = excluded.last_backup_date,
+                lichess_db_date = excluded.lichess_db_date,
+                lichess_db_rows = excluded.lichess_db_rows
         ")
         .bind(&app_data.environment)
         .bind(&app_data.lichess_db_imported)
         .bind(&app_data.last_backup_date.as_ref().map(DateTime::to_rfc3339))
+        .bind(app_data.lichess_db_date.as_ref().map(DateTime::to_rfc3339))
+        .bind(app_data.lichess_db_rows)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the checkpoint of the lichess puzzle database import in progress, if there is one.
+    pub async fn get_import_checkpoint(&self) -> DbResult<Option<ImportCheckpoint>> {
+        Ok(sqlx::query("
+                SELECT import_source, import_rows, import_last_puzzle_id
+                FROM app_data
+                WHERE environment = ''
+            ")
+            .map(|row: SqliteRow| -> Result<_, sqlx::Error> {
+                let source: Option<String> = row.try_get("import_source")?;
+                let rows: Option<i64> = row.try_get("import_rows")?;
+                let last_puzzle_id: Option<String> = row.try_get("import_last_puzzle_id")?;
+
+                Ok(match (source, rows, last_puzzle_id) {
+                    (Some(source), Some(rows), Some(last_puzzle_id))
+                        => Some(ImportCheckpoint { source, rows, last_puzzle_id }),
+                    _ => None,
+                })
+            })
+            .fetch_optional(&self.pool)
+            .await?
+            .transpose()?
+            .flatten())
+    }
+
+    /// Set the checkpoint of the lichess puzzle database import in progress, or clear it.
+    pub async fn set_import_checkpoint(&self, checkpoint: Option<&ImportCheckpoint>) -> DbResult<()> {
+        sqlx::query("
+            UPDATE app_data
+            SET import_source = ?, import_rows = ?, import_last_puzzle_id = ?
+            WHERE environment = ''
+        ")
+        .bind(checkpoint.map(|checkpoint| &checkpoint.source))
+        .bind(checkpoint.map(|checkpoint| checkpoint.rows))
+        .bind(checkpoint.map(|checkpoint| &checkpoint.last_puzzle_id))
         .execute(&self.pool)
         .await?;
 
@@ -81,8 +81,20 @@ impl PuzzleDatabase {
         log::info!("Backing up tables");
 
         // Back up all tables *except* the puzzles table, which is quite big and can just be
-        // imported again at next start.
+        // imported again at next start. Puzzles that aren't from lichess can't be imported again
+        // though, so they're backed up along with their themes and opening tags.
         let query = sqlx::query("
+            INSERT OR REPLACE INTO backup_db.puzzles
+            SELECT * FROM puzzles WHERE source <> 'lichess';
+
+            INSERT OR REPLACE INTO backup_db.puzzle_themes
+            SELECT * FROM puzzle_themes
+            WHERE puzzle_id IN (SELECT puzzle_id FROM backup_db.puzzles);
+
+            INSERT OR REPLACE INTO backup_db.puzzle_opening_tags
+            SELECT * FROM puzzle_opening_tags
+            WHERE puzzle_id IN (SELECT puzzle_id FROM backup_db.puzzles);
+
             INSERT OR REPLACE INTO backup_db.app_data
             SELECT * FROM app_data;
 
@@ -95,11 +107,45 @@ impl PuzzleDatabase {
             INSERT OR REPLACE INTO backup_db.skipped_puzzles
             SELECT * FROM skipped_puzzles;
 
+            INSERT OR REPLACE INTO backup_db.seen_puzzles
+            SELECT * FROM seen_puzzles;
+
+            INSERT OR REPLACE INTO backup_db.collections
+            SELECT * FROM collections;
+
+            INSERT OR REPLACE INTO backup_db.collection_puzzles
+            SELECT * FROM collection_puzzles;
+
+            INSERT OR REPLACE INTO backup_db.rush_runs
+            SELECT * FROM rush_runs;
+
+            INSERT OR REPLACE INTO backup_db.rush_run_puzzles
+            SELECT * FROM rush_run_puzzles;
+
+            INSERT OR REPLACE INTO backup_db.daily_puzzles
+            SELECT * FROM daily_puzzles;
+
+            INSERT OR REPLACE INTO backup_db.daily_puzzle_results
+            SELECT * FROM daily_puzzle_results;
+
+            INSERT OR REPLACE INTO backup_db.puzzle_notes
+            SELECT * FROM puzzle_notes;
+
+            INSERT OR REPLACE INTO backup_db.puzzle_user_tags
+            SELECT * FROM puzzle_user_tags;
+
+            INSERT OR REPLACE INTO backup_db.puzzle_reports
+            SELECT * FROM puzzle_reports;
+
             INSERT OR REPLACE INTO backup_db.users
             SELECT * FROM users;
 
+            INSERT OR REPLACE INTO backup_db.puzzle_packs
+            SELECT * FROM puzzle_packs;
+
             UPDATE backup_db.app_data
-            SET lichess_db_imported=0;
+            SET lichess_db_imported=0, import_source=NULL, import_rows=NULL,
+                import_last_puzzle_id=NULL;
         ");
 
         query.execute(conn).await?;
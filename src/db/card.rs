@@ -1,11 +1,13 @@
 
+use std::collections::HashSet;
+
 use chrono::{DateTime, FixedOffset, Duration};
 use futures::{TryStreamExt, StreamExt};
 use sqlx::{Row, FromRow, QueryBuilder};
-use sqlx::sqlite::SqliteRow;
+use sqlx::sqlite::{Sqlite, SqliteRow};
 
 use crate::srs::{Card, Difficulty, ReviewOrder};
-use crate::db::{PuzzleDatabase, DbResult, Puzzle, ErrorDetails};
+use crate::db::{PuzzleDatabase, DbResult, Puzzle, PuzzleNotes, ErrorDetails};
 
 use super::DatabaseError;
 
@@ -17,6 +19,29 @@ pub struct Review {
     pub difficulty: Difficulty,
     pub date: DateTime<FixedOffset>,
     pub user_rating: Option<i64>,
+    pub attempt: AttemptData,
+}
+
+/// The details of a user's attempt at a puzzle, as reported by the client. All of the fields are
+/// optional, as older reviews (and older clients) don't have them.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct AttemptData {
+    /// The time taken to solve the puzzle in milliseconds.
+    #[serde(default)]
+    pub duration_ms: Option<i64>,
+
+    /// The number of wrong moves that were tried.
+    #[serde(default)]
+    pub mistakes: Option<i64>,
+
+    /// Whether the hint button was used.
+    #[serde(default)]
+    pub hint_used: Option<bool>,
+
+    /// The moves played by the user, as space separated UCI moves (the same format as
+    /// `Puzzle::moves`).
+    #[serde(default)]
+    pub moves: Option<String>,
 }
 
 /// A bucket of review scores for puzzles in the given review range with the given score.
@@ -28,6 +53,23 @@ pub struct ReviewScoreBucket {
     pub review_count: i64,
 }
 
+/// The kind of a puzzle tag, i.e. a theme or an opening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum PuzzleTagKind {
+    Theme,
+    Opening,
+}
+
+/// A bucket of reviews of puzzles with the given tag, in the given rating range.
+#[derive(Debug)]
+pub struct TagReviewBucket {
+    pub kind: PuzzleTagKind,
+    pub tag: String,
+    pub puzzle_rating_min: i64,
+    pub review_count: i64,
+    pub failure_count: i64,
+}
+
 /// A single puzzle history entry.
 #[derive(Debug, serde::Serialize)]
 pub struct PuzzleHistoryEntry {
@@ -39,6 +81,13 @@ pub struct PuzzleHistoryEntry {
 
     /// Whether the puzzle was skipped or not.
     pub skipped: bool,
+
+    /// The user's notes and tags for the puzzle.
+    pub notes: PuzzleNotes,
+
+    /// The puzzle's solution in SAN, with move numbers. This isn't stored in the database, and is
+    /// filled in by the tactics service.
+    pub san: Option<String>,
 }
 
 impl<'r> sqlx::FromRow<'r, SqliteRow> for Review
@@ -46,7 +95,7 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for Review
     fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
         Ok(Self {
             user_id: row.try_get("user_id")?,
-            puzzle_id: row.try_get("user_id")?,
+            puzzle_id: row.try_get("puzzle_id")?,
             difficulty: Difficulty::from_i64(row.try_get("difficulty")?)
                 .map_err(|e| sqlx::Error::ColumnDecode {
                     index: "Difficulty".to_string(),
@@ -58,6 +107,19 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for Review
                     source: e.to_string().into(),
                 })?,
             user_rating: row.try_get("user_rating").ok(),
+            attempt: AttemptData::from_row(row)?,
+        })
+    }
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for AttemptData
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            duration_ms: row.try_get("duration_ms")?,
+            mistakes: row.try_get("mistakes")?,
+            hint_used: row.try_get("hint_used")?,
+            moves: row.try_get("moves")?,
         })
     }
 }
@@ -84,7 +146,8 @@ impl PuzzleDatabase {
 
     /// Get the next due review. min_interval allows us to filter out cards with short intervals
     /// (e.g. because they're still in learning), because otherwise they'll show up, possibly
-    /// repeatedly if learning or relearning, before other cards that are due later today.
+    /// repeatedly if learning or relearning, before other cards that are due later today. Cards for
+    /// reported puzzles aren't included, for any user, as cards don't have a user yet.
     pub async fn get_next_review_due(&self, time: DateTime<FixedOffset>, min_interval: Option<Duration>,
         review_order: ReviewOrder) -> DbResult<Option<(Card, Puzzle)>>
     {
@@ -98,6 +161,7 @@ impl PuzzleDatabase {
             WHERE datetime(due) <= datetime(?)
             AND interval >= ?
             AND puzzles.puzzle_id NOT NULL
+            AND cards.puzzle_id NOT IN (SELECT puzzle_id FROM puzzle_reports)
         ");
 
         // Add order by clause based on `review_order`.
@@ -141,6 +205,37 @@ impl PuzzleDatabase {
             .transpose()?)
     }
 
+    /// Get all of the cards.
+    pub async fn get_cards(&self) -> DbResult<Vec<Card>> {
+        let query = sqlx::query("
+            SELECT *
+            FROM cards
+            ORDER BY puzzle_id
+        ");
+
+        Ok(query
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| self.card_from_row(row))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Get all of a user's reviews, oldest first.
+    pub async fn get_user_reviews(&self, user_id: &str) -> DbResult<Vec<Review>> {
+        let query = sqlx::query_as("
+            SELECT *
+            FROM reviews
+            WHERE user_id = ?
+            ORDER BY datetime(date)
+        ");
+
+        Ok(query
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
     /// Update (or create) a card by ID.
     pub async fn update_or_create_card(&mut self, card: &Card) -> DbResult<()> {
         log::info!("Updating card for puzzle {}: {card:?}", card.id);
@@ -167,8 +262,9 @@ impl PuzzleDatabase {
     pub async fn add_review_for_user(&mut self, review: Review) -> DbResult<()>
     {
         let query = sqlx::query("
-            INSERT INTO reviews (user_id, puzzle_id, difficulty, date, user_rating)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO reviews (user_id, puzzle_id, difficulty, date, user_rating, duration_ms,
+                mistakes, hint_used, moves)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
         ");
 
         query
@@ -177,12 +273,141 @@ impl PuzzleDatabase {
             .bind(review.difficulty.to_i64())
             .bind(review.date.to_rfc3339())
             .bind(review.user_rating)
+            .bind(review.attempt.duration_ms)
+            .bind(review.attempt.mistakes)
+            .bind(review.attempt.hint_used)
+            .bind(&review.attempt.moves)
             .execute(&self.pool)
             .await?;
 
+        self.add_seen_puzzle(&review.user_id, &review.puzzle_id).await
+    }
+
+    /// Get the IDs of the puzzles a user has reviewed or has a card for.
+    pub async fn get_reviewed_puzzle_ids(&self, user_id: &str) -> DbResult<HashSet<String>> {
+        let query = sqlx::query("
+            SELECT puzzle_id FROM reviews WHERE user_id = ?
+            UNION SELECT puzzle_id FROM cards
+        ");
+
+        Ok(query
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| row.try_get("puzzle_id"))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Add a batch of reviews and cards that were imported from elsewhere, such as the user's
+    /// lichess puzzle history. The puzzles are marked as seen by the reviews' users.
+    pub async fn add_imported_reviews(&mut self, reviews: &[Review], cards: &[Card]) -> DbResult<()> {
+        const BATCH_SIZE: usize = 500;
+
+        let mut conn = self.pool.begin().await?;
+
+        for batch in reviews.chunks(BATCH_SIZE) {
+            QueryBuilder::<Sqlite>::new("
+                INSERT INTO reviews (user_id, puzzle_id, difficulty, date, user_rating, duration_ms,
+                    mistakes, hint_used, moves) ")
+                .push_values(batch, |mut b, review| {
+                    b.push_bind(&review.user_id)
+                        .push_bind(&review.puzzle_id)
+                        .push_bind(review.difficulty.to_i64())
+                        .push_bind(review.date.to_rfc3339())
+                        .push_bind(review.user_rating)
+                        .push_bind(review.attempt.duration_ms)
+                        .push_bind(review.attempt.mistakes)
+                        .push_bind(review.attempt.hint_used)
+                        .push_bind(&review.attempt.moves);
+                })
+                .build()
+                .execute(&mut *conn)
+                .await?;
+
+            QueryBuilder::<Sqlite>::new("INSERT OR IGNORE INTO seen_puzzles (user_id, puzzle_id) ")
+                .push_values(batch, |mut b, review| {
+                    b.push_bind(&review.user_id).push_bind(&review.puzzle_id);
+                })
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        for batch in cards.chunks(BATCH_SIZE) {
+            QueryBuilder::<Sqlite>::new("
+                INSERT OR REPLACE INTO cards (puzzle_id, due, interval, review_count, ease,
+                    learning_stage) ")
+                .push_values(batch, |mut b, card| {
+                    b.push_bind(&card.id)
+                        .push_bind(card.due.to_rfc3339())
+                        .push_bind(card.interval.num_seconds())
+                        .push_bind(card.review_count)
+                        .push_bind(card.ease)
+                        .push_bind(card.learning_stage);
+                })
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        conn.commit().await?;
+
         Ok(())
     }
 
+    /// Get the durations of a user's clean solves (without mistakes or hints) of puzzles in the
+    /// given rating range, in ascending order.
+    pub async fn get_solve_durations(&self, user_id: &str, min_rating: i64, max_rating: i64)
+        -> DbResult<Vec<i64>>
+    {
+        let query = sqlx::query("
+            SELECT reviews.duration_ms
+            FROM reviews
+            JOIN puzzles ON reviews.puzzle_id = puzzles.puzzle_id
+            WHERE reviews.user_id = ?
+            AND puzzles.rating >= ?
+            AND puzzles.rating <= ?
+            AND reviews.duration_ms IS NOT NULL
+            AND reviews.mistakes = 0
+            AND NOT reviews.hint_used
+            ORDER BY reviews.duration_ms
+        ");
+
+        Ok(query
+            .bind(user_id)
+            .bind(min_rating)
+            .bind(max_rating)
+            .map(|row: SqliteRow| row.try_get("duration_ms"))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Get whether each of a user's most recent reviews was successful (i.e. not 'Again'), most
+    /// recent first.
+    pub async fn get_recent_review_successes(&self, user_id: &str, count: i64)
+        -> DbResult<Vec<bool>>
+    {
+        let query = sqlx::query("
+            SELECT difficulty > 0 AS success
+            FROM reviews
+            WHERE user_id = ?
+            ORDER BY date DESC
+            LIMIT ?
+        ");
+
+        Ok(query
+            .bind(user_id)
+            .bind(count)
+            .map(|row: SqliteRow| row.try_get("success"))
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .collect::<Result<_, _>>()?)
+    }
+
     /// Get a rating history for a user. 
     pub async fn get_user_rating_history(&self, user_id: &str)
         -> DbResult<Vec<(DateTime<FixedOffset>, i64)>>
@@ -255,9 +480,10 @@ impl PuzzleDatabase {
         let query = sqlx::query("
             SELECT count(*) as card_count
             FROM cards
-            WHERE (datetime(due) <= datetime(?)
+            WHERE ((datetime(due) <= datetime(?)
                     AND cards.interval >= ?)
-            OR datetime(due) <= datetime(?)
+                OR datetime(due) <= datetime(?))
+            AND cards.puzzle_id NOT IN (SELECT puzzle_id FROM puzzle_reports)
         ");
 
         let max_learning_interval = crate::srs::INITIAL_INTERVALS.last().map(|d| *d)
@@ -314,6 +540,56 @@ impl PuzzleDatabase {
             .await?)
     }
 
+    /// Get the number of reviews and failed reviews for a user for each puzzle theme and opening,
+    /// in buckets of `rating_bucket_span` rating span like `get_review_score_history`.
+    pub async fn get_review_tag_history(&self, user_id: &str, rating_bucket_span: i64)
+        -> DbResult<Vec<TagReviewBucket>>
+    {
+        let query = sqlx::query("
+            SELECT 'theme' AS kind,
+                puzzle_themes.theme AS tag,
+                puzzle_themes.rating - puzzle_themes.rating % ? AS min_rating,
+                count(*) AS review_count,
+                sum(reviews.difficulty = 0) AS failure_count
+            FROM reviews
+            JOIN puzzle_themes ON reviews.puzzle_id = puzzle_themes.puzzle_id
+            WHERE reviews.user_id = ?
+            GROUP BY tag, min_rating
+
+            UNION ALL
+
+            SELECT 'opening' AS kind,
+                puzzle_opening_tags.opening_tag AS tag,
+                puzzle_opening_tags.rating - puzzle_opening_tags.rating % ? AS min_rating,
+                count(*) AS review_count,
+                sum(reviews.difficulty = 0) AS failure_count
+            FROM reviews
+            JOIN puzzle_opening_tags ON reviews.puzzle_id = puzzle_opening_tags.puzzle_id
+            WHERE reviews.user_id = ?
+            GROUP BY tag, min_rating
+        ");
+
+        Ok(query
+            .bind(rating_bucket_span)
+            .bind(user_id)
+            .bind(rating_bucket_span)
+            .bind(user_id)
+            .try_map(|row: SqliteRow| {
+                Ok(TagReviewBucket {
+                    kind: match row.try_get("kind")? {
+                        "theme" => PuzzleTagKind::Theme,
+                        _ => PuzzleTagKind::Opening,
+                    },
+                    tag: row.try_get("tag")?,
+                    puzzle_rating_min: row.try_get("min_rating")?,
+                    review_count: row.try_get("review_count")?,
+                    failure_count: row.try_get("failure_count")?,
+                })
+            })
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
     /// Get the review forecast for a user.
     pub async fn get_review_forecast(&self, day_end: DateTime<FixedOffset>, max_days: i64)
         -> DbResult<Vec<(i64, i64)>>
@@ -327,6 +603,7 @@ impl PuzzleDatabase {
                 count(ROWID) as reviews_due
             FROM cards
             WHERE day_due < ?
+            AND cards.puzzle_id NOT IN (SELECT puzzle_id FROM puzzle_reports)
             GROUP BY day_due
         ");
 
@@ -345,13 +622,20 @@ impl PuzzleDatabase {
     }
 
     /// Get a list of the most recent reviews for each puzzle, returning a vector of reviews, and the
-    /// total number of unique reviews.
-    pub async fn get_distinct_puzzle_history(&self, user_id: &str, offset: i64, limit: i64)
-        -> DbResult<(Vec<PuzzleHistoryEntry>, i64)>
+    /// total number of unique reviews. If a tag is given, only puzzles the user has given that tag
+    /// are included.
+    pub async fn get_distinct_puzzle_history(&self, user_id: &str, tag: Option<&str>, offset: i64,
+        limit: i64) -> DbResult<(Vec<PuzzleHistoryEntry>, i64)>
     {
         let reviews_query = sqlx::query("
             SELECT *,
-                   count(*) OVER () AS total_count
+                   count(*) OVER () AS total_count,
+                   (SELECT notes FROM puzzle_notes
+                    WHERE puzzle_notes.user_id = row.user_id AND puzzle_notes.puzzle_id = row.puzzle_id)
+                    AS notes,
+                   (SELECT group_concat(tag, ' ') FROM puzzle_user_tags
+                    WHERE puzzle_user_tags.user_id = row.user_id AND puzzle_user_tags.puzzle_id = row.puzzle_id)
+                    AS tags
             FROM (
                 SELECT user_id, puzzle_id, date, difficulty, 0 AS skipped
                 FROM reviews
@@ -361,6 +645,9 @@ impl PuzzleDatabase {
             JOIN puzzles
             ON row.puzzle_id = puzzles.puzzle_id
             WHERE row.user_id = ?
+            AND (? IS NULL OR row.puzzle_id IN (
+                SELECT puzzle_id FROM puzzle_user_tags WHERE user_id = row.user_id AND tag = ?
+            ))
             GROUP BY row.puzzle_id
             ORDER BY max(datetime(date)) DESC
             LIMIT ?
@@ -370,6 +657,8 @@ impl PuzzleDatabase {
         let mut total_count = 0;
         let reviews = reviews_query
             .bind(user_id)
+            .bind(tag)
+            .bind(tag)
             .bind(limit)
             .bind(offset)
             .fetch(&self.pool)
@@ -387,6 +676,8 @@ impl PuzzleDatabase {
                             source: Some(e),
                         }))?,
                     skipped: row.try_get("skipped")?,
+                    notes: PuzzleNotes::from_row(&row)?,
+                    san: None,
                 })
             })
             .try_collect()
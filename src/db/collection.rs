@@ -0,0 +1,170 @@
+use chrono::{DateTime, FixedOffset};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult};
+
+/// A user-defined collection of puzzles.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Collection {
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+    #[serde(serialize_with = "crate::util::serialize_datetime")]
+    pub created: DateTime<FixedOffset>,
+    pub puzzle_count: i64,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Collection
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            created: DateTime::parse_from_rfc3339(row.try_get("created")?)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "created".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            puzzle_count: row.try_get("puzzle_count")?,
+        })
+    }
+}
+
+/// Collection related database implementations.
+impl PuzzleDatabase {
+    /// Get all of a user's collections, in the order they were created.
+    pub async fn get_collections(&self, user_id: &str) -> DbResult<Vec<Collection>> {
+        Ok(sqlx::query_as("
+            SELECT collections.*, count(collection_puzzles.puzzle_id) AS puzzle_count
+            FROM collections
+            LEFT JOIN collection_puzzles ON collection_puzzles.collection_id = collections.id
+            WHERE collections.user_id = ?
+            GROUP BY collections.id
+            ORDER BY collections.id
+        ")
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Get one of a user's collections by ID.
+    pub async fn get_collection(&self, user_id: &str, collection_id: i64)
+        -> DbResult<Option<Collection>>
+    {
+        Ok(sqlx::query_as("
+            SELECT collections.*, count(collection_puzzles.puzzle_id) AS puzzle_count
+            FROM collections
+            LEFT JOIN collection_puzzles ON collection_puzzles.collection_id = collections.id
+            WHERE collections.user_id = ?
+            AND collections.id = ?
+            GROUP BY collections.id
+        ")
+        .bind(user_id)
+        .bind(collection_id)
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Create a new collection, returning its ID.
+    pub async fn create_collection(&mut self, user_id: &str, name: &str,
+        created: DateTime<FixedOffset>) -> DbResult<i64>
+    {
+        let result = sqlx::query("
+            INSERT INTO collections (user_id, name, created)
+            VALUES (?, ?, ?)
+        ")
+        .bind(user_id)
+        .bind(name)
+        .bind(created.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Rename a collection.
+    pub async fn rename_collection(&mut self, collection_id: i64, name: &str) -> DbResult<()> {
+        sqlx::query("
+            UPDATE collections
+            SET name = ?
+            WHERE id = ?
+        ")
+        .bind(name)
+        .bind(collection_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a collection and its list of puzzles.
+    pub async fn delete_collection(&mut self, collection_id: i64) -> DbResult<()> {
+        let mut conn = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM collection_puzzles WHERE collection_id = ?")
+            .bind(collection_id)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query("DELETE FROM collections WHERE id = ?")
+            .bind(collection_id)
+            .execute(&mut *conn)
+            .await?;
+
+        conn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Get the IDs of the puzzles in a collection, in the order they were added.
+    pub async fn get_collection_puzzle_ids(&self, collection_id: i64) -> DbResult<Vec<String>> {
+        Ok(sqlx::query("
+            SELECT puzzle_id
+            FROM collection_puzzles
+            WHERE collection_id = ?
+            ORDER BY position
+        ")
+        .bind(collection_id)
+        .try_map(|row: SqliteRow| row.try_get("puzzle_id"))
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Add a puzzle to the end of a collection, if it isn't already in it.
+    pub async fn add_collection_puzzle(&mut self, collection_id: i64, puzzle_id: &str)
+        -> DbResult<()>
+    {
+        sqlx::query("
+            INSERT OR IGNORE INTO collection_puzzles (collection_id, puzzle_id, position)
+            SELECT ?, ?, coalesce(max(position), 0) + 1
+            FROM collection_puzzles
+            WHERE collection_id = ?
+        ")
+        .bind(collection_id)
+        .bind(puzzle_id)
+        .bind(collection_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove a puzzle from a collection.
+    pub async fn remove_collection_puzzle(&mut self, collection_id: i64, puzzle_id: &str)
+        -> DbResult<()>
+    {
+        sqlx::query("
+            DELETE FROM collection_puzzles
+            WHERE collection_id = ?
+            AND puzzle_id = ?
+        ")
+        .bind(collection_id)
+        .bind(puzzle_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
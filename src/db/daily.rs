@@ -0,0 +1,107 @@
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult};
+
+/// The format dates are stored in.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A user's result for a daily puzzle.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DailyResult {
+    pub puzzle_id: String,
+    pub success: bool,
+    #[serde(serialize_with = "crate::util::serialize_datetime")]
+    pub completed: DateTime<FixedOffset>,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for DailyResult
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            puzzle_id: row.try_get("puzzle_id")?,
+            success: row.try_get("success")?,
+            completed: DateTime::parse_from_rfc3339(row.try_get("completed")?)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "completed".to_string(),
+                    source: e.to_string().into(),
+                })?,
+        })
+    }
+}
+
+/// Daily puzzle related database implementations.
+impl PuzzleDatabase {
+    /// Get the ID of the daily puzzle for the given date, if one has been chosen.
+    pub async fn get_daily_puzzle_id(&self, date: NaiveDate) -> DbResult<Option<String>> {
+        Ok(sqlx::query("SELECT puzzle_id FROM daily_puzzles WHERE date = ?")
+            .bind(date.format(DATE_FORMAT).to_string())
+            .try_map(|row: SqliteRow| row.try_get("puzzle_id"))
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Set the daily puzzle for the given date, if it hasn't already been set.
+    pub async fn set_daily_puzzle_id(&mut self, date: NaiveDate, puzzle_id: &str) -> DbResult<()> {
+        sqlx::query("INSERT OR IGNORE INTO daily_puzzles (date, puzzle_id) VALUES (?, ?)")
+            .bind(date.format(DATE_FORMAT).to_string())
+            .bind(puzzle_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the user's result for the daily puzzle on the given date.
+    pub async fn get_daily_result(&self, user_id: &str, date: NaiveDate)
+        -> DbResult<Option<DailyResult>>
+    {
+        Ok(sqlx::query_as("SELECT * FROM daily_puzzle_results WHERE user_id = ? AND date = ?")
+            .bind(user_id)
+            .bind(date.format(DATE_FORMAT).to_string())
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Add the user's result for the daily puzzle on the given date, if they don't already have
+    /// one.
+    pub async fn add_daily_result(&mut self, user_id: &str, date: NaiveDate, result: &DailyResult)
+        -> DbResult<()>
+    {
+        sqlx::query("
+            INSERT OR IGNORE INTO daily_puzzle_results (user_id, date, puzzle_id, success, completed)
+            VALUES (?, ?, ?, ?, ?)
+        ")
+        .bind(user_id)
+        .bind(date.format(DATE_FORMAT).to_string())
+        .bind(&result.puzzle_id)
+        .bind(result.success)
+        .bind(result.completed.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the dates of the daily puzzles the user has solved, in order.
+    pub async fn get_solved_daily_dates(&self, user_id: &str) -> DbResult<Vec<NaiveDate>> {
+        Ok(sqlx::query("
+            SELECT date
+            FROM daily_puzzle_results
+            WHERE user_id = ?
+            AND success = 1
+            ORDER BY date
+        ")
+        .bind(user_id)
+        .try_map(|row: SqliteRow| {
+            NaiveDate::parse_from_str(row.try_get("date")?, DATE_FORMAT)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "date".to_string(),
+                    source: e.to_string().into(),
+                })
+        })
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
@@ -0,0 +1,201 @@
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult};
+
+/// A user's notes and tags for a puzzle.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PuzzleNotes {
+    pub notes: String,
+    pub tags: Vec<String>,
+}
+
+impl PuzzleNotes {
+    /// Create a set of notes, tidying up the tags so that they don't contain whitespace, and
+    /// removing empty and duplicate tags.
+    pub fn new(notes: &str, tags: &[String]) -> Self {
+        let mut unique_tags: Vec<String> = Vec::new();
+
+        for tag in tags {
+            let tag = tag.split_whitespace().collect::<Vec<_>>().join("-");
+            if !tag.is_empty() && !unique_tags.contains(&tag) {
+                unique_tags.push(tag);
+            }
+        }
+
+        Self {
+            notes: notes.trim().to_string(),
+            tags: unique_tags,
+        }
+    }
+
+    /// Get the notes from a row with `notes` and `tags` columns, where the tags are
+    /// space-separated, like the themes of a puzzle. Both can be null if the user has no notes.
+    pub(super) fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            notes: row.try_get::<Option<String>, _>("notes")?.unwrap_or_default(),
+            tags: row.try_get::<Option<String>, _>("tags")?
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(ToString::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Puzzle notes related database implementations.
+impl PuzzleDatabase {
+    /// Get a user's notes and tags for a puzzle, which are empty if they haven't added any.
+    pub async fn get_puzzle_notes(&self, user_id: &str, puzzle_id: &str) -> DbResult<PuzzleNotes> {
+        let row = sqlx::query("
+            SELECT
+                (SELECT notes FROM puzzle_notes WHERE user_id = ?1 AND puzzle_id = ?2) AS notes,
+                (SELECT group_concat(tag, ' ') FROM puzzle_user_tags WHERE user_id = ?1 AND puzzle_id = ?2)
+                    AS tags
+        ")
+        .bind(user_id)
+        .bind(puzzle_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(PuzzleNotes::from_row(&row)?)
+    }
+
+    /// Replace a user's notes and tags for a puzzle.
+    pub async fn set_puzzle_notes(&mut self, user_id: &str, puzzle_id: &str, notes: &PuzzleNotes)
+        -> DbResult<()>
+    {
+        let mut conn = self.pool.begin().await?;
+
+        if notes.notes.is_empty() {
+            sqlx::query("DELETE FROM puzzle_notes WHERE user_id = ? AND puzzle_id = ?")
+                .bind(user_id)
+                .bind(puzzle_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        else {
+            sqlx::query("INSERT OR REPLACE INTO puzzle_notes (user_id, puzzle_id, notes) VALUES (?, ?, ?)")
+                .bind(user_id)
+                .bind(puzzle_id)
+                .bind(&notes.notes)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        sqlx::query("DELETE FROM puzzle_user_tags WHERE user_id = ? AND puzzle_id = ?")
+            .bind(user_id)
+            .bind(puzzle_id)
+            .execute(&mut *conn)
+            .await?;
+
+        for tag in &notes.tags {
+            sqlx::query("INSERT OR IGNORE INTO puzzle_user_tags (user_id, puzzle_id, tag) VALUES (?, ?, ?)")
+                .bind(user_id)
+                .bind(puzzle_id)
+                .bind(tag)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        conn.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::db::{PuzzleDatabase, Puzzle, PuzzleFilter, PuzzleNotes, PuzzleSearch};
+    use crate::srs::SrsConfig;
+
+    fn puzzle(puzzle_id: &str, rating: i64) -> Puzzle {
+        Puzzle {
+            puzzle_id: puzzle_id.to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating,
+            rating_deviation: 75,
+            popularity: 90,
+            number_of_plays: 1000,
+            themes: vec![],
+            game_url: String::new(),
+            opening_tags: vec![],
+            source: "lichess".to_string(),
+        }
+    }
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_new_notes() {
+        let notes = PuzzleNotes::new("  Missed the defence \n", &tags(&["missed defence", "", "calculation",
+            "missed-defence"]));
+        assert_eq!(notes.notes, "Missed the defence");
+        assert_eq!(notes.tags, tags(&["missed-defence", "calculation"]));
+    }
+
+    #[tokio::test]
+    async fn test_puzzle_notes() {
+        let mut db = PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default())
+            .await.unwrap();
+        db.add_puzzles(&vec![puzzle("00001", 1000), puzzle("00002", 1500), puzzle("00003", 2000)])
+            .await.unwrap();
+
+        // Puzzles without notes have empty ones.
+        assert_eq!(db.get_puzzle_notes("a", "00001").await.unwrap(), PuzzleNotes::default());
+
+        let notes = PuzzleNotes::new("Missed the defence", &tags(&["calculation", "missed-defence"]));
+        db.set_puzzle_notes("a", "00001", &notes).await.unwrap();
+        db.set_puzzle_notes("a", "00002", &PuzzleNotes::new("", &tags(&["calculation"]))).await.unwrap();
+        db.set_puzzle_notes("b", "00003", &PuzzleNotes::new("", &tags(&["calculation"]))).await.unwrap();
+
+        let mut saved = db.get_puzzle_notes("a", "00001").await.unwrap();
+        saved.tags.sort();
+        assert_eq!(saved, notes);
+        assert_eq!(db.get_puzzle_notes("b", "00001").await.unwrap(), PuzzleNotes::default());
+
+        // The user's tags work as a filter, just for their own tags.
+        let reader = db.clone();
+        let tagged = |user_id: &'static str, user_tags: &[&str]| {
+            let db = reader.clone();
+            let filter = PuzzleFilter { user_tags: tags(user_tags), ..Default::default() };
+            async move {
+                let search = PuzzleSearch { filter, ..Default::default() };
+                let mut puzzle_ids: Vec<_> = db.search_puzzles(user_id, &search, 0, 100).await.unwrap()
+                    .into_iter()
+                    .map(|result| result.puzzle.puzzle_id)
+                    .collect();
+                puzzle_ids.sort();
+                puzzle_ids
+            }
+        };
+        assert_eq!(tagged("a", &["calculation"]).await, ["00001", "00002"]);
+        assert_eq!(tagged("a", &["missed-defence", "other"]).await, ["00001"]);
+        assert_eq!(tagged("b", &["calculation"]).await, ["00003"]);
+        assert!(tagged("b", &["missed-defence"]).await.is_empty());
+
+        // Including for new puzzles, which are picked from the tagged puzzles in the rating range.
+        let filter = PuzzleFilter { user_tags: tags(&["calculation"]), ..Default::default() };
+        for _ in 0..10 {
+            let puzzle = db.get_random_unseen_puzzle("a", 1200, 2000, &filter).await.unwrap().unwrap();
+            assert_eq!(puzzle.puzzle_id, "00002");
+        }
+        db.add_seen_puzzle("a", "00002").await.unwrap();
+        assert!(db.get_random_unseen_puzzle("a", 1200, 2000, &filter).await.unwrap().is_none());
+
+        // Replacing the notes replaces the tags too, and empty notes are removed.
+        db.set_puzzle_notes("a", "00001", &PuzzleNotes::new(" ", &tags(&["endgame"]))).await.unwrap();
+        assert_eq!(db.get_puzzle_notes("a", "00001").await.unwrap(), PuzzleNotes::new("", &tags(&["endgame"])));
+        assert_eq!(tagged("a", &["calculation"]).await, ["00002"]);
+
+        db.set_puzzle_notes("a", "00001", &PuzzleNotes::default()).await.unwrap();
+        assert_eq!(db.get_puzzle_notes("a", "00001").await.unwrap(), PuzzleNotes::default());
+        assert!(tagged("a", &["endgame"]).await.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, FixedOffset};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult};
+
+/// A pack of puzzles imported from a file.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzlePack {
+    /// The source of the pack's puzzles, e.g. "pack-club-puzzles".
+    pub source: String,
+    pub name: String,
+    /// The format it was imported from, e.g. "pgn".
+    pub format: String,
+    #[serde(serialize_with = "crate::util::serialize_datetime")]
+    pub imported: DateTime<FixedOffset>,
+    pub puzzle_count: i64,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for PuzzlePack
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            source: row.try_get("source")?,
+            name: row.try_get("name")?,
+            format: row.try_get("format")?,
+            imported: DateTime::parse_from_rfc3339(row.try_get("imported")?)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "imported".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            puzzle_count: row.try_get("puzzle_count")?,
+        })
+    }
+}
+
+/// Puzzle pack related database implementations.
+impl PuzzleDatabase {
+    /// Get all of the puzzle packs, in order of name.
+    pub async fn get_puzzle_packs(&self) -> DbResult<Vec<PuzzlePack>> {
+        Ok(sqlx::query_as("
+            SELECT puzzle_packs.*,
+                (SELECT count(*) FROM puzzles WHERE puzzles.source = puzzle_packs.source) AS puzzle_count
+            FROM puzzle_packs
+            ORDER BY name
+        ")
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Add a puzzle pack, or update it if one with the same source has been imported before.
+    pub async fn set_puzzle_pack(&self, source: &str, name: &str, format: &str,
+        imported: DateTime<FixedOffset>) -> DbResult<()>
+    {
+        sqlx::query("
+            INSERT INTO puzzle_packs (source, name, format, imported)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (source) DO UPDATE SET
+                name = excluded.name,
+                format = excluded.format,
+                imported = excluded.imported
+        ")
+        .bind(source)
+        .bind(name)
+        .bind(format)
+        .bind(imported.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
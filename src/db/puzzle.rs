@@ -1,7 +1,11 @@
-use futures::TryStreamExt;
+use std::sync::Arc;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
 use sqlx::{Row, Sqlite, QueryBuilder, sqlite::SqliteRow};
 
 use crate::db::{PuzzleDatabase, DbResult};
+use crate::lichess::LICHESS_SOURCE;
 
 /// A puzzle record from the db.
 #[derive(Debug, Clone, serde::Serialize)]
@@ -16,6 +20,179 @@ pub struct Puzzle {
     pub themes: Vec<String>,
     pub game_url: String,
     pub opening_tags: Vec<String>,
+    /// Where the puzzle came from, e.g. "lichess" or "personal".
+    pub source: String,
+}
+
+/// The number of puzzles with each rating in one of the indexes new puzzles are picked from, in
+/// rating order.
+pub type RatingCounts = Arc<Vec<(i64, i64)>>;
+
+/// A filter for selecting new puzzles. Puzzles must have at least one of `include_themes`,
+/// `opening_tags` and the user's own `user_tags` (if they aren't empty), and none of
+/// `exclude_themes`. If `sources` isn't empty, they must be from one of them, e.g. "lichess" or a
+/// puzzle pack. New puzzles are only picked from the lichess puzzles if it's empty, as puzzle
+/// packs are only used when they're chosen.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PuzzleFilter {
+    pub include_themes: Vec<String>,
+    pub exclude_themes: Vec<String>,
+    pub opening_tags: Vec<String>,
+    pub min_popularity: Option<i64>,
+    pub min_plays: Option<i64>,
+    pub max_rating_deviation: Option<i64>,
+    pub sources: Vec<String>,
+    pub user_tags: Vec<String>,
+}
+
+impl PuzzleFilter {
+    /// The filter with its sources defaulting to just the lichess puzzles, for picking new puzzles.
+    pub fn with_default_sources(&self) -> Self {
+        let mut filter = self.clone();
+        if filter.sources.is_empty() {
+            filter.sources.push(LICHESS_SOURCE.to_string());
+        }
+        filter
+    }
+
+    /// Push the filter's conditions onto a query selecting from the puzzles table, as a series of
+    /// `AND` clauses. The user's tags are those of the given user.
+    pub(super) fn push_conditions<'a>(&'a self, query_builder: &mut QueryBuilder<'a, Sqlite>, user_id: &'a str,
+        min_rating: i64, max_rating: i64)
+    {
+        if let Some(min_popularity) = self.min_popularity {
+            query_builder.push("\nAND puzzles.popularity >= ").push_bind(min_popularity);
+        }
+
+        if let Some(min_plays) = self.min_plays {
+            query_builder.push("\nAND puzzles.number_of_plays >= ").push_bind(min_plays);
+        }
+
+        if let Some(max_rating_deviation) = self.max_rating_deviation {
+            query_builder.push("\nAND puzzles.rating_deviation <= ").push_bind(max_rating_deviation);
+        }
+
+        // The rating range is repeated in the subqueries so that they only need to look at the
+        // matching section of the (theme, rating) indexes.
+        if !self.include_themes.is_empty() {
+            query_builder.push("\nAND puzzles.puzzle_id IN (SELECT puzzle_id FROM puzzle_themes WHERE theme IN (");
+            let mut separated = query_builder.separated(", ");
+            for theme in &self.include_themes {
+                separated.push_bind(theme);
+            }
+            query_builder.push(") AND rating >= ").push_bind(min_rating)
+                .push(" AND rating <= ").push_bind(max_rating)
+                .push(")");
+        }
+
+        if !self.opening_tags.is_empty() {
+            query_builder.push(concat!("\nAND puzzles.puzzle_id IN (SELECT puzzle_id FROM puzzle_opening_tags ",
+                "WHERE opening_tag IN ("));
+            let mut separated = query_builder.separated(", ");
+            for opening_tag in &self.opening_tags {
+                separated.push_bind(opening_tag);
+            }
+            query_builder.push(") AND rating >= ").push_bind(min_rating)
+                .push(" AND rating <= ").push_bind(max_rating)
+                .push(")");
+        }
+
+        if !self.user_tags.is_empty() {
+            query_builder.push("\nAND puzzles.puzzle_id IN (SELECT puzzle_id FROM puzzle_user_tags WHERE user_id = ")
+                .push_bind(user_id)
+                .push(" AND tag IN (");
+            let mut separated = query_builder.separated(", ");
+            for tag in &self.user_tags {
+                separated.push_bind(tag);
+            }
+            query_builder.push("))");
+        }
+
+        if !self.sources.is_empty() {
+            query_builder.push("\nAND puzzles.source IN (");
+            let mut separated = query_builder.separated(", ");
+            for source in &self.sources {
+                separated.push_bind(source);
+            }
+            query_builder.push(")");
+        }
+
+        if !self.exclude_themes.is_empty() {
+            query_builder.push(concat!("\nAND NOT EXISTS (SELECT 1 FROM puzzle_themes ",
+                "WHERE puzzle_themes.puzzle_id = puzzles.puzzle_id AND theme IN ("));
+            let mut separated = query_builder.separated(", ");
+            for theme in &self.exclude_themes {
+                separated.push_bind(theme);
+            }
+            query_builder.push("))");
+        }
+    }
+}
+
+/// The table a random puzzle is selected from, which needs an index on (rating) or (key, rating) so
+/// that it can be walked in (rating, rowid) order.
+enum PuzzleSource<'a> {
+    Source(&'a str),
+    Theme(&'a str),
+    OpeningTag(&'a str),
+}
+
+impl<'a> PuzzleSource<'a> {
+    /// The name of the table.
+    fn table(&self) -> &'static str {
+        match self {
+            PuzzleSource::Source(_) => "puzzles",
+            PuzzleSource::Theme(_) => "puzzle_themes",
+            PuzzleSource::OpeningTag(_) => "puzzle_opening_tags",
+        }
+    }
+
+    /// The key of the source's rating counts in the cache.
+    fn cache_key(&self) -> String {
+        match self {
+            PuzzleSource::Source(source) => format!("source:{source}"),
+            PuzzleSource::Theme(theme) => format!("theme:{theme}"),
+            PuzzleSource::OpeningTag(opening_tag) => format!("opening_tag:{opening_tag}"),
+        }
+    }
+
+    /// Push the FROM clause and the start of the WHERE clause for selecting from just the source's
+    /// index, without joining the puzzles table.
+    fn push_index_from<'b>(&self, query_builder: &mut QueryBuilder<'b, Sqlite>) where 'a: 'b {
+        match self {
+            PuzzleSource::Source(_) => self.push_from(query_builder),
+            PuzzleSource::Theme(theme) => {
+                query_builder.push("\nFROM puzzle_themes\nWHERE puzzle_themes.theme = ").push_bind(*theme);
+            },
+            PuzzleSource::OpeningTag(opening_tag) => {
+                query_builder.push("\nFROM puzzle_opening_tags\nWHERE puzzle_opening_tags.opening_tag = ")
+                    .push_bind(*opening_tag);
+            },
+        }
+    }
+
+    /// Push the FROM clause and the start of the WHERE clause for selecting puzzles from this
+    /// source.
+    fn push_from<'b>(&self, query_builder: &mut QueryBuilder<'b, Sqlite>) where 'a: 'b {
+        match self {
+            PuzzleSource::Source(source) => {
+                query_builder.push("\nFROM puzzles\nWHERE puzzles.source = ").push_bind(*source);
+            },
+            PuzzleSource::Theme(theme) => {
+                query_builder.push(concat!("\nFROM puzzle_themes\n",
+                    "JOIN puzzles ON puzzles.puzzle_id = puzzle_themes.puzzle_id\n",
+                    "WHERE puzzle_themes.theme = "))
+                    .push_bind(*theme);
+            },
+            PuzzleSource::OpeningTag(opening_tag) => {
+                query_builder.push(concat!("\nFROM puzzle_opening_tags\n",
+                    "JOIN puzzles ON puzzles.puzzle_id = puzzle_opening_tags.puzzle_id\n",
+                    "WHERE puzzle_opening_tags.opening_tag = "))
+                    .push_bind(*opening_tag);
+            },
+        }
+    }
 }
 
 impl<'r> sqlx::FromRow<'r, SqliteRow> for Puzzle
@@ -34,6 +211,7 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for Puzzle
             game_url: row.try_get("game_url")?,
             opening_tags: row.try_get::<String, _>("opening_tags")?
                 .split_whitespace().map(ToString::to_string).collect(),
+            source: row.try_get("source")?,
         })
     }
 }
@@ -66,6 +244,18 @@ impl PuzzleDatabase {
             .unwrap_or(0))
     }
 
+    /// Get the (lowest, highest) puzzle rating. This is cached until puzzles are next added.
+    pub async fn get_puzzle_rating_range(&self) -> DbResult<(i64, i64)> {
+        if let Some(range) = *self.puzzle_rating_range.read().unwrap() {
+            return Ok(range);
+        }
+
+        let range = (self.get_min_puzzle_rating().await?, self.get_max_puzzle_rating().await?);
+        *self.puzzle_rating_range.write().unwrap() = Some(range);
+
+        Ok(range)
+    }
+
     /// Get the highest puzzle rating.
     pub async fn get_max_puzzle_rating(&self) -> DbResult<i64> {
         // https://stackoverflow.com/questions/11515165/sqlite3-select-min-max-together-is-much-slower-than-select-them-separately
@@ -77,6 +267,28 @@ impl PuzzleDatabase {
             .unwrap_or(0))
     }
 
+    /// Get the (lowest, highest) rating of the puzzles from the given sources, if there are any.
+    pub async fn get_source_rating_range(&self, sources: &[String]) -> DbResult<Option<(i64, i64)>> {
+        let mut min_rating: Option<i64> = None;
+        let mut max_rating: Option<i64> = None;
+
+        // One source at a time so the (source, rating) index can be used.
+        for source in sources {
+            let (min, max) = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(
+                    "SELECT min(rating), max(rating) FROM puzzles WHERE source = ?")
+                .bind(source)
+                .fetch_one(&self.pool)
+                .await?;
+
+            if let (Some(min), Some(max)) = (min, max) {
+                min_rating = Some(min_rating.map_or(min, |rating| i64::min(rating, min)));
+                max_rating = Some(max_rating.map_or(max, |rating| i64::max(rating, max)));
+            }
+        }
+
+        Ok(min_rating.zip(max_rating))
+    }
+
     /// Add a batch of puzzles to the database.
     pub async fn add_puzzles(&mut self, puzzles: &Vec<Puzzle>) -> DbResult<()> {
         const BATCH_SIZE: usize = 500;
@@ -94,7 +306,14 @@ impl PuzzleDatabase {
                 number_of_plays INTEGER,
                 themes TEXT,
                 game_url TEXT,
-                opening_tags TEXT
+                opening_tags TEXT,
+                source TEXT
+            );
+
+            CREATE TEMPORARY TABLE IF NOT EXISTS lichess_puzzle_tags (
+                puzzle_id TEXT,
+                kind TEXT,
+                tag TEXT
             );
         ").execute(&mut *conn).await?;
 
@@ -104,7 +323,7 @@ impl PuzzleDatabase {
         // creating it every time, but building the query is much faster.
         let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
             "INSERT OR REPLACE INTO lichess_puzzles (puzzle_id, fen, moves, rating, rating_deviation,
-            popularity, number_of_plays, themes, game_url, opening_tags) "
+            popularity, number_of_plays, themes, game_url, opening_tags, source) "
             );
 
         for batch in puzzles.chunks(BATCH_SIZE) {
@@ -120,7 +339,34 @@ impl PuzzleDatabase {
                     .push_bind(puzzle.number_of_plays)
                     .push_bind(puzzle.themes.join(" "))
                     .push_bind(&puzzle.game_url)
-                    .push_bind(puzzle.opening_tags.join(" "));
+                    .push_bind(puzzle.opening_tags.join(" "))
+                    .push_bind(&puzzle.source);
+            });
+
+            query_builder
+                .build()
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        // The puzzles' themes and opening tags, one per row.
+        let tags: Vec<(&str, &str, &str)> = puzzles.iter()
+            .flat_map(|puzzle| {
+                let themes = puzzle.themes.iter().map(|theme| ("theme", theme));
+                let opening_tags = puzzle.opening_tags.iter().map(|tag| ("opening_tag", tag));
+                themes.chain(opening_tags)
+                    .map(|(kind, tag)| (puzzle.puzzle_id.as_str(), kind, tag.as_str()))
+            })
+            .collect();
+
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "INSERT INTO lichess_puzzle_tags (puzzle_id, kind, tag) ");
+
+        for batch in tags.chunks(BATCH_SIZE) {
+            query_builder.reset();
+
+            query_builder.push_values(batch, |mut b, (puzzle_id, kind, tag)| {
+                b.push_bind(*puzzle_id).push_bind(*kind).push_bind(*tag);
             });
 
             query_builder
@@ -129,18 +375,119 @@ impl PuzzleDatabase {
                 .await?;
         }
 
+        // Leave out the puzzles that haven't changed, which is most of them when the lichess puzzle
+        // database is imported again, so that their theme and opening tag entries aren't rewritten.
+        // Then replace the other puzzles' entries in the theme and opening tag tables, and update
+        // the puzzles. Puzzles that were marked as removed from the lichess puzzle database are
+        // back if they're being added again.
         sqlx::query("
-            INSERT OR REPLACE INTO puzzles
-            SELECT * FROM lichess_puzzles;
+            INSERT OR IGNORE INTO imported_lichess_puzzles (puzzle_id)
+            SELECT puzzle_id FROM lichess_puzzles WHERE source = 'lichess';
+
+            DELETE FROM lichess_puzzles
+            WHERE EXISTS (
+                SELECT 1 FROM puzzles
+                WHERE puzzles.puzzle_id = lichess_puzzles.puzzle_id
+                    AND puzzles.fen IS lichess_puzzles.fen
+                    AND puzzles.moves IS lichess_puzzles.moves
+                    AND puzzles.rating IS lichess_puzzles.rating
+                    AND puzzles.rating_deviation IS lichess_puzzles.rating_deviation
+                    AND puzzles.popularity IS lichess_puzzles.popularity
+                    AND puzzles.number_of_plays IS lichess_puzzles.number_of_plays
+                    AND puzzles.themes IS lichess_puzzles.themes
+                    AND puzzles.game_url IS lichess_puzzles.game_url
+                    AND puzzles.opening_tags IS lichess_puzzles.opening_tags
+                    AND puzzles.source IS lichess_puzzles.source
+                    AND puzzles.removed = 0
+            );
+
+            DELETE FROM puzzle_themes
+            WHERE puzzle_id IN (SELECT puzzle_id FROM lichess_puzzles);
+
+            DELETE FROM puzzle_opening_tags
+            WHERE puzzle_id IN (SELECT puzzle_id FROM lichess_puzzles);
+
+            INSERT OR IGNORE INTO puzzle_themes (puzzle_id, theme, rating)
+            SELECT lichess_puzzles.puzzle_id, lichess_puzzle_tags.tag, lichess_puzzles.rating
+            FROM lichess_puzzle_tags
+            JOIN lichess_puzzles ON lichess_puzzles.puzzle_id = lichess_puzzle_tags.puzzle_id
+            WHERE lichess_puzzle_tags.kind = 'theme' AND lichess_puzzle_tags.tag <> '';
+
+            INSERT OR IGNORE INTO puzzle_opening_tags (puzzle_id, opening_tag, rating)
+            SELECT lichess_puzzles.puzzle_id, lichess_puzzle_tags.tag, lichess_puzzles.rating
+            FROM lichess_puzzle_tags
+            JOIN lichess_puzzles ON lichess_puzzles.puzzle_id = lichess_puzzle_tags.puzzle_id
+            WHERE lichess_puzzle_tags.kind = 'opening_tag' AND lichess_puzzle_tags.tag <> '';
+
+            INSERT INTO puzzles (puzzle_id, fen, moves, rating, rating_deviation,
+                popularity, number_of_plays, themes, game_url, opening_tags, source)
+            SELECT * FROM lichess_puzzles WHERE true
+            ON CONFLICT (puzzle_id) DO UPDATE SET
+                fen = excluded.fen,
+                moves = excluded.moves,
+                rating = excluded.rating,
+                rating_deviation = excluded.rating_deviation,
+                popularity = excluded.popularity,
+                number_of_plays = excluded.number_of_plays,
+                themes = excluded.themes,
+                game_url = excluded.game_url,
+                opening_tags = excluded.opening_tags,
+                source = excluded.source,
+                removed = 0;
 
             DELETE FROM lichess_puzzles;
+            DELETE FROM lichess_puzzle_tags;
         ").execute(&mut *conn).await?;
 
         conn.commit().await?;
 
+        // The rating range and counts may have changed.
+        *self.puzzle_rating_range.write().unwrap() = None;
+        self.rating_counts.write().unwrap().clear();
+
+        Ok(())
+    }
+
+    /// Forget which lichess puzzles have been seen by the last import of the lichess puzzle
+    /// database, before starting a new one.
+    pub async fn clear_imported_lichess_puzzles(&self) -> DbResult<()> {
+        sqlx::query("DELETE FROM imported_lichess_puzzles")
+            .execute(&self.pool)
+            .await?;
+
         Ok(())
     }
 
+    /// Mark the lichess puzzles that weren't seen by a finished import of the lichess puzzle
+    /// database as removed, as they aren't in it anymore. They're kept so that the user's cards
+    /// for them still work. Returns the number of puzzles that were newly removed.
+    pub async fn mark_removed_lichess_puzzles(&self) -> DbResult<u64> {
+        let mut conn = self.pool.begin().await?;
+
+        let removed = sqlx::query("
+                UPDATE puzzles
+                SET removed = 1
+                WHERE source = 'lichess'
+                AND removed = 0
+                AND puzzle_id NOT IN (SELECT puzzle_id FROM imported_lichess_puzzles)
+            ")
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+        sqlx::query("DELETE FROM imported_lichess_puzzles")
+            .execute(&mut *conn)
+            .await?;
+
+        conn.commit().await?;
+
+        // The rating range and counts may have changed.
+        *self.puzzle_rating_range.write().unwrap() = None;
+        self.rating_counts.write().unwrap().clear();
+
+        Ok(removed)
+    }
+
     /// Get a puzzle by ID.
     pub async fn get_puzzle_by_id(&self, puzzle_id: &str) -> DbResult<Option<Puzzle>>
     {
@@ -158,26 +505,638 @@ impl PuzzleDatabase {
             .await?)
     }
 
-    /// Get a random set of puzzles by rating.
-    pub async fn get_puzzles_by_rating(&self, min_rating: i64, max_rating: i64, max_puzzles: i64)
-        -> DbResult<Vec<Puzzle>>
+    /// Get the puzzles with the given IDs, leaving out any that don't exist.
+    pub async fn get_puzzles_by_ids(&self, puzzle_ids: &[&str]) -> DbResult<Vec<Puzzle>> {
+        const BATCH_SIZE: usize = 500;
+
+        let mut puzzles = Vec::new();
+        for batch in puzzle_ids.chunks(BATCH_SIZE) {
+            let mut query_builder: QueryBuilder<Sqlite> =
+                QueryBuilder::new("SELECT * FROM puzzles WHERE puzzle_id IN (");
+
+            let mut separated = query_builder.separated(", ");
+            for puzzle_id in batch {
+                separated.push_bind(*puzzle_id);
+            }
+            separated.push_unseparated(")");
+
+            puzzles.extend(query_builder.build_query_as().fetch_all(&self.pool).await?);
+        }
+
+        Ok(puzzles)
+    }
+
+    /// Get up to `max_puzzles` different random puzzles in the given rating range, matching the
+    /// given filter, that the user hasn't seen yet (see `get_random_unseen_puzzle`). There can be
+    /// fewer if there aren't enough unseen puzzles, or the same ones keep being picked.
+    pub async fn get_puzzles_by_rating(&self, user_id: &str, min_rating: i64, max_rating: i64,
+        max_puzzles: usize, filter: &PuzzleFilter) -> DbResult<Vec<Puzzle>>
     {
-        log::info!("Getting puzzles..");
+        const ATTEMPTS_PER_PUZZLE: usize = 2;
 
-        let query = sqlx::query_as("
-            SELECT *
-            FROM puzzles
-            WHERE rating >= ?
-            AND rating <= ?
-            ORDER BY random()
-            LIMIT ?");
+        let mut puzzles: Vec<Puzzle> = Vec::new();
+        for _ in 0..max_puzzles * ATTEMPTS_PER_PUZZLE {
+            if puzzles.len() >= max_puzzles {
+                break;
+            }
 
-        Ok(query
-            .bind(min_rating)
-            .bind(max_rating)
-            .bind(max_puzzles)
-            .fetch(&self.pool)
-            .try_collect()
+            let Some(puzzle) = self.get_random_unseen_puzzle(user_id, min_rating, max_rating, filter).await? else {
+                break;
+            };
+
+            if !puzzles.iter().any(|p| p.puzzle_id == puzzle.puzzle_id) {
+                puzzles.push(puzzle);
+            }
+        }
+
+        Ok(puzzles)
+    }
+
+    /// Get a random puzzle in the given rating range, matching the given filter, that the user
+    /// hasn't seen yet.
+    ///
+    /// Rather than sorting the whole rating range randomly, we pick a random puzzle in the range
+    /// (see `get_random_start`) and walk the rating index from there to the first unseen puzzle,
+    /// wrapping around to the bottom of the range if there isn't one. If the filter includes themes
+    /// or opening tags, their (key, rating) index is walked instead, so that we don't have to skip
+    /// over all the puzzles that don't match, and otherwise the (source, rating) index of the
+    /// sources.
+    ///
+    /// Finding the start is O(log n), but the walk steps over the puzzles that the user has seen
+    /// or that don't match the rest of the filter (excluded themes, the popularity, plays and
+    /// rating deviation limits, or the opening tags and sources when themes are walked) one at a
+    /// time, as they aren't in the index being walked. So it's only O(log n) overall when most
+    /// puzzles in the index match, and a narrow filter over a wide index can walk a long way.
+    ///
+    /// If the filter includes the user's own tags, the puzzles with them are picked from directly
+    /// instead, as there are only ever a few of them.
+    pub async fn get_random_unseen_puzzle(&self, user_id: &str, min_rating: i64, max_rating: i64,
+        filter: &PuzzleFilter) -> DbResult<Option<Puzzle>>
+    {
+        log::info!("Getting random unseen puzzle..");
+
+        if min_rating > max_rating {
+            return Ok(None);
+        }
+
+        if !filter.user_tags.is_empty() {
+            return self.get_random_unseen_tagged_puzzle(user_id, min_rating, max_rating, filter).await;
+        }
+
+        // Puzzles only need to match one of the included themes (or opening tags, or sources), so
+        // we try them in a random order, and leave them out of the rest of the filter.
+        let mut filter = filter.with_default_sources();
+        let mut themes = std::mem::take(&mut filter.include_themes);
+        let mut opening_tags = match themes.is_empty() {
+            true => std::mem::take(&mut filter.opening_tags),
+            false => Vec::new(),
+        };
+        let mut source_names = match themes.is_empty() && opening_tags.is_empty() {
+            true => std::mem::take(&mut filter.sources),
+            false => Vec::new(),
+        };
+
+        {
+            let mut rng = rand::thread_rng();
+            themes.shuffle(&mut rng);
+            opening_tags.shuffle(&mut rng);
+            source_names.shuffle(&mut rng);
+        }
+
+        let sources: Vec<_> = if !themes.is_empty() {
+            themes.iter().map(|theme| PuzzleSource::Theme(theme)).collect()
+        }
+        else if !opening_tags.is_empty() {
+            opening_tags.iter().map(|opening_tag| PuzzleSource::OpeningTag(opening_tag)).collect()
+        }
+        else {
+            source_names.iter().map(|source| PuzzleSource::Source(source)).collect()
+        };
+
+        for source in sources {
+            let Some(start) = self.get_random_start(&source, min_rating, max_rating).await? else {
+                continue;
+            };
+
+            // Search from the start to the top of the range, and then wrap around to the bottom.
+            let puzzle = match self.find_unseen_puzzle(user_id, &source, &filter, start, None,
+                min_rating, max_rating).await?
+            {
+                Some(puzzle) => Some(puzzle),
+                None => self.find_unseen_puzzle(user_id, &source, &filter, (min_rating, 0),
+                    Some(start), min_rating, max_rating).await?,
+            };
+
+            if puzzle.is_some() {
+                return Ok(puzzle);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get a random puzzle with one of the user's tags in the filter, in the given rating range,
+    /// matching the rest of the filter, that the user hasn't seen yet.
+    async fn get_random_unseen_tagged_puzzle(&self, user_id: &str, min_rating: i64, max_rating: i64,
+        filter: &PuzzleFilter) -> DbResult<Option<Puzzle>>
+    {
+        let filter = filter.with_default_sources();
+
+        let mut query_builder = QueryBuilder::new("SELECT puzzles.*\nFROM puzzles\nWHERE puzzles.rating >= ");
+        query_builder.push_bind(min_rating)
+            .push("\nAND puzzles.rating <= ").push_bind(max_rating);
+
+        filter.push_conditions(&mut query_builder, user_id, min_rating, max_rating);
+
+        query_builder.push(concat!("\nAND NOT EXISTS (SELECT 1 FROM seen_puzzles ",
+                "WHERE seen_puzzles.user_id = "))
+            .push_bind(user_id)
+            .push(" AND seen_puzzles.puzzle_id = puzzles.puzzle_id)")
+            .push("\nAND NOT EXISTS (SELECT 1 FROM puzzle_reports WHERE puzzle_reports.user_id = ")
+            .push_bind(user_id)
+            .push(" AND puzzle_reports.puzzle_id = puzzles.puzzle_id)")
+            .push("\nAND puzzles.removed = 0")
+            .push("\nORDER BY random()\nLIMIT 1");
+
+        Ok(query_builder
+            .build_query_as()
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Pick a random puzzle from the source in the rating range, and get its (rating, rowid) to
+    /// start searching for an unseen puzzle from. The rating is picked weighted by the number of
+    /// puzzles with it, and then the puzzle by its position within the rating, so that every puzzle
+    /// is as likely as any other, rather than the ones after gaps in the ratings or rowids being
+    /// more likely. Returns None if there aren't any puzzles in the range.
+    async fn get_random_start(&self, source: &PuzzleSource<'_>, min_rating: i64, max_rating: i64)
+        -> DbResult<Option<(i64, i64)>>
+    {
+        let rating_counts = self.get_rating_counts(source).await?;
+        let start = rating_counts.partition_point(|(rating, _)| *rating < min_rating);
+        let end = rating_counts.partition_point(|(rating, _)| *rating <= max_rating);
+        let rating_counts = &rating_counts[start..end];
+
+        let total: i64 = rating_counts.iter().map(|(_, count)| count).sum();
+        if total == 0 {
+            return Ok(None);
+        }
+
+        let mut offset = rand::thread_rng().gen_range(0..total);
+        let mut rating = min_rating;
+        for &(r, count) in rating_counts {
+            rating = r;
+            if offset < count {
+                break;
+            }
+            offset -= count;
+        }
+
+        let table = source.table();
+        let mut query_builder = QueryBuilder::new(format!("SELECT {table}.rowid"));
+        source.push_index_from(&mut query_builder);
+        query_builder.push(format!("\nAND {table}.rating = ")).push_bind(rating)
+            .push(format!("\nORDER BY {table}.rowid\nLIMIT 1 OFFSET ")).push_bind(offset);
+
+        let rowid: Option<i64> = query_builder
+            .build_query_scalar()
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(Some((rating, rowid.unwrap_or(0))))
+    }
+
+    /// Get the number of puzzles with each rating from a source, in rating order. These are cached
+    /// until puzzles are next added.
+    async fn get_rating_counts(&self, source: &PuzzleSource<'_>) -> DbResult<RatingCounts> {
+        let cache_key = source.cache_key();
+        if let Some(rating_counts) = self.rating_counts.read().unwrap().get(&cache_key) {
+            return Ok(rating_counts.clone());
+        }
+
+        let table = source.table();
+        let mut query_builder = QueryBuilder::new(format!("SELECT {table}.rating, count(*)"));
+        source.push_index_from(&mut query_builder);
+        query_builder.push(format!("\nGROUP BY {table}.rating\nORDER BY {table}.rating"));
+
+        let rating_counts: RatingCounts = Arc::new(query_builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await?);
+
+        self.rating_counts.write().unwrap().insert(cache_key, rating_counts.clone());
+
+        Ok(rating_counts)
+    }
+
+    /// Find the first puzzle from the given source, from the (rating, rowid) `from` up to `to`
+    /// (exclusive) or the max rating, that matches the filter and hasn't been seen by the user.
+    #[allow(clippy::too_many_arguments)]
+    async fn find_unseen_puzzle(&self, user_id: &str, source: &PuzzleSource<'_>,
+        filter: &PuzzleFilter, from: (i64, i64), to: Option<(i64, i64)>, min_rating: i64,
+        max_rating: i64) -> DbResult<Option<Puzzle>>
+    {
+        let table = source.table();
+
+        let mut query_builder = QueryBuilder::new("SELECT puzzles.*");
+        source.push_from(&mut query_builder);
+
+        query_builder.push(format!("\nAND ({table}.rating, {table}.rowid) >= ("))
+            .push_bind(from.0).push(", ").push_bind(from.1).push(")");
+
+        if let Some(to) = to {
+            query_builder.push(format!("\nAND ({table}.rating, {table}.rowid) < ("))
+                .push_bind(to.0).push(", ").push_bind(to.1).push(")");
+        }
+
+        query_builder.push(format!("\nAND {table}.rating <= ")).push_bind(max_rating);
+
+        filter.push_conditions(&mut query_builder, user_id, min_rating, max_rating);
+
+        query_builder.push(concat!("\nAND NOT EXISTS (SELECT 1 FROM seen_puzzles ",
+                "WHERE seen_puzzles.user_id = "))
+            .push_bind(user_id)
+            .push(" AND seen_puzzles.puzzle_id = puzzles.puzzle_id)")
+            .push("\nAND NOT EXISTS (SELECT 1 FROM puzzle_reports WHERE puzzle_reports.user_id = ")
+            .push_bind(user_id)
+            .push(" AND puzzle_reports.puzzle_id = puzzles.puzzle_id)")
+            .push("\nAND puzzles.removed = 0")
+            .push(format!("\nORDER BY {table}.rating, {table}.rowid"))
+            .push("\nLIMIT 1");
+
+        Ok(query_builder
+            .build_query_as()
+            .fetch_optional(&self.pool)
             .await?)
     }
+
+    /// Get a puzzle matching the filter, picked deterministically from the seed, so that the same
+    /// seed always gives the same puzzle as long as the puzzles don't change. The seed picks a
+    /// rowid, and the first matching puzzle from there is used, wrapping around to the start.
+    /// Like new puzzles, it's only picked from the lichess puzzles by default, and never one the
+    /// user has reported.
+    pub async fn get_seeded_puzzle(&self, user_id: &str, seed: u64, filter: &PuzzleFilter)
+        -> DbResult<Option<Puzzle>>
+    {
+        let filter = filter.with_default_sources();
+        let max_rowid = self.get_max_rowid("puzzles").await?;
+        let start = (seed % (max_rowid as u64 + 1)) as i64;
+        let (min_rating, max_rating) = self.get_puzzle_rating_range().await?;
+
+        for (from, to) in [(start, None), (0, Some(start))] {
+            let mut query_builder = QueryBuilder::new(
+                "SELECT puzzles.*\nFROM puzzles\nWHERE removed = 0\nAND rowid >= ");
+            query_builder.push_bind(from);
+
+            if let Some(to) = to {
+                query_builder.push("\nAND rowid < ").push_bind(to);
+            }
+
+            filter.push_conditions(&mut query_builder, user_id, min_rating, max_rating);
+            query_builder
+                .push("\nAND NOT EXISTS (SELECT 1 FROM puzzle_reports WHERE puzzle_reports.user_id = ")
+                .push_bind(user_id)
+                .push(" AND puzzle_reports.puzzle_id = puzzles.puzzle_id)")
+                .push("\nORDER BY rowid\nLIMIT 1");
+
+            let puzzle = query_builder
+                .build_query_as()
+                .fetch_optional(&self.pool)
+                .await?;
+
+            if puzzle.is_some() {
+                return Ok(puzzle);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the highest rowid in one of the puzzle tables.
+    async fn get_max_rowid(&self, table: &str) -> DbResult<i64> {
+        Ok(sqlx::query(&format!("SELECT max(rowid) AS max_rowid FROM {table}"))
+            .map(|row: SqliteRow| row.try_get::<Option<i64>, _>("max_rowid"))
+            .fetch_one(&self.pool)
+            .await??
+            .unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Local;
+    use sqlx::{QueryBuilder, Sqlite};
+    use url::Url;
+
+    use super::PuzzleSource;
+    use crate::db::{PuzzleDatabase, Puzzle, PuzzleFilter, PuzzleReport, PuzzleSearch, AttemptData};
+    use crate::srs::SrsConfig;
+
+    /// Open a new in-memory database.
+    async fn test_db() -> PuzzleDatabase {
+        PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default()).await.unwrap()
+    }
+
+    fn puzzle(puzzle_id: &str, rating: i64, themes: &[&str], opening_tags: &[&str]) -> Puzzle {
+        Puzzle {
+            puzzle_id: puzzle_id.to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating,
+            rating_deviation: 75,
+            popularity: 90,
+            number_of_plays: 1000,
+            themes: themes.iter().map(ToString::to_string).collect(),
+            game_url: String::new(),
+            opening_tags: opening_tags.iter().map(ToString::to_string).collect(),
+            source: "lichess".to_string(),
+        }
+    }
+
+    /// Get the IDs of the puzzles in the rating range matching the filter, in order, by searching
+    /// for them.
+    async fn filtered_puzzle_ids(db: &PuzzleDatabase, filter: &PuzzleFilter, min_rating: i64,
+        max_rating: i64) -> Vec<String>
+    {
+        let search = PuzzleSearch {
+            filter: filter.clone(),
+            min_rating: Some(min_rating),
+            max_rating: Some(max_rating),
+            ..Default::default()
+        };
+
+        let mut puzzle_ids: Vec<_> = db.search_puzzles("local", &search, 0, i64::MAX).await
+            .unwrap()
+            .into_iter()
+            .map(|result| result.puzzle.puzzle_id)
+            .collect();
+        puzzle_ids.sort();
+        puzzle_ids
+    }
+
+    #[test]
+    fn test_filter_sql() {
+        let sql = |filter: &PuzzleFilter| {
+            let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT * FROM puzzles WHERE 1");
+            filter.push_conditions(&mut query_builder, "local", 1000, 1500);
+            query_builder.into_sql()
+        };
+
+        // An empty filter doesn't add anything.
+        assert_eq!(sql(&PuzzleFilter::default()), "SELECT * FROM puzzles WHERE 1");
+
+        // Theme and opening subqueries are limited to the rating range so they can use the
+        // (key, rating) indexes, and have a parameter for each value.
+        let filter = PuzzleFilter {
+            include_themes: vec!["fork".to_string(), "pin".to_string()],
+            opening_tags: vec!["Sicilian_Defense".to_string()],
+            ..Default::default()
+        };
+        let sql = sql(&filter);
+        assert!(sql.contains(concat!("\nAND puzzles.puzzle_id IN (SELECT puzzle_id FROM puzzle_themes ",
+            "WHERE theme IN (?, ?) AND rating >= ? AND rating <= ?)")));
+        assert!(sql.contains(concat!("\nAND puzzles.puzzle_id IN (SELECT puzzle_id FROM puzzle_opening_tags ",
+            "WHERE opening_tag IN (?) AND rating >= ? AND rating <= ?)")));
+    }
+
+    #[tokio::test]
+    async fn test_add_unchanged_puzzles() {
+        let mut db = test_db().await;
+        let theme_count = |db: &PuzzleDatabase, theme: &'static str| {
+            let pool = db.pool.clone();
+            async move {
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM puzzle_themes WHERE theme = ?")
+                    .bind(theme)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        db.add_puzzles(&vec![puzzle("a", 1500, &["fork"], &[])]).await.unwrap();
+        assert_eq!(theme_count(&db, "fork").await, 1);
+
+        // A theme entry that isn't in the puzzle's themes shows whether its entries were replaced.
+        sqlx::query("INSERT INTO puzzle_themes (puzzle_id, theme, rating) VALUES ('a', 'pin', 1500)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        // Adding the same puzzle again leaves its entries alone.
+        db.add_puzzles(&vec![puzzle("a", 1500, &["fork"], &[])]).await.unwrap();
+        assert_eq!(theme_count(&db, "pin").await, 1);
+
+        // Adding a changed puzzle replaces them, and updates the puzzle.
+        db.add_puzzles(&vec![puzzle("a", 1600, &["fork"], &[])]).await.unwrap();
+        assert_eq!(theme_count(&db, "pin").await, 0);
+        assert_eq!(theme_count(&db, "fork").await, 1);
+        assert_eq!(db.get_puzzle_by_id("a").await.unwrap().unwrap().rating, 1600);
+
+        // Unchanged puzzles still count as imported from the lichess puzzle database.
+        db.clear_imported_lichess_puzzles().await.unwrap();
+        db.add_puzzles(&vec![puzzle("a", 1600, &["fork"], &[])]).await.unwrap();
+        assert_eq!(db.mark_removed_lichess_puzzles().await.unwrap(), 0);
+
+        // Removing puzzles clears the cached rating range and counts.
+        db.get_puzzle_rating_range().await.unwrap();
+        db.get_rating_counts(&PuzzleSource::Source("lichess")).await.unwrap();
+        db.clear_imported_lichess_puzzles().await.unwrap();
+        assert_eq!(db.mark_removed_lichess_puzzles().await.unwrap(), 1);
+        assert!(db.puzzle_rating_range.read().unwrap().is_none());
+        assert!(db.rating_counts.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_filter_conditions() {
+        let mut db = test_db().await;
+
+        let mut popular = puzzle("00005", 1300, &["pin"], &[]);
+        popular.popularity = 99;
+        popular.number_of_plays = 50;
+        popular.rating_deviation = 200;
+
+        db.add_puzzles(&vec![
+            puzzle("00001", 1200, &["fork", "short"], &["Sicilian_Defense"]),
+            puzzle("00002", 1400, &["pin", "long"], &["French_Defense"]),
+            puzzle("00003", 1800, &["fork"], &["Sicilian_Defense"]),
+            puzzle("00004", 1250, &["mateIn1"], &[]),
+            popular,
+        ]).await.unwrap();
+
+        let ids = |filter: PuzzleFilter, min_rating, max_rating| {
+            let db = db.clone();
+            async move { filtered_puzzle_ids(&db, &filter, min_rating, max_rating).await }
+        };
+        let themes = |themes: &[&str]| themes.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        assert_eq!(ids(PuzzleFilter::default(), 0, 3000).await, ["00001", "00002", "00003", "00004", "00005"]);
+
+        // Puzzles need any of the included themes, within the rating range.
+        let filter = PuzzleFilter { include_themes: themes(&["fork", "pin"]), ..Default::default() };
+        assert_eq!(ids(filter.clone(), 0, 3000).await, ["00001", "00002", "00003", "00005"]);
+        assert_eq!(ids(filter, 1000, 1500).await, ["00001", "00002", "00005"]);
+
+        // And none of the excluded ones.
+        let filter = PuzzleFilter { exclude_themes: themes(&["short", "long"]), ..Default::default() };
+        assert_eq!(ids(filter, 0, 3000).await, ["00003", "00004", "00005"]);
+
+        // Puzzles need any of the opening tags, and the included themes too if there are both.
+        let filter = PuzzleFilter { opening_tags: themes(&["Sicilian_Defense"]), ..Default::default() };
+        assert_eq!(ids(filter.clone(), 0, 3000).await, ["00001", "00003"]);
+        assert_eq!(ids(filter.clone(), 1500, 2000).await, ["00003"]);
+        let filter = PuzzleFilter { include_themes: themes(&["short"]), ..filter };
+        assert_eq!(ids(filter, 0, 3000).await, ["00001"]);
+
+        // The popularity, plays and rating deviation limits.
+        let filter = PuzzleFilter { min_popularity: Some(95), ..Default::default() };
+        assert_eq!(ids(filter, 0, 3000).await, ["00005"]);
+        let filter = PuzzleFilter { min_plays: Some(100), ..Default::default() };
+        assert_eq!(ids(filter, 0, 3000).await, ["00001", "00002", "00003", "00004"]);
+        let filter = PuzzleFilter { max_rating_deviation: Some(100), ..Default::default() };
+        assert_eq!(ids(filter, 1250, 1400).await, ["00002", "00004"]);
+    }
+
+    #[tokio::test]
+    async fn test_random_unseen_puzzle() {
+        let mut db = test_db().await;
+        db.add_puzzles(&vec![
+            puzzle("00001", 1000, &["fork"], &[]),
+            puzzle("00002", 1100, &["fork"], &[]),
+            puzzle("00003", 1200, &["pin"], &[]),
+            puzzle("00004", 1300, &["fork"], &[]),
+            puzzle("00005", 2000, &["fork"], &[]),
+        ]).await.unwrap();
+
+        let reader = db.clone();
+        let random_puzzle_id = |user_id: &'static str, min_rating, max_rating, filter: PuzzleFilter| {
+            let db = reader.clone();
+            async move {
+                db.get_random_unseen_puzzle(user_id, min_rating, max_rating, &filter).await.unwrap()
+                    .map(|puzzle| puzzle.puzzle_id)
+            }
+        };
+
+        // Puzzles are only picked from the rating range, and the filter.
+        for _ in 0..20 {
+            let puzzle_id = random_puzzle_id("a", 1050, 1350, PuzzleFilter::default()).await.unwrap();
+            assert!(["00002", "00003", "00004"].contains(&puzzle_id.as_str()));
+
+            let filter = PuzzleFilter { include_themes: vec!["pin".to_string()], ..Default::default() };
+            assert_eq!(random_puzzle_id("a", 0, 3000, filter).await.as_deref(), Some("00003"));
+        }
+        assert_eq!(random_puzzle_id("a", 1400, 1900, PuzzleFilter::default()).await, None);
+        assert_eq!(random_puzzle_id("a", 1300, 1200, PuzzleFilter::default()).await, None);
+
+        // Seen and reported puzzles aren't picked again. Only the lowest puzzle in the range is
+        // left, so searches that start above it have to wrap around to it.
+        db.add_seen_puzzle("a", "00003").await.unwrap();
+        db.add_skipped_puzzle("a", "00004", Local::now().fixed_offset(), &AttemptData::default()).await.unwrap();
+        db.add_puzzle_report("a", &PuzzleReport {
+            puzzle_id: "00005".to_string(),
+            reason: "Ambiguous".to_string(),
+            date: Local::now().fixed_offset(),
+        }).await.unwrap();
+
+        for _ in 0..20 {
+            assert_eq!(random_puzzle_id("a", 1100, 2000, PuzzleFilter::default()).await.as_deref(), Some("00002"));
+        }
+
+        // Until every puzzle in the range has been seen. Seeing a puzzle twice is fine.
+        db.add_seen_puzzle("a", "00002").await.unwrap();
+        db.add_seen_puzzle("a", "00002").await.unwrap();
+        assert_eq!(random_puzzle_id("a", 1100, 2000, PuzzleFilter::default()).await, None);
+
+        // Other users haven't seen them.
+        assert!(random_puzzle_id("b", 1100, 2000, PuzzleFilter::default()).await.is_some());
+
+        // Several different puzzles can be picked at once, up to the number that are unseen.
+        let puzzles = db.get_puzzles_by_rating("b", 1000, 1300, 3, &PuzzleFilter::default()).await.unwrap();
+        let mut puzzle_ids: Vec<_> = puzzles.iter().map(|puzzle| puzzle.puzzle_id.as_str()).collect();
+        puzzle_ids.sort();
+        puzzle_ids.dedup();
+        assert!(!puzzle_ids.is_empty() && puzzle_ids.len() == puzzles.len());
+        assert!(puzzle_ids.iter().all(|id| ["00001", "00002", "00003", "00004"].contains(id)));
+        assert!(db.get_puzzles_by_rating("a", 1100, 1300, 3, &PuzzleFilter::default()).await.unwrap().is_empty());
+
+        // Puzzles from packs are only picked when their source is chosen.
+        let pack_puzzle = puzzle("pack-test-1", 1500, &["fork"], &[]);
+        db.add_puzzles(&vec![Puzzle { source: "pack-test".to_string(), ..pack_puzzle }]).await.unwrap();
+        let fork = PuzzleFilter { include_themes: vec!["fork".to_string()], ..Default::default() };
+        assert_eq!(random_puzzle_id("a", 1400, 1900, PuzzleFilter::default()).await, None);
+        assert_eq!(random_puzzle_id("a", 1400, 1900, fork.clone()).await, None);
+
+        let pack = PuzzleFilter { sources: vec!["pack-test".to_string()], ..fork };
+        assert_eq!(random_puzzle_id("a", 1400, 1900, pack).await.as_deref(), Some("pack-test-1"));
+    }
+
+    #[tokio::test]
+    async fn test_seeded_puzzle() {
+        let mut db = test_db().await;
+        let pack_puzzle = puzzle("pack-test-1", 1500, &[], &[]);
+        db.add_puzzles(&vec![
+            puzzle("00001", 1000, &[], &[]),
+            Puzzle { source: "pack-test".to_string(), ..pack_puzzle },
+            puzzle("00002", 1200, &[], &[]),
+        ]).await.unwrap();
+
+        let reader = db.clone();
+        let seeded_puzzle_ids = |user_id: &'static str| {
+            let db = reader.clone();
+            async move {
+                let mut puzzle_ids = Vec::new();
+                for seed in 0..10 {
+                    let puzzle = db.get_seeded_puzzle(user_id, seed, &PuzzleFilter::default()).await.unwrap();
+                    puzzle_ids.push(puzzle.map(|puzzle| puzzle.puzzle_id));
+                }
+                puzzle_ids.sort();
+                puzzle_ids.dedup();
+                puzzle_ids
+            }
+        };
+
+        // Puzzles from packs aren't picked by default.
+        assert_eq!(seeded_puzzle_ids("a").await, [Some("00001".to_string()), Some("00002".to_string())]);
+
+        // Nor are puzzles the user has reported, for any seed.
+        db.add_puzzle_report("a", &PuzzleReport {
+            puzzle_id: "00001".to_string(),
+            reason: "Ambiguous".to_string(),
+            date: Local::now().fixed_offset(),
+        }).await.unwrap();
+        assert_eq!(seeded_puzzle_ids("a").await, [Some("00002".to_string())]);
+        assert_eq!(seeded_puzzle_ids("b").await.len(), 2);
+
+        db.add_puzzle_report("a", &PuzzleReport {
+            puzzle_id: "00002".to_string(),
+            reason: "Ambiguous".to_string(),
+            date: Local::now().fixed_offset(),
+        }).await.unwrap();
+        assert_eq!(seeded_puzzle_ids("a").await, [None]);
+    }
+
+    #[tokio::test]
+    async fn test_random_puzzle_distribution() {
+        // Ten puzzles close together in rating, and one after a large gap, which shouldn't be any
+        // more likely to be picked than the others.
+        let mut db = test_db().await;
+        let mut puzzles: Vec<_> = (0..10)
+            .map(|i| puzzle(&format!("0000{i}"), 1000 + i, &["fork"], &[]))
+            .collect();
+        puzzles.push(puzzle("00010", 2000, &["fork"], &[]));
+        db.add_puzzles(&puzzles).await.unwrap();
+
+        for filter in [PuzzleFilter::default(), PuzzleFilter { include_themes: vec!["fork".to_string()], ..Default::default() }] {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for _ in 0..550 {
+                let puzzle = db.get_random_unseen_puzzle("a", 1000, 2000, &filter).await.unwrap().unwrap();
+                *counts.entry(puzzle.puzzle_id).or_default() += 1;
+            }
+
+            // Each puzzle is expected 50 times.
+            assert_eq!(counts.len(), 11);
+            assert!(counts.values().all(|count| (15..=100).contains(count)), "{counts:?}");
+        }
+    }
 }
@@ -0,0 +1,142 @@
+use chrono::{DateTime, FixedOffset};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult};
+
+/// A puzzle a user has reported, which is excluded from their new puzzles and reviews.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzleReport {
+    pub puzzle_id: String,
+    pub reason: String,
+    #[serde(serialize_with = "crate::util::serialize_datetime")]
+    pub date: DateTime<FixedOffset>,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for PuzzleReport
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            puzzle_id: row.try_get("puzzle_id")?,
+            reason: row.try_get("reason")?,
+            date: DateTime::parse_from_rfc3339(row.try_get("date")?)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "date".to_string(),
+                    source: e.to_string().into(),
+                })?,
+        })
+    }
+}
+
+/// Puzzle report related database implementations.
+impl PuzzleDatabase {
+    /// Report a puzzle, replacing the reason if it's already been reported.
+    pub async fn add_puzzle_report(&mut self, user_id: &str, report: &PuzzleReport) -> DbResult<()> {
+        sqlx::query("
+            INSERT OR REPLACE INTO puzzle_reports (user_id, puzzle_id, reason, date)
+            VALUES (?, ?, ?, ?)
+        ")
+        .bind(user_id)
+        .bind(&report.puzzle_id)
+        .bind(&report.reason)
+        .bind(report.date.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove a user's report of a puzzle, returning whether there was one.
+    pub async fn remove_puzzle_report(&mut self, user_id: &str, puzzle_id: &str) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM puzzle_reports WHERE user_id = ? AND puzzle_id = ?")
+            .bind(user_id)
+            .bind(puzzle_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get all of a user's puzzle reports, most recent first.
+    pub async fn get_puzzle_reports(&self, user_id: &str) -> DbResult<Vec<PuzzleReport>> {
+        Ok(sqlx::query_as("
+            SELECT *
+            FROM puzzle_reports
+            WHERE user_id = ?
+            ORDER BY datetime(date) DESC
+        ")
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, FixedOffset, Local};
+    use url::Url;
+
+    use crate::db::{PuzzleDatabase, Puzzle, PuzzleFilter, PuzzleReport};
+    use crate::srs::{Card, ReviewOrder, SrsConfig};
+
+    fn puzzle(puzzle_id: &str, rating: i64) -> Puzzle {
+        Puzzle {
+            puzzle_id: puzzle_id.to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating,
+            rating_deviation: 75,
+            popularity: 90,
+            number_of_plays: 1000,
+            themes: vec![],
+            game_url: String::new(),
+            opening_tags: vec![],
+            source: "lichess".to_string(),
+        }
+    }
+
+    fn report(puzzle_id: &str, reason: &str, date: DateTime<FixedOffset>) -> PuzzleReport {
+        PuzzleReport { puzzle_id: puzzle_id.to_string(), reason: reason.to_string(), date }
+    }
+
+    #[tokio::test]
+    async fn test_reported_puzzles() {
+        let mut db = PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default())
+            .await.unwrap();
+        db.add_puzzles(&vec![puzzle("00001", 1000), puzzle("00002", 2000)]).await.unwrap();
+
+        // Both puzzles have cards that are due.
+        let now = Local::now().fixed_offset();
+        for puzzle_id in ["00001", "00002"] {
+            db.update_or_create_card(&Card::new(puzzle_id, now - Duration::hours(1), SrsConfig::default()))
+                .await.unwrap();
+        }
+        assert_eq!(db.reviews_due_by(now, now).await.unwrap(), 2);
+
+        // Reporting a puzzle twice keeps one report, with the latest reason.
+        db.add_puzzle_report("local", &report("00001", "Ambiguous", now - Duration::minutes(1))).await.unwrap();
+        db.add_puzzle_report("local", &report("00001", "Second solution", now)).await.unwrap();
+        let reports = db.get_puzzle_reports("local").await.unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].reason, "Second solution");
+
+        // The reported puzzle's card isn't due anymore, and it isn't picked as a new puzzle.
+        assert_eq!(db.reviews_due_by(now, now).await.unwrap(), 1);
+        for review_order in [ReviewOrder::DueTime, ReviewOrder::PuzzleRating, ReviewOrder::Random] {
+            let (card, _) = db.get_next_review_due(now, None, review_order).await.unwrap().unwrap();
+            assert_eq!(card.id, "00002");
+        }
+        for _ in 0..10 {
+            let puzzle = db.get_random_unseen_puzzle("local", 0, 3000, &PuzzleFilter::default()).await.unwrap();
+            assert_eq!(puzzle.unwrap().puzzle_id, "00002");
+        }
+
+        // Undoing the report brings it back.
+        assert!(db.remove_puzzle_report("local", "00001").await.unwrap());
+        assert!(!db.remove_puzzle_report("local", "00001").await.unwrap());
+        assert!(db.get_puzzle_reports("local").await.unwrap().is_empty());
+        assert_eq!(db.reviews_due_by(now, now).await.unwrap(), 2);
+        let (card, _) = db.get_next_review_due(now, None, ReviewOrder::PuzzleRating).await.unwrap().unwrap();
+        assert_eq!(card.id, "00001");
+    }
+}
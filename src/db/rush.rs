@@ -0,0 +1,203 @@
+use chrono::{DateTime, FixedOffset, Duration};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult, AttemptData};
+
+/// A timed puzzle rush run.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RushRun {
+    pub id: i64,
+    pub user_id: String,
+    #[serde(serialize_with = "crate::util::serialize_datetime")]
+    pub started: DateTime<FixedOffset>,
+    pub duration_secs: i64,
+    pub finished: bool,
+    /// The number of puzzles solved.
+    pub score: i64,
+    /// The number of puzzles failed.
+    pub mistakes: i64,
+}
+
+impl RushRun {
+    /// The time the run ends, if it isn't ended by mistakes first.
+    pub fn deadline(&self) -> DateTime<FixedOffset> {
+        self.started + Duration::seconds(self.duration_secs)
+    }
+}
+
+/// A puzzle shown in a rush run, and its outcome, which is None if the user hasn't finished it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RushRunPuzzle {
+    pub position: i64,
+    pub puzzle_id: String,
+    pub puzzle_rating: i64,
+    pub success: Option<bool>,
+    pub duration_ms: Option<i64>,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for RushRun
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            started: DateTime::parse_from_rfc3339(row.try_get("started")?)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "started".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            duration_secs: row.try_get("duration_secs")?,
+            finished: row.try_get("finished")?,
+            score: row.try_get("score")?,
+            mistakes: row.try_get("mistakes")?,
+        })
+    }
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for RushRunPuzzle
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            position: row.try_get("position")?,
+            puzzle_id: row.try_get("puzzle_id")?,
+            puzzle_rating: row.try_get("puzzle_rating")?,
+            success: row.try_get("success")?,
+            duration_ms: row.try_get("duration_ms")?,
+        })
+    }
+}
+
+/// Puzzle rush related database implementations.
+impl PuzzleDatabase {
+    /// Create a new rush run, returning its ID.
+    pub async fn create_rush_run(&mut self, user_id: &str, started: DateTime<FixedOffset>,
+        duration_secs: i64) -> DbResult<i64>
+    {
+        let result = sqlx::query("
+            INSERT INTO rush_runs (user_id, started, duration_secs)
+            VALUES (?, ?, ?)
+        ")
+        .bind(user_id)
+        .bind(started.to_rfc3339())
+        .bind(duration_secs)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Get one of a user's rush runs by ID.
+    pub async fn get_rush_run(&self, user_id: &str, run_id: i64) -> DbResult<Option<RushRun>> {
+        Ok(sqlx::query_as("SELECT * FROM rush_runs WHERE user_id = ? AND id = ?")
+            .bind(user_id)
+            .bind(run_id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Get all of a user's rush runs, most recent first.
+    pub async fn get_rush_runs(&self, user_id: &str) -> DbResult<Vec<RushRun>> {
+        Ok(sqlx::query_as("SELECT * FROM rush_runs WHERE user_id = ? ORDER BY id DESC")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    /// Get the user's highest scoring finished run for each duration, excluding the given run.
+    /// The earliest run is used if there's a tie.
+    pub async fn get_rush_personal_bests(&self, user_id: &str, exclude_run_id: Option<i64>)
+        -> DbResult<Vec<RushRun>>
+    {
+        Ok(sqlx::query_as("
+            SELECT *
+            FROM rush_runs AS runs
+            WHERE user_id = ?
+            AND finished = 1
+            AND id IS NOT ?
+            AND NOT EXISTS (
+                SELECT 1 FROM rush_runs AS better
+                WHERE better.user_id = runs.user_id
+                AND better.duration_secs = runs.duration_secs
+                AND better.finished = 1
+                AND better.id IS NOT ?
+                AND (better.score > runs.score OR (better.score = runs.score AND better.id < runs.id))
+            )
+            ORDER BY duration_secs
+        ")
+        .bind(user_id)
+        .bind(exclude_run_id)
+        .bind(exclude_run_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Update a rush run's score, mistakes and whether it's finished.
+    pub async fn update_rush_run(&mut self, run: &RushRun) -> DbResult<()> {
+        sqlx::query("
+            UPDATE rush_runs
+            SET finished = ?, score = ?, mistakes = ?
+            WHERE id = ?
+        ")
+        .bind(run.finished)
+        .bind(run.score)
+        .bind(run.mistakes)
+        .bind(run.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the puzzles shown in a rush run, in order.
+    pub async fn get_rush_run_puzzles(&self, run_id: i64) -> DbResult<Vec<RushRunPuzzle>> {
+        Ok(sqlx::query_as("
+            SELECT *
+            FROM rush_run_puzzles
+            WHERE run_id = ?
+            ORDER BY position
+        ")
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Add a puzzle to the end of a rush run.
+    pub async fn add_rush_run_puzzle(&mut self, run_id: i64, position: i64, puzzle_id: &str,
+        puzzle_rating: i64) -> DbResult<()>
+    {
+        sqlx::query("
+            INSERT INTO rush_run_puzzles (run_id, position, puzzle_id, puzzle_rating)
+            VALUES (?, ?, ?, ?)
+        ")
+        .bind(run_id)
+        .bind(position)
+        .bind(puzzle_id)
+        .bind(puzzle_rating)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the outcome of a puzzle in a rush run.
+    pub async fn set_rush_run_puzzle_result(&mut self, run_id: i64, position: i64, success: bool,
+        attempt: &AttemptData) -> DbResult<()>
+    {
+        sqlx::query("
+            UPDATE rush_run_puzzles
+            SET success = ?, duration_ms = ?, moves = ?
+            WHERE run_id = ?
+            AND position = ?
+        ")
+        .bind(success)
+        .bind(attempt.duration_ms)
+        .bind(&attempt.moves)
+        .bind(run_id)
+        .bind(position)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
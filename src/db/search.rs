@@ -0,0 +1,309 @@
+use chrono::{DateTime, FixedOffset};
+use sqlx::{Row, QueryBuilder, Sqlite, sqlite::SqliteRow};
+
+use crate::db::{PuzzleDatabase, DbResult, Puzzle, PuzzleFilter};
+use crate::srs::Difficulty;
+
+/// The outcome of the user's last review of a puzzle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewOutcome {
+    Passed,
+    Failed,
+}
+
+/// The order of puzzle search results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSort {
+    #[default]
+    Rating,
+    Popularity,
+    Plays,
+    LastReviewed,
+}
+
+/// A puzzle search. Any criteria that are None aren't checked.
+#[derive(Debug, Clone, Default)]
+pub struct PuzzleSearch {
+    pub filter: PuzzleFilter,
+    pub min_rating: Option<i64>,
+    pub max_rating: Option<i64>,
+    /// Whether the user has a card for the puzzle.
+    pub has_card: Option<bool>,
+    /// The outcome of the user's last review of the puzzle, which excludes puzzles they've never
+    /// reviewed.
+    pub last_outcome: Option<ReviewOutcome>,
+    pub sort: SearchSort,
+    pub descending: bool,
+}
+
+/// A puzzle search result, with the user's card and review status for it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzleSearchResult {
+    pub puzzle: Puzzle,
+    pub has_card: bool,
+    pub last_difficulty: Option<Difficulty>,
+    #[serde(serialize_with = "crate::util::serialize_optional_datetime")]
+    pub last_reviewed: Option<DateTime<FixedOffset>>,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for PuzzleSearchResult
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            puzzle: Puzzle::from_row(row)?,
+            has_card: row.try_get("has_card")?,
+            last_difficulty: row.try_get::<Option<i64>, _>("last_difficulty")?
+                .map(Difficulty::from_i64)
+                .transpose()
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "last_difficulty".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            last_reviewed: row.try_get::<Option<&str>, _>("last_reviewed")?
+                .map(DateTime::parse_from_rfc3339)
+                .transpose()
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "last_reviewed".to_string(),
+                    source: e.to_string().into(),
+                })?,
+        })
+    }
+}
+
+/// Puzzle search related database implementations.
+impl PuzzleDatabase {
+    /// Search the puzzles, returning up to `limit` results starting from `offset`.
+    pub async fn search_puzzles(&self, user_id: &str, search: &PuzzleSearch, offset: i64,
+        limit: i64) -> DbResult<Vec<PuzzleSearchResult>>
+    {
+        let filter = PuzzleFilter { sources: Vec::new(), ..search.filter.clone() };
+        let mut query_builder = QueryBuilder::new("");
+        push_search_query(&mut query_builder, user_id, search, &filter, offset, limit);
+
+        Ok(query_builder
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
+
+/// Push the query for a puzzle search onto the query builder. `filter` is the search's filter
+/// without its sources, which are pushed separately so that they can use the right index.
+fn push_search_query<'a>(query_builder: &mut QueryBuilder<'a, Sqlite>, user_id: &'a str,
+    search: &'a PuzzleSearch, filter: &'a PuzzleFilter, offset: i64, limit: i64)
+{
+    query_builder.push(concat!("SELECT puzzles.*,\n",
+        "EXISTS (SELECT 1 FROM cards WHERE cards.puzzle_id = puzzles.puzzle_id) AS has_card,\n",
+        "last_review.difficulty AS last_difficulty,\n",
+        "last_review.date AS last_reviewed\n",
+        "FROM puzzles\n",
+        // The bare columns in an aggregate query come from the row with the max date.
+        "LEFT JOIN (SELECT puzzle_id, difficulty, date, max(julianday(date)) AS max_date ",
+        "FROM reviews WHERE user_id = "));
+    query_builder.push_bind(user_id)
+        .push(" GROUP BY puzzle_id) AS last_review ON last_review.puzzle_id = puzzles.puzzle_id")
+        .push("\nWHERE 1");
+
+    // Only an index on the column being sorted by gives the order of the results without sorting
+    // all of the matching puzzles, so the rating and source conditions are kept from using any
+    // other index (with a unary +). The (rating) index gives the order when sorting by rating, as
+    // does the (source, rating) index for a single source.
+    let (rating_column, source_column) = match search.sort {
+        SearchSort::Rating if search.filter.sources.len() == 1 => ("puzzles.rating", "puzzles.source"),
+        SearchSort::Rating => ("puzzles.rating", "+puzzles.source"),
+        SearchSort::Popularity | SearchSort::Plays => ("+puzzles.rating", "+puzzles.source"),
+        SearchSort::LastReviewed => ("puzzles.rating", "puzzles.source"),
+    };
+
+    if let Some(min_rating) = search.min_rating {
+        query_builder.push(format!("\nAND {rating_column} >= ")).push_bind(min_rating);
+    }
+
+    if let Some(max_rating) = search.max_rating {
+        query_builder.push(format!("\nAND {rating_column} <= ")).push_bind(max_rating);
+    }
+
+    if !search.filter.sources.is_empty() {
+        query_builder.push(format!("\nAND {source_column} IN ("));
+        let mut separated = query_builder.separated(", ");
+        for source in &search.filter.sources {
+            separated.push_bind(source);
+        }
+        query_builder.push(")");
+    }
+
+    filter.push_conditions(query_builder, user_id, search.min_rating.unwrap_or(i64::MIN),
+        search.max_rating.unwrap_or(i64::MAX));
+
+    if let Some(has_card) = search.has_card {
+        query_builder.push(match has_card {
+            true => "\nAND puzzles.puzzle_id IN (SELECT puzzle_id FROM cards)",
+            false => "\nAND puzzles.puzzle_id NOT IN (SELECT puzzle_id FROM cards)",
+        });
+    }
+
+    if let Some(last_outcome) = search.last_outcome {
+        query_builder.push(match last_outcome {
+            ReviewOutcome::Passed => "\nAND last_review.difficulty > 0",
+            ReviewOutcome::Failed => "\nAND last_review.difficulty = 0",
+        });
+    }
+
+    let direction = match search.descending {
+        true => "DESC",
+        false => "ASC",
+    };
+
+    let order_by = match search.sort {
+        SearchSort::Rating => format!("puzzles.rating {direction}"),
+        SearchSort::Popularity => format!("puzzles.popularity {direction}"),
+        SearchSort::Plays => format!("puzzles.number_of_plays {direction}"),
+        // Puzzles that have never been reviewed go last either way.
+        SearchSort::LastReviewed =>
+            format!("last_review.max_date IS NULL, last_review.max_date {direction}"),
+    };
+
+    // Ties are broken by rowid, in the same direction, so that the (rating), (popularity) and
+    // (number_of_plays) indexes give the whole order.
+    query_builder.push(format!("\nORDER BY {order_by}, puzzles.rowid {direction}"))
+        .push("\nLIMIT ").push_bind(limit)
+        .push("\nOFFSET ").push_bind(offset);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use sqlx::{QueryBuilder, Row, Sqlite};
+    use url::Url;
+
+    use crate::db::{AttemptData, PuzzleDatabase, Puzzle, PuzzleFilter, PuzzleSearch, Review};
+    use crate::db::search::{push_search_query, SearchSort};
+    use crate::srs::{Difficulty, SrsConfig};
+
+    fn puzzle(puzzle_id: &str, rating: i64, popularity: i64, number_of_plays: i64, source: &str) -> Puzzle {
+        Puzzle {
+            puzzle_id: puzzle_id.to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating,
+            rating_deviation: 75,
+            popularity,
+            number_of_plays,
+            themes: vec![],
+            game_url: String::new(),
+            opening_tags: vec![],
+            source: source.to_string(),
+        }
+    }
+
+    async fn test_db() -> PuzzleDatabase {
+        PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_search_order() {
+        let mut db = test_db().await;
+        db.add_puzzles(&vec![
+            puzzle("00001", 1500, 80, 300, "lichess"),
+            puzzle("00002", 1200, 90, 100, "lichess"),
+            puzzle("00003", 1500, 70, 200, "lichess"),
+            puzzle("00004", 1800, 90, 500, "pack-test"),
+        ]).await.unwrap();
+
+        for (puzzle_id, days_ago) in [("00003", 2), ("00001", 1)] {
+            db.add_review_for_user(Review {
+                user_id: "local".to_string(),
+                puzzle_id: puzzle_id.to_string(),
+                difficulty: Difficulty::Good,
+                date: Local::now().fixed_offset() - Duration::days(days_ago),
+                user_rating: None,
+                attempt: AttemptData::default(),
+            }).await.unwrap();
+        }
+
+        let search = |sort, descending, sources: &[&str]| {
+            let db = db.clone();
+            let search = PuzzleSearch {
+                filter: PuzzleFilter {
+                    sources: sources.iter().map(ToString::to_string).collect(),
+                    ..Default::default()
+                },
+                sort,
+                descending,
+                ..Default::default()
+            };
+            async move {
+                db.search_puzzles("local", &search, 0, 100).await.unwrap()
+                    .into_iter()
+                    .map(|result| result.puzzle.puzzle_id)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // Ties are broken by the order the puzzles were added, in the same direction as the sort.
+        assert_eq!(search(SearchSort::Rating, false, &[]).await, ["00002", "00001", "00003", "00004"]);
+        assert_eq!(search(SearchSort::Rating, true, &[]).await, ["00004", "00003", "00001", "00002"]);
+        assert_eq!(search(SearchSort::Popularity, true, &[]).await, ["00004", "00002", "00001", "00003"]);
+        assert_eq!(search(SearchSort::Plays, false, &[]).await, ["00002", "00003", "00001", "00004"]);
+
+        // Puzzles that have never been reviewed go last either way.
+        assert_eq!(search(SearchSort::LastReviewed, true, &[]).await, ["00001", "00003", "00004", "00002"]);
+        assert_eq!(search(SearchSort::LastReviewed, false, &[]).await, ["00003", "00001", "00002", "00004"]);
+
+        // The order is the same with one or more sources.
+        assert_eq!(search(SearchSort::Rating, true, &["lichess"]).await, ["00003", "00001", "00002"]);
+        assert_eq!(search(SearchSort::Popularity, false, &["lichess"]).await, ["00003", "00001", "00002"]);
+        assert_eq!(search(SearchSort::Rating, false, &["lichess", "pack-test"]).await,
+            ["00002", "00001", "00003", "00004"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_query_plan() {
+        let db = test_db().await;
+
+        // The results are in the order of the sort's index, whatever the rating range and sources,
+        // rather than all of the matching puzzles having to be sorted.
+        let cases = [
+            (SearchSort::Rating, &["lichess"][..], "puzzles_source_rating"),
+            (SearchSort::Rating, &["lichess", "pack-test"][..], "puzzle_rating"),
+            (SearchSort::Rating, &[][..], "puzzle_rating"),
+            (SearchSort::Popularity, &["lichess"][..], "puzzles_popularity"),
+            (SearchSort::Plays, &["lichess", "pack-test"][..], "puzzles_number_of_plays"),
+        ];
+
+        for (sort, sources, index) in cases {
+            for (min_rating, max_rating) in [(None, None), (Some(1000), Some(1500))] {
+                for descending in [false, true] {
+                    let search = PuzzleSearch {
+                        filter: PuzzleFilter {
+                            sources: sources.iter().map(ToString::to_string).collect(),
+                            ..Default::default()
+                        },
+                        min_rating,
+                        max_rating,
+                        sort,
+                        descending,
+                        ..Default::default()
+                    };
+                    let filter = PuzzleFilter { sources: Vec::new(), ..search.filter.clone() };
+
+                    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new("EXPLAIN QUERY PLAN ");
+                    push_search_query(&mut query_builder, "local", &search, &filter, 0, 20);
+                    let plan: Vec<String> = query_builder.build()
+                        .fetch_all(&db.pool)
+                        .await
+                        .unwrap()
+                        .iter()
+                        .map(|row| row.get("detail"))
+                        .collect();
+
+                    let case = format!("{sort:?} {sources:?} {min_rating:?} {descending}: {plan:?}");
+                    assert!(plan.iter().any(|detail| detail.contains(&format!("puzzles USING INDEX {index}"))), "{case}");
+                    assert!(!plan.iter().any(|detail| detail.contains("TEMP B-TREE")), "{case}");
+                }
+            }
+        }
+    }
+}
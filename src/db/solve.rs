@@ -0,0 +1,129 @@
+use chrono::{DateTime, FixedOffset};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::db::{PuzzleDatabase, DbResult};
+
+/// A session for solving a puzzle, with each of the user's moves checked against the solution.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SolveSession {
+    pub id: i64,
+    pub user_id: String,
+    pub puzzle_id: String,
+    #[serde(serialize_with = "crate::util::serialize_datetime")]
+    pub started: DateTime<FixedOffset>,
+    /// The number of moves of the solution that have been played, including the opponent's.
+    pub ply: i64,
+    /// The number of wrong moves that were tried.
+    pub mistakes: i64,
+    pub hint_used: bool,
+    /// All of the moves the user tried, as space separated UCI moves.
+    pub moves: String,
+    /// The time the user first completed the solution.
+    #[serde(serialize_with = "crate::util::serialize_optional_datetime")]
+    pub completed: Option<DateTime<FixedOffset>>,
+    /// Whether the session has been used for a review, which it can only be once.
+    pub reviewed: bool,
+}
+
+impl<'r> sqlx::FromRow<'r, SqliteRow> for SolveSession
+{
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            puzzle_id: row.try_get("puzzle_id")?,
+            started: DateTime::parse_from_rfc3339(row.try_get("started")?)
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "started".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            ply: row.try_get("ply")?,
+            mistakes: row.try_get("mistakes")?,
+            hint_used: row.try_get("hint_used")?,
+            moves: row.try_get("moves")?,
+            completed: row.try_get::<Option<&str>, _>("completed")?
+                .map(DateTime::parse_from_rfc3339)
+                .transpose()
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "completed".to_string(),
+                    source: e.to_string().into(),
+                })?,
+            reviewed: row.try_get("reviewed")?,
+        })
+    }
+}
+
+/// Solve session related database implementations.
+impl PuzzleDatabase {
+    /// Create a new solve session, returning its ID.
+    pub async fn create_solve_session(&mut self, user_id: &str, puzzle_id: &str,
+        started: DateTime<FixedOffset>, ply: i64) -> DbResult<i64>
+    {
+        let result = sqlx::query("
+            INSERT INTO solve_sessions (user_id, puzzle_id, started, ply)
+            VALUES (?, ?, ?, ?)
+        ")
+        .bind(user_id)
+        .bind(puzzle_id)
+        .bind(started.to_rfc3339())
+        .bind(ply)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Get one of a user's solve sessions by ID.
+    pub async fn get_solve_session(&self, user_id: &str, session_id: i64)
+        -> DbResult<Option<SolveSession>>
+    {
+        Ok(sqlx::query_as("SELECT * FROM solve_sessions WHERE user_id = ? AND id = ?")
+            .bind(user_id)
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    /// Update a solve session's progress.
+    pub async fn update_solve_session(&mut self, session: &SolveSession) -> DbResult<()> {
+        sqlx::query("
+            UPDATE solve_sessions
+            SET ply = ?, mistakes = ?, hint_used = ?, moves = ?, completed = ?
+            WHERE id = ?
+        ")
+        .bind(session.ply)
+        .bind(session.mistakes)
+        .bind(session.hint_used)
+        .bind(&session.moves)
+        .bind(session.completed.map(|dt| dt.to_rfc3339()))
+        .bind(session.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark a solve session as used for a review. Returns false if it already had been, so that
+    /// two reviews submitted at the same time can't both use it.
+    pub async fn mark_solve_session_reviewed(&mut self, session_id: i64) -> DbResult<bool> {
+        let result = sqlx::query("UPDATE solve_sessions SET reviewed = 1 WHERE id = ? AND reviewed = 0")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete solve sessions started before the given time, returning the number deleted.
+    pub async fn delete_solve_sessions_before(&mut self, before: DateTime<FixedOffset>)
+        -> DbResult<u64>
+    {
+        let result = sqlx::query("DELETE FROM solve_sessions WHERE julianday(started) < julianday(?)")
+            .bind(before.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -4,7 +4,7 @@ use sqlx::sqlite::*;
 use sqlx::Row;
 
 use crate::rating::Rating;
-use crate::db::{PuzzleDatabase, DbResult};
+use crate::db::{PuzzleDatabase, DbResult, DatabaseError, ErrorDetails, AttemptData, PuzzleFilter};
 
 /// A user record from the db.
 #[derive(Debug, Clone)]
@@ -12,6 +12,48 @@ pub struct User {
     pub id: String,
     pub rating: Rating,
     pub next_puzzle: Option<String>,
+    pub settings: UserSettings,
+}
+
+/// A user's settings, which are stored as json in the users table.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    /// The default filter for new puzzles.
+    pub puzzle_filter: PuzzleFilter,
+    /// The target probability of solving new puzzles, if the puzzle rating range should be chosen
+    /// adaptively instead of from the configured rating variation.
+    pub target_success_rate: Option<f64>,
+}
+
+impl UserSettings {
+    /// Get the settings with a partial update applied, as a JSON merge patch: fields that are in
+    /// the update are replaced (objects are merged recursively), fields that are null are reset to
+    /// their defaults, and the rest are kept.
+    pub fn merge(&self, update: serde_json::Value) -> serde_json::Result<Self> {
+        fn merge_value(target: &mut serde_json::Value, update: serde_json::Value) {
+            match update {
+                serde_json::Value::Object(update) => {
+                    if !target.is_object() {
+                        *target = serde_json::Value::Object(Default::default());
+                    }
+
+                    let target = target.as_object_mut().unwrap();
+                    for (key, value) in update {
+                        match value.is_null() {
+                            true => { target.remove(&key); },
+                            false => merge_value(target.entry(key).or_insert(serde_json::Value::Null), value),
+                        }
+                    }
+                },
+                update => *target = update,
+            }
+        }
+
+        let mut settings = serde_json::to_value(self)?;
+        merge_value(&mut settings, update);
+        serde_json::from_value(settings)
+    }
 }
 
 impl<'r> sqlx::FromRow<'r, SqliteRow> for User
@@ -25,6 +67,14 @@ impl<'r> sqlx::FromRow<'r, SqliteRow> for User
                 volatility: row.try_get("rating_volatility")?,
             },
             next_puzzle: row.try_get("next_puzzle").ok(),
+            settings: row.try_get::<Option<&str>, _>("settings")?
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(|e| sqlx::Error::ColumnDecode {
+                    index: "settings".to_string(),
+                    source: e.to_string().into(),
+                })?
+                .unwrap_or_default(),
         })
     }
 }
@@ -44,18 +94,27 @@ impl PuzzleDatabase {
 
     /// Update the user record with the given ID.
     pub async fn update_user(&mut self, user: &User) -> DbResult<()> {
+        let settings = serde_json::to_string(&user.settings)
+            .map_err(|e| DatabaseError::ParsingError(ErrorDetails {
+                backend: "serde_json".to_string(),
+                description: format!("Failed to serialize user settings: {e}"),
+                source: Some(e.into()),
+            }))?;
+
         sqlx::query("
             UPDATE users
             SET rating = ?,
                 rating_deviation = ?,
                 rating_volatility = ?,
-                next_puzzle = ?
+                next_puzzle = ?,
+                settings = ?
             WHERE id = ?
         ")
         .bind(user.rating.rating)
         .bind(user.rating.deviation)
         .bind(user.rating.volatility)
         .bind(user.next_puzzle.as_ref())
+        .bind(settings)
         .bind(&user.id)
         .execute(&self.pool)
         .await.map(|_| ()).map_err(Into::into)
@@ -63,15 +122,74 @@ impl PuzzleDatabase {
 
     /// Add a puzzle to the skipped puzzles list for a user.
     pub async fn add_skipped_puzzle(&mut self, user_id: &str, puzzle_id: &str,
-                                    dt: DateTime<FixedOffset>) -> DbResult<()>
+                                    dt: DateTime<FixedOffset>, attempt: &AttemptData) -> DbResult<()>
     {
-        sqlx::query("INSERT INTO skipped_puzzles (user_id, puzzle_id, date) VALUES (?, ?, ?)")
+        sqlx::query("
+            INSERT INTO skipped_puzzles (user_id, puzzle_id, date, duration_ms, mistakes, hint_used, moves)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+        ")
             .bind(user_id)
             .bind(puzzle_id)
             .bind(&dt.to_rfc3339())
+            .bind(attempt.duration_ms)
+            .bind(attempt.mistakes)
+            .bind(attempt.hint_used)
+            .bind(&attempt.moves)
+            .execute(&self.pool)
+            .await?;
+
+        self.add_seen_puzzle(user_id, puzzle_id).await
+    }
+
+    /// Mark a puzzle as seen by a user, so that it isn't picked as a new puzzle again.
+    pub async fn add_seen_puzzle(&mut self, user_id: &str, puzzle_id: &str) -> DbResult<()> {
+        sqlx::query("
+            INSERT OR IGNORE INTO seen_puzzles (user_id, puzzle_id)
+            VALUES (?, ?)
+        ")
+            .bind(user_id)
+            .bind(puzzle_id)
             .execute(&self.pool)
             .await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::db::{PuzzleFilter, UserSettings};
+
+    #[test]
+    fn test_merge_settings() {
+        let settings = UserSettings {
+            puzzle_filter: PuzzleFilter {
+                include_themes: vec!["fork".to_string()],
+                min_plays: Some(100),
+                ..Default::default()
+            },
+            target_success_rate: Some(0.8),
+        };
+
+        // Fields that aren't in the update are kept, including the rest of the filter.
+        let merged = settings.merge(json!({"puzzle_filter": {"min_popularity": 50}})).unwrap();
+        assert_eq!(merged.puzzle_filter.include_themes, vec!["fork"]);
+        assert_eq!(merged.puzzle_filter.min_plays, Some(100));
+        assert_eq!(merged.puzzle_filter.min_popularity, Some(50));
+        assert_eq!(merged.target_success_rate, Some(0.8));
+
+        // Arrays are replaced rather than merged, and nulls reset fields to their defaults.
+        let merged = settings.merge(json!({
+            "puzzle_filter": {"include_themes": ["pin"], "min_plays": null},
+            "target_success_rate": null,
+        })).unwrap();
+        assert_eq!(merged.puzzle_filter.include_themes, vec!["pin"]);
+        assert_eq!(merged.puzzle_filter.min_plays, None);
+        assert_eq!(merged.target_success_rate, None);
+
+        // Updates with the wrong types are errors.
+        assert!(settings.merge(json!({"target_success_rate": "high"})).is_err());
+    }
+}
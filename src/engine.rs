@@ -0,0 +1,403 @@
+use std::fmt;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Semaphore;
+
+use crate::app::EngineConfig;
+
+/// Engine errors.
+#[derive(Debug)]
+pub enum EngineError {
+    Spawn(String),
+    Io(String),
+    Protocol(String),
+    Timeout,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(desc) => write!(f, "Failed to start engine: {desc}"),
+            Self::Io(desc) => write!(f, "Engine io error: {desc}"),
+            Self::Protocol(desc) => write!(f, "Unexpected engine output: {desc}"),
+            Self::Timeout => write!(f, "Engine timed out"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<std::io::Error> for EngineError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err.to_string())
+    }
+}
+
+/// An engine evaluation, relative to the side to move like in UCI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Score {
+    /// The evaluation in centipawns.
+    Cp(i64),
+    /// Mate in this many moves, which is negative if the side to move is getting mated.
+    Mate(i64),
+}
+
+impl Score {
+    /// The same score from the other side's point of view.
+    pub fn negate(self) -> Self {
+        match self {
+            Self::Cp(cp) => Self::Cp(-cp),
+            Self::Mate(moves) => Self::Mate(-moves),
+        }
+    }
+}
+
+/// How long the engine should search for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchLimit {
+    Depth(u32),
+    MoveTime(u64),
+}
+
+/// The result of an engine search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineAnalysis {
+    pub depth: u32,
+    pub score: Option<Score>,
+    /// The best move in UCI notation, or None if there are no legal moves.
+    pub best_move: Option<String>,
+    /// The principal variation in UCI notation.
+    pub pv: Vec<String>,
+    /// The score of the second best move, if more than one line was searched and there is one.
+    pub second_score: Option<Score>,
+}
+
+/// A pool of UCI engine processes. Engines are started when they're first needed and kept
+/// running between searches, up to `size` of them, and any engine that fails or times out is
+/// killed and replaced the next time one is needed.
+#[derive(Clone)]
+pub struct EnginePool {
+    path: String,
+    timeout: Duration,
+    idle: Arc<Mutex<Vec<Engine>>>,
+    permits: Arc<Semaphore>,
+}
+
+impl EnginePool {
+    pub fn new(path: &str, size: usize, timeout: Duration) -> Self {
+        Self {
+            path: path.to_string(),
+            timeout,
+            idle: Arc::new(Mutex::new(Vec::new())),
+            permits: Arc::new(Semaphore::new(size.max(1))),
+        }
+    }
+
+    /// Create a pool for the configured engine, if there is one.
+    pub fn from_config(config: &EngineConfig) -> Option<Self> {
+        config.path
+            .as_ref()
+            .map(|path| Self::new(path, config.pool_size, Duration::from_secs(config.timeout_secs)))
+    }
+
+    /// Search a position, given as a FEN and space separated UCI moves played from it, waiting
+    /// for an engine to be free if they're all busy. `lines` is the number of best moves the
+    /// engine searches (its MultiPV option), which is only needed to get the second best score.
+    pub async fn analyse(&self, fen: &str, moves: &str, limit: SearchLimit, lines: u32)
+        -> Result<EngineAnalysis, EngineError>
+    {
+        let _permit = self.permits.acquire().await
+            .map_err(|e| EngineError::Io(e.to_string()))?;
+
+        let engine = self.idle.lock().expect("Engine pool lock poisoned").pop();
+
+        let search = async {
+            let mut engine = match engine {
+                Some(engine) => engine,
+                None => Engine::spawn(&self.path).await?,
+            };
+            let analysis = engine.analyse(fen, moves, limit, lines).await?;
+            Ok::<_, EngineError>((engine, analysis))
+        };
+
+        // Engines are only returned to the pool if they succeeded, otherwise they're dropped,
+        // which kills them.
+        let (engine, analysis) = tokio::time::timeout(self.timeout, search).await
+            .map_err(|_| EngineError::Timeout)??;
+
+        self.idle.lock().expect("Engine pool lock poisoned").push(engine);
+
+        Ok(analysis)
+    }
+}
+
+/// A running UCI engine process.
+struct Engine {
+    // The child process, which is killed when the engine is dropped.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl Engine {
+    /// Start an engine and wait for it to be ready.
+    async fn spawn(path: &str) -> Result<Self, EngineError> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| EngineError::Spawn(format!("{path}: {e}")))?;
+
+        let stdin = child.stdin.take()
+            .ok_or_else(|| EngineError::Spawn("no stdin".to_string()))?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| EngineError::Spawn("no stdout".to_string()))?;
+
+        let mut engine = Self {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        };
+
+        engine.send("uci").await?;
+        engine.read_until("uciok").await?;
+
+        log::info!("Started engine {path}");
+
+        Ok(engine)
+    }
+
+    /// Search a position and return the last complete info line from the search.
+    async fn analyse(&mut self, fen: &str, moves: &str, limit: SearchLimit, lines: u32)
+        -> Result<EngineAnalysis, EngineError>
+    {
+        // Engines are reused, so the option is always set in case a previous search changed it.
+        self.send(&format!("setoption name MultiPV value {}", lines.max(1))).await?;
+        self.send("ucinewgame").await?;
+        self.send("isready").await?;
+        self.read_until("readyok").await?;
+
+        let moves = moves.split_whitespace().collect::<Vec<_>>();
+        match moves.is_empty() {
+            true => self.send(&format!("position fen {fen}")).await?,
+            false => self.send(&format!("position fen {fen} moves {}", moves.join(" "))).await?,
+        }
+
+        match limit {
+            SearchLimit::Depth(depth) => self.send(&format!("go depth {depth}")).await?,
+            SearchLimit::MoveTime(ms) => self.send(&format!("go movetime {ms}")).await?,
+        }
+
+        let mut analysis = EngineAnalysis {
+            depth: 0,
+            score: None,
+            best_move: None,
+            pv: Vec::new(),
+            second_score: None,
+        };
+
+        loop {
+            let line = self.read_line().await?;
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("info") => Self::parse_info(&mut analysis, tokens),
+                Some("bestmove") => {
+                    let best_move = tokens.next()
+                        .ok_or_else(|| EngineError::Protocol(line.clone()))?;
+                    analysis.best_move = Some(best_move)
+                        .filter(|m| *m != "(none)")
+                        .map(|m| m.to_string());
+                    return Ok(analysis);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    /// Update the analysis from an info line, if it's for the principal variation or the second
+    /// best line.
+    fn parse_info<'a>(analysis: &mut EngineAnalysis, mut tokens: impl Iterator<Item = &'a str>) {
+        let mut depth = None;
+        let mut score = None;
+        let mut multipv = 1;
+        let mut pv = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => depth = tokens.next().and_then(|d| d.parse().ok()),
+                "score" => {
+                    let kind = tokens.next();
+                    let value = tokens.next().and_then(|v| v.parse().ok());
+                    score = match (kind, value) {
+                        (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                        (Some("mate"), Some(moves)) => Some(Score::Mate(moves)),
+                        _ => None,
+                    };
+                },
+                "multipv" => multipv = tokens.next().and_then(|n| n.parse().ok()).unwrap_or(0),
+                // The pv is always the last thing in an info line.
+                "pv" => pv = tokens.by_ref().map(|m| m.to_string()).collect(),
+                // Strings can contain anything, so ignore the rest of the line.
+                "string" => return,
+                _ => (),
+            }
+        }
+
+        match (multipv, depth, score, pv.is_empty()) {
+            (1, Some(depth), Some(score), false) => {
+                analysis.depth = depth;
+                analysis.score = Some(score);
+                analysis.pv = pv;
+            },
+            // Only the score of the second line is used, and other lines are ignored.
+            (2, Some(_), Some(score), false) => analysis.second_score = Some(score),
+            _ => (),
+        }
+    }
+
+    async fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        self.stdin.write_all(format!("{command}\n").as_bytes()).await?;
+        Ok(self.stdin.flush().await?)
+    }
+
+    async fn read_line(&mut self) -> Result<String, EngineError> {
+        self.stdout.next_line().await?
+            .ok_or_else(|| EngineError::Io("engine exited".to_string()))
+    }
+
+    async fn read_until(&mut self, expected: &str) -> Result<(), EngineError> {
+        while self.read_line().await?.trim() != expected {}
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    use tempfile::{NamedTempFile, TempPath};
+
+    use super::*;
+
+    const FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    /// A fake UCI engine that gives a canned response to searches, and logs the commands it gets
+    /// to a file next to the script.
+    const FAKE_ENGINE: &str = r#"#!/bin/sh
+while read -r line; do
+    echo "$line" >> "$0.log"
+    case "$line" in
+        uci) echo "id name Fake"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        "position fen 8/8/8/8/8/8/8/8"*) hang=1 ;;
+        go*)
+            if [ -n "$hang" ]; then sleep 10; fi
+            echo "info string starting search"
+            echo "info depth 1 seldepth 1 multipv 1 score cp 20 nodes 20 pv e2e4"
+            echo "info depth 2 seldepth 2 multipv 1 score cp 35 nodes 100 pv e2e4 e7e5"
+            echo "info depth 2 seldepth 2 multipv 2 score cp 10 nodes 100 pv d2d4 d7d5"
+            echo "info depth 3 currmove e2e4 currmovenumber 1"
+            echo "bestmove e2e4 ponder e7e5"
+            ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    fn fake_engine(script: &str) -> TempPath {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(script.as_bytes()).unwrap();
+        let path = file.into_temp_path();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn commands(engine: &TempPath) -> Vec<String> {
+        std::fs::read_to_string(format!("{}.log", engine.display()))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_analyse() {
+        let engine = fake_engine(FAKE_ENGINE);
+        let pool = EnginePool::new(engine.to_str().unwrap(), 1, Duration::from_secs(5));
+
+        let analysis = pool.analyse(FEN, "", SearchLimit::Depth(2), 1).await.unwrap();
+        assert_eq!(analysis, EngineAnalysis {
+            depth: 2,
+            score: Some(Score::Cp(35)),
+            best_move: Some("e2e4".to_string()),
+            pv: vec!["e2e4".to_string(), "e7e5".to_string()],
+            second_score: Some(Score::Cp(10)),
+        });
+
+        // The same engine process is used for the second search.
+        pool.analyse(FEN, "e2e4 e7e5", SearchLimit::MoveTime(100), 2).await.unwrap();
+        assert_eq!(commands(&engine), [
+            "uci",
+            "setoption name MultiPV value 1",
+            "ucinewgame",
+            "isready",
+            &format!("position fen {FEN}"),
+            "go depth 2",
+            "setoption name MultiPV value 2",
+            "ucinewgame",
+            "isready",
+            &format!("position fen {FEN} moves e2e4 e7e5"),
+            "go movetime 100",
+        ]);
+
+        let _ = std::fs::remove_file(format!("{}.log", engine.display()));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_analyses() {
+        let engine = fake_engine(FAKE_ENGINE);
+        let pool = EnginePool::new(engine.to_str().unwrap(), 2, Duration::from_secs(5));
+
+        let results = futures::future::join_all((0..4)
+            .map(|_| pool.analyse(FEN, "", SearchLimit::Depth(2), 1)))
+            .await;
+        assert!(results.iter().all(|result| result.as_ref().is_ok_and(|a| a.depth == 2)));
+
+        // No more than two engines were started.
+        let starts = commands(&engine).iter().filter(|command| *command == "uci").count();
+        assert!((1..=2).contains(&starts), "{starts}");
+
+        let _ = std::fs::remove_file(format!("{}.log", engine.display()));
+    }
+
+    #[tokio::test]
+    async fn test_engine_errors() {
+        // Engines that don't exist.
+        let pool = EnginePool::new("/nonexistent/engine", 1, Duration::from_secs(5));
+        assert!(matches!(pool.analyse(FEN, "", SearchLimit::Depth(2), 1).await, Err(EngineError::Spawn(_))));
+
+        // Engines that exit.
+        let engine = fake_engine("#!/bin/sh\nread -r line\necho uciok\nexit 0\n");
+        let pool = EnginePool::new(engine.to_str().unwrap(), 1, Duration::from_secs(5));
+        assert!(matches!(pool.analyse(FEN, "", SearchLimit::Depth(2), 1).await, Err(EngineError::Io(_))));
+
+        // Engines that take too long, which are replaced for the next search.
+        let engine = fake_engine(FAKE_ENGINE);
+        let pool = EnginePool::new(engine.to_str().unwrap(), 1, Duration::from_millis(500));
+        assert!(matches!(pool.analyse("8/8/8/8/8/8/8/8 w - - 0 1", "", SearchLimit::Depth(2), 1).await,
+            Err(EngineError::Timeout)));
+        assert!(pool.analyse(FEN, "", SearchLimit::Depth(2), 1).await.is_ok());
+
+        let _ = std::fs::remove_file(format!("{}.log", engine.display()));
+    }
+}
@@ -1,17 +1,74 @@
-use std::fs::File;
-use std::io::{Write, SeekFrom, Seek};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write, SeekFrom, Seek};
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use chrono::{DateTime, FixedOffset, Local};
 use csv::StringRecord;
+use filetime::FileTime;
 use futures::StreamExt;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use sha2::{Digest, Sha256};
+use url::Url;
 
-use crate::db::{PuzzleDatabase, Puzzle};
+use crate::app::{PuzzleDbConfig, PuzzleDbSource};
+use crate::db::{PuzzleDatabase, Puzzle, ImportCheckpoint};
+
+/// The source of puzzles from the lichess puzzle database.
+pub const LICHESS_SOURCE: &str = "lichess";
+
+/// The url the lichess puzzle database is downloaded from by default.
+pub const LICHESS_DB_URL: &str = "https://database.lichess.org/lichess_db_puzzle.csv.zst";
+
+/// The names the puzzle database is looked for under in a directory, compressed or not.
+const LICHESS_DB_NAMES: [&str; 2] = ["lichess_db_puzzle.csv.zst", "lichess_db_puzzle.csv"];
+
+/// The magic number at the start of zstd compressed files.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Whether the lichess puzzle database is being imported or refreshed, so that only one import
+/// runs at once.
+static IMPORT_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the lichess puzzle database is being imported or refreshed right now.
+pub fn import_running() -> bool {
+    IMPORT_RUNNING.load(Ordering::Acquire)
+}
+
+/// Marks an import as running until it's dropped.
+pub struct ImportGuard;
+
+impl ImportGuard {
+    /// Mark an import as running, or return None if one is already running.
+    pub fn acquire() -> Option<Self> {
+        IMPORT_RUNNING.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| Self)
+    }
+}
+
+impl Drop for ImportGuard {
+    fn drop(&mut self) {
+        IMPORT_RUNNING.store(false, Ordering::Release);
+    }
+}
+
+/// A copy of the lichess puzzle database, and the date it was last modified if it's known.
+#[derive(Debug)]
+struct PuzzleDbFile {
+    file: File,
+    date: Option<DateTime<FixedOffset>>,
+}
 
 /// Initialise the puzzle db if necessary. Returns Ok(true) if the database import was complete,
 /// Ok(false) if the database was already imported, or an error if one occurs.
-pub async fn init_db(db: PuzzleDatabase, cancel_import: Arc<AtomicBool>)
+pub async fn init_db(db: PuzzleDatabase, config: PuzzleDbConfig, cancel_import: Arc<AtomicBool>)
     -> Result<bool, String>
 {
+    let _guard = ImportGuard::acquire()
+        .ok_or_else(|| "A puzzle database import is already running".to_string())?;
+
     let app_data = db.get_app_data("").await
         .map_err(|e| format!("Failed to get app data: {e}"))?
         .ok_or_else(|| format!("Internal error: no app_data row in database"))?;
@@ -21,11 +78,8 @@ pub async fn init_db(db: PuzzleDatabase, cancel_import: Arc<AtomicBool>)
         log::info!(
             "Puzzle database not fully initialised, initialising from lichess puzzles database in background");
 
-        // Download the lichess database.
-        let mut lichess_db = download_puzzle_db(cancel_import.clone()).await
-            .map_err(|e| format!("Failed to download lichess puzzle database: {e}"))?;
-        lichess_db.seek(SeekFrom::Start(0))
-            .map_err(|e| format!("Failed to seek lichess db file: {e}"))?;
+        // Download the lichess database, or open it if it's local.
+        let lichess_db = open_puzzle_db(&config, cancel_import.clone()).await?;
 
         // Initialise our database with it.
         if !cancel_import.load(Ordering::Relaxed) {
@@ -44,188 +98,926 @@ pub async fn init_db(db: PuzzleDatabase, cancel_import: Arc<AtomicBool>)
     }
 }
 
-/// Download the lichess puzzles db as a temporary file.
-async fn download_puzzle_db(cancel_import: Arc<AtomicBool>) -> Result<File, String> {
-    const LICHESS_DB_NAME: &'static str = "lichess_db_puzzle.csv.zst";
-    const LICHESS_DB_URL: &'static str = "https://database.lichess.org/lichess_db_puzzle.csv.zst";
+/// Refresh the puzzle db from a newer version of the lichess puzzle database, updating the puzzles
+/// that have changed and adding new ones. Unless `force` is set, it's only refreshed if the source
+/// has a newer date than the last import. Returns Ok(true) if it was refreshed, or Ok(false) if it
+/// was already up to date or the refresh was cancelled.
+pub async fn refresh_db(db: PuzzleDatabase, config: PuzzleDbConfig, cancel_import: Arc<AtomicBool>,
+    force: bool) -> Result<bool, String>
+{
+    let guard = ImportGuard::acquire()
+        .ok_or_else(|| "A puzzle database import is already running".to_string())?;
+
+    refresh_db_with_guard(guard, db, config, cancel_import, force).await
+}
+
+/// Refresh the puzzle db like `refresh_db`, with the import already marked as running by `guard`,
+/// so that callers can check that no other import is running before they start it.
+pub async fn refresh_db_with_guard(_guard: ImportGuard, db: PuzzleDatabase, config: PuzzleDbConfig,
+    cancel_import: Arc<AtomicBool>, force: bool) -> Result<bool, String>
+{
+    let app_data = db.get_app_data("").await
+        .map_err(|e| format!("Failed to get app data: {e}"))?
+        .ok_or_else(|| "No app_data row in database".to_string())?;
+
+    if !app_data.lichess_db_imported {
+        return Err("The puzzle database hasn't been imported yet".to_string());
+    }
+
+    if !force {
+        match (puzzle_db_date(&config).await?, app_data.lichess_db_date) {
+            (None, _) => {
+                log::info!("Not refreshing the puzzle database, as the date of {:?} isn't known",
+                    config.source);
+                return Ok(false);
+            },
+            (Some(date), Some(imported_date)) if date <= imported_date => {
+                log::info!("The puzzle database is up to date ({imported_date})");
+                return Ok(false);
+            },
+            _ => {},
+        }
+    }
+
+    log::info!("Refreshing puzzle database from lichess puzzles database in background");
+
+    let lichess_db = open_puzzle_db(&config, cancel_import.clone()).await?;
+    if cancel_import.load(Ordering::Relaxed) {
+        return Ok(false);
+    }
+
+    import_lichess_database(db, lichess_db, cancel_import).await
+        .map_err(|e| format!("Failed to import lichess puzzle db: {e}"))
+}
+
+/// Get the date of the puzzle db at the configured source without downloading it, if it's known.
+async fn puzzle_db_date(config: &PuzzleDbConfig) -> Result<Option<DateTime<FixedOffset>>, String> {
+    match &config.source {
+        PuzzleDbSource::Url(url) if local_puzzle_db(url, Path::new(".")).is_none() && config.download => {
+            let client = reqwest::Client::builder()
+                .user_agent(crate::app::APP_USER_AGENT)
+                .build()
+                .map_err(|e| format!("Failed to create reqwest client: {e}"))?;
+
+            let response = client.head(url.clone()).send().await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| format!("Failed to request lichess puzzle db: {e}"))?;
+
+            Ok(last_modified(response.headers()))
+        },
+        _ => Ok(open_puzzle_db(config, Arc::new(AtomicBool::new(false))).await?.date),
+    }
+}
+
+/// Open the lichess puzzles db from the configured source, downloading it if it's a url.
+async fn open_puzzle_db(config: &PuzzleDbConfig, cancel_import: Arc<AtomicBool>)
+    -> Result<PuzzleDbFile, String>
+{
+    let file = match &config.source {
+        PuzzleDbSource::Path(path) if path.is_dir() => find_puzzle_db(path)
+            .ok_or_else(|| format!("No puzzle database found in directory {}, expected one of {}",
+                path.display(), LICHESS_DB_NAMES.join(", ")))?,
+        PuzzleDbSource::Path(path) => File::open(path)
+            .map_err(|e| format!("Failed to open puzzle database {}: {e}", path.display()))?,
+        PuzzleDbSource::Url(url) => {
+            // If the puzzle db is just already in the current working directory, just use that.
+            if let Some(file) = local_puzzle_db(url, Path::new(".")) {
+                return Ok(PuzzleDbFile::local(file));
+            }
+
+            if !config.download {
+                return Err(format!("Downloading the puzzle database is disabled, and it isn't in the \
+                    working directory. Set PUZZLE_DB_SOURCE to a local copy of {url}"));
+            }
+
+            return download_puzzle_db(url, config, cancel_import).await
+                .map_err(|e| format!("Failed to download lichess puzzle database: {e}"));
+        },
+    };
 
-    const BYTES_PER_MEGABYTE: usize = 1024 * 1024;
-    const BYTES_PER_PROGRESS_REPORT: usize = 25 * BYTES_PER_MEGABYTE;
+    Ok(PuzzleDbFile::local(file))
+}
 
-    // If the puzzle db is just already in the current working directory, just use that.
-    if let Ok(file) = File::open(LICHESS_DB_NAME) {
-        return Ok(file);
+impl PuzzleDbFile {
+    /// A local copy of the puzzle db, which is dated by when it was last modified.
+    fn local(file: File) -> Self {
+        let date = file.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| DateTime::<Local>::from(modified).fixed_offset());
+
+        Self { file, date }
     }
 
-    // Otherwise, create a temporary file and download it.
-    let mut file = tempfile::tempfile()
-        .map_err(|e| format!("Failed to create temp file: {e}"))?;
+    /// Identifies this copy of the puzzle db by its size and date, so that an import of it can be
+    /// resumed, but an import of a different one isn't.
+    fn identity(&self) -> Result<String, String> {
+        let size = self.file.metadata()
+            .map_err(|e| format!("Failed to get puzzle database metadata: {e}"))?
+            .len();
+        let date = self.date.map_or("unknown date".to_string(), |date| date.to_rfc3339());
 
-    log::info!("Downloading {LICHESS_DB_URL}");
+        Ok(format!("{size} bytes, {date}"))
+    }
+}
+
+/// Get the date from a Last-Modified header, if there is one.
+fn last_modified(headers: &HeaderMap) -> Option<DateTime<FixedOffset>> {
+    headers.get(LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+}
+
+/// Find the puzzle db in a directory, if it's there.
+fn find_puzzle_db(dir: &Path) -> Option<File> {
+    LICHESS_DB_NAMES
+        .iter()
+        .find_map(|name| File::open(dir.join(name)).ok())
+}
+
+/// Find a local copy of the puzzle db at a url in a directory. Only the lichess puzzle db can have
+/// one, as other urls may serve a different database under the same file name.
+fn local_puzzle_db(url: &Url, dir: &Path) -> Option<File> {
+    match url.as_str() == LICHESS_DB_URL {
+        true => find_puzzle_db(dir),
+        false => None,
+    }
+}
+
+/// Download the lichess puzzles db into the cache directory. A download that was stopped partway
+/// through is resumed, and a completed one is reused if it's still the latest version.
+async fn download_puzzle_db(url: &Url, config: &PuzzleDbConfig, cancel_import: Arc<AtomicBool>)
+    -> Result<PuzzleDbFile, String>
+{
+    const BYTES_PER_MEGABYTE: u64 = 1024 * 1024;
+    const BYTES_PER_PROGRESS_REPORT: u64 = 25 * BYTES_PER_MEGABYTE;
+
+    std::fs::create_dir_all(&config.cache_dir)
+        .map_err(|e| format!("Failed to create cache directory {}: {e}", config.cache_dir.display()))?;
+
+    let file_name = url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(LICHESS_DB_NAMES[0]);
+    let path = config.cache_dir.join(file_name);
+    let partial_path = config.cache_dir.join(format!("{file_name}.part"));
+    let validator_path = config.cache_dir.join(format!("{file_name}.part.validator"));
 
     let client = reqwest::Client::builder()
         .user_agent(crate::app::APP_USER_AGENT)
         .build()
         .map_err(|e| format!("Failed to create reqwest client: {e}"))?;
 
-    let response = client.get(LICHESS_DB_URL).send().await
+    // Find out which version of the puzzle db is there now.
+    let response = client.head(url.clone()).send().await
+        .and_then(|response| response.error_for_status())
         .map_err(|e| format!("Failed to request lichess puzzle db: {e}"))?;
 
-    let total_length_mb = response.content_length()
-        .map(|bytes| (bytes as usize / BYTES_PER_MEGABYTE).to_string())
-        .unwrap_or("?".to_string());
-
-    let mut response_stream = response.bytes_stream();
+    let date = last_modified(response.headers());
+    let total_length = content_length(response.headers());
+
+    // The ETag or Last-Modified date identifies the version, so that a partial download is only
+    // resumed if it hasn't changed since.
+    let validator = response.headers().get(ETAG)
+        .or(response.headers().get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+
+    // Completed downloads are dated by the Last-Modified date, so we can tell if it's still current.
+    if let (Some(date), Some(total_length), Ok(file)) = (date, total_length, File::open(&path)) {
+        let cached = PuzzleDbFile::local(file);
+        let cached_length = cached.file.metadata().map(|metadata| metadata.len()).ok();
+        if cached.date == Some(date) && cached_length == Some(total_length) {
+            log::info!("Using previously downloaded {}", path.display());
+            return Ok(cached);
+        }
+    }
 
-    let mut counter = 0;
-    let mut bytes_since_reported = 0;
-    while let Some(v) = response_stream.next().await {
-        let bytes = v
-            .map_err(|e| format!("Failed to read byte stream: {e}"))?;
-        counter += bytes.len();
-        bytes_since_reported += bytes.len();
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+        .open(&partial_path)
+        .map_err(|e| format!("Failed to open {}: {e}", partial_path.display()))?;
+    let partial_length = file.metadata()
+        .map_err(|e| format!("Failed to get metadata of {}: {e}", partial_path.display()))?
+        .len();
+
+    let resume_from = match (&validator, std::fs::read_to_string(&validator_path)) {
+        (Some(validator), Ok(partial_validator)) if *validator == partial_validator => partial_length,
+        _ => 0,
+    };
+
+    let mut offset = resume_from;
+    if Some(resume_from) != total_length || resume_from == 0 {
+        let mut request = client.get(url.clone());
+        if resume_from > 0 {
+            log::info!("Resuming download of {url} from {}MB", resume_from / BYTES_PER_MEGABYTE);
+            request = request
+                .header(RANGE, format!("bytes={resume_from}-"))
+                .header(IF_RANGE, validator.clone().unwrap_or_default());
+        }
+        else {
+            log::info!("Downloading {url}");
+        }
 
-        file.write_all(&bytes)
-            .map_err(|e| format!("Failed to write bytes to temp file: {e}"))?;
+        let response = request.send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to request lichess puzzle db: {e}"))?;
+
+        // The server sends the whole file instead if it's changed, or if it doesn't support ranges.
+        offset = match response.status() {
+            StatusCode::PARTIAL_CONTENT => content_range_start(response.headers())
+                .filter(|start| *start == resume_from)
+                .ok_or_else(|| "Server sent the wrong range of the puzzle db".to_string())?,
+            _ => 0,
+        };
+
+        file.set_len(offset)
+            .and_then(|_| file.seek(SeekFrom::Start(offset)))
+            .map_err(|e| format!("Failed to write to {}: {e}", partial_path.display()))?;
+
+        match &validator {
+            Some(validator) => std::fs::write(&validator_path, validator),
+            None => std::fs::remove_file(&validator_path).or(Ok(())),
+        }.map_err(|e| format!("Failed to write {}: {e}", validator_path.display()))?;
+
+        let total_length = total_length
+            .or(content_length(response.headers()).map(|length| offset + length));
+        let total_length_mb = total_length
+            .map(|bytes| (bytes / BYTES_PER_MEGABYTE).to_string())
+            .unwrap_or("?".to_string());
+
+        let mut response_stream = response.bytes_stream();
+
+        let mut bytes_since_reported = 0;
+        while let Some(v) = response_stream.next().await {
+            let bytes = v
+                .map_err(|e| format!("Failed to read byte stream: {e}"))?;
+            offset += bytes.len() as u64;
+            bytes_since_reported += bytes.len() as u64;
+
+            file.write_all(&bytes)
+                .map_err(|e| format!("Failed to write bytes to {}: {e}", partial_path.display()))?;
+
+            while bytes_since_reported > BYTES_PER_PROGRESS_REPORT {
+                if cancel_import.load(Ordering::Relaxed) {
+                    return Ok(PuzzleDbFile { file, date });
+                }
 
-        while bytes_since_reported > BYTES_PER_PROGRESS_REPORT {
-            if cancel_import.load(Ordering::Relaxed) {
-                return Ok(file);
+                log::info!("Lichess puzzle database: {}/{}MB downloaded",
+                           offset / BYTES_PER_MEGABYTE,
+                           total_length_mb);
+                bytes_since_reported -= BYTES_PER_PROGRESS_REPORT;
             }
+        }
+    }
+
+    // Check that we got all of it. If we got too much or the checksum is wrong, the partial
+    // download is no good, so it's removed to start over next time.
+    let discard = || {
+        let _ = std::fs::remove_file(&partial_path);
+        let _ = std::fs::remove_file(&validator_path);
+    };
 
-            log::info!("Lichess puzzle database: {}/{}MB downloaded",
-                       counter / BYTES_PER_MEGABYTE,
-                       total_length_mb);
-            bytes_since_reported -= BYTES_PER_PROGRESS_REPORT;
+    if let Some(total_length) = total_length {
+        if offset > total_length {
+            discard();
         }
+        if offset != total_length {
+            return Err(format!("Downloaded {offset} bytes, expected {total_length}"));
+        }
+    }
+
+    if let Some(expected_sha256) = &config.sha256 {
+        let sha256 = file_sha256(&mut file)?;
+        if sha256 != *expected_sha256 {
+            discard();
+            return Err(format!("Checksum mismatch, expected sha256 {expected_sha256} but got {sha256}"));
+        }
+    }
+
+    log::info!("Downloaded {offset} bytes");
+
+    // Keep the completed download for future imports.
+    if let Some(date) = date {
+        filetime::set_file_mtime(&partial_path, FileTime::from_unix_time(date.timestamp(), 0))
+            .map_err(|e| format!("Failed to set date of {}: {e}", partial_path.display()))?;
     }
+    std::fs::rename(&partial_path, &path)
+        .map_err(|e| format!("Failed to rename {} to {}: {e}", partial_path.display(), path.display()))?;
+    let _ = std::fs::remove_file(&validator_path);
+
+    Ok(PuzzleDbFile::local(file))
+}
+
+/// Get the length from a Content-Length header, if there is one.
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
 
-    log::info!("Downloaded {counter} bytes");
+/// Get the start of the range from a Content-Range header, e.g. 100 for "bytes 100-199/200".
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("bytes "))
+        .and_then(|value| value.split_once('-'))
+        .and_then(|(start, _)| start.parse().ok())
+}
+
+/// Get the sha256 of a file as a lowercase hex string.
+fn file_sha256(file: &mut File) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| std::io::copy(file, &mut hasher))
+        .map_err(|e| format!("Failed to read puzzle database: {e}"))?;
 
-    Ok(file)
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 /// Import lichess database from file.
-async fn import_lichess_database(mut db: PuzzleDatabase, lichess_db_raw: File,
-    cancel_import: Arc<AtomicBool>) -> Result<(), String>
+async fn import_lichess_database(mut db: PuzzleDatabase, lichess_db: PuzzleDbFile,
+    cancel_import: Arc<AtomicBool>) -> Result<bool, String>
 {
     /// The total number of puzzles in the database. It's a shame to have to have this hardcoded
     /// here, but there's no easy way to tell as we're reading it since we're streaming it from a
     /// file, and this should work in the majority of cases. If it ever changes significantly, we
-    /// can just update it.
+    /// can just update it. When refreshing, the number of puzzles in the last import is used
+    /// instead.
     const TOTAL_PUZZLES: usize = 3_500_000;
 
-    const PUZZLES_PER_IMPORT_BATCH: usize = 10000;
+    // Batches are small in tests, so that the import checkpoints can be tested without a huge csv.
+    const PUZZLES_PER_IMPORT_BATCH: usize = if cfg!(test) { 10 } else { 10000 };
     const PUZZLES_PER_PROGRESS_UPDATE: usize = 100000;
 
-    // We expect 10 rows per puzzle entry.
-    const EXPECTED_ROWS: usize = 10;
-
     log::info!("Importing lichess puzzle database in background...");
 
-    if let Ok(decoder) = zstd::stream::Decoder::new(lichess_db_raw) {
-        let mut csv_reader = csv::Reader::from_reader(decoder);
+    let total_puzzles = db.get_app_data("").await
+        .map_err(|e| format!("Failed to get app data: {e}"))?
+        .and_then(|app_data| app_data.lichess_db_rows)
+        .map_or(TOTAL_PUZZLES, |rows| rows as usize);
+
+    let source = lichess_db.identity()?;
+    let open_reader = || -> Result<_, String> {
+        let file = lichess_db.file.try_clone()
+            .map_err(|e| format!("Failed to open puzzle database: {e}"))?;
+        Ok(csv::Reader::from_reader(puzzle_db_reader(file)?))
+    };
+
+    let mut csv_reader = open_reader()?;
+
+    // If an import of this same database was stopped partway through, skip the rows it already
+    // imported, as long as they're the rows we expect.
+    let checkpoint = db.get_import_checkpoint().await
+        .map_err(|e| format!("Failed to get import checkpoint: {e}"))?
+        .filter(|checkpoint| checkpoint.source == source);
+
+    let checkpoint = match checkpoint {
+        Some(checkpoint) if skip_to_checkpoint(&mut csv_reader, &checkpoint)? => {
+            log::info!("Resuming import after {} puzzles", checkpoint.rows);
+            Some(checkpoint)
+        },
+        Some(_) => {
+            log::warn!("The puzzle database doesn't match the import checkpoint, starting over");
+            csv_reader = open_reader()?;
+            None
+        },
+        None => None,
+    };
+
+    // Keep track of which puzzles are in this version of the database, so that the ones that
+    // aren't can be marked as removed at the end. When resuming, the ones from before the
+    // checkpoint are already in there.
+    if checkpoint.is_none() {
+        db.clear_imported_lichess_puzzles().await
+            .map_err(|e| format!("Failed to clear imported puzzles: {e}"))?;
+    }
 
-        let mut puzzles_imported = 0;
-        let mut last_report = 0;
+    let mut rows_read = checkpoint.map_or(0, |checkpoint| checkpoint.rows as usize);
+    let mut puzzles_imported = rows_read;
+    let mut last_report = rows_read;
 
-        let mut puzzles = Vec::new();
-        let mut record: StringRecord = StringRecord::new();
+    let mut puzzles = Vec::new();
+    let mut record: StringRecord = StringRecord::new();
 
-        loop {
-            // Check if termination requested.
-            if cancel_import.load(Ordering::Relaxed) {
-                log::info!("Lichess puzzle database import cancelled.");
-                log::info!(concat!("The process will resume next time the application starts, but ",
-                                   "it's recommended to let it complete fully."));
-                return Ok(());
-            }
+    loop {
+        // Check if termination requested.
+        if cancel_import.load(Ordering::Relaxed) {
+            log::info!("Lichess puzzle database import cancelled.");
+            log::info!(concat!("The process will resume next time the application starts, but ",
+                               "it's recommended to let it complete fully."));
+            return Ok(false);
+        }
 
-            let read_record = csv_reader.read_record(&mut record).map_err(|e| {
-                format!("CSV parse error when importing lichess puzzles database: {e}")
-            })?;
+        let read_record = csv_reader.read_record(&mut record).map_err(|e| {
+            format!("CSV parse error when importing lichess puzzles database: {e}")
+        })?;
 
-            if !read_record {
-                break;
-            }
+        if !read_record {
+            break;
+        }
+        rows_read += 1;
 
-            if record.len() != EXPECTED_ROWS {
-                log::warn!("Skipping record with {} entries, expected at least {}",
-                    record.len(), EXPECTED_ROWS);
-                continue;
-            }
+        if record.len() != EXPECTED_ROWS {
+            log::warn!("Skipping record with {} entries, expected at least {}",
+                record.len(), EXPECTED_ROWS);
+            continue;
+        }
 
-            // Import puzzle.
-            let puzzle_id = record[0].to_string();
-            let fen = record[1].to_string();
-            let moves = record[2].to_string();
-            let rating = record[3].parse()
-                .map_err(|e| format!("Failed to parse rating field {e}"))?;
-            let rating_deviation = record[4].parse()
-                .map_err(|e| format!("Failed to parse rating_deviation field {e}"))?;
-            let popularity = record[5].parse()
-                .map_err(|e| format!("Failed to parse popularity field {e}"))?;
-            let number_of_plays = record[6].parse()
-                .map_err(|e| format!("Failed to parse number_of_plays field {e}"))?;
-            let themes = record[7].to_string().split_whitespace().map(ToString::to_string).collect();
-            let game_url = record[8].to_string();
-            let opening_tags = record[9].to_string().split_whitespace().map(ToString::to_string).collect();
-
-            puzzles.push(Puzzle {
-                puzzle_id,
-                fen,
-                moves,
-                rating,
-                rating_deviation,
-                popularity,
-                number_of_plays,
-                themes,
-                game_url,
-                opening_tags,
-            });
-            puzzles_imported += 1;
-
-            // Bulk insert if we have enough.
-            if puzzles.len() >= PUZZLES_PER_IMPORT_BATCH {
-                if puzzles_imported == PUZZLES_PER_IMPORT_BATCH {
-                    log::info!("Importing first puzzle batch...");
-                }
+        puzzles.push(puzzle_from_record(&record, record[0].to_string(), LICHESS_SOURCE)?);
+        puzzles_imported += 1;
 
-                db.add_puzzles(&puzzles).await
-                    .map_err(|e| format!("Failed to add puzzles to db: {e}"))?;
-                puzzles.clear();
+        // Bulk insert if we have enough.
+        if puzzles.len() >= PUZZLES_PER_IMPORT_BATCH {
+            if puzzles_imported == PUZZLES_PER_IMPORT_BATCH {
+                log::info!("Importing first puzzle batch...");
             }
 
-            if puzzles_imported - last_report >= PUZZLES_PER_PROGRESS_UPDATE {
-                last_report = puzzles_imported;
+            db.add_puzzles(&puzzles).await
+                .map_err(|e| format!("Failed to add puzzles to db: {e}"))?;
 
-                // Calculate imported percent.
-                let percent = 100.0 * puzzles_imported as f64 / TOTAL_PUZZLES as f64;
-                // Round it to the nearest .5.
-                let percent = f64::floor(2.0 * percent) / 2.0;
+            // The batch was just completed by the row we've just read, so the import can be resumed
+            // from here.
+            let checkpoint = ImportCheckpoint {
+                source: source.clone(),
+                rows: rows_read as i64,
+                last_puzzle_id: record[0].to_string(),
+            };
+            db.set_import_checkpoint(Some(&checkpoint)).await
+                .map_err(|e| format!("Failed to set import checkpoint: {e}"))?;
 
-                log::info!("Puzzle database: {puzzles_imported} puzzles ({percent:.1}%) imported...");
-            }
+            puzzles.clear();
         }
 
-        // Add last batch (should be less than the batch size or it'll be empty).
-        if !puzzles.is_empty() {
-            db.add_puzzles(&puzzles).await
-                .map_err(|e| format!("Failed to add puzzles to db: {e}"))?;
-            puzzles.clear();
+        if puzzles_imported - last_report >= PUZZLES_PER_PROGRESS_UPDATE {
+            last_report = puzzles_imported;
+
+            // Calculate imported percent.
+            let percent = 100.0 * puzzles_imported as f64 / total_puzzles as f64;
+            // Round it to the nearest .5.
+            let percent = f64::floor(2.0 * percent) / 2.0;
+
+            log::info!("Puzzle database: {puzzles_imported} puzzles ({percent:.1}%) imported...");
         }
+    }
+
+    // Add last batch (should be less than the batch size or it'll be empty).
+    if !puzzles.is_empty() {
+        db.add_puzzles(&puzzles).await
+            .map_err(|e| format!("Failed to add puzzles to db: {e}"))?;
+        puzzles.clear();
+    }
+
+    // Puzzles that aren't in the database anymore are kept, as the user might have cards for them.
+    let removed = db.mark_removed_lichess_puzzles().await
+        .map_err(|e| format!("Failed to mark removed puzzles: {e}"))?;
+
+    // Update flag in db to say the puzzles table is initialised, and record which version of the
+    // database it's from.
+    let mut app_data = db.get_app_data("").await
+        .map_err(|e| format!("Failed to get app data: {e}"))?
+        .ok_or_else(|| "No app_data row in database".to_string())?;
+    app_data.lichess_db_imported = true;
+    app_data.lichess_db_date = lichess_db.date;
+    app_data.lichess_db_rows = Some(puzzles_imported as i64);
+    db.set_app_data(&app_data).await
+        .map_err(|e| format!("Failed to update app data: {e}"))?;
+    db.set_import_checkpoint(None).await
+        .map_err(|e| format!("Failed to clear import checkpoint: {e}"))?;
+
+    log::info!("Finished importing {puzzles_imported} puzzles");
+    if removed > 0 {
+        log::info!("{removed} puzzles aren't in the lichess puzzle database anymore");
+    }
 
-        // Update flag in db to say the puzzles table is initialised.
-        let mut app_data = db.get_app_data("").await
-            .map_err(|e| format!("Failed to get app data: {e}"))?
-            .ok_or_else(|| format!("No app_data row in database"))?;
-        app_data.lichess_db_imported = true;
-        db.set_app_data(&app_data).await
-            .map_err(|e| format!("Failed to update app data: {e}"))?;
+    Ok(true)
+}
+
+/// We expect 10 rows per puzzle entry.
+pub const EXPECTED_ROWS: usize = 10;
+
+/// Read a puzzle from a record of a csv in the lichess puzzle database's format, giving it the
+/// specified ID and source.
+pub fn puzzle_from_record(record: &StringRecord, puzzle_id: String, source: &str)
+    -> Result<Puzzle, String>
+{
+    let rating = record[3].parse()
+        .map_err(|e| format!("Failed to parse rating field {e}"))?;
+    let rating_deviation = record[4].parse()
+        .map_err(|e| format!("Failed to parse rating_deviation field {e}"))?;
+    let popularity = record[5].parse()
+        .map_err(|e| format!("Failed to parse popularity field {e}"))?;
+    let number_of_plays = record[6].parse()
+        .map_err(|e| format!("Failed to parse number_of_plays field {e}"))?;
+
+    Ok(Puzzle {
+        puzzle_id,
+        fen: record[1].to_string(),
+        moves: record[2].to_string(),
+        rating,
+        rating_deviation,
+        popularity,
+        number_of_plays,
+        themes: record[7].split_whitespace().map(ToString::to_string).collect(),
+        game_url: record[8].to_string(),
+        opening_tags: record[9].split_whitespace().map(ToString::to_string).collect(),
+        source: source.to_string(),
+    })
+}
 
-        log::info!("Finished importing {puzzles_imported} puzzles");
+/// Skip the rows of the puzzle db that were imported before the checkpoint. Returns false if the
+/// last of them isn't the puzzle the checkpoint was at, in which case it's not safe to resume.
+fn skip_to_checkpoint<R: Read>(csv_reader: &mut csv::Reader<R>, checkpoint: &ImportCheckpoint)
+    -> Result<bool, String>
+{
+    let mut record = StringRecord::new();
+    for _ in 0..checkpoint.rows {
+        let read_record = csv_reader.read_record(&mut record).map_err(|e| {
+            format!("CSV parse error when importing lichess puzzles database: {e}")
+        })?;
+
+        if !read_record {
+            return Ok(false);
+        }
     }
 
-    Ok(())
+    Ok(checkpoint.rows > 0 && record.get(0) == Some(checkpoint.last_puzzle_id.as_str()))
+}
+
+/// Get a reader for the puzzle db csv from the start of the file, decompressing it if it's
+/// compressed with zstd.
+fn puzzle_db_reader(mut file: File) -> Result<Box<dyn Read + Send>, String> {
+    let mut magic = [0; ZSTD_MAGIC.len()];
+    file.seek(SeekFrom::Start(0))
+        .map_err(|e| format!("Failed to seek lichess db file: {e}"))?;
+    let compressed = file.read_exact(&mut magic).is_ok() && magic == ZSTD_MAGIC;
+    file.seek(SeekFrom::Start(0))
+        .map_err(|e| format!("Failed to seek lichess db file: {e}"))?;
+
+    match compressed {
+        true => Ok(Box::new(zstd::stream::Decoder::new(file)
+            .map_err(|e| format!("Failed to create zstd decoder: {e}"))?)),
+        false => Ok(Box::new(file)),
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::AtomicBool;
+
+    use axum::extract::State;
+    use axum::http::{header, HeaderMap, Method, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use sha2::{Digest, Sha256};
+    use url::Url;
+
+    use crate::app::{PuzzleDbConfig, PuzzleDbSource};
+    use crate::db::{PuzzleDatabase, PuzzleFilter, ImportCheckpoint};
+    use crate::lichess::{open_puzzle_db, puzzle_db_reader, init_db, refresh_db, import_lichess_database,
+        download_puzzle_db, local_puzzle_db, LICHESS_DB_URL};
+    use crate::srs::SrsConfig;
+
+    /// A small copy of the puzzle db with 25 puzzles, 00001 to 00025.
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/lichess_db_puzzle.csv.zst");
+
+    const CSV: &str = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags\n\
+        00008,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1913,75,94,6230,crushing hangingPiece long middlegame,https://lichess.org/787zsVup/black#48,\n";
+
+    fn read_all(file: std::fs::File) -> String {
+        let mut csv = String::new();
+        puzzle_db_reader(file).unwrap().read_to_string(&mut csv).unwrap();
+        csv
+    }
+
+    #[test]
+    fn test_puzzle_db_reader() {
+        // Uncompressed csv is read as is.
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(CSV.as_bytes()).unwrap();
+        assert_eq!(read_all(file), CSV);
+
+        // Compressed csv is decompressed.
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&zstd::encode_all(CSV.as_bytes(), 0).unwrap()).unwrap();
+        assert_eq!(read_all(file), CSV);
+
+        // Files too short to have the zstd magic number are fine too.
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"a,b").unwrap();
+        assert_eq!(read_all(file), "a,b");
+    }
+
+    #[tokio::test]
+    async fn test_open_puzzle_db() {
+        let cancel_import = Arc::new(AtomicBool::new(false));
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = |source: &str, download| PuzzleDbConfig {
+            source: source.parse().unwrap(),
+            download,
+            ..Default::default()
+        };
+
+        // A directory without the puzzle db in it.
+        let dir_path = dir.path().to_str().unwrap();
+        assert!(open_puzzle_db(&config(dir_path, true), cancel_import.clone()).await.is_err());
+
+        // A directory containing the uncompressed puzzle db, or the path to it directly.
+        std::fs::write(dir.path().join("lichess_db_puzzle.csv"), CSV).unwrap();
+        let file = open_puzzle_db(&config(dir_path, true), cancel_import.clone()).await.unwrap();
+        assert!(file.date.is_some());
+        assert_eq!(read_all(file.file), CSV);
+
+        let file_path = dir.path().join("lichess_db_puzzle.csv");
+        let file = open_puzzle_db(&config(file_path.to_str().unwrap(), true), cancel_import.clone())
+            .await.unwrap();
+        assert_eq!(read_all(file.file), CSV);
+
+        // Only the lichess puzzle db url uses a copy in the directory.
+        assert!(local_puzzle_db(&Url::parse(LICHESS_DB_URL).unwrap(), dir.path()).is_some());
+        let url = Url::parse("https://example.invalid/lichess_db_puzzle.csv.zst").unwrap();
+        assert!(local_puzzle_db(&url, dir.path()).is_none());
+
+        // Urls aren't downloaded if downloading is disabled.
+        let config = config("https://example.invalid/puzzles.csv.zst", false);
+        assert_eq!(config.source,
+            PuzzleDbSource::Url(Url::parse("https://example.invalid/puzzles.csv.zst").unwrap()));
+        let error = open_puzzle_db(&config, cancel_import).await.unwrap_err();
+        assert!(error.contains("disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_db() {
+        const HEADER: &str = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags\n";
+        const PUZZLE_A: &str = "0000A,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1913,75,94,6230,crushing long,https://lichess.org/787zsVup/black#48,\n";
+        const PUZZLE_B: &str = "0000B,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1500,75,94,6230,long,https://lichess.org/787zsVup/black#48,\n";
+        const PUZZLE_C: &str = "0000C,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1700,75,94,6230,long,https://lichess.org/787zsVup/black#48,\n";
+
+        let cancel_import = Arc::new(AtomicBool::new(false));
+        let dir = tempfile::tempdir().unwrap();
+        let db_url = Url::parse(&format!("sqlite://{}/puzzles.sqlite", dir.path().display())).unwrap();
+        let db = PuzzleDatabase::open(&db_url, SrsConfig::default()).await.unwrap();
+
+        let config = PuzzleDbConfig {
+            source: PuzzleDbSource::Path(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let db_path = dir.path().join("lichess_db_puzzle.csv");
+
+        // The initial import records how many puzzles there were.
+        std::fs::write(&db_path, [HEADER, PUZZLE_A, PUZZLE_B].concat()).unwrap();
+        assert!(init_db(db.clone(), config.clone(), cancel_import.clone()).await.unwrap());
+        let app_data = db.get_app_data("").await.unwrap().unwrap();
+        assert!(app_data.lichess_db_imported);
+        assert!(app_data.lichess_db_date.is_some());
+        assert_eq!(app_data.lichess_db_rows, Some(2));
+
+        // It isn't refreshed if the database hasn't changed, unless it's forced.
+        assert!(!refresh_db(db.clone(), config.clone(), cancel_import.clone(), false).await.unwrap());
+
+        // Puzzle A's rating changes, puzzle B is removed and puzzle C is added.
+        let puzzle_a = PUZZLE_A.replace(",1913,", ",1950,");
+        std::fs::write(&db_path, [HEADER, &puzzle_a, PUZZLE_C].concat()).unwrap();
+        assert!(refresh_db(db.clone(), config.clone(), cancel_import.clone(), true).await.unwrap());
+
+        let app_data = db.get_app_data("").await.unwrap().unwrap();
+        assert_eq!(app_data.lichess_db_rows, Some(2));
+        assert_eq!(db.get_puzzle_by_id("0000A").await.unwrap().unwrap().rating, 1950);
+        assert!(db.get_puzzle_by_id("0000C").await.unwrap().is_some());
+
+        // Puzzle B is kept, but isn't picked as a new puzzle anymore.
+        assert!(db.get_puzzle_by_id("0000B").await.unwrap().is_some());
+        for seed in 0..10 {
+            let puzzle = db.get_seeded_puzzle("local", seed, &PuzzleFilter::default()).await.unwrap().unwrap();
+            assert_ne!(puzzle.puzzle_id, "0000B");
+        }
+
+        // It comes back if it's in the database again.
+        std::fs::write(&db_path, [HEADER, &puzzle_a, PUZZLE_B, PUZZLE_C].concat()).unwrap();
+        assert!(refresh_db(db.clone(), config, cancel_import, true).await.unwrap());
+        let mut puzzle_ids = Vec::new();
+        for seed in 0..10 {
+            let puzzle = db.get_seeded_puzzle("local", seed, &PuzzleFilter::default()).await.unwrap().unwrap();
+            puzzle_ids.push(puzzle.puzzle_id);
+        }
+        assert!(puzzle_ids.contains(&"0000B".to_string()));
+    }
+
+    async fn open_test_db(dir: &tempfile::TempDir) -> PuzzleDatabase {
+        let db_url = Url::parse(&format!("sqlite://{}/puzzles.sqlite", dir.path().display())).unwrap();
+        PuzzleDatabase::open(&db_url, SrsConfig::default()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_checkpoint() {
+        let cancel_import = Arc::new(AtomicBool::new(false));
+        let dir = tempfile::tempdir().unwrap();
+        let db = open_test_db(&dir).await;
+
+        let config = PuzzleDbConfig {
+            source: PuzzleDbSource::Path(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        // An import that fails partway through keeps the puzzles from the batches it completed, and
+        // records where it got to.
+        let csv = String::from_utf8(zstd::decode_all(FIXTURE).unwrap()).unwrap();
+        let broken = csv.replace(",1700,", ",invalid,");
+        std::fs::write(dir.path().join("lichess_db_puzzle.csv.zst"),
+            zstd::encode_all(broken.as_bytes(), 0).unwrap()).unwrap();
+        assert!(init_db(db.clone(), config.clone(), cancel_import.clone()).await.is_err());
+
+        let checkpoint = db.get_import_checkpoint().await.unwrap().unwrap();
+        assert_eq!(checkpoint.rows, 10);
+        assert_eq!(checkpoint.last_puzzle_id, "00010");
+        assert_eq!(db.get_puzzle_count().await.unwrap(), 10);
+        assert!(!db.get_app_data("").await.unwrap().unwrap().lichess_db_imported);
+
+        // Setting the app data doesn't lose the checkpoint.
+        let app_data = db.get_app_data("").await.unwrap().unwrap();
+        db.set_app_data(&app_data).await.unwrap();
+        assert_eq!(db.get_import_checkpoint().await.unwrap(), Some(checkpoint));
+    }
+
+    #[tokio::test]
+    async fn test_resume_import() {
+        let cancel_import = Arc::new(AtomicBool::new(false));
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lichess_db_puzzle.csv.zst"), FIXTURE).unwrap();
+
+        let config = PuzzleDbConfig {
+            source: PuzzleDbSource::Path(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let source = open_puzzle_db(&config, cancel_import.clone()).await.unwrap().identity().unwrap();
+
+        // With a checkpoint after the first 20 puzzles, only the last 5 are imported.
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = open_test_db(&db_dir).await;
+        db.set_import_checkpoint(Some(&ImportCheckpoint {
+            source: source.clone(),
+            rows: 20,
+            last_puzzle_id: "00020".to_string(),
+        })).await.unwrap();
+
+        let lichess_db = open_puzzle_db(&config, cancel_import.clone()).await.unwrap();
+        assert!(import_lichess_database(db.clone(), lichess_db, cancel_import.clone()).await.unwrap());
+        assert_eq!(db.get_puzzle_count().await.unwrap(), 5);
+        assert!(db.get_puzzle_by_id("00020").await.unwrap().is_none());
+        assert!(db.get_puzzle_by_id("00021").await.unwrap().is_some());
+
+        let app_data = db.get_app_data("").await.unwrap().unwrap();
+        assert!(app_data.lichess_db_imported);
+        assert_eq!(app_data.lichess_db_rows, Some(25));
+        assert_eq!(db.get_import_checkpoint().await.unwrap(), None);
+
+        // Checkpoints that don't match the puzzles in the database, or that are from a different
+        // copy of it, are ignored and it's imported from the start.
+        let checkpoints = [
+            ImportCheckpoint { source: source.clone(), rows: 20, last_puzzle_id: "00019".to_string() },
+            ImportCheckpoint { source: source.clone(), rows: 30, last_puzzle_id: "00030".to_string() },
+            ImportCheckpoint { source: "other".to_string(), rows: 20, last_puzzle_id: "00020".to_string() },
+        ];
+        for checkpoint in checkpoints {
+            let db_dir = tempfile::tempdir().unwrap();
+            let db = open_test_db(&db_dir).await;
+            db.set_import_checkpoint(Some(&checkpoint)).await.unwrap();
+
+            let lichess_db = open_puzzle_db(&config, cancel_import.clone()).await.unwrap();
+            assert!(import_lichess_database(db.clone(), lichess_db, cancel_import.clone()).await.unwrap());
+            assert_eq!(db.get_puzzle_count().await.unwrap(), 25);
+            assert_eq!(db.get_import_checkpoint().await.unwrap(), None);
+        }
+    }
+
+    /// A stand-in for the lichess server, which serves a file with support for range requests.
+    struct TestServer {
+        data: Vec<u8>,
+        etag: String,
+        requests: Mutex<Vec<String>>,
+    }
+
+    async fn serve_file(
+        State(server): State<Arc<TestServer>>,
+        method: Method,
+        headers: HeaderMap,
+    ) -> Response
+    {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let range = header(header::RANGE);
+
+        let mut request = method.to_string();
+        if let Some(range) = range {
+            request = format!("{request} {range}");
+        }
+        server.requests.lock().unwrap().push(request);
+
+        let version_headers = [
+            (header::ETAG, server.etag.clone()),
+            (header::LAST_MODIFIED, "Sat, 04 Nov 2023 10:00:00 GMT".to_string()),
+        ];
+
+        let start = range
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.strip_suffix('-'))
+            .and_then(|start| start.parse::<usize>().ok())
+            .filter(|_| matches!(header(header::IF_RANGE), Some(etag) if etag == server.etag));
+
+        match start {
+            Some(start) => (
+                StatusCode::PARTIAL_CONTENT,
+                version_headers,
+                [(header::CONTENT_RANGE,
+                    format!("bytes {start}-{}/{}", server.data.len() - 1, server.data.len()))],
+                server.data[start..].to_vec(),
+            ).into_response(),
+            None => (
+                version_headers,
+                [(header::CONTENT_LENGTH, server.data.len().to_string())],
+                server.data.clone(),
+            ).into_response(),
+        }
+    }
+
+    /// Start a test server and get the url of the file it serves.
+    fn start_test_server(server: Arc<TestServer>) -> Url {
+        let app = axum::Router::new()
+            .route("/lichess_db_puzzle.csv.zst", axum::routing::get(serve_file))
+            .with_state(server);
+
+        let http_server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(app.into_make_service());
+        let addr = http_server.local_addr();
+        tokio::spawn(http_server);
+
+        Url::parse(&format!("http://{addr}/lichess_db_puzzle.csv.zst")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_download_puzzle_db() {
+        let cancel_import = Arc::new(AtomicBool::new(false));
+        let cache_dir = tempfile::tempdir().unwrap();
+        let path = cache_dir.path().join("lichess_db_puzzle.csv.zst");
+        let partial_path = cache_dir.path().join("lichess_db_puzzle.csv.zst.part");
+        let validator_path = cache_dir.path().join("lichess_db_puzzle.csv.zst.part.validator");
+
+        let server = Arc::new(TestServer {
+            data: FIXTURE.to_vec(),
+            etag: "\"v1\"".to_string(),
+            requests: Mutex::new(Vec::new()),
+        });
+        let url = start_test_server(server.clone());
+        let take_requests = || std::mem::take(&mut *server.requests.lock().unwrap());
+
+        let config = PuzzleDbConfig {
+            source: PuzzleDbSource::Url(url.clone()),
+            cache_dir: cache_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        // The completed download is kept, dated by its Last-Modified date.
+        let file = download_puzzle_db(&url, &config, cancel_import.clone()).await.unwrap();
+        assert_eq!(file.date.unwrap().to_rfc2822(), "Sat, 4 Nov 2023 10:00:00 +0000");
+        assert_eq!(std::fs::read(&path).unwrap(), FIXTURE);
+        assert!(!partial_path.exists());
+        assert_eq!(take_requests(), ["HEAD", "GET"]);
+
+        // It's reused next time, as it's still the latest version.
+        let file = download_puzzle_db(&url, &config, cancel_import.clone()).await.unwrap();
+        assert_eq!(read_all(file.file), String::from_utf8(zstd::decode_all(FIXTURE).unwrap()).unwrap());
+        assert_eq!(take_requests(), ["HEAD"]);
+
+        // A partial download of the same version is resumed from where it got to.
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&partial_path, &FIXTURE[..100]).unwrap();
+        std::fs::write(&validator_path, "\"v1\"").unwrap();
+        download_puzzle_db(&url, &config, cancel_import.clone()).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), FIXTURE);
+        assert_eq!(take_requests(), ["HEAD", "GET bytes=100-"]);
+        assert!(!validator_path.exists());
+
+        // A partial download of a different version is started over.
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&partial_path, [0; 100]).unwrap();
+        std::fs::write(&validator_path, "\"v0\"").unwrap();
+        download_puzzle_db(&url, &config, cancel_import.clone()).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), FIXTURE);
+        assert_eq!(take_requests(), ["HEAD", "GET"]);
+
+        // The checksum is verified if there is one, and the download is thrown away if it's wrong.
+        std::fs::remove_file(&path).unwrap();
+        let sha256 = format!("{:x}", Sha256::digest(FIXTURE));
+        let config = PuzzleDbConfig { sha256: Some(sha256), ..config };
+        download_puzzle_db(&url, &config, cancel_import.clone()).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), FIXTURE);
+
+        std::fs::remove_file(&path).unwrap();
+        let config = PuzzleDbConfig { sha256: Some("0".repeat(64)), ..config };
+        let error = download_puzzle_db(&url, &config, cancel_import).await.unwrap_err();
+        assert!(error.contains("Checksum mismatch"));
+        assert!(!path.exists());
+        assert!(!partial_path.exists());
+    }
+}
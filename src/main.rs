@@ -1,14 +1,25 @@
+mod activity;
+mod anki;
 mod api;
 mod assets;
 mod app;
+mod chess;
 mod controllers;
+mod daily;
 mod db;
+mod engine;
 mod lichess;
+mod packs;
+mod pgn;
+mod personal;
 mod rating;
+mod render;
 mod services;
+mod solution;
 mod srs;
 mod time;
 mod util;
+mod weakness;
 
 use std::env;
 use std::error::Error;
@@ -16,6 +27,7 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
+use chrono::{Local, Timelike};
 use futures::TryFutureExt;
 use tokio_cron_scheduler::{Job, JobScheduler};
 
@@ -44,8 +56,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
         try_run_backup(app_config.clone(), puzzle_db.clone()).await;
     }
 
+    // Set on shutdown to stop any import of the lichess puzzle database that's running.
+    let cancel_import = Arc::new(AtomicBool::new(false));
+
     // Start job scheduler.
-    tokio::spawn(start_job_scheduler(app_config.clone(), puzzle_db.clone()));
+    tokio::spawn(start_job_scheduler(app_config.clone(), puzzle_db.clone(), cancel_import.clone()));
 
     // Web server url, or http://localhost:* if the bind interface was 0.0.0.0.
     let url = match app_config.bind_interface.is_unspecified() {
@@ -54,9 +69,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     };
 
     // Initialise puzzle database in background if necessary.
-    let cancel_import = Arc::new(AtomicBool::new(false));
     let import_done = Arc::new(AtomicBool::new(false));
-    tokio::spawn(lichess::init_db(puzzle_db.clone(), cancel_import.clone())
+    tokio::spawn(lichess::init_db(puzzle_db.clone(), app_config.puzzle_db.clone(), cancel_import.clone())
         .and_then({
             let import_done = import_done.clone();
             let cancel_import = cancel_import.clone();
@@ -83,7 +97,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }));
 
     // Create application routes.
-    let app_state = AppState::new(app_config.clone(), puzzle_db.clone());
+    let app_state = AppState::new(app_config.clone(), puzzle_db.clone(), cancel_import.clone());
     let app = controllers::routes(app_state.clone())
         .nest_service("/api", api::routes(app_state))
         .nest_service(assets::STATIC_ASSETS_PATH, assets::routes());
@@ -101,24 +115,29 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(server_task.await??)
 }
 
-async fn start_job_scheduler(app_config: AppConfig, db: PuzzleDatabase)
+async fn start_job_scheduler(app_config: AppConfig, db: PuzzleDatabase, cancel_import: Arc<AtomicBool>)
     -> Result<(), String>
 {
-    run_job_scheduler(app_config, db)
+    run_job_scheduler(app_config, db, cancel_import)
         .await
         .map_err(|e| e.to_string())
 }
 
-async fn run_job_scheduler(app_config: AppConfig, db: PuzzleDatabase)
+async fn run_job_scheduler(app_config: AppConfig, db: PuzzleDatabase, cancel_import: Arc<AtomicBool>)
     -> Result<(), Box<dyn Error>>
 {
     let scheduler = JobScheduler::new().await?;
 
     if app_config.backup.enabled {
-        let backup_job = create_backup_job(app_config, db)?;
+        let backup_job = create_backup_job(app_config.clone(), db.clone())?;
         scheduler.add(backup_job).await?;
     }
 
+    if app_config.puzzle_db.refresh_enabled {
+        let refresh_job = create_refresh_job(app_config, db, cancel_import)?;
+        scheduler.add(refresh_job).await?;
+    }
+
     Ok(scheduler.start().await?)
 }
 
@@ -132,6 +151,27 @@ fn create_backup_job(app_config: AppConfig, db: PuzzleDatabase)
     })?)
 }
 
+fn create_refresh_job(app_config: AppConfig, db: PuzzleDatabase, cancel_import: Arc<AtomicBool>)
+    -> Result<Job, Box<dyn Error>>
+{
+    // The scheduler uses utc, so check every hour whether it's the refresh hour in local time. The
+    // refresh only downloads the lichess puzzle database if there's a newer one.
+    Ok(Job::new_async("0 0 * * * *", move |_, _| {
+        let (app_config, db, cancel_import) = (app_config.clone(), db.clone(), cancel_import.clone());
+        Box::pin(async move {
+            if Local::now().hour() == app_config.puzzle_db.refresh_hour.hour() {
+                try_run_refresh(app_config, db, cancel_import).await;
+            }
+        })
+    })?)
+}
+
+async fn try_run_refresh(app_config: AppConfig, db: PuzzleDatabase, cancel_import: Arc<AtomicBool>) {
+    if let Err(e) = lichess::refresh_db(db, app_config.puzzle_db, cancel_import, false).await {
+        log::error!("Error when refreshing puzzle database: {e}");
+    }
+}
+
 async fn try_run_backup(app_config: AppConfig, db: PuzzleDatabase) {
     if let Err(e) = app::backup::run_backup(app_config, db).await {
         log::error!("Error when backing up database: {e}");
@@ -149,12 +189,12 @@ async fn shutdown_signal(db: PuzzleDatabase, import_done: Arc<AtomicBool>,
 
     log::info!("Termination requested, closing database...");
 
-    // If the lichess puzzle database is still importing, stop it.
-    if !import_done.load(Ordering::Relaxed) {
+    // If the lichess puzzle database is still importing or being refreshed, stop it.
+    if !import_done.load(Ordering::Relaxed) || lichess::import_running() {
         cancel_import.store(true, Ordering::Relaxed);
 
         // Wait for the import task to stop.
-        while !import_done.load(Ordering::Relaxed) {
+        while !import_done.load(Ordering::Relaxed) || lichess::import_running() {
             log::info!("Waiting for lichess puzzle db import to stop...");
             tokio::time::sleep(Duration::from_secs(1)).await;
         }
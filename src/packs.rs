@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+
+use csv::StringRecord;
+use sha2::{Digest, Sha256};
+
+use crate::chess::Position;
+use crate::db::Puzzle;
+use crate::lichess;
+use crate::pgn::{self, PgnGame};
+use crate::solution::Solution;
+
+/// The start of the source of each puzzle pack, which is followed by the pack's name, e.g.
+/// "pack-club-puzzles". Puzzle IDs in packs start with the source too, so that they can't collide
+/// with lichess puzzle IDs or each other.
+pub const PACK_SOURCE_PREFIX: &str = "pack-";
+
+/// The rating of puzzles that don't have one, which is just a guess.
+const DEFAULT_RATING: i64 = 1500;
+
+/// The rating deviation of puzzles that don't have a rating, which is high as it's just a guess.
+const DEFAULT_RATING_DEVIATION: i64 = 500;
+
+/// The formats puzzle packs can be imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+    strum_macros::Display, strum_macros::EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum PackFormat {
+    /// A csv in the same format as the lichess puzzle database.
+    Csv,
+    /// PGN games starting from a FEN tag, with the solution as the mainline.
+    Pgn,
+    /// EPD positions with the solution in a `bm` (best move) operation.
+    Epd,
+}
+
+/// The puzzles read from a pack, and errors for the ones that couldn't be read.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedPack {
+    pub puzzles: Vec<Puzzle>,
+    pub errors: Vec<String>,
+}
+
+/// Get the source of a pack from its name, e.g. "pack-club-puzzles" for "Club puzzles!", or None
+/// if there's nothing usable in the name.
+pub fn pack_source(name: &str) -> Option<String> {
+    let slug = id_part(&name.to_lowercase());
+    (!slug.is_empty()).then(|| format!("{PACK_SOURCE_PREFIX}{slug}"))
+}
+
+/// Read the puzzles from a pack. Puzzles that can't be read, have an invalid solution or have the
+/// same ID as an earlier puzzle are left out, with an error saying which entry they were.
+pub fn parse_pack(format: PackFormat, source: &str, contents: &str) -> ParsedPack {
+    let results = match format {
+        PackFormat::Csv => parse_csv(source, contents),
+        PackFormat::Pgn => parse_pgn(source, contents),
+        PackFormat::Epd => parse_epd(source, contents),
+    };
+
+    let mut pack = ParsedPack::default();
+    let mut puzzle_ids = HashSet::new();
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(puzzle) if !puzzle_ids.insert(puzzle.puzzle_id.clone()) =>
+                pack.errors.push(format!("Puzzle {}: duplicate puzzle ID {}", i + 1, puzzle.puzzle_id)),
+            Ok(puzzle) => pack.puzzles.push(puzzle),
+            Err(e) => pack.errors.push(format!("Puzzle {}: {e}", i + 1)),
+        }
+    }
+
+    pack
+}
+
+/// Read puzzles from a csv in the lichess puzzle database's format, which can have a header row.
+fn parse_csv(source: &str, contents: &str) -> Vec<Result<Puzzle, String>> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+
+    let mut results = Vec::new();
+    let mut record = StringRecord::new();
+
+    loop {
+        let result = match csv_reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) if record.get(0) == Some("PuzzleId") => continue,
+            Ok(true) if record.len() != lichess::EXPECTED_ROWS =>
+                Err(format!("expected {} fields, found {}", lichess::EXPECTED_ROWS, record.len())),
+            Ok(true) => {
+                let id = match id_part(&record[0]) {
+                    id if id.is_empty() => (results.len() + 1).to_string(),
+                    id => id,
+                };
+                lichess::puzzle_from_record(&record, format!("{source}-{id}"), source)
+                    .and_then(|puzzle| {
+                        Solution::new(&puzzle.fen, &puzzle.moves).map_err(|e| e.to_string())?;
+                        Ok(puzzle)
+                    })
+            },
+            Err(e) => Err(format!("invalid csv: {e}")),
+        };
+
+        results.push(result);
+    }
+
+    results
+}
+
+/// Read puzzles from PGN games, which must start from a position given by a FEN tag with the
+/// player to move, and have the solution as their mainline. Their IDs come from the position and
+/// solution, so they stay the same when the pack is imported again after games are added or moved.
+fn parse_pgn(source: &str, contents: &str) -> Vec<Result<Puzzle, String>> {
+    pgn::parse_games(contents)
+        .into_iter()
+        .map(|game| {
+            let game = game.map_err(|e| e.to_string())?;
+            if game.tag("FEN").is_none() {
+                return Err("no FEN tag".to_string());
+            }
+
+            let id = content_id(&game.fen, &game.moves);
+            let mut puzzle = puzzle_from_solution(format!("{source}-{id}"), &game.fen,
+                &game.moves, source)?;
+            puzzle.game_url = game_url(&game);
+            Ok(puzzle)
+        })
+        .collect()
+}
+
+/// Read puzzles from EPD lines, e.g. `6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Rd8#; id "mate.1";`.
+/// The first best move is the solution, and the ID is used in the puzzle's ID if there is one, or
+/// otherwise one is made from the position and solution like for PGN.
+fn parse_epd(source: &str, contents: &str) -> Vec<Result<Puzzle, String>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let fen_fields: Vec<&str> = fields.by_ref().take(4).collect();
+            if fen_fields.len() < 4 {
+                return Err("missing FEN fields".to_string());
+            }
+            let fen = format!("{} 0 1", fen_fields.join(" "));
+            let operations = fields.collect::<Vec<_>>().join(" ");
+
+            let mut best_move = None;
+            let mut id = None;
+            for operation in operations.split(';') {
+                let (opcode, operands) = operation.trim().split_once(' ').unwrap_or((operation.trim(), ""));
+                match opcode {
+                    "bm" => best_move = operands.split_whitespace().next(),
+                    "id" => id = Some(id_part(operands.trim_matches('"'))).filter(|id| !id.is_empty()),
+                    _ => (),
+                }
+            }
+
+            let best_move = best_move.ok_or_else(|| "no bm operation".to_string())?;
+            let best_move = Position::from_fen(&fen)
+                .and_then(|position| position.parse_san(best_move))
+                .map_err(|e| e.to_string())?;
+
+            let solution = [best_move.to_string()];
+            let id = id.unwrap_or_else(|| content_id(&fen, &solution));
+            puzzle_from_solution(format!("{source}-{id}"), &fen, &solution, source)
+        })
+        .collect()
+}
+
+/// Build a puzzle from a position with the player to move and the moves of the solution. Puzzles
+/// always start with the opponent's move, so a move for the opponent that leads to the position is
+/// made up.
+fn puzzle_from_solution(puzzle_id: String, fen: &str, solution: &[String], source: &str)
+    -> Result<Puzzle, String>
+{
+    if solution.is_empty() {
+        return Err("no solution moves".to_string());
+    }
+
+    let position = Position::from_fen(fen).map_err(|e| e.to_string())?;
+    let (before, opponent_move) = position.retract_quiet_move()
+        .ok_or_else(|| "no move for the opponent could be found to start the puzzle".to_string())?;
+
+    let fen = before.to_fen();
+    let moves = format!("{opponent_move} {}", solution.join(" "));
+    let solution = Solution::new(&fen, &moves).map_err(|e| e.to_string())?;
+
+    // Tag mates, and how long the solution is, like lichess does.
+    let mut themes = Vec::new();
+    let player_moves = solution.len() / 2;
+    if solution.position_after(solution.len()).is_checkmate() {
+        themes.push("mate".to_string());
+        themes.push(format!("mateIn{player_moves}"));
+    }
+    themes.push(match player_moves {
+        1 => "oneMove",
+        2 => "short",
+        3 => "long",
+        _ => "veryLong",
+    }.to_string());
+
+    Ok(Puzzle {
+        puzzle_id,
+        fen,
+        moves,
+        rating: DEFAULT_RATING,
+        rating_deviation: DEFAULT_RATING_DEVIATION,
+        popularity: 0,
+        number_of_plays: 0,
+        themes,
+        game_url: String::new(),
+        opening_tags: Vec::new(),
+        source: source.to_string(),
+    })
+}
+
+/// The game's url, if its Site tag is one.
+fn game_url(game: &PgnGame) -> String {
+    match game.tag("Site") {
+        Some(site) if site.starts_with("http") => site.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Make an ID for a puzzle from its position and solution, which is the start of their hash.
+fn content_id(fen: &str, solution: &[String]) -> String {
+    let hash = Sha256::digest(format!("{fen} {}", solution.join(" ")));
+    hash[..8].iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Make part of an ID that's safe to use in urls, by replacing anything other than letters,
+/// numbers, dashes and underscores with dashes.
+fn id_part(s: &str) -> String {
+    s.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATE_FEN: &str = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+
+    #[test]
+    fn test_pack_source() {
+        assert_eq!(pack_source("Club puzzles!").as_deref(), Some("pack-club-puzzles"));
+        assert_eq!(pack_source("WAC 2nd ed."), Some("pack-wac-2nd-ed".to_string()));
+        assert_eq!(pack_source(" ?! "), None);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags\n\
+            00008,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2g3 e6e7 b2b1 b3c1 b1c1 h6c1,1913,75,94,6230,crushing long,https://lichess.org/787zsVup/black#48,\n\
+            00009,r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24,f2h4,1500,75,94,0,,,\n\
+            00010,too few fields\n";
+
+        let pack = parse_pack(PackFormat::Csv, "pack-test", csv);
+        assert_eq!(pack.puzzles.len(), 1);
+        assert_eq!(pack.puzzles[0].puzzle_id, "pack-test-00008");
+        assert_eq!(pack.puzzles[0].source, "pack-test");
+        assert_eq!(pack.puzzles[0].rating, 1913);
+        assert_eq!(pack.puzzles[0].themes, ["crushing", "long"]);
+
+        // The illegal solution and the short row are errors.
+        assert_eq!(pack.errors.len(), 2);
+        assert!(pack.errors[0].starts_with("Puzzle 2:"));
+        assert!(pack.errors[1].starts_with("Puzzle 3:"));
+    }
+
+    #[test]
+    fn test_parse_pgn() {
+        let pgn = format!("[Event \"Back rank\"]\n[Site \"https://example.com/1\"]\n[SetUp \"1\"]\n\
+            [FEN \"{MATE_FEN}\"]\n\n1. Rd8# 1-0\n\n\
+            [Event \"No position\"]\n\n1. e4 e5 *\n");
+
+        let pack = parse_pack(PackFormat::Pgn, "pack-test", &pgn);
+        assert_eq!(pack.puzzles.len(), 1);
+        assert_eq!(pack.errors.len(), 1);
+
+        // The opponent's king move is made up to start the puzzle.
+        let puzzle = &pack.puzzles[0];
+        assert_eq!(puzzle.puzzle_id, format!("pack-test-{}", content_id(MATE_FEN, &["d1d8".to_string()])));
+        assert_eq!(puzzle.puzzle_id.len(), "pack-test-".len() + 16);
+        assert_eq!(puzzle.fen, "5k2/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1");
+        assert_eq!(puzzle.moves, "f8g8 d1d8");
+        assert_eq!(puzzle.themes, ["mate", "mateIn1", "oneMove"]);
+        assert_eq!(puzzle.game_url, "https://example.com/1");
+
+        // The ID stays the same when other games are added before it, and the same puzzle twice
+        // is a duplicate.
+        let pgn = format!("[Event \"No position\"]\n\n1. e4 *\n\n{pgn}{pgn}");
+        let pack = parse_pack(PackFormat::Pgn, "pack-test", &pgn);
+        assert_eq!(pack.puzzles.len(), 1);
+        assert_eq!(pack.puzzles[0].puzzle_id, puzzle.puzzle_id);
+        assert_eq!(pack.errors.len(), 4);
+        assert!(pack.errors[2].starts_with("Puzzle 4: duplicate puzzle ID"));
+    }
+
+    #[test]
+    fn test_parse_epd() {
+        let epd = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Rd8#; id \"back rank.1\";\n\
+            \n\
+            6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Rd7;\n\
+            6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - id \"no best move\";\n\
+            6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Qd8;\n\
+            6k1/5ppp/8/8/8/8/5PPP/3R2K1\tw  -\t- bm Rd8#; id \"back rank 2\";\n\
+            6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - bm Rd8#; id \"back rank.1\";\n";
+
+        let pack = parse_pack(PackFormat::Epd, "pack-test", epd);
+        let ids: Vec<_> = pack.puzzles.iter().map(|puzzle| puzzle.puzzle_id.as_str()).collect();
+        let rd7_id = format!("pack-test-{}", content_id(MATE_FEN, &["d1d7".to_string()]));
+        assert_eq!(ids, ["pack-test-back-rank-1", rd7_id.as_str(), "pack-test-back-rank-2"]);
+        assert_eq!(pack.puzzles[0].moves, "f8g8 d1d8");
+        assert_eq!(pack.puzzles[1].themes, ["oneMove"]);
+        assert_eq!(pack.puzzles[2].moves, "f8g8 d1d8");
+
+        // The FEN fields can be separated by any whitespace, and IDs can't be used twice.
+        assert_eq!(pack.errors.len(), 3);
+        assert!(pack.errors[0].contains("no bm"));
+        assert_eq!(pack.errors[2], "Puzzle 6: duplicate puzzle ID pack-test-back-rank-1");
+    }
+}
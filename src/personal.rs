@@ -0,0 +1,382 @@
+use crate::chess::{Position, Color};
+use crate::db::Puzzle;
+use crate::engine::Score;
+use crate::pgn::PgnGame;
+use crate::solution::Solution;
+
+/// The source of puzzles generated from the user's own games.
+pub const PERSONAL_SOURCE: &str = "personal";
+
+/// The value of a mate in centipawns, before taking off the number of moves until the mate.
+const MATE_VALUE: i64 = 100_000;
+
+/// The evaluation in centipawns at which a position counts as clearly winning.
+const WINNING_CP: i64 = 300;
+
+/// The evaluation in centipawns below which a winning position counts as thrown away.
+const MISSED_WIN_CP: i64 = 100;
+
+/// How many centipawns a move has to lose to count as a blunder, from a position that wasn't
+/// already winning or losing.
+const BLUNDER_CP: i64 = 300;
+
+/// How many centipawns better than the second best move the best move has to be for a missed
+/// win or blunder to be a puzzle, so that the best move is the only solution.
+const ONLY_MOVE_CP: i64 = 200;
+
+/// The longest mate that's turned into a puzzle.
+const MAX_MATE_MOVES: i64 = 5;
+
+/// The evaluation in centipawns from which a winning line is 'crushing' rather than an 'advantage'.
+const CRUSHING_CP: i64 = 600;
+
+/// The rating used for puzzles from games without ratings in their tags.
+const DEFAULT_RATING: i64 = 1500;
+
+/// The rating added to the estimate for each extra move in the solution.
+const RATING_PER_EXTRA_MOVE: i64 = 150;
+
+/// The rating deviation of generated puzzles, which is high as their rating is only an estimate.
+const RATING_DEVIATION: i64 = 500;
+
+/// The engine's evaluation and best line in a position, with the scores from the point of view of
+/// the side to move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionAnalysis {
+    pub score: Score,
+    pub pv: Vec<String>,
+    /// The score of the second best move, which is None if the engine didn't give one.
+    pub second_score: Option<Score>,
+}
+
+/// Whether `find_puzzles` needs the analysis of the position at `ply` of a game with `moves` moves,
+/// with `turn` to move. These are the positions before and after each of the player's moves (or
+/// both players' if `player` is None), apart from the first position, as a puzzle starts before
+/// the opponent's previous move.
+pub fn needs_analysis(ply: usize, turn: Color, moves: usize, player: Option<Color>) -> bool {
+    let before_move = ply > 0 && ply < moves && player.unwrap_or(turn) == turn;
+    let after_move = ply > 1 && player != Some(turn);
+    before_move || after_move
+}
+
+/// Find the positions in a game where a player missed a clear win or blundered, and turn them into
+/// puzzles, like the ones in the lichess puzzle database. Each puzzle starts before the opponent's
+/// previous move, and its solution is the engine's best line: the whole line for forced mates, or
+/// otherwise just the best move, as the moves after it may not be the only good ones. Positions
+/// where the second best move is nearly as good are skipped, as they have more than one solution.
+///
+/// `analyses` has the analysis of the position before each move of the game and after the last
+/// one, and positions that don't need analysing (such as ones where it's the opponent's move
+/// before their own move) can be None. Only the moves of `player` are checked, or both players'
+/// if it's None.
+pub fn find_puzzles(game: &PgnGame, analyses: &[Option<PositionAnalysis>], player: Option<Color>)
+    -> Vec<Puzzle>
+{
+    let Ok(mut position) = Position::from_fen(&game.fen) else {
+        return Vec::new();
+    };
+
+    let mut puzzles = Vec::new();
+    let mut previous_fen: Option<String> = None;
+
+    for (ply, played) in game.moves.iter().enumerate() {
+        let fen = position.to_fen();
+
+        if let (Some(previous_fen), Some(Some(before)), Some(Some(after))) =
+            (&previous_fen, analyses.get(ply), analyses.get(ply + 1))
+        {
+            if !matches!(player, Some(color) if color != position.turn()) {
+                let puzzle = missed_tactic(&position, played, before, after)
+                    .and_then(|(moves, themes)| {
+                        let moves = format!("{} {}", game.moves[ply - 1], moves.join(" "));
+                        build_puzzle(game, ply, previous_fen, &moves, themes, position.turn())
+                    });
+                puzzles.extend(puzzle);
+            }
+        }
+
+        if position.play_uci(played).is_err() {
+            break;
+        }
+        previous_fen = Some(fen);
+    }
+
+    puzzles
+}
+
+/// Check whether the move played in a position missed a tactic, returning the solution moves from
+/// the position and the puzzle's themes if it did.
+fn missed_tactic(position: &Position, played: &str, before: &PositionAnalysis, after: &PositionAnalysis)
+    -> Option<(Vec<String>, Vec<String>)>
+{
+    let best_move = before.pv.first()?;
+    if best_move == played {
+        return None;
+    }
+
+    let best = score_value(before.score);
+    // The score after the move is from the opponent's point of view.
+    let result = -score_value(after.score);
+    let still_mating = matches!(after.score, Score::Mate(moves) if moves < 0);
+    // Without a second best score, the best move is only known to be the only good one if it's
+    // the only legal move.
+    let only_move = match before.second_score {
+        Some(second) => best - score_value(second) >= ONLY_MOVE_CP,
+        None => position.legal_moves().len() == 1,
+    };
+
+    let mut themes = Vec::new();
+    let moves = match before.score {
+        // Missed mates, which are solved by playing the whole line.
+        Score::Mate(mate) if (1..=MAX_MATE_MOVES).contains(&mate) && !still_mating => {
+            let moves = before.pv.iter().take(2 * mate as usize - 1).cloned().collect::<Vec<_>>();
+
+            // The line might be cut short, so check that it really is mate.
+            let mut mated = position.clone();
+            for m in &moves {
+                mated.play_uci(m).ok()?;
+            }
+            if !mated.is_checkmate() {
+                return None;
+            }
+
+            themes.push("mate".to_string());
+            themes.push(format!("mateIn{mate}"));
+            moves
+        },
+        // Mates that are too long to be puzzles.
+        Score::Mate(_) => return None,
+        // Missed wins.
+        Score::Cp(_) if best >= WINNING_CP && result < MISSED_WIN_CP && only_move => {
+            themes.push(if best >= CRUSHING_CP { "crushing" } else { "advantage" }.to_string());
+            vec![best_move.clone()]
+        },
+        // Blunders that threw away an equal position.
+        Score::Cp(_) if best > -WINNING_CP && best - result >= BLUNDER_CP && only_move => {
+            themes.push("equality".to_string());
+            vec![best_move.clone()]
+        },
+        _ => return None,
+    };
+
+    // The solution always ends with one of the player's moves.
+    themes.push(match moves.len() / 2 + 1 {
+        1 => "oneMove",
+        2 => "short",
+        3 => "long",
+        _ => "veryLong",
+    }.to_string());
+
+    Some((moves, themes))
+}
+
+/// Build a puzzle from a game, checking that its solution is legal.
+fn build_puzzle(game: &PgnGame, ply: usize, fen: &str, moves: &str, themes: Vec<String>, color: Color)
+    -> Option<Puzzle>
+{
+    let solution = Solution::new(fen, moves).ok()?;
+
+    // Puzzles the player missed in a game are probably around their rating, and longer ones
+    // are harder.
+    let elo_tag = match color {
+        Color::White => "WhiteElo",
+        Color::Black => "BlackElo",
+    };
+    let rating = game.tag(elo_tag).and_then(|elo| elo.parse().ok()).unwrap_or(DEFAULT_RATING)
+        + RATING_PER_EXTRA_MOVE * (solution.len() as i64 / 2 - 1);
+
+    let game_url = match game.tag("Site") {
+        Some(site) if site.starts_with("http") => format!("{site}#{ply}"),
+        _ => String::new(),
+    };
+
+    Some(Puzzle {
+        puzzle_id: puzzle_id(fen, moves),
+        fen: fen.to_string(),
+        moves: moves.to_string(),
+        rating,
+        rating_deviation: RATING_DEVIATION,
+        popularity: 0,
+        number_of_plays: 0,
+        themes,
+        game_url,
+        opening_tags: game.tag("Opening").map(opening_tags).unwrap_or_default(),
+        source: PERSONAL_SOURCE.to_string(),
+    })
+}
+
+/// Convert a score to centipawns so that scores can be compared, with sooner mates being worth
+/// more.
+fn score_value(score: Score) -> i64 {
+    match score {
+        Score::Cp(cp) => cp,
+        Score::Mate(moves) if moves > 0 => MATE_VALUE - moves,
+        Score::Mate(moves) => -MATE_VALUE - moves,
+    }
+}
+
+/// Get the lichess style opening tags for an opening name, e.g. "Sicilian Defense: Najdorf
+/// Variation, English Attack" is tagged "Sicilian_Defense Sicilian_Defense_Najdorf_Variation".
+fn opening_tags(opening: &str) -> Vec<String> {
+    let tag = |name: &str| name
+        .split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+
+    let (family, variation) = opening.split_once(':').unwrap_or((opening, ""));
+    let variation = variation.split(',').next().unwrap_or("");
+
+    let mut tags = vec![tag(family)];
+    if !tag(variation).is_empty() {
+        tags.push(format!("{}_{}", tag(family), tag(variation)));
+    }
+    tags.retain(|tag| !tag.is_empty());
+    tags
+}
+
+/// A puzzle ID for a generated puzzle, which is the same if the same puzzle is generated again.
+/// They're prefixed so that they can't collide with lichess puzzle IDs.
+fn puzzle_id(fen: &str, moves: &str) -> String {
+    // FNV-1a, which unlike the standard library's hasher is stable between versions.
+    let hash = format!("{fen} {moves}")
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+
+    format!("{PERSONAL_SOURCE}-{:010x}", hash >> 24)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::parse_games;
+
+    fn analysis(score: Score, pv: &str) -> Option<PositionAnalysis> {
+        Some(PositionAnalysis {
+            score,
+            pv: pv.split_whitespace().map(ToString::to_string).collect(),
+            second_score: None,
+        })
+    }
+
+    fn analysis_with_second(score: Score, pv: &str, second: Score) -> Option<PositionAnalysis> {
+        analysis(score, pv).map(|analysis| PositionAnalysis { second_score: Some(second), ..analysis })
+    }
+
+    #[test]
+    fn test_needs_analysis() {
+        // A game of 1. e4 e5 2. Nf3, with White to move in the even plies.
+        let turn = |ply| if ply % 2 == 0 { Color::White } else { Color::Black };
+        let needed = |player| (0..=3)
+            .filter(|&ply| needs_analysis(ply, turn(ply), 3, player))
+            .collect::<Vec<_>>();
+
+        // Before and after 2. Nf3, as 1. e4 has no previous move to start a puzzle from.
+        assert_eq!(needed(Some(Color::White)), [2, 3]);
+        // Before and after 1... e5, and not the position after 2. Nf3.
+        assert_eq!(needed(Some(Color::Black)), [1, 2]);
+        assert_eq!(needed(None), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_missed_mate() {
+        // White missed 4. Qxf7#.
+        let game = parse_games(concat!(
+            "[White \"me\"]\n[Black \"them\"]\n[WhiteElo \"1400\"]\n",
+            "[Site \"https://lichess.org/abcdefgh\"]\n",
+            "[Opening \"Italian Game: Two Knights Defense\"]\n\n",
+            "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Nc3 g6 *",
+        )).remove(0).unwrap();
+
+        let analyses = [
+            None, None, None, None, None,
+            analysis(Score::Cp(-200), "g8f6 h5f7"),
+            analysis(Score::Mate(1), "h5f7"),
+            analysis(Score::Cp(50), "g7g6"),
+            analysis(Score::Cp(0), "g1f3"),
+        ];
+
+        let puzzles = find_puzzles(&game, &analyses, Some(Color::White));
+        assert_eq!(puzzles.len(), 1);
+
+        let puzzle = &puzzles[0];
+        assert_eq!(puzzle.fen, "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 3 3");
+        assert_eq!(puzzle.moves, "g8f6 h5f7");
+        assert_eq!(puzzle.rating, 1400);
+        assert_eq!(puzzle.themes, ["mate", "mateIn1", "oneMove"]);
+        assert_eq!(puzzle.game_url, "https://lichess.org/abcdefgh#6");
+        assert_eq!(puzzle.opening_tags, ["Italian_Game", "Italian_Game_Two_Knights_Defense"]);
+        assert_eq!(puzzle.source, PERSONAL_SOURCE);
+        assert!(puzzle.puzzle_id.starts_with("personal-"));
+        assert_eq!(puzzle.puzzle_id, puzzle_id(&puzzle.fen, &puzzle.moves));
+
+        // Black's moves aren't checked unless they're the player.
+        assert!(find_puzzles(&game, &analyses, Some(Color::Black)).is_empty());
+    }
+
+    #[test]
+    fn test_missed_win_and_blunder() {
+        let game = parse_games("1. e4 e5 2. Nf3 Nc6 3. Bc4 Nd4 4. Nxe5 Qg5 *").remove(0).unwrap();
+
+        let mut analyses = [
+            None,
+            analysis_with_second(Score::Cp(-30), "e7e5", Score::Cp(-60)),
+            analysis_with_second(Score::Cp(40), "g1f3", Score::Cp(30)),
+            analysis_with_second(Score::Cp(-30), "b8c6", Score::Cp(-40)),
+            analysis_with_second(Score::Cp(40), "f1b5", Score::Cp(35)),
+            analysis_with_second(Score::Cp(-30), "g8f6", Score::Cp(-250)),
+            // White misses the win of the knight with 4. Nxd4, which black then blunders back.
+            analysis_with_second(Score::Cp(350), "f3d4 e5d4", Score::Cp(100)),
+            analysis_with_second(Score::Cp(-20), "d8g5", Score::Cp(-50)),
+            analysis_with_second(Score::Cp(-20), "e5f7", Score::Cp(-30)),
+        ];
+
+        let puzzles = find_puzzles(&game, &analyses, None);
+        assert_eq!(puzzles.len(), 2);
+
+        // 3... Nd4 threw away an equal position.
+        assert_eq!(puzzles[0].moves, "f1c4 g8f6");
+        assert_eq!(puzzles[0].themes, ["equality", "oneMove"]);
+
+        assert_eq!(puzzles[1].moves, "c6d4 f3d4");
+        assert_eq!(puzzles[1].themes, ["advantage", "oneMove"]);
+        assert_eq!(puzzles[1].rating, DEFAULT_RATING);
+        assert_eq!(puzzles[1].game_url, "");
+
+        // Positions where the second best move is nearly as good have more than one solution.
+        analyses[5] = analysis_with_second(Score::Cp(-30), "g8f6", Score::Cp(-100));
+        analyses[6] = analysis_with_second(Score::Cp(350), "f3d4 e5d4", Score::Cp(300));
+        assert!(find_puzzles(&game, &analyses, None).is_empty());
+
+        // Without a second best score, only a position's only legal move is known to be the only
+        // solution.
+        analyses[5] = analysis(Score::Cp(-30), "g8f6");
+        analyses[6] = analysis(Score::Cp(350), "f3d4 e5d4");
+        assert!(find_puzzles(&game, &analyses, None).is_empty());
+    }
+
+    #[test]
+    fn test_no_puzzles() {
+        let game = parse_games("1. e4 e5 2. Nf3 Nc6 *").remove(0).unwrap();
+
+        // The best move was played, the position was already lost, and a mate that's too long.
+        let analyses = [
+            analysis(Score::Cp(30), "e2e4"),
+            analysis(Score::Cp(-30), "e7e5"),
+            analysis(Score::Mate(-8), "d1h5"),
+            analysis(Score::Mate(7), "g8f6"),
+            analysis(Score::Cp(-1000), "f1b5"),
+        ];
+
+        assert!(find_puzzles(&game, &analyses, None).is_empty());
+    }
+
+    #[test]
+    fn test_opening_tags() {
+        assert_eq!(opening_tags("Sicilian Defense: Najdorf Variation, English Attack"),
+            ["Sicilian_Defense", "Sicilian_Defense_Najdorf_Variation"]);
+        assert_eq!(opening_tags("King's Pawn Game"), ["Kings_Pawn_Game"]);
+        assert!(opening_tags("").is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::chess::{Position, Move, Color, ChessError, STARTING_FEN};
+use crate::db::Puzzle;
+
+/// The maximum length of a line of PGN movetext.
+const PGN_LINE_LENGTH: usize = 80;
+
+/// A game read from a PGN file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    /// The starting position, from the FEN tag or the standard starting position.
+    pub fen: String,
+    /// The mainline moves in UCI notation.
+    pub moves: Vec<String>,
+}
+
+impl PgnGame {
+    /// Get the value of a tag, e.g. "White".
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse the games in a PGN file. Only the mainline of each game is kept, and comments,
+/// variations and NAGs are skipped. Games with invalid or illegal moves are an error, but don't
+/// stop the rest of the games from being read.
+pub fn parse_games(pgn: &str) -> Vec<Result<PgnGame, ChessError>> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut sans = Vec::new();
+    let mut variation_depth = 0;
+
+    let mut chars = pgn.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if variation_depth == 0 => {
+                // A tag after some moves is the start of the next game, if the last one didn't
+                // have a result.
+                if !sans.is_empty() {
+                    games.push(read_game(std::mem::take(&mut tags), std::mem::take(&mut sans)));
+                }
+                if let Some(tag) = read_tag(&mut chars) {
+                    tags.push(tag);
+                }
+            },
+            '{' => {
+                chars.by_ref().find(|c| *c == '}');
+            },
+            ';' => {
+                chars.by_ref().find(|c| *c == '\n');
+            },
+            '(' => variation_depth += 1,
+            ')' => variation_depth = usize::max(variation_depth, 1) - 1,
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"[]{}();".contains(*c)) {
+                    token.push(c);
+                }
+
+                if variation_depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+
+                match token.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        games.push(read_game(std::mem::take(&mut tags), std::mem::take(&mut sans)));
+                    },
+                    _ => {
+                        // Skip move numbers, which can be attached to the move, e.g. "12.e4".
+                        let san = match token.rfind('.') {
+                            Some(i) if token[..i].chars().all(|c| c.is_ascii_digit() || c == '.') => &token[i + 1..],
+                            _ => &token,
+                        };
+                        if !san.is_empty() {
+                            sans.push(san.to_string());
+                        }
+                    },
+                }
+            },
+        }
+    }
+
+    if !tags.is_empty() || !sans.is_empty() {
+        games.push(read_game(tags, sans));
+    }
+
+    games
+}
+
+/// Read a tag pair, e.g. `Event "Casual game"`, up to the closing bracket.
+fn read_tag(chars: &mut Peekable<Chars>) -> Option<(String, String)> {
+    let mut name = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+
+    while let Some(c) = chars.next() {
+        match (c, in_value) {
+            (']', false) => break,
+            ('"', false) => in_value = true,
+            ('"', true) => in_value = false,
+            ('\\', true) => value.extend(chars.next()),
+            (c, true) => value.push(c),
+            (c, false) if !c.is_whitespace() => name.push(c),
+            _ => (),
+        }
+    }
+
+    (!name.is_empty()).then_some((name, value))
+}
+
+/// Play through a game's moves to convert them to UCI.
+fn read_game(tags: Vec<(String, String)>, sans: Vec<String>) -> Result<PgnGame, ChessError> {
+    let fen = tags
+        .iter()
+        .find(|(tag, _)| tag == "FEN")
+        .map(|(_, fen)| fen.clone())
+        .unwrap_or(STARTING_FEN.to_string());
+
+    let mut position = Position::from_fen(&fen)?;
+    let moves = sans
+        .iter()
+        .map(|san| {
+            let m = position.parse_san(san)?;
+            position.play(&m)?;
+            Ok(m.to_string())
+        })
+        .collect::<Result<_, ChessError>>()?;
+
+    Ok(PgnGame { tags, fen, moves })
+}
+
+/// Convert a line of space separated UCI moves played from a position to SAN, with move numbers,
+/// e.g. "24... Bxg3 25. Rxe7 Qb1+".
+pub fn san_line(fen: &str, moves: &str) -> Result<String, ChessError> {
+    Ok(san_tokens(fen, moves)?.join(" "))
+}
+
+/// Render a puzzle as a PGN game starting from the puzzle's position, with the solution as the
+/// moves and the puzzle's details as tags.
+pub fn puzzle_pgn(puzzle: &Puzzle) -> Result<String, ChessError> {
+    let mut tags = vec![
+        ("Event", format!("Puzzle {}", puzzle.puzzle_id)),
+        ("Site", puzzle.game_url.clone()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", "-".to_string()),
+        ("White", "?".to_string()),
+        ("Black", "?".to_string()),
+        ("Result", "*".to_string()),
+        ("FEN", puzzle.fen.clone()),
+        ("SetUp", "1".to_string()),
+        ("PuzzleId", puzzle.puzzle_id.clone()),
+        ("PuzzleRating", puzzle.rating.to_string()),
+        ("Themes", puzzle.themes.join(" ")),
+    ];
+
+    if !puzzle.opening_tags.is_empty() {
+        tags.push(("Openings", puzzle.opening_tags.join(" ")));
+    }
+
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+    }
+    pgn.push('\n');
+
+    let mut tokens = san_tokens(&puzzle.fen, &puzzle.moves)?;
+    tokens.push("*".to_string());
+
+    // Wrap the movetext so that lines aren't too long.
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() + 1 > PGN_LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+
+    Ok(pgn)
+}
+
+/// Convert a line of UCI moves to SAN moves and move numbers.
+fn san_tokens(fen: &str, moves: &str) -> Result<Vec<String>, ChessError> {
+    let mut position = Position::from_fen(fen)?;
+    let mut tokens = Vec::new();
+
+    for (i, uci) in moves.split_whitespace().enumerate() {
+        // Move numbers go before white's moves, and before the first move if it's black's.
+        match position.turn() {
+            Color::White => tokens.push(format!("{}.", position.fullmove_number())),
+            Color::Black if i == 0 => tokens.push(format!("{}...", position.fullmove_number())),
+            Color::Black => (),
+        }
+
+        let m = Move::from_uci(uci)?;
+        let san = position.san(&m);
+        position.play(&m)?;
+
+        match tokens.last_mut() {
+            Some(number) if number.ends_with('.') => number.push_str(&format!(" {san}")),
+            _ => tokens.push(san),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Puzzle;
+    use crate::pgn::{san_line, puzzle_pgn, parse_games};
+
+    fn puzzle() -> Puzzle {
+        Puzzle {
+            puzzle_id: "00008".to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating: 1913,
+            rating_deviation: 75,
+            popularity: 94,
+            number_of_plays: 6230,
+            themes: vec!["crushing".to_string(), "hangingPiece".to_string(), "long".to_string(),
+                "middlegame".to_string()],
+            game_url: "https://lichess.org/787zsVup/black#48".to_string(),
+            opening_tags: vec![],
+            source: crate::lichess::LICHESS_SOURCE.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_san_line() {
+        let puzzle = puzzle();
+        assert_eq!(san_line(&puzzle.fen, &puzzle.moves).unwrap(),
+            "24... Bxg3 25. Rxe7 Qb1+ 26. Nc1 Qxc1+ 27. Qxc1");
+
+        // Puzzle 0000D, which starts with white's move.
+        assert_eq!(san_line("5rk1/1p3ppp/pq3b2/8/8/1P1Q1N2/P4PPP/3R2K1 w - - 2 27", "d3d6 f8d8 d6d8 f6d8")
+            .unwrap(), "27. Qd6 Rd8 28. Qxd8+ Bxd8");
+
+        assert!(san_line(&puzzle.fen, "f2g3 e6e9").is_err());
+    }
+
+    #[test]
+    fn test_puzzle_pgn() {
+        assert_eq!(puzzle_pgn(&puzzle()).unwrap(), concat!(
+            "[Event \"Puzzle 00008\"]\n",
+            "[Site \"https://lichess.org/787zsVup/black#48\"]\n",
+            "[Date \"????.??.??\"]\n",
+            "[Round \"-\"]\n",
+            "[White \"?\"]\n",
+            "[Black \"?\"]\n",
+            "[Result \"*\"]\n",
+            "[FEN \"r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24\"]\n",
+            "[SetUp \"1\"]\n",
+            "[PuzzleId \"00008\"]\n",
+            "[PuzzleRating \"1913\"]\n",
+            "[Themes \"crushing hangingPiece long middlegame\"]\n",
+            "\n",
+            "24... Bxg3 25. Rxe7 Qb1+ 26. Nc1 Qxc1+ 27. Qxc1 *\n",
+        ));
+    }
+
+    #[test]
+    fn test_parse_games() {
+        let pgn = concat!(
+            "[Event \"Casual \\\"blitz\\\" game\"]\n",
+            "[White \"me\"]\n",
+            "[Black \"them\"]\n",
+            "\n",
+            "1. e4 {best by test} e5 2. Nf3 (2. Bc4 Nc6 (2... Nf6)) 2... Nc6 $1 3.Bb5 a6 ; the Ruy Lopez\n",
+            "4. O-O 1-0\n",
+            "\n",
+            "[Event \"From a position\"]\n",
+            "[FEN \"r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24\"]\n",
+            "[SetUp \"1\"]\n",
+            "\n",
+            "24... Bxg3 25. Rxe7 Qb1+ *\n",
+            "\n",
+            "[Event \"Illegal\"]\n",
+            "\n",
+            "1. e5 *\n",
+            "\n",
+            "[Event \"No result\"]\n",
+            "\n",
+            "1. d4 d5\n",
+            "[Event \"Last game\"]\n",
+            "\n",
+            "1. c4\n",
+        );
+
+        let games = parse_games(pgn);
+        assert_eq!(games.len(), 5);
+
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\" game"));
+        assert_eq!(game.tag("White"), Some("me"));
+        assert_eq!(game.tag("Site"), None);
+        assert_eq!(game.moves, ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "e1g1"]);
+
+        let game = games[1].as_ref().unwrap();
+        assert_eq!(game.fen, "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24");
+        assert_eq!(game.moves, ["f2g3", "e6e7", "b2b1"]);
+
+        assert!(games[2].is_err());
+        assert_eq!(games[3].as_ref().unwrap().moves, ["d2d4", "d7d5"]);
+        assert_eq!(games[4].as_ref().unwrap().moves, ["c2c4"]);
+    }
+}
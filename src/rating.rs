@@ -8,6 +8,10 @@ use std::f64::consts::PI;
 /// rating changes after upset results.
 const TAU: f64 = 0.2;
 
+/// The conversion factor and offset for ratings to the glicko-2 scale.
+const RATING_SCALE: f64 = 173.7178;
+const RATING_OFFSET: f64 = 1500.0;
+
 /// A struct representing a player's Glicko2 rating and rating deviation.
 #[derive(Debug, Copy, Clone)]
 pub struct Rating {
@@ -28,10 +32,6 @@ pub struct GameResult<T> {
 impl Rating {
     /// Update the rating based on a list of game results over a rating period.
     pub fn update(&mut self, results: Vec<GameResult<i64>>) {
-        // The conversion factor and offset for ratings to the glicko-2 scale.
-        const RATING_SCALE: f64 = 173.7178;
-        const RATING_OFFSET: f64 = 1500.0;
-
         // Convert the ratings onto the glicko-2 scale.
         let rating = (self.rating as f64 - RATING_OFFSET) / RATING_SCALE;
         let deviation = self.deviation as f64 / RATING_SCALE;
@@ -82,6 +82,37 @@ impl Rating {
         self.deviation = (RATING_SCALE * deviation_new) as i64;
     }
 
+    /// The expected score against an opponent (i.e. the probability of solving a puzzle) with the
+    /// given rating and deviation. Our own deviation is combined with the opponent's, so that
+    /// the expected score is less certain when our rating is.
+    pub fn expected_score(&self, rating_other: i64, deviation_other: i64) -> f64 {
+        let rating = (self.rating as f64 - RATING_OFFSET) / RATING_SCALE;
+        let rating_other = (rating_other as f64 - RATING_OFFSET) / RATING_SCALE;
+        let deviation = self.combined_deviation(deviation_other);
+
+        Self::e(rating, rating_other, deviation)
+    }
+
+    /// The inverse of `expected_score`, the rating of an opponent with the given deviation that
+    /// we'd have the given expected score against.
+    pub fn rating_for_expected_score(&self, expected_score: f64, deviation_other: i64) -> i64 {
+        let expected_score = expected_score.clamp(0.001, 0.999);
+        let rating = (self.rating as f64 - RATING_OFFSET) / RATING_SCALE;
+        let deviation = self.combined_deviation(deviation_other);
+
+        // Solve E = 1 / (1 + exp(-g * (rating - rating_other))) for rating_other.
+        let rating_other = rating - f64::ln(expected_score / (1.0 - expected_score)) / Self::g(deviation);
+
+        (rating_other * RATING_SCALE + RATING_OFFSET).round() as i64
+    }
+
+    /// Our deviation combined with an opponent's, on the glicko-2 scale.
+    fn combined_deviation(&self, deviation_other: i64) -> f64 {
+        let deviation = self.deviation as f64 / RATING_SCALE;
+        let deviation_other = deviation_other as f64 / RATING_SCALE;
+        f64::sqrt(deviation * deviation + deviation_other * deviation_other)
+    }
+
     /// The iterative method to calculate the new volatility value from the paper. For the function
     /// f(x) defined in the paper, determine the value of x where f(x) = 0. (Don't ask me how this
     /// works though, because I don't really know. Hopefully it's correct enough though...)
@@ -148,3 +179,41 @@ impl Rating {
         1.0 / (1.0 + f64::exp(-Self::g(deviation_other) * (rating - rating_other)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::rating::Rating;
+
+    #[test]
+    fn test_expected_score() {
+        let rating = Rating { rating: 1500, deviation: 50, volatility: 0.06 };
+
+        // An equally rated opponent should have an expected score of 0.5.
+        assert!((rating.expected_score(1500, 75) - 0.5).abs() < 0.0001);
+
+        // Higher rated opponents should be harder, and lower rated ones easier.
+        assert!(rating.expected_score(1700, 75) < 0.5);
+        assert!(rating.expected_score(1300, 75) > 0.5);
+
+        // A higher deviation should make the expected score less certain.
+        let uncertain = Rating { deviation: 350, ..rating };
+        assert!(uncertain.expected_score(1300, 75) < rating.expected_score(1300, 75));
+    }
+
+    #[test]
+    fn test_rating_for_expected_score() {
+        let rating = Rating { rating: 1200, deviation: 120, volatility: 0.06 };
+
+        assert_eq!(rating.rating_for_expected_score(0.5, 75), 1200);
+
+        // It should be the inverse of expected_score.
+        for expected_score in [0.1, 0.25, 0.6, 0.75, 0.9] {
+            let rating_other = rating.rating_for_expected_score(expected_score, 75);
+            assert!(rating_other != 1200);
+            assert!((rating.expected_score(rating_other, 75) - expected_score).abs() < 0.01);
+        }
+
+        // A higher target success rate should mean an easier opponent.
+        assert!(rating.rating_for_expected_score(0.75, 75) < rating.rating_for_expected_score(0.6, 75));
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt::Write;
+
+use resvg::{tiny_skia, usvg};
+use resvg::usvg::TreeParsing;
+use sha2::{Digest, Sha256};
+
+use crate::assets;
+use crate::chess::{Position, Move, Color, Role, Square, ChessError};
+use crate::db::Puzzle;
+
+/// The size of a square, which is the size of the piece images.
+const SQUARE_SIZE: u32 = 45;
+
+/// The size of a rendered board.
+pub const BOARD_SIZE: u32 = SQUARE_SIZE * 8;
+
+/// The square colors, matching chessground's brown board.
+const LIGHT_SQUARE_COLOR: &str = "#f0d9b5";
+const DARK_SQUARE_COLOR: &str = "#b58863";
+
+/// The color the squares of the last move are highlighted in.
+const LAST_MOVE_COLOR: &str = "rgba(155,199,0,0.41)";
+
+/// The version of the rendering, which is part of thumbnails' ETags. Increase it whenever the way
+/// boards are drawn changes, so that browsers don't keep showing the old images.
+const RENDER_VERSION: u32 = 1;
+
+/// Render a puzzle's starting position as an SVG, which is the position after the opponent's first
+/// move, from the point of view of the player solving it.
+pub fn puzzle_svg(puzzle: &Puzzle) -> Result<String, ChessError> {
+    let (position, last_move) = puzzle_start(puzzle)?;
+    Ok(board_svg(&position, position.turn(), last_move))
+}
+
+/// Get the ETag for a puzzle's thumbnail in the given format and size. It's a hash of what the
+/// image is rendered from, so it can be checked without rendering the image.
+pub fn thumbnail_etag(puzzle: &Puzzle, format: &str, size: u32) -> Result<String, ChessError> {
+    let (position, last_move) = puzzle_start(puzzle)?;
+    let last_move = last_move.map(|m| m.to_string()).unwrap_or_default();
+
+    let inputs = format!("{RENDER_VERSION} {format} {size} {:?} {} {last_move}",
+        position.turn(), position.to_fen());
+    Ok(format!("\"{:x}\"", Sha256::digest(inputs)))
+}
+
+/// The position shown in a puzzle's thumbnail, and the opponent's move that led to it.
+fn puzzle_start(puzzle: &Puzzle) -> Result<(Position, Option<Move>), ChessError> {
+    let mut position = Position::from_fen(&puzzle.fen)?;
+
+    let last_move = match puzzle.moves.split_whitespace().next() {
+        Some(uci) => Some(position.play_uci(uci)?),
+        None => None,
+    };
+
+    Ok((position, last_move))
+}
+
+/// Render a position as an SVG, with the given color at the bottom and the last move highlighted.
+pub fn board_svg(position: &Position, orientation: Color, last_move: Option<Move>) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+         width=\"{BOARD_SIZE}\" height=\"{BOARD_SIZE}\" viewBox=\"0 0 {BOARD_SIZE} {BOARD_SIZE}\">"
+    );
+
+    // Each piece that's on the board is defined once and then used on each of its squares.
+    let mut pieces = Vec::new();
+    for square in all_squares() {
+        if let Some(piece) = position.piece_at(square) {
+            let name = piece_name(piece.color, piece.role);
+            if !pieces.contains(&name) {
+                pieces.push(name);
+            }
+        }
+    }
+
+    svg.push_str("<defs>");
+    for name in &pieces {
+        let _ = write!(svg, "<g id=\"{name}\">{}</g>", piece_svg_contents(name));
+    }
+    svg.push_str("</defs>");
+
+    for square in all_squares() {
+        let (x, y) = square_position(square, orientation);
+        let color = match (square.file() + square.rank()) % 2 {
+            0 => DARK_SQUARE_COLOR,
+            _ => LIGHT_SQUARE_COLOR,
+        };
+        let _ = write!(svg, "<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE_SIZE}\" height=\"{SQUARE_SIZE}\" \
+            fill=\"{color}\"/>");
+    }
+
+    if let Some(m) = last_move {
+        for square in [m.from, m.to] {
+            let (x, y) = square_position(square, orientation);
+            let _ = write!(svg, "<rect x=\"{x}\" y=\"{y}\" width=\"{SQUARE_SIZE}\" \
+                height=\"{SQUARE_SIZE}\" fill=\"{LAST_MOVE_COLOR}\"/>");
+        }
+    }
+
+    for square in all_squares() {
+        if let Some(piece) = position.piece_at(square) {
+            let (x, y) = square_position(square, orientation);
+            let name = piece_name(piece.color, piece.role);
+            let _ = write!(svg, "<use xlink:href=\"#{name}\" x=\"{x}\" y=\"{y}\"/>");
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Rasterize an SVG to a PNG of the given width and height.
+pub fn svg_to_png(svg: &str, size: u32) -> Result<Vec<u8>, String> {
+    let tree = usvg::Tree::from_str(svg, &usvg::Options::default())
+        .map_err(|e| format!("Failed to parse svg: {e}"))?;
+
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)
+        .ok_or_else(|| format!("Invalid image size {size}"))?;
+
+    let scale = size as f32 / tree.size.width().max(tree.size.height());
+    resvg::Tree::from_usvg(&tree)
+        .render(tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    pixmap.encode_png()
+        .map_err(|e| format!("Failed to encode png: {e}"))
+}
+
+fn all_squares() -> impl Iterator<Item = Square> {
+    (0..8).flat_map(|rank| (0..8).map(move |file| Square::new(file, rank)))
+}
+
+/// The top left corner of a square on the board.
+fn square_position(square: Square, orientation: Color) -> (u32, u32) {
+    let (column, row) = match orientation {
+        Color::White => (square.file(), 7 - square.rank()),
+        Color::Black => (7 - square.file(), square.rank()),
+    };
+    (column as u32 * SQUARE_SIZE, row as u32 * SQUARE_SIZE)
+}
+
+/// The name of a piece's image, e.g. "white-king".
+fn piece_name(color: Color, role: Role) -> String {
+    let color = match color {
+        Color::White => "white",
+        Color::Black => "black",
+    };
+    let role = match role {
+        Role::Pawn => "pawn",
+        Role::Knight => "knight",
+        Role::Bishop => "bishop",
+        Role::Rook => "rook",
+        Role::Queen => "queen",
+        Role::King => "king",
+    };
+    format!("{color}-{role}")
+}
+
+/// The contents of a piece's embedded svg, without the outer svg element, so that it can be
+/// included in the board.
+fn piece_svg_contents(name: &str) -> String {
+    let path = format!("images/pieces/{name}.svg");
+    let data = assets::get(&path).unwrap_or_else(|| panic!("Missing piece image {path}"));
+    let svg = String::from_utf8_lossy(&data);
+
+    let start = svg.find("<svg").and_then(|i| svg[i..].find('>').map(|j| i + j + 1));
+    let end = svg.rfind("</svg>");
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => svg[start..end].to_string(),
+        _ => panic!("Invalid piece image {path}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess::{Position, Color, Move, STARTING_FEN};
+    use crate::db::Puzzle;
+    use crate::render::{board_svg, puzzle_svg, svg_to_png, thumbnail_etag, BOARD_SIZE};
+
+    #[test]
+    fn test_board_svg() {
+        let position = Position::from_fen(STARTING_FEN).unwrap();
+        let svg = board_svg(&position, Color::White, None);
+
+        // Each kind of piece is defined once, and there's one of each piece on the board.
+        assert_eq!(svg.matches("<g id=").count(), 12);
+        assert_eq!(svg.matches("<use ").count(), 32);
+        assert_eq!(svg.matches("<rect ").count(), 64);
+
+        // The white king is on e1, which is at the bottom for white and the top for black.
+        assert!(svg.contains("<use xlink:href=\"#white-king\" x=\"180\" y=\"315\"/>"));
+        let svg = board_svg(&position, Color::Black, None);
+        assert!(svg.contains("<use xlink:href=\"#white-king\" x=\"135\" y=\"0\"/>"));
+    }
+
+    #[test]
+    fn test_puzzle_svg() {
+        let puzzle = Puzzle {
+            puzzle_id: "00008".to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating: 1913,
+            rating_deviation: 75,
+            popularity: 94,
+            number_of_plays: 6230,
+            themes: vec![],
+            game_url: "https://lichess.org/787zsVup/black#48".to_string(),
+            opening_tags: vec![],
+            source: crate::lichess::LICHESS_SOURCE.to_string(),
+        };
+
+        // Black's first move is played, and the board is from white's point of view with the move
+        // highlighted.
+        let mut position = Position::from_fen(&puzzle.fen).unwrap();
+        position.play_uci("f2g3").unwrap();
+        let expected = board_svg(&position, Color::White, Some(Move::from_uci("f2g3").unwrap()));
+        assert_eq!(puzzle_svg(&puzzle).unwrap(), expected);
+        assert_eq!(expected.matches("rgba(155,199,0,0.41)").count(), 2);
+
+        let puzzle = Puzzle { moves: "f2h4".to_string(), ..puzzle };
+        assert!(puzzle_svg(&puzzle).is_err());
+    }
+
+    #[test]
+    fn test_thumbnail_etag() {
+        let puzzle = Puzzle {
+            puzzle_id: "00008".to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating: 1913,
+            rating_deviation: 75,
+            popularity: 94,
+            number_of_plays: 6230,
+            themes: vec![],
+            game_url: "https://lichess.org/787zsVup/black#48".to_string(),
+            opening_tags: vec![],
+            source: crate::lichess::LICHESS_SOURCE.to_string(),
+        };
+
+        // The ETag only depends on what the image is rendered from, so it's the same each time.
+        let etag = thumbnail_etag(&puzzle, "png", 100).unwrap();
+        assert_eq!(etag.len(), 66);
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+        assert_eq!(thumbnail_etag(&puzzle, "png", 100).unwrap(), etag);
+        let rated = Puzzle { rating: 2000, number_of_plays: 7000, ..puzzle.clone() };
+        assert_eq!(thumbnail_etag(&rated, "png", 100).unwrap(), etag);
+
+        // It changes with the format, size or position.
+        assert_ne!(thumbnail_etag(&puzzle, "png", 200).unwrap(), etag);
+        assert_ne!(thumbnail_etag(&puzzle, "svg", 100).unwrap(), etag);
+        let moved = Puzzle { moves: "f2e1 e6e7".to_string(), ..puzzle.clone() };
+        assert_ne!(thumbnail_etag(&moved, "png", 100).unwrap(), etag);
+
+        let puzzle = Puzzle { moves: "f2h4".to_string(), ..puzzle };
+        assert!(thumbnail_etag(&puzzle, "png", 100).is_err());
+    }
+
+    #[test]
+    fn test_svg_to_png() {
+        let position = Position::from_fen(STARTING_FEN).unwrap();
+        let svg = board_svg(&position, Color::White, None);
+
+        for size in [BOARD_SIZE, 100] {
+            let png = svg_to_png(&svg, size).unwrap();
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+            // The width and height are in the IHDR chunk.
+            assert_eq!(&png[16..20], size.to_be_bytes());
+            assert_eq!(&png[20..24], size.to_be_bytes());
+        }
+
+        assert!(svg_to_png(&svg, 0).is_err());
+    }
+}
@@ -1,5 +1,14 @@
 pub mod user_service;
 pub mod tactics_service;
+pub mod collection_service;
+pub mod rush_service;
+pub mod daily_service;
+pub mod solve_service;
+pub mod analysis_service;
+pub mod personal_service;
+pub mod puzzle_db_service;
+pub mod pack_service;
+pub mod anki_service;
 
 use crate::db::DatabaseError;
 
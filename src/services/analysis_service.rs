@@ -0,0 +1,158 @@
+use crate::app::{AppConfig, EngineConfig};
+use crate::chess::{Position, Color, ChessError};
+use crate::engine::{EnginePool, SearchLimit, Score};
+use crate::pgn;
+
+use super::ServiceResult;
+
+/// The maximum depth that can be requested for an analysis.
+pub const MAX_ANALYSIS_DEPTH: u32 = 30;
+
+/// The maximum search time that can be requested for an analysis, in milliseconds.
+pub const MAX_ANALYSIS_MOVETIME_MS: u64 = 30000;
+
+/// An engine analysis of a position.
+#[derive(Debug, serde::Serialize)]
+pub struct Analysis {
+    /// The position that was analysed, after the requested moves were played.
+    pub fen: String,
+    pub depth: u32,
+    /// The evaluation from white's point of view, if the engine gave one.
+    pub score: Option<Score>,
+    /// The best move in UCI notation, or None if the game is over.
+    pub best_move: Option<String>,
+    /// The best line in UCI notation.
+    pub pv: Vec<String>,
+    /// The best line in SAN, with move numbers.
+    pub pv_san: String,
+}
+
+/// Encapsulates analysing positions with a locally installed UCI engine, if one is configured.
+#[derive(Clone)]
+pub struct AnalysisService {
+    engine_config: EngineConfig,
+    pool: Option<EnginePool>,
+}
+
+impl AnalysisService {
+    pub fn new(app_config: AppConfig, pool: Option<EnginePool>) -> Self {
+        Self {
+            engine_config: app_config.engine,
+            pool,
+        }
+    }
+
+    /// Whether an engine is configured.
+    pub fn enabled(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Analyse the position after playing the given space separated UCI moves from a FEN. The
+    /// search is limited by the move time if one is given, then the depth, and otherwise uses the
+    /// configured defaults. Returns an error if the position or moves are invalid.
+    pub async fn analyse(&self, fen: &str, moves: &str, depth: Option<u32>, movetime_ms: Option<u64>)
+        -> ServiceResult<Result<Analysis, ChessError>>
+    {
+        let pool = self.pool.as_ref()
+            .ok_or_else(|| "No engine configured".to_string())?;
+
+        let mut position = match Position::from_fen(fen) {
+            Ok(position) => position,
+            Err(e) => return Ok(Err(e)),
+        };
+        // Send the engine the parsed position rather than the FEN as given, which may have
+        // extra whitespace or fields that the engine would parse differently.
+        let start_fen = position.to_fen();
+        for uci in moves.split_whitespace() {
+            if let Err(e) = position.play_uci(uci) {
+                return Ok(Err(e));
+            }
+        }
+
+        let limit = match (movetime_ms, depth) {
+            (Some(ms), _) => SearchLimit::MoveTime(ms.clamp(1, MAX_ANALYSIS_MOVETIME_MS)),
+            (None, Some(depth)) => SearchLimit::Depth(depth.clamp(1, MAX_ANALYSIS_DEPTH)),
+            (None, None) => self.engine_config.search_limit(),
+        };
+
+        let analysis = pool.analyse(&start_fen, moves, limit, 1).await
+            .map_err(|e| e.to_string())?;
+
+        let fen = position.to_fen();
+
+        // An engine can give moves that aren't legal, e.g. if it parsed the position differently,
+        // so the line is cut off at the first one rather than failing the whole analysis.
+        let mut pv = analysis.pv;
+        let mut line = position.clone();
+        if let Some(invalid) = pv.iter().position(|m| line.play_uci(m).is_err()) {
+            log::warn!("Engine returned an invalid line {pv:?} from {fen}, ignoring it from move {}",
+                invalid + 1);
+            pv.truncate(invalid);
+        }
+        let best_move = analysis.best_move.filter(|m| position.clone().play_uci(m).is_ok());
+
+        let pv_san = pgn::san_line(&fen, &pv.join(" "))
+            .map_err(|e| e.to_string())?;
+
+        Ok(Ok(Analysis {
+            fen,
+            depth: analysis.depth,
+            score: analysis.score.map(|score| match position.turn() {
+                Color::White => score,
+                Color::Black => score.negate(),
+            }),
+            best_move,
+            pv,
+            pv_san,
+        }))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    use tempfile::{NamedTempFile, TempPath};
+
+    use super::*;
+
+    /// A fake UCI engine whose best line has an illegal move in it.
+    const FAKE_ENGINE: &str = r#"#!/bin/sh
+while read -r line; do
+    case "$line" in
+        uci) echo "uciok" ;;
+        isready) echo "readyok" ;;
+        go*)
+            echo "info depth 2 multipv 1 score cp 35 pv e2e4 e7e5 e1e3 b8c6"
+            echo "bestmove e2e4"
+            ;;
+    esac
+done
+"#;
+
+    fn fake_engine() -> TempPath {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(FAKE_ENGINE.as_bytes()).unwrap();
+        let path = file.into_temp_path();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_invalid_line() {
+        let engine = fake_engine();
+        let pool = EnginePool::new(engine.to_str().unwrap(), 1, Duration::from_secs(5));
+        let service = AnalysisService::new(AppConfig::default(), Some(pool));
+
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let analysis = service.analyse(fen, "", Some(2), None).await.unwrap().unwrap();
+
+        // The line is cut off before the illegal move.
+        assert_eq!(analysis.pv, ["e2e4", "e7e5"]);
+        assert_eq!(analysis.pv_san, "1. e4 e5");
+        assert_eq!(analysis.best_move.as_deref(), Some("e2e4"));
+        assert_eq!(analysis.score, Some(Score::Cp(35)));
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Local;
+
+use crate::anki::{self, ExportedPuzzle};
+use crate::app::AppConfig;
+use crate::db::PuzzleDatabase;
+
+use super::ServiceResult;
+
+/// The result of importing an Anki deck.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AnkiImportResult {
+    pub cards_updated: usize,
+    pub reviews_added: usize,
+    /// The number of notes for puzzles that aren't in the puzzle database.
+    pub notes_missing: usize,
+}
+
+/// Encapsulates exporting cards and reviews to Anki, and importing them back again.
+#[derive(Clone)]
+pub struct AnkiService {
+    app_config: AppConfig,
+    db: PuzzleDatabase,
+}
+
+impl AnkiService {
+    pub fn new(app_config: AppConfig, db: PuzzleDatabase) -> Self {
+        Self {
+            app_config,
+            db,
+        }
+    }
+
+    /// Export all of the cards, and the user's reviews of them, as an Anki deck (.apkg).
+    pub async fn export_deck(&self, user_id: &str) -> ServiceResult<Vec<u8>> {
+        let cards = self.db.get_cards().await?;
+
+        let puzzle_ids: Vec<&str> = cards.iter().map(|card| card.id.as_str()).collect();
+        let mut puzzles: HashMap<String, _> = self.db.get_puzzles_by_ids(&puzzle_ids).await?
+            .into_iter()
+            .map(|puzzle| (puzzle.puzzle_id.clone(), puzzle))
+            .collect();
+
+        let mut reviews: HashMap<String, Vec<_>> = HashMap::new();
+        for review in self.db.get_user_reviews(user_id).await? {
+            reviews.entry(review.puzzle_id.clone()).or_default().push(review);
+        }
+
+        let exported: Vec<_> = cards.into_iter()
+            .filter_map(|card| Some(ExportedPuzzle {
+                puzzle: puzzles.remove(&card.id)?,
+                reviews: reviews.remove(&card.id).unwrap_or_default(),
+                card,
+            }))
+            .collect();
+
+        log::info!("Exporting {} cards to Anki", exported.len());
+
+        Ok(anki::write_apkg(&exported, Local::now().fixed_offset()).await?)
+    }
+
+    /// Import the reviews of puzzles exported by `export_deck` from an Anki deck or collection
+    /// package. Reviews that are already in the database are skipped, and cards are only updated
+    /// if they've been reviewed in Anki since, or don't exist yet, so that scheduling changes made
+    /// in Anki carry over.
+    pub async fn import_deck(&mut self, user_id: &str, apkg: &[u8]) -> ServiceResult<AnkiImportResult> {
+        let collection = anki::read_apkg(apkg).await?;
+        log::info!("Importing {} puzzles from Anki", collection.puzzles.len());
+
+        let puzzle_ids: Vec<&str> = collection.puzzles.iter()
+            .map(|puzzle| puzzle.puzzle_id.as_str())
+            .collect();
+        let existing_puzzle_ids: HashSet<String> = self.db.get_puzzles_by_ids(&puzzle_ids).await?
+            .into_iter()
+            .map(|puzzle| puzzle.puzzle_id)
+            .collect();
+
+        // Reviews are matched to revlog entries by their time, which Anki stores in milliseconds.
+        let mut review_times: HashMap<String, Vec<i64>> = HashMap::new();
+        for review in self.db.get_user_reviews(user_id).await? {
+            review_times.entry(review.puzzle_id).or_default().push(review.date.timestamp_millis());
+        }
+        let card_ids: HashSet<String> = self.db.get_cards().await?
+            .into_iter()
+            .map(|card| card.id)
+            .collect();
+
+        let mut result = AnkiImportResult { cards_updated: 0, reviews_added: 0, notes_missing: 0 };
+        let mut reviews = Vec::new();
+        let mut cards = Vec::new();
+
+        for puzzle in &collection.puzzles {
+            if !existing_puzzle_ids.contains(&puzzle.puzzle_id) {
+                result.notes_missing += 1;
+                continue;
+            }
+
+            let puzzle_review_times = review_times.get(&puzzle.puzzle_id).map_or(&[][..], Vec::as_slice);
+            let new_reviews: Vec<_> = anki::new_revlog_entries(&puzzle.revlog, puzzle_review_times)
+                .into_iter()
+                .filter_map(|entry| anki::review_from_revlog(user_id, &puzzle.puzzle_id, entry))
+                .collect();
+
+            if !new_reviews.is_empty() || !card_ids.contains(&puzzle.puzzle_id) {
+                let last_ivl = puzzle.revlog.last().map(|entry| entry.ivl);
+                let card = puzzle.card.as_ref().and_then(|card| anki::card_from_anki(&puzzle.puzzle_id,
+                    card, last_ivl, collection.crt, self.app_config.srs));
+                cards.extend(card);
+            }
+
+            reviews.extend(new_reviews);
+        }
+
+        self.db.add_imported_reviews(&reviews, &cards).await?;
+        result.reviews_added = reviews.len();
+        result.cards_updated = cards.len();
+
+        Ok(result)
+    }
+}
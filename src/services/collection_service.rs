@@ -0,0 +1,140 @@
+use chrono::Local;
+
+use crate::db::{PuzzleDatabase, Collection};
+use crate::pgn;
+
+use super::{ServiceResult, ServiceError};
+
+/// Encapsulates any kind of application logic to do with user-defined puzzle collections.
+#[derive(Clone)]
+pub struct CollectionService {
+    db: PuzzleDatabase,
+}
+
+impl CollectionService {
+    pub fn new(db: PuzzleDatabase) -> Self {
+        Self {
+            db,
+        }
+    }
+
+    /// Get all of a user's collections.
+    pub async fn get_collections(&self, user_id: &str) -> ServiceResult<Vec<Collection>> {
+        Ok(self.db.get_collections(user_id).await?)
+    }
+
+    /// Get one of a user's collections and the IDs of the puzzles in it, in order, or None if
+    /// the user doesn't have a collection with that ID.
+    pub async fn get_collection(&self, user_id: &str, collection_id: i64)
+        -> ServiceResult<Option<(Collection, Vec<String>)>>
+    {
+        let Some(collection) = self.db.get_collection(user_id, collection_id).await? else {
+            return Ok(None);
+        };
+
+        let puzzle_ids = self.db.get_collection_puzzle_ids(collection_id).await?;
+
+        Ok(Some((collection, puzzle_ids)))
+    }
+
+    /// Create a new collection with the given name.
+    pub async fn create_collection(&mut self, user_id: &str, name: &str)
+        -> ServiceResult<Collection>
+    {
+        let name = Self::validate_name(name)?;
+        let collection_id = self.db
+            .create_collection(user_id, name, Local::now().fixed_offset())
+            .await?;
+
+        self.db.get_collection(user_id, collection_id).await?
+            .ok_or_else(|| ServiceError::InternalError(
+                format!("Failed to get new collection {collection_id}")))
+    }
+
+    /// Rename one of a user's collections. Returns false if there's no such collection.
+    pub async fn rename_collection(&mut self, user_id: &str, collection_id: i64, name: &str)
+        -> ServiceResult<bool>
+    {
+        let name = Self::validate_name(name)?;
+
+        if self.db.get_collection(user_id, collection_id).await?.is_none() {
+            return Ok(false);
+        }
+
+        self.db.rename_collection(collection_id, name).await?;
+
+        Ok(true)
+    }
+
+    /// Delete one of a user's collections. Returns false if there's no such collection.
+    pub async fn delete_collection(&mut self, user_id: &str, collection_id: i64)
+        -> ServiceResult<bool>
+    {
+        if self.db.get_collection(user_id, collection_id).await?.is_none() {
+            return Ok(false);
+        }
+
+        self.db.delete_collection(collection_id).await?;
+
+        Ok(true)
+    }
+
+    /// Add a puzzle to the end of one of a user's collections. Returns false if there's no such
+    /// collection or puzzle.
+    pub async fn add_puzzle(&mut self, user_id: &str, collection_id: i64, puzzle_id: &str)
+        -> ServiceResult<bool>
+    {
+        if self.db.get_collection(user_id, collection_id).await?.is_none()
+            || self.db.get_puzzle_by_id(puzzle_id).await?.is_none()
+        {
+            return Ok(false);
+        }
+
+        self.db.add_collection_puzzle(collection_id, puzzle_id).await?;
+
+        Ok(true)
+    }
+
+    /// Remove a puzzle from one of a user's collections. Returns false if there's no such
+    /// collection.
+    pub async fn remove_puzzle(&mut self, user_id: &str, collection_id: i64, puzzle_id: &str)
+        -> ServiceResult<bool>
+    {
+        if self.db.get_collection(user_id, collection_id).await?.is_none() {
+            return Ok(false);
+        }
+
+        self.db.remove_collection_puzzle(collection_id, puzzle_id).await?;
+
+        Ok(true)
+    }
+
+    /// Get the puzzles in one of a user's collections as PGN games, in order. Returns None if
+    /// there's no such collection.
+    pub async fn get_collection_pgn(&self, user_id: &str, collection_id: i64)
+        -> ServiceResult<Option<String>>
+    {
+        let Some((_, puzzle_ids)) = self.get_collection(user_id, collection_id).await? else {
+            return Ok(None);
+        };
+
+        let mut games = Vec::new();
+        for puzzle_id in puzzle_ids {
+            if let Some(puzzle) = self.db.get_puzzle_by_id(&puzzle_id).await? {
+                games.push(pgn::puzzle_pgn(&puzzle)
+                    .map_err(|e| format!("Failed to convert puzzle {puzzle_id} to PGN: {e}"))?);
+            }
+        }
+
+        Ok(Some(games.join("\n")))
+    }
+
+    /// Check a collection name isn't empty, and trim any whitespace from it.
+    fn validate_name(name: &str) -> ServiceResult<&str> {
+        let name = name.trim();
+        match name.is_empty() {
+            true => Err(ServiceError::InternalError("Collection names can't be empty".to_string())),
+            false => Ok(name),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use chrono::{Local, NaiveDate};
+
+use crate::app::AppConfig;
+use crate::daily;
+use crate::db::{PuzzleDatabase, Puzzle, DailyResult, PuzzleFilter};
+use crate::time::LocalTimeProvider;
+
+use super::ServiceResult;
+
+/// The puzzle of the day, and the user's result and streak.
+#[derive(Debug, serde::Serialize)]
+pub struct DailyPuzzle {
+    #[serde(serialize_with = "crate::util::serialize_date")]
+    pub date: NaiveDate,
+    #[serde(skip)]
+    pub puzzle: Option<Puzzle>,
+    /// The user's result for today's puzzle, if they've attempted it.
+    pub result: Option<DailyResult>,
+    /// The number of consecutive days the user has solved the daily puzzle.
+    pub streak: i64,
+    pub best_streak: i64,
+}
+
+/// Encapsulates any kind of application logic to do with the puzzle of the day.
+#[derive(Clone)]
+pub struct DailyService {
+    app_config: AppConfig,
+    db: PuzzleDatabase,
+}
+
+impl DailyService {
+    pub fn new(app_config: AppConfig, db: PuzzleDatabase) -> Self {
+        Self {
+            app_config,
+            db,
+        }
+    }
+
+    /// Get today's puzzle, picking it if it hasn't been picked yet. The day ends at the srs day
+    /// end time, rather than at midnight.
+    pub async fn get_daily_puzzle(&mut self, user_id: &str) -> ServiceResult<DailyPuzzle> {
+        let date = self.app_config.srs.current_day::<LocalTimeProvider>();
+
+        let puzzle_id = match self.db.get_daily_puzzle_id(date).await? {
+            Some(puzzle_id) => Some(puzzle_id),
+            None => self.pick_daily_puzzle(user_id, date).await?,
+        };
+
+        let puzzle = match puzzle_id {
+            Some(puzzle_id) => self.db.get_puzzle_by_id(&puzzle_id).await?,
+            None => None,
+        };
+
+        let result = self.db.get_daily_result(user_id, date).await?;
+
+        let solved_dates = self.db.get_solved_daily_dates(user_id).await?;
+        let (streak, best_streak) = daily::daily_streaks(&solved_dates, date);
+
+        Ok(DailyPuzzle {
+            date,
+            puzzle,
+            result,
+            streak,
+            best_streak,
+        })
+    }
+
+    /// Record the user's result for today's puzzle, from the solve session they played it in. It's
+    /// solved if the session's solution was completed without any mistakes, and failed as soon as
+    /// there's a mistake. Only the first result counts, and results for any other puzzle (e.g.
+    /// yesterday's, if the day ended while the user was solving it) are ignored. Returns None if
+    /// there's no such session for the puzzle.
+    pub async fn submit_result(&mut self, user_id: &str, puzzle_id: &str, session_id: i64)
+        -> ServiceResult<Option<DailyPuzzle>>
+    {
+        let Some(session) = self.db.get_solve_session(user_id, session_id).await?
+            .filter(|session| session.puzzle_id == puzzle_id) else {
+            return Ok(None);
+        };
+
+        let date = self.app_config.srs.current_day::<LocalTimeProvider>();
+        let finished = session.completed.is_some() || session.mistakes > 0;
+        let success = session.completed.is_some() && session.mistakes == 0;
+
+        if finished && self.db.get_daily_puzzle_id(date).await?.as_deref() == Some(puzzle_id) {
+            log::info!("Daily puzzle {puzzle_id} for {date} {}", if success { "solved" } else { "failed" });
+
+            self.db.add_daily_result(user_id, date, &DailyResult {
+                puzzle_id: puzzle_id.to_string(),
+                success,
+                completed: Local::now().fixed_offset(),
+            }).await?;
+        }
+
+        Ok(Some(self.get_daily_puzzle(user_id).await?))
+    }
+
+    /// Pick the daily puzzle for the given date from a seeded hash of the date, out of the
+    /// popular puzzles that the user hasn't reported.
+    async fn pick_daily_puzzle(&mut self, user_id: &str, date: NaiveDate) -> ServiceResult<Option<String>> {
+        let config = &self.app_config.tactics;
+        let filter = PuzzleFilter {
+            min_popularity: Some(config.daily_min_popularity),
+            min_plays: Some(config.daily_min_plays),
+            ..Default::default()
+        };
+
+        let Some(puzzle) = self.db.get_seeded_puzzle(user_id, daily::daily_seed(date), &filter).await? else {
+            log::warn!("No puzzles found for the puzzle of the day");
+            return Ok(None);
+        };
+
+        log::info!("Picked puzzle {} as the puzzle of the day for {date}", puzzle.puzzle_id);
+
+        // Use whatever's in the database in case another request picked it at the same time.
+        self.db.set_daily_puzzle_id(date, &puzzle.puzzle_id).await?;
+        Ok(self.db.get_daily_puzzle_id(date).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::app::AppConfig;
+    use crate::db::{Puzzle, PuzzleDatabase};
+    use crate::services::daily_service::DailyService;
+    use crate::services::solve_service::SolveService;
+    use crate::srs::SrsConfig;
+
+    /// Create a daily service and a solve service with puzzle 00008 from the lichess puzzle
+    /// database as the only puzzle, so that it's the daily puzzle.
+    async fn services() -> (DailyService, SolveService) {
+        let mut db = PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default())
+            .await.unwrap();
+
+        db.add_puzzles(&vec![Puzzle {
+            puzzle_id: "00008".to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating: 1500,
+            rating_deviation: 75,
+            popularity: 95,
+            number_of_plays: 5000,
+            themes: vec![],
+            game_url: String::new(),
+            opening_tags: vec![],
+            source: "lichess".to_string(),
+        }]).await.unwrap();
+
+        (DailyService::new(AppConfig::default(), db.clone()), SolveService::new(db))
+    }
+
+    #[tokio::test]
+    async fn test_daily_result() {
+        let (mut daily_service, mut solve_service) = services().await;
+        assert_eq!(daily_service.get_daily_puzzle("local").await.unwrap().puzzle.unwrap().puzzle_id, "00008");
+
+        // The result comes from the solve session, so it isn't recorded until the puzzle has been
+        // solved or failed.
+        let session_id = solve_service.start("local", "00008").await.unwrap().unwrap().session_id;
+        let daily = daily_service.submit_result("local", "00008", session_id).await.unwrap().unwrap();
+        assert!(daily.result.is_none());
+
+        solve_service.submit_move("local", session_id, "e6e7").await.unwrap().unwrap().unwrap();
+        let daily = daily_service.submit_result("local", "00008", session_id).await.unwrap().unwrap();
+        assert!(daily.result.is_none());
+
+        for uci in ["b3c1", "h6c1"] {
+            solve_service.submit_move("local", session_id, uci).await.unwrap().unwrap().unwrap();
+        }
+        let daily = daily_service.submit_result("local", "00008", session_id).await.unwrap().unwrap();
+        assert!(daily.result.unwrap().success);
+        assert_eq!(daily.streak, 1);
+
+        // Sessions for another puzzle or user aren't accepted.
+        assert!(daily_service.submit_result("local", "00009", session_id).await.unwrap().is_none());
+        assert!(daily_service.submit_result("other", "00008", session_id).await.unwrap().is_none());
+
+        // A mistake fails it, even if the solution is completed afterwards.
+        let session_id = solve_service.start("other", "00008").await.unwrap().unwrap().session_id;
+        for uci in ["h6h7", "e6e7", "b3c1", "h6c1"] {
+            solve_service.submit_move("other", session_id, uci).await.unwrap().unwrap().unwrap();
+        }
+        let daily = daily_service.submit_result("other", "00008", session_id).await.unwrap().unwrap();
+        assert!(!daily.result.unwrap().success);
+        assert_eq!(daily.streak, 0);
+    }
+}
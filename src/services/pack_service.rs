@@ -0,0 +1,60 @@
+use chrono::Local;
+
+use crate::db::{PuzzleDatabase, PuzzlePack};
+use crate::packs::{self, PackFormat};
+
+use super::ServiceResult;
+
+/// The result of importing a puzzle pack.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PackImportResult {
+    pub pack: Option<PuzzlePack>,
+    pub puzzles_added: usize,
+    /// The puzzles that couldn't be imported, and why.
+    pub errors: Vec<String>,
+}
+
+/// Encapsulates importing packs of puzzles from files, such as puzzle books and club-made sets.
+#[derive(Clone)]
+pub struct PackService {
+    db: PuzzleDatabase,
+}
+
+impl PackService {
+    pub fn new(db: PuzzleDatabase) -> Self {
+        Self {
+            db,
+        }
+    }
+
+    /// Get all of the puzzle packs.
+    pub async fn get_packs(&self) -> ServiceResult<Vec<PuzzlePack>> {
+        Ok(self.db.get_puzzle_packs().await?)
+    }
+
+    /// Import the puzzles in a pack with the given source. If the pack has been imported before,
+    /// its puzzles are updated.
+    pub async fn import_pack(&mut self, source: &str, name: &str, format: PackFormat, contents: &str)
+        -> ServiceResult<PackImportResult>
+    {
+        let pack = packs::parse_pack(format, source, contents);
+        log::info!("Importing {} puzzles from pack {name} ({} couldn't be read)",
+            pack.puzzles.len(), pack.errors.len());
+
+        if !pack.puzzles.is_empty() {
+            self.db.add_puzzles(&pack.puzzles).await?;
+            self.db.set_puzzle_pack(source, name, &format.to_string(), Local::now().fixed_offset())
+                .await?;
+        }
+
+        let imported_pack = self.get_packs().await?
+            .into_iter()
+            .find(|imported_pack| imported_pack.source == source);
+
+        Ok(PackImportResult {
+            pack: imported_pack,
+            puzzles_added: pack.puzzles.len(),
+            errors: pack.errors,
+        })
+    }
+}
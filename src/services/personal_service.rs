@@ -0,0 +1,236 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::Local;
+
+use crate::app::{AppConfig, EngineConfig};
+use crate::chess::{Position, Color, ChessError};
+use crate::db::PuzzleDatabase;
+use crate::engine::{EnginePool, Score};
+use crate::pgn::{self, PgnGame};
+use crate::personal::{self, PositionAnalysis};
+
+use super::{ServiceResult, ServiceError};
+
+/// The name of the collection that generated puzzles are added to.
+pub const PERSONAL_COLLECTION_NAME: &str = "Personal puzzles";
+
+/// The progress of generating puzzles from a PGN file.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PersonalImportStatus {
+    pub running: bool,
+    pub games_total: usize,
+    pub games_done: usize,
+    /// Games that couldn't be read, or that the player didn't play in.
+    pub games_skipped: usize,
+    pub puzzles_added: usize,
+    pub collection_id: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Encapsulates generating personal puzzles from the user's own games, by analysing them with the
+/// local engine. Games are analysed in the background, one import at a time.
+#[derive(Clone)]
+pub struct PersonalService {
+    engine_config: EngineConfig,
+    db: PuzzleDatabase,
+    pool: Option<EnginePool>,
+    status: Arc<Mutex<PersonalImportStatus>>,
+}
+
+impl PersonalService {
+    pub fn new(app_config: AppConfig, db: PuzzleDatabase, pool: Option<EnginePool>) -> Self {
+        Self {
+            engine_config: app_config.engine,
+            db,
+            pool,
+            status: Default::default(),
+        }
+    }
+
+    /// Whether an engine is configured, which is needed to generate puzzles.
+    pub fn enabled(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// The progress of the current or last import.
+    pub fn status(&self) -> PersonalImportStatus {
+        self.status.lock().expect("Import status lock poisoned").clone()
+    }
+
+    /// Start generating puzzles from the games in a PGN file in the background. Only the moves of
+    /// the player with the given name are checked, or both players' if it's empty. Returns None if
+    /// an import is already running.
+    pub fn start_import(&self, user_id: &str, pgn: &str, player: &str)
+        -> ServiceResult<Option<PersonalImportStatus>>
+    {
+        let pool = self.pool.clone()
+            .ok_or_else(|| "No engine configured".to_string())?;
+
+        let games = pgn::parse_games(pgn);
+
+        {
+            let mut status = self.status.lock().expect("Import status lock poisoned");
+            if status.running {
+                return Ok(None);
+            }
+            *status = PersonalImportStatus {
+                running: true,
+                games_total: games.len(),
+                ..Default::default()
+            };
+        }
+
+        let service = self.clone();
+        let user_id = user_id.to_string();
+        let player = player.trim().to_string();
+        tokio::spawn(async move {
+            let result = service.import_games(&user_id, games, &player, &pool).await;
+
+            let mut status = service.status.lock().expect("Import status lock poisoned");
+            status.running = false;
+            if let Err(e) = result {
+                let ServiceError::InternalError(e) = e;
+                log::error!("Failed to generate puzzles from games: {e}");
+                status.error = Some(e);
+            }
+        });
+
+        Ok(Some(self.status()))
+    }
+
+    async fn import_games(&self, user_id: &str, games: Vec<Result<PgnGame, ChessError>>,
+        player: &str, pool: &EnginePool) -> ServiceResult<()>
+    {
+        let mut db = self.db.clone();
+
+        for (i, game) in games.into_iter().enumerate() {
+            let game = match game {
+                Ok(game) => game,
+                Err(e) => {
+                    log::warn!("Skipping game {} of PGN: {e}", i + 1);
+                    self.skip_game();
+                    continue;
+                },
+            };
+
+            // Find which side the player was, if we're only looking at one player's moves.
+            let color = match player.is_empty() {
+                true => None,
+                false => match Self::player_color(&game, player) {
+                    Some(color) => Some(color),
+                    None => {
+                        self.skip_game();
+                        continue;
+                    },
+                },
+            };
+
+            let analyses = match self.analyse_game(&game, color, pool).await {
+                Ok(analyses) => analyses,
+                Err(ServiceError::InternalError(e)) => {
+                    log::warn!("Failed to analyse game {} of PGN, skipping it: {e}", i + 1);
+                    self.skip_game();
+                    continue;
+                },
+            };
+            let puzzles = personal::find_puzzles(&game, &analyses, color);
+
+            if !puzzles.is_empty() {
+                db.add_puzzles(&puzzles).await?;
+
+                let collection_id = self.personal_collection_id(user_id).await?;
+                for puzzle in &puzzles {
+                    db.add_collection_puzzle(collection_id, &puzzle.puzzle_id).await?;
+                }
+                self.update_status(|status| status.collection_id = Some(collection_id));
+            }
+
+            self.update_status(|status| {
+                status.games_done += 1;
+                status.puzzles_added += puzzles.len();
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Analyse the positions in a game that are needed to check the player's moves, which are the
+    /// positions before and after each of them.
+    async fn analyse_game(&self, game: &PgnGame, player: Option<Color>, pool: &EnginePool)
+        -> ServiceResult<Vec<Option<PositionAnalysis>>>
+    {
+        let mut analyses = Vec::new();
+        let mut position = Position::from_fen(&game.fen)
+            .map_err(|e| e.to_string())?;
+
+        for ply in 0..=game.moves.len() {
+            let needed = personal::needs_analysis(ply, position.turn(), game.moves.len(), player);
+
+            let analysis = if !needed {
+                None
+            }
+            else if position.is_checkmate() {
+                Some(PositionAnalysis { score: Score::Mate(0), pv: Vec::new(), second_score: None })
+            }
+            else if position.is_stalemate() {
+                Some(PositionAnalysis { score: Score::Cp(0), pv: Vec::new(), second_score: None })
+            }
+            else {
+                // The second best move is needed to check that a missed tactic's move is the
+                // only good one.
+                let moves = game.moves[..ply].join(" ");
+                let analysis = pool.analyse(&game.fen, &moves, self.engine_config.search_limit(), 2)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                analysis.score.map(|score| PositionAnalysis {
+                    score,
+                    pv: analysis.pv,
+                    second_score: analysis.second_score,
+                })
+            };
+            analyses.push(analysis);
+
+            if let Some(m) = game.moves.get(ply) {
+                position.play_uci(m).map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(analyses)
+    }
+
+    /// The color the player played in a game, by their name in the White and Black tags.
+    fn player_color(game: &PgnGame, player: &str) -> Option<Color> {
+        let played = |tag| matches!(game.tag(tag), Some(name) if name.eq_ignore_ascii_case(player));
+
+        match (played("White"), played("Black")) {
+            (true, false) => Some(Color::White),
+            (false, true) => Some(Color::Black),
+            _ => None,
+        }
+    }
+
+    /// Get the ID of the user's collection of personal puzzles, creating it if it doesn't exist.
+    async fn personal_collection_id(&self, user_id: &str) -> ServiceResult<i64> {
+        let existing = self.db.get_collections(user_id).await?
+            .into_iter()
+            .find(|collection| collection.name == PERSONAL_COLLECTION_NAME);
+
+        Ok(match existing {
+            Some(collection) => collection.id,
+            None => self.db.clone()
+                .create_collection(user_id, PERSONAL_COLLECTION_NAME, Local::now().fixed_offset())
+                .await?,
+        })
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut PersonalImportStatus)) {
+        update(&mut self.status.lock().expect("Import status lock poisoned"));
+    }
+
+    fn skip_game(&self) {
+        self.update_status(|status| {
+            status.games_done += 1;
+            status.games_skipped += 1;
+        });
+    }
+}
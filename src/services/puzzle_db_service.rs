@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::app::{AppConfig, PuzzleDbConfig};
+use crate::db::PuzzleDatabase;
+use crate::lichess;
+
+use super::ServiceResult;
+
+/// The state of the lichess puzzle database.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzleDbStatus {
+    /// Whether the lichess puzzle database has been fully imported.
+    pub imported: bool,
+    /// Whether an import or refresh is running right now.
+    pub importing: bool,
+    /// The date of the last imported lichess puzzle database, if it's known.
+    #[serde(serialize_with = "crate::util::serialize_optional_datetime")]
+    pub date: Option<DateTime<FixedOffset>>,
+    /// The number of puzzles in the last imported lichess puzzle database.
+    pub rows: Option<i64>,
+    /// The number of puzzles in our database, including ones that aren't from lichess.
+    pub puzzle_count: usize,
+}
+
+/// Encapsulates importing and refreshing the lichess puzzle database.
+#[derive(Clone)]
+pub struct PuzzleDbService {
+    config: PuzzleDbConfig,
+    db: PuzzleDatabase,
+    cancel_import: Arc<AtomicBool>,
+}
+
+impl PuzzleDbService {
+    pub fn new(app_config: AppConfig, db: PuzzleDatabase, cancel_import: Arc<AtomicBool>) -> Self {
+        Self {
+            config: app_config.puzzle_db,
+            db,
+            cancel_import,
+        }
+    }
+
+    /// Get the state of the lichess puzzle database.
+    pub async fn status(&self) -> ServiceResult<PuzzleDbStatus> {
+        let app_data = self.db.get_app_data("").await?
+            .ok_or_else(|| "No app_data row in database".to_string())?;
+
+        Ok(PuzzleDbStatus {
+            imported: app_data.lichess_db_imported,
+            importing: lichess::import_running(),
+            date: app_data.lichess_db_date,
+            rows: app_data.lichess_db_rows,
+            puzzle_count: self.db.get_puzzle_count().await?,
+        })
+    }
+
+    /// Start refreshing the lichess puzzle database in the background, if it isn't already being
+    /// imported. Unless `force` is set, it's only refreshed if there's a newer version. Returns
+    /// false if an import is already running.
+    pub fn start_refresh(&self, force: bool) -> bool {
+        // Mark the import as running now rather than in the task, so that two requests at once
+        // can't both start one.
+        let Some(guard) = lichess::ImportGuard::acquire() else {
+            return false;
+        };
+
+        let refresh = lichess::refresh_db_with_guard(guard, self.db.clone(), self.config.clone(),
+            self.cancel_import.clone(), force);
+
+        tokio::spawn(async move {
+            if let Err(e) = refresh.await {
+                log::error!("Failed to refresh puzzle database: {e}");
+            }
+        });
+
+        true
+    }
+}
@@ -0,0 +1,411 @@
+use chrono::{Local, Duration};
+
+use crate::app::AppConfig;
+use crate::db::{PuzzleDatabase, Puzzle, RushRun, RushRunPuzzle, AttemptData, PuzzleFilter};
+use crate::solution::Solution;
+use crate::srs::Card;
+
+use super::ServiceResult;
+
+/// The lengths of rush runs the user can pick from, in minutes.
+pub const RUSH_DURATIONS_MINUTES: [i64; 2] = [3, 5];
+
+/// The number of failed puzzles that ends a rush run.
+pub const RUSH_MAX_MISTAKES: i64 = 3;
+
+/// How long after a run's deadline results are still accepted, to allow for the time it takes the
+/// client to submit them.
+const RUSH_GRACE_PERIOD_SECS: i64 = 2;
+
+/// The number of puzzles to pick from at each rating, in case some of them are already in the run,
+/// which can happen once the rating goes past the highest rated puzzle.
+const RUSH_PUZZLE_CANDIDATES: usize = 3;
+
+/// The state of a rush run, including the puzzle the user is currently on, if the run isn't over.
+#[derive(Debug, serde::Serialize)]
+pub struct RushState {
+    pub run: RushRun,
+    pub puzzle: Option<Puzzle>,
+    pub puzzles: Vec<RushRunPuzzle>,
+    pub ms_remaining: i64,
+    pub max_mistakes: i64,
+    /// The user's best score for runs of the same duration, not including this one.
+    pub personal_best: Option<i64>,
+}
+
+/// Encapsulates any kind of application logic to do with timed puzzle rush runs, which are
+/// separate from spaced repetition and don't create cards unless the user asks for them.
+#[derive(Clone)]
+pub struct RushService {
+    app_config: AppConfig,
+    db: PuzzleDatabase,
+}
+
+impl RushService {
+    pub fn new(app_config: AppConfig, db: PuzzleDatabase) -> Self {
+        Self {
+            app_config,
+            db,
+        }
+    }
+
+    /// Start a new run of the given duration, and pick its first puzzle.
+    pub async fn start_run(&mut self, user_id: &str, duration_minutes: i64)
+        -> ServiceResult<RushState>
+    {
+        let run_id = self.db
+            .create_rush_run(user_id, Local::now().fixed_offset(), duration_minutes * 60)
+            .await?;
+
+        log::info!("Started {duration_minutes} minute rush run {run_id}");
+
+        let mut run = self.db.get_rush_run(user_id, run_id).await?
+            .ok_or_else(|| format!("Failed to get new rush run {run_id}"))?;
+
+        self.next_puzzle(&mut run, &[]).await?;
+        self.get_state(run).await
+    }
+
+    /// Get the state of one of a user's runs, finishing it if it's run out of time. Returns None
+    /// if there's no such run.
+    pub async fn get_run(&mut self, user_id: &str, run_id: i64) -> ServiceResult<Option<RushState>> {
+        let Some(mut run) = self.db.get_rush_run(user_id, run_id).await? else {
+            return Ok(None);
+        };
+
+        self.finish_if_expired(&mut run).await?;
+
+        Ok(Some(self.get_state(run).await?))
+    }
+
+    /// Record the result of the current puzzle in a run and pick the next one, unless the run is
+    /// over. Whether the puzzle was solved is checked from the moves in the attempt. Results for
+    /// puzzles other than the current one are ignored, so that submitting the same result twice
+    /// doesn't count it twice. Returns None if there's no such run.
+    pub async fn submit_result(&mut self, user_id: &str, run_id: i64, puzzle_id: &str,
+        attempt: &AttemptData) -> ServiceResult<Option<RushState>>
+    {
+        let Some(mut run) = self.db.get_rush_run(user_id, run_id).await? else {
+            return Ok(None);
+        };
+
+        self.finish_if_expired(&mut run).await?;
+
+        let puzzles = self.db.get_rush_run_puzzles(run.id).await?;
+        let current = puzzles.last().filter(|puzzle| puzzle.success.is_none());
+
+        if let Some(current) = current.filter(|current| !run.finished && current.puzzle_id == puzzle_id) {
+            let success = self.check_attempt(&current.puzzle_id, attempt).await?;
+            self.db.set_rush_run_puzzle_result(run.id, current.position, success, attempt).await?;
+
+            match success {
+                true => run.score += 1,
+                false => run.mistakes += 1,
+            }
+
+            run.finished = run.mistakes >= RUSH_MAX_MISTAKES;
+            self.db.update_rush_run(&run).await?;
+
+            if !run.finished {
+                self.next_puzzle(&mut run, &puzzles).await?;
+            }
+        }
+
+        Ok(Some(self.get_state(run).await?))
+    }
+
+    /// Create cards for the puzzles the user failed in a run, if they don't already have them.
+    /// Returns the number of cards created, or None if there's no such run.
+    pub async fn add_failed_puzzles(&mut self, user_id: &str, run_id: i64)
+        -> ServiceResult<Option<usize>>
+    {
+        if self.db.get_rush_run(user_id, run_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let time_now = Local::now().fixed_offset();
+        let mut count = 0;
+
+        for puzzle in self.db.get_rush_run_puzzles(run_id).await? {
+            if puzzle.success == Some(false) && self.db.get_card_by_id(&puzzle.puzzle_id).await?.is_none() {
+                let card = Card::new(&puzzle.puzzle_id, time_now, self.app_config.srs);
+                self.db.update_or_create_card(&card).await?;
+                self.db.add_seen_puzzle(user_id, &puzzle.puzzle_id).await?;
+                count += 1;
+            }
+        }
+
+        log::info!("Created {count} cards from failed puzzles in rush run {run_id}");
+
+        Ok(Some(count))
+    }
+
+    /// Get all of a user's runs, most recent first.
+    pub async fn get_history(&mut self, user_id: &str) -> ServiceResult<Vec<RushRun>> {
+        let mut runs = self.db.get_rush_runs(user_id).await?;
+
+        for run in runs.iter_mut() {
+            self.finish_if_expired(run).await?;
+        }
+
+        Ok(runs)
+    }
+
+    /// Get the user's best run for each duration.
+    pub async fn get_personal_bests(&mut self, user_id: &str) -> ServiceResult<Vec<RushRun>> {
+        // Make sure any runs that were abandoned are counted.
+        self.get_history(user_id).await?;
+
+        Ok(self.db.get_rush_personal_bests(user_id, None).await?)
+    }
+
+    /// Check whether the moves in an attempt solved the puzzle. Moves that aren't legal count as a
+    /// failed attempt.
+    async fn check_attempt(&self, puzzle_id: &str, attempt: &AttemptData) -> ServiceResult<bool> {
+        let puzzle = self.db.get_puzzle_by_id(puzzle_id).await?
+            .ok_or_else(|| format!("No such puzzle {puzzle_id}"))?;
+
+        let solution = Solution::new(&puzzle.fen, &puzzle.moves)
+            .map_err(|e| format!("Invalid solution for puzzle {puzzle_id}: {e}"))?;
+
+        let moves = attempt.moves.as_deref().unwrap_or_default();
+        Ok(solution.check_attempt(moves).unwrap_or_else(|e| {
+            log::warn!("Invalid moves '{moves}' for rush puzzle {puzzle_id}: {e}");
+            false
+        }))
+    }
+
+    /// Mark a run as finished if its time is up.
+    async fn finish_if_expired(&mut self, run: &mut RushRun) -> ServiceResult<()> {
+        let deadline = run.deadline() + Duration::seconds(RUSH_GRACE_PERIOD_SECS);
+        if !run.finished && Local::now().fixed_offset() > deadline {
+            run.finished = true;
+            self.db.update_rush_run(run).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Pick the next puzzle for a run, with a rating that goes up with each puzzle, and add it to
+    /// the run. If there aren't any puzzles left, the run is finished instead.
+    async fn next_puzzle(&mut self, run: &mut RushRun, puzzles: &[RushRunPuzzle])
+        -> ServiceResult<()>
+    {
+        let config = &self.app_config.tactics;
+        let position = puzzles.len() as i64;
+        let min_rating = config.rush_start_rating + position * config.rush_rating_step;
+        let max_rating = min_rating + config.rush_rating_step;
+
+        // Clamp the rating range to that of the puzzle database so that we keep getting puzzles
+        // after the rating goes past the highest rated one.
+        let (min_puzzle_rating, max_puzzle_rating) = self.db.get_puzzle_rating_range().await?;
+        let min_rating = i64::clamp(min_rating, min_puzzle_rating, max_puzzle_rating);
+        let max_rating = i64::clamp(max_rating, min_puzzle_rating, max_puzzle_rating);
+
+        // If there's a gap in the puzzle ratings, widen the range to any harder puzzle, and then to
+        // any puzzle at all.
+        let rating_ranges = [
+            (min_rating, max_rating),
+            (min_rating, max_puzzle_rating),
+            (min_puzzle_rating, max_puzzle_rating),
+        ];
+
+        for (min_rating, max_rating) in rating_ranges {
+            let candidates = self.db.get_puzzles_by_rating(&run.user_id, min_rating, max_rating,
+                RUSH_PUZZLE_CANDIDATES, &PuzzleFilter::default()).await?;
+
+            let puzzle = candidates.into_iter()
+                .find(|puzzle| !puzzles.iter().any(|p| p.puzzle_id == puzzle.puzzle_id));
+            if let Some(puzzle) = puzzle {
+                self.db.add_rush_run_puzzle(run.id, position, &puzzle.puzzle_id, puzzle.rating).await?;
+                return Ok(());
+            }
+        }
+
+        log::warn!("No puzzles left for rush run {}", run.id);
+        run.finished = true;
+        self.db.update_rush_run(run).await?;
+
+        Ok(())
+    }
+
+    /// Get the full state of a run.
+    async fn get_state(&self, run: RushRun) -> ServiceResult<RushState> {
+        let puzzles = self.db.get_rush_run_puzzles(run.id).await?;
+
+        let puzzle = match puzzles.last() {
+            Some(current) if !run.finished && current.success.is_none()
+                => self.db.get_puzzle_by_id(&current.puzzle_id).await?,
+            _ => None,
+        };
+
+        let personal_best = self.db.get_rush_personal_bests(&run.user_id, Some(run.id)).await?
+            .into_iter()
+            .find(|best| best.duration_secs == run.duration_secs)
+            .map(|best| best.score);
+
+        let ms_remaining = match run.finished {
+            true => 0,
+            false => i64::max(0, (run.deadline() - Local::now().fixed_offset()).num_milliseconds()),
+        };
+
+        Ok(RushState {
+            run,
+            puzzle,
+            puzzles,
+            ms_remaining,
+            max_mistakes: RUSH_MAX_MISTAKES,
+            personal_best,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+    use url::Url;
+
+    use crate::app::AppConfig;
+    use crate::db::{AttemptData, Puzzle, PuzzleDatabase};
+    use crate::services::rush_service::{RushService, RUSH_MAX_MISTAKES};
+    use crate::srs::SrsConfig;
+
+    /// Puzzle 00008 from the lichess puzzle database with the given ID and rating, and the user's
+    /// moves that solve it.
+    const SOLVED: &str = "e6e7 b3c1 h6c1";
+
+    fn puzzle(puzzle_id: &str, rating: i64) -> Puzzle {
+        Puzzle {
+            puzzle_id: puzzle_id.to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating,
+            rating_deviation: 75,
+            popularity: 90,
+            number_of_plays: 1000,
+            themes: vec![],
+            game_url: String::new(),
+            opening_tags: vec![],
+            source: "lichess".to_string(),
+        }
+    }
+
+    fn attempt(moves: &str) -> AttemptData {
+        AttemptData {
+            moves: Some(moves.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Create a rush service with puzzles of the given ratings, where runs start at 1000 and go
+    /// up by 100 for each puzzle.
+    async fn rush_service(ratings: &[i64]) -> (RushService, PuzzleDatabase) {
+        let mut db = PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default())
+            .await.unwrap();
+
+        let puzzles = ratings.iter()
+            .enumerate()
+            .map(|(i, rating)| puzzle(&format!("{i:05}"), *rating))
+            .collect();
+        db.add_puzzles(&puzzles).await.unwrap();
+
+        let mut app_config = AppConfig::default();
+        app_config.tactics.rush_start_rating = 1000;
+        app_config.tactics.rush_rating_step = 100;
+
+        (RushService::new(app_config, db.clone()), db)
+    }
+
+    #[tokio::test]
+    async fn test_rush_results() {
+        let (mut service, _) = rush_service(&[1000, 1000, 1000, 1000, 1000]).await;
+
+        let state = service.start_run("local", 3).await.unwrap();
+        let run_id = state.run.id;
+        let first = state.puzzle.unwrap().puzzle_id;
+
+        // Solving the puzzle is checked from the moves rather than trusted.
+        let state = service.submit_result("local", run_id, &first, &attempt(SOLVED)).await.unwrap().unwrap();
+        assert_eq!(state.run.score, 1);
+        assert_eq!(state.puzzles[0].success, Some(true));
+        let second = state.puzzle.unwrap().puzzle_id;
+        assert_ne!(first, second);
+
+        // Submitting the same result again doesn't count it twice.
+        let state = service.submit_result("local", run_id, &first, &attempt(SOLVED)).await.unwrap().unwrap();
+        assert_eq!(state.run.score, 1);
+        assert_eq!(state.puzzles.len(), 2);
+
+        // Wrong, incomplete and illegal moves are all mistakes, and the run ends at the limit.
+        let mut current = second;
+        for (i, moves) in ["e6e7 b3c1 e7e1", "e6e7", "e6e8"].into_iter().enumerate() {
+            let state = service.submit_result("local", run_id, &current, &attempt(moves)).await.unwrap().unwrap();
+            assert_eq!(state.run.mistakes, i as i64 + 1);
+            assert_eq!(state.run.finished, state.run.mistakes >= RUSH_MAX_MISTAKES);
+
+            if let Some(puzzle) = state.puzzle {
+                current = puzzle.puzzle_id;
+            }
+        }
+
+        let state = service.get_run("local", run_id).await.unwrap().unwrap();
+        assert!(state.run.finished);
+        assert!(state.puzzle.is_none());
+        assert_eq!(state.ms_remaining, 0);
+        assert_eq!((state.run.score, state.run.mistakes), (1, RUSH_MAX_MISTAKES));
+
+        // Other users' runs aren't found.
+        assert!(service.get_run("other", run_id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rush_expiry() {
+        let (mut service, mut db) = rush_service(&[1000, 1000]).await;
+
+        let state = service.start_run("local", 3).await.unwrap();
+        assert!(!state.run.finished);
+        assert!(state.ms_remaining > 0);
+
+        // A run that started longer ago than its duration is finished, and doesn't take results.
+        let started = Local::now().fixed_offset() - Duration::minutes(10);
+        let run_id = db.create_rush_run("local", started, 180).await.unwrap();
+        let state = service.get_run("local", run_id).await.unwrap().unwrap();
+        assert!(state.run.finished);
+        assert_eq!(state.ms_remaining, 0);
+
+        let state = service.submit_result("local", run_id, "00000", &attempt(SOLVED)).await.unwrap().unwrap();
+        assert_eq!(state.run.score, 0);
+        assert!(state.puzzles.is_empty());
+
+        // It's counted as finished in the history too.
+        let history = service.get_history("local").await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().any(|run| run.id == run_id && run.finished));
+    }
+
+    #[tokio::test]
+    async fn test_rush_rating_range() {
+        // There's a gap between 1000 and 1500, so the second puzzle's range has to be widened to
+        // the harder puzzles, and then the run ends when there aren't any puzzles left.
+        let (mut service, _) = rush_service(&[1000, 1500, 2500]).await;
+
+        let state = service.start_run("local", 5).await.unwrap();
+        let run_id = state.run.id;
+        assert_eq!(state.puzzles[0].puzzle_rating, 1000);
+
+        let mut puzzle_id = state.puzzle.unwrap().puzzle_id;
+        for _ in 0..2 {
+            let state = service.submit_result("local", run_id, &puzzle_id, &attempt(SOLVED)).await.unwrap().unwrap();
+            let last = state.puzzles.last().unwrap();
+            assert!(last.puzzle_rating > 1000);
+            puzzle_id = last.puzzle_id.clone();
+        }
+
+        let state = service.submit_result("local", run_id, &puzzle_id, &attempt(SOLVED)).await.unwrap().unwrap();
+        assert!(state.run.finished);
+        assert_eq!(state.run.score, 3);
+        let mut ratings: Vec<_> = state.puzzles.iter().map(|puzzle| puzzle.puzzle_rating).collect();
+        ratings.sort();
+        assert_eq!(ratings, [1000, 1500, 2500]);
+    }
+}
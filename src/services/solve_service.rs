@@ -0,0 +1,243 @@
+use chrono::{Local, Duration};
+
+use crate::db::{PuzzleDatabase, Puzzle, SolveSession, AttemptData};
+use crate::chess::ChessError;
+use crate::solution::{Solution, MoveResult};
+
+use super::ServiceResult;
+
+/// How long solve sessions are kept for. Older sessions are deleted when new ones are started.
+const SOLVE_SESSION_EXPIRY_DAYS: i64 = 7;
+
+/// The state of a solve session.
+#[derive(Debug, serde::Serialize)]
+pub struct SolveState {
+    pub session_id: i64,
+    pub puzzle_id: String,
+    /// The position the user is to move in, or the final position if the solution is complete.
+    pub fen: String,
+    pub ply: i64,
+    pub complete: bool,
+    pub mistakes: i64,
+    pub hint_used: bool,
+}
+
+/// The outcome of a move submitted in a solve session.
+#[derive(Debug, serde::Serialize)]
+pub struct SolveMoveResult {
+    pub correct: bool,
+    /// The opponent's reply to a correct move, as a UCI move, unless the solution is complete.
+    pub reply: Option<String>,
+    pub state: SolveState,
+}
+
+/// A hint for the next move in a solve session.
+#[derive(Debug, serde::Serialize)]
+pub struct SolveHint {
+    /// The next move of the solution, as a UCI move, unless the solution is complete.
+    pub next_move: Option<String>,
+    pub state: SolveState,
+}
+
+/// Encapsulates any kind of application logic to do with solving puzzles on the server, which
+/// checks each of the user's moves against the solution so that the attempt data for reviews
+/// doesn't have to be trusted from the client.
+#[derive(Clone)]
+pub struct SolveService {
+    db: PuzzleDatabase,
+}
+
+impl SolveService {
+    pub fn new(db: PuzzleDatabase) -> Self {
+        Self {
+            db,
+        }
+    }
+
+    /// Start a session for solving a puzzle, with the opponent's first move already played.
+    /// Returns None if there's no such puzzle.
+    pub async fn start(&mut self, user_id: &str, puzzle_id: &str) -> ServiceResult<Option<SolveState>> {
+        let Some(puzzle) = self.db.get_puzzle_by_id(puzzle_id).await? else {
+            return Ok(None);
+        };
+
+        let solution = Self::solution(&puzzle)?;
+
+        let time_now = Local::now().fixed_offset();
+        let expired = self.db
+            .delete_solve_sessions_before(time_now - Duration::days(SOLVE_SESSION_EXPIRY_DAYS))
+            .await?;
+        if expired > 0 {
+            log::info!("Deleted {expired} expired solve sessions");
+        }
+
+        let session_id = self.db.create_solve_session(user_id, puzzle_id, time_now, 1).await?;
+        let session = self.db.get_solve_session(user_id, session_id).await?
+            .ok_or_else(|| format!("Failed to get new solve session {session_id}"))?;
+
+        Ok(Some(Self::state(&session, &solution)))
+    }
+
+    /// Get the state of one of a user's sessions. Returns None if there's no such session.
+    pub async fn get_session(&self, user_id: &str, session_id: i64) -> ServiceResult<Option<SolveState>> {
+        let Some((session, solution)) = self.get_session_and_solution(user_id, session_id).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self::state(&session, &solution)))
+    }
+
+    /// Check a move played by the user. Correct moves advance the session and are followed by the
+    /// opponent's reply, and wrong moves are counted as mistakes. Moves are ignored once the
+    /// solution is complete. Returns None if there's no such session, or an error if the move
+    /// is invalid or illegal in the current position.
+    pub async fn submit_move(&mut self, user_id: &str, session_id: i64, uci: &str)
+        -> ServiceResult<Option<Result<SolveMoveResult, ChessError>>>
+    {
+        let Some((mut session, solution)) = self.get_session_and_solution(user_id, session_id).await? else {
+            return Ok(None);
+        };
+
+        let ply = session.ply as usize;
+        if ply >= solution.len() {
+            return Ok(Some(Ok(SolveMoveResult {
+                correct: false,
+                reply: None,
+                state: Self::state(&session, &solution),
+            })));
+        }
+
+        let result = match solution.check_move(ply, uci) {
+            Ok(result) => result,
+            Err(e) => return Ok(Some(Err(e))),
+        };
+
+        session.moves = match session.moves.is_empty() {
+            true => uci.to_string(),
+            false => format!("{} {uci}", session.moves),
+        };
+
+        let mut reply = None;
+        match result {
+            MoveResult::Correct { complete: true } => {
+                // The solution may have been cut short by an alternative mate.
+                session.ply = solution.len() as i64;
+                session.completed.get_or_insert(Local::now().fixed_offset());
+            },
+            MoveResult::Correct { complete: false } => {
+                // Play the opponent's reply too.
+                reply = solution.move_at(ply + 1).map(|m| m.to_string());
+                session.ply += 2;
+            },
+            MoveResult::Wrong => {
+                session.mistakes += 1;
+            },
+        }
+
+        self.db.update_solve_session(&session).await?;
+
+        Ok(Some(Ok(SolveMoveResult {
+            correct: result != MoveResult::Wrong,
+            reply,
+            state: Self::state(&session, &solution),
+        })))
+    }
+
+    /// Go back to the start of the solution so the user can try again. The mistakes they've
+    /// already made still count. Returns None if there's no such session.
+    pub async fn reset(&mut self, user_id: &str, session_id: i64) -> ServiceResult<Option<SolveState>> {
+        let Some((mut session, solution)) = self.get_session_and_solution(user_id, session_id).await? else {
+            return Ok(None);
+        };
+
+        session.ply = 1;
+        self.db.update_solve_session(&session).await?;
+
+        Ok(Some(Self::state(&session, &solution)))
+    }
+
+    /// Get the next move of the solution as a hint, recording that a hint was used. Returns None
+    /// if there's no such session.
+    pub async fn hint(&mut self, user_id: &str, session_id: i64) -> ServiceResult<Option<SolveHint>> {
+        let Some((mut session, solution)) = self.get_session_and_solution(user_id, session_id).await? else {
+            return Ok(None);
+        };
+
+        let next_move = solution.move_at(session.ply as usize);
+
+        if next_move.is_some() && !session.hint_used {
+            session.hint_used = true;
+            self.db.update_solve_session(&session).await?;
+        }
+
+        Ok(Some(SolveHint {
+            next_move: next_move.map(|m| m.to_string()),
+            state: Self::state(&session, &solution),
+        }))
+    }
+
+    /// Get one of a user's sessions as it's stored in the database, for reviewing its puzzle.
+    pub async fn get_solve_session(&self, user_id: &str, session_id: i64)
+        -> ServiceResult<Option<SolveSession>>
+    {
+        Ok(self.db.get_solve_session(user_id, session_id).await?)
+    }
+
+    /// Mark a session as used for a review of its puzzle. Returns false if it already has been.
+    pub async fn mark_reviewed(&mut self, session_id: i64) -> ServiceResult<bool> {
+        Ok(self.db.mark_solve_session_reviewed(session_id).await?)
+    }
+
+    /// Get the attempt data for a session, for reviewing its puzzle. The duration is the time
+    /// until the solution was first completed, or until now if it hasn't been.
+    pub fn attempt_data(session: &SolveSession) -> AttemptData {
+        let end = session.completed.unwrap_or(Local::now().fixed_offset());
+
+        AttemptData {
+            duration_ms: Some((end - session.started).num_milliseconds()),
+            mistakes: Some(session.mistakes),
+            hint_used: Some(session.hint_used),
+            moves: Some(session.moves.clone()),
+        }
+    }
+
+    async fn get_session_and_solution(&self, user_id: &str, session_id: i64)
+        -> ServiceResult<Option<(SolveSession, Solution)>>
+    {
+        let Some(session) = self.db.get_solve_session(user_id, session_id).await? else {
+            return Ok(None);
+        };
+
+        let puzzle = self.db.get_puzzle_by_id(&session.puzzle_id).await?
+            .ok_or_else(|| format!("No such puzzle {} for solve session {session_id}", session.puzzle_id))?;
+
+        Ok(Some((session, Self::solution(&puzzle)?)))
+    }
+
+    fn solution(puzzle: &Puzzle) -> ServiceResult<Solution> {
+        Ok(Solution::new(&puzzle.fen, &puzzle.moves)
+            .map_err(|e| format!("Invalid solution for puzzle {}: {e}", puzzle.puzzle_id))?)
+    }
+
+    fn state(session: &SolveSession, solution: &Solution) -> SolveState {
+        let complete = session.completed.is_some() && session.ply as usize >= solution.len();
+
+        // The solution may have been completed with an alternative mate, in which case the final
+        // position is after the user's mating move rather than the solution's last move. Moves
+        // are ignored once the solution is complete, so it's the last one they played.
+        let position = match session.moves.split_whitespace().last() {
+            Some(last_move) if complete => solution.final_position(last_move),
+            _ => solution.position_after(session.ply as usize),
+        };
+
+        SolveState {
+            session_id: session.id,
+            puzzle_id: session.puzzle_id.clone(),
+            fen: position.to_fen(),
+            ply: session.ply,
+            complete,
+            mistakes: session.mistakes,
+            hint_used: session.hint_used,
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use chrono::Local;
 
 use crate::app::AppConfig;
-use crate::db::{PuzzleDatabase, Puzzle, Review, PuzzleHistoryEntry};
+use crate::db::{PuzzleDatabase, Puzzle, Review, PuzzleHistoryEntry, AttemptData, PuzzleFilter,
+    PuzzleSearch, PuzzleSearchResult, PuzzleNotes, PuzzleReport};
+use crate::pgn;
 use crate::rating::Rating;
+use crate::render;
 use crate::srs::{Card, Difficulty, ReviewOrder};
 use crate::time::LocalTimeProvider;
 
@@ -78,54 +81,80 @@ impl TacticsService {
         }
     }
 
-    pub async fn get_random_puzzle(&self, min_rating: i64, max_rating: i64)
-        -> ServiceResult<(Option<Puzzle>, Option<Card>)>
+    /// Get the rating range for new puzzles for a user. If there's a target success rate, the
+    /// range is chosen so that the user's expected score is within the configured window of it,
+    /// and the target is adjusted by how far the user's recent results have been from it.
+    /// Otherwise, the configured rating variation is used.
+    pub async fn get_new_puzzle_rating_range(&self, user_id: &str, user_rating: Rating,
+        target_success_rate: Option<f64>) -> ServiceResult<(i64, i64)>
     {
-        // Clamp min and max rating to those of the puzzle database, or the request may come back
-        // with nothing.
-        let min_puzzle_rating = self.db.get_min_puzzle_rating().await?;
-        let max_puzzle_rating = self.db.get_max_puzzle_rating().await?;
+        // The rating deviation of a typical puzzle in the lichess database.
+        const PUZZLE_DEVIATION: i64 = 75;
+
+        let config = &self.app_config.tactics;
+
+        let Some(target_success_rate) = target_success_rate else {
+            let rating = user_rating.rating as f32;
+            return Ok((
+                user_rating.rating - f32::floor(rating * config.puzzle_rating_variation_down) as i64,
+                user_rating.rating + f32::floor(rating * config.puzzle_rating_variation_up) as i64,
+            ));
+        };
+
+        // If the user has been doing better than the target recently, aim for a lower success rate
+        // to make the puzzles harder, or vice versa, weighted by how many recent reviews there are.
+        let recent_results = self.db
+            .get_recent_review_successes(user_id, config.adaptive_recent_reviews)
+            .await?;
+
+        let adjustment = match recent_results.is_empty() {
+            true => 0.0,
+            false => {
+                let success_rate = recent_results.iter().filter(|success| **success).count() as f64
+                    / recent_results.len() as f64;
+                let weight = recent_results.len() as f64 / config.adaptive_recent_reviews as f64;
+                (target_success_rate - success_rate) * weight
+            },
+        };
+
+        let adjusted_target = f64::clamp(target_success_rate + adjustment, 0.05, 0.95);
+
+        log::info!("Adaptive target success rate {adjusted_target:.2} (target {target_success_rate:.2}, \
+            {} recent reviews)", recent_results.len());
+
+        // Easier puzzles have a higher expected score.
+        let min_rating = user_rating.rating_for_expected_score(
+            adjusted_target + config.adaptive_score_window, PUZZLE_DEVIATION);
+        let max_rating = user_rating.rating_for_expected_score(
+            adjusted_target - config.adaptive_score_window, PUZZLE_DEVIATION);
+
+        log::info!("Adaptive rating range {min_rating}-{max_rating} (expected score {:.2}-{:.2})",
+            user_rating.expected_score(max_rating, PUZZLE_DEVIATION),
+            user_rating.expected_score(min_rating, PUZZLE_DEVIATION));
+
+        Ok((min_rating, max_rating))
+    }
+
+    /// Get a random puzzle in the given rating range that the user hasn't seen before.
+    pub async fn get_random_puzzle(&self, user_id: &str, min_rating: i64, max_rating: i64,
+        filter: &PuzzleFilter) -> ServiceResult<Option<Puzzle>>
+    {
+        // Clamp min and max rating to those of the puzzle database (or the chosen sources, as
+        // puzzle packs are often all the same rating), or the request may come back with nothing.
+        let filter = filter.with_default_sources();
+        let source_rating_range = self.db.get_source_rating_range(&filter.sources).await?;
+        let (min_puzzle_rating, max_puzzle_rating) = match source_rating_range {
+            Some(range) => range,
+            None => self.db.get_puzzle_rating_range().await?,
+        };
         let min_rating = i64::clamp(min_rating, min_puzzle_rating, max_puzzle_rating);
         let max_rating = i64::clamp(max_rating, min_puzzle_rating, max_puzzle_rating);
 
-        // The puzzles database is pretty big and we still use string ids to refer to the cards
-        // so it's quite slow in sqlite to join them to find out if there's already a card
-        // associated with the puzzle. Instead, we just retry a few times to make it unlikely to
-        // ever happen. If it does return a puzzle the user already has a card for, that just means
-        // they're reviewing ahead, and isn't the end of the world.
-        const NEW_RETRY_COUNT: usize = 5;
-
-        let mut puzzle = None;
-        let mut card = None;
-
-        for retry in 0..NEW_RETRY_COUNT {
-            if retry > 0 {
-                log::warn!("Retry {retry} of trying to get a new random puzzle");
-            }
-
-            puzzle = self.db.get_puzzles_by_rating(min_rating, max_rating, 1).await?
-                .into_iter().next();
-
-            if let Some(puzzle) = puzzle.as_ref() {
-                card = self.db.get_card_by_id(&puzzle.puzzle_id).await?;
-                // If we got a new puzzle that doesn't already have a card associated, we can just
-                // return at this point. Otherwise we try again up to NEW_RETRY_COUNT times.
-                if card.is_none() {
-                    break;
-                }
-            }
-            else {
-                // If we didn't get a puzzle at all we can just break since we aren't going to get one
-                // next time.
-                break;
-            }
-        }
-
-        Ok((puzzle, card))
+        Ok(self.db.get_random_unseen_puzzle(user_id, min_rating, max_rating, &filter).await?)
     }
 
     pub async fn apply_review(&mut self, user_id: &str, user_rating: Rating, mut card: Card,
-        difficulty: Difficulty) -> ServiceResult<()>
+        difficulty: Difficulty, attempt: AttemptData) -> ServiceResult<()>
     {
         // Apply the review to the card.
         log::info!("Reviewing card");
@@ -143,27 +172,260 @@ impl TacticsService {
             difficulty,
             date: Local::now().fixed_offset(),
             user_rating: Some(user_rating.rating),
+            attempt,
         }).await?;
 
         Ok(())
     }
 
-    pub async fn get_puzzle_history(&self, user_id: &str, offset: i64, count: i64)
+    /// Derive the difficulty of an attempt at a puzzle, relative to the user's median solve time
+    /// for puzzles of a similar rating.
+    pub async fn auto_grade(&self, user_id: &str, puzzle: &Puzzle, attempt: &AttemptData)
+        -> ServiceResult<Difficulty>
+    {
+        let config = self.app_config.auto_grade;
+
+        let durations = self.db.get_solve_durations(user_id, puzzle.rating - config.rating_range,
+            puzzle.rating + config.rating_range).await?;
+
+        let median_duration = match durations.len() as i64 >= config.min_samples {
+            true => durations.get(durations.len() / 2).copied(),
+            false => None,
+        };
+
+        let difficulty = config.grade(attempt.mistakes.unwrap_or(0), attempt.hint_used.unwrap_or(false),
+            attempt.duration_ms, median_duration);
+
+        log::info!("Auto graded attempt at puzzle {} as {difficulty:?} (duration: {:?}, median: {:?})",
+            puzzle.puzzle_id, attempt.duration_ms, median_duration);
+
+        Ok(difficulty)
+    }
+
+    pub async fn get_puzzle_history(&self, user_id: &str, tag: Option<&str>, offset: i64, count: i64)
         -> ServiceResult<(Vec<PuzzleHistoryEntry>, i64)>
+    {
+        let (mut history, total_count) = self.db
+            .get_distinct_puzzle_history(user_id, tag, offset, count)
+            .await?;
+
+        // Add the solutions in SAN so they can be shown without a board.
+        for entry in history.iter_mut() {
+            entry.san = pgn::san_line(&entry.puzzle.fen, &entry.puzzle.moves)
+                .map_err(|e| log::warn!("Failed to convert puzzle {} to SAN: {e}", entry.puzzle.puzzle_id))
+                .ok();
+        }
+
+        Ok((history, total_count))
+    }
+
+    /// Get a puzzle as a PGN game. Returns None if there's no such puzzle.
+    pub async fn get_puzzle_pgn(&self, puzzle_id: &str) -> ServiceResult<Option<String>> {
+        let Some(puzzle) = self.db.get_puzzle_by_id(puzzle_id).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(pgn::puzzle_pgn(&puzzle)
+            .map_err(|e| format!("Failed to convert puzzle {puzzle_id} to PGN: {e}"))?))
+    }
+
+    /// Get the ETag for a puzzle's thumbnail in the given format and size, without rendering it.
+    /// Returns None if there's no such puzzle.
+    pub async fn get_puzzle_thumbnail_etag(&self, puzzle_id: &str, format: &str, size: u32)
+        -> ServiceResult<Option<String>>
+    {
+        let Some(puzzle) = self.db.get_puzzle_by_id(puzzle_id).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(render::thumbnail_etag(&puzzle, format, size)
+            .map_err(|e| format!("Failed to render puzzle {puzzle_id}: {e}"))?))
+    }
+
+    /// Render a puzzle's starting position as an SVG. Returns None if there's no such puzzle.
+    pub async fn get_puzzle_svg(&self, puzzle_id: &str) -> ServiceResult<Option<String>> {
+        let Some(puzzle) = self.db.get_puzzle_by_id(puzzle_id).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(render::puzzle_svg(&puzzle)
+            .map_err(|e| format!("Failed to render puzzle {puzzle_id}: {e}"))?))
+    }
+
+    /// Render a puzzle's starting position as a PNG of the given size. Returns None if there's no
+    /// such puzzle.
+    pub async fn get_puzzle_png(&self, puzzle_id: &str, size: u32) -> ServiceResult<Option<Vec<u8>>> {
+        let Some(svg) = self.get_puzzle_svg(puzzle_id).await? else {
+            return Ok(None);
+        };
+
+        // Rasterizing is cpu bound, so don't hold up other requests while it happens.
+        let png = tokio::task::spawn_blocking(move || render::svg_to_png(&svg, size))
+            .await
+            .map_err(|e| format!("Failed to render puzzle {puzzle_id}: {e}"))??;
+
+        Ok(Some(png))
+    }
+
+    /// Get the user's notes and tags for a puzzle. Returns None if there's no such puzzle.
+    pub async fn get_puzzle_notes(&self, user_id: &str, puzzle_id: &str)
+        -> ServiceResult<Option<PuzzleNotes>>
+    {
+        if self.db.get_puzzle_by_id(puzzle_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.db.get_puzzle_notes(user_id, puzzle_id).await?))
+    }
+
+    /// Replace the user's notes and tags for a puzzle. Returns None if there's no such puzzle.
+    pub async fn set_puzzle_notes(&mut self, user_id: &str, puzzle_id: &str, notes: &str,
+        tags: &[String]) -> ServiceResult<Option<PuzzleNotes>>
+    {
+        if self.db.get_puzzle_by_id(puzzle_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let notes = PuzzleNotes::new(notes, tags);
+        self.db.set_puzzle_notes(user_id, puzzle_id, &notes).await?;
+
+        Ok(Some(notes))
+    }
+
+    pub async fn search_puzzles(&self, user_id: &str, search: &PuzzleSearch, offset: i64, count: i64)
+        -> ServiceResult<Vec<PuzzleSearchResult>>
     {
         Ok(self.db
-            .get_distinct_puzzle_history(user_id, offset, count)
+            .search_puzzles(user_id, search, offset, count)
             .await?)
     }
 
-    pub async fn skip_puzzle(&mut self, user_id: &str, puzzle: &Puzzle)
+    /// Report a puzzle as broken or disliked, which excludes it from the user's new puzzles and
+    /// reviews until the report is undone. Returns None if there's no such puzzle.
+    pub async fn report_puzzle(&mut self, user_id: &str, puzzle_id: &str, reason: &str)
+        -> ServiceResult<Option<PuzzleReport>>
+    {
+        if self.db.get_puzzle_by_id(puzzle_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let report = PuzzleReport {
+            puzzle_id: puzzle_id.to_string(),
+            reason: reason.trim().to_string(),
+            date: Local::now().fixed_offset(),
+        };
+
+        log::info!("Reported puzzle {puzzle_id}: {}", report.reason);
+        self.db.add_puzzle_report(user_id, &report).await?;
+
+        Ok(Some(report))
+    }
+
+    /// Undo a report, so the puzzle can come up again. Returns whether there was a report.
+    pub async fn undo_report(&mut self, user_id: &str, puzzle_id: &str) -> ServiceResult<bool> {
+        Ok(self.db.remove_puzzle_report(user_id, puzzle_id).await?)
+    }
+
+    pub async fn get_reports(&self, user_id: &str) -> ServiceResult<Vec<PuzzleReport>> {
+        Ok(self.db.get_puzzle_reports(user_id).await?)
+    }
+
+    /// Get the user's reports as a csv of puzzle IDs and reasons, for passing on to lichess.
+    pub async fn get_reports_csv(&self, user_id: &str) -> ServiceResult<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["puzzle_id", "reason"]).map_err(|e| e.to_string())?;
+
+        for report in self.db.get_puzzle_reports(user_id).await? {
+            writer.write_record([&report.puzzle_id, &report.reason]).map_err(|e| e.to_string())?;
+        }
+
+        let csv = writer.into_inner().map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(csv).map_err(|e| e.to_string())?)
+    }
+
+    pub async fn skip_puzzle(&mut self, user_id: &str, puzzle: &Puzzle, attempt: &AttemptData)
         -> ServiceResult<()>
     {
         if self.db.get_puzzle_by_id(&puzzle.puzzle_id).await?.is_some() {
             let time_now = Local::now().fixed_offset();
-            self.db.add_skipped_puzzle(user_id, &puzzle.puzzle_id, time_now).await?;
+            self.db.add_skipped_puzzle(user_id, &puzzle.puzzle_id, time_now, attempt).await?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::app::AppConfig;
+    use crate::db::{AttemptData, Puzzle, PuzzleDatabase};
+    use crate::services::tactics_service::TacticsService;
+    use crate::srs::SrsConfig;
+
+    fn puzzle(puzzle_id: &str) -> Puzzle {
+        Puzzle {
+            puzzle_id: puzzle_id.to_string(),
+            fen: "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24".to_string(),
+            moves: "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1".to_string(),
+            rating: 1500,
+            rating_deviation: 75,
+            popularity: 90,
+            number_of_plays: 1000,
+            themes: vec![],
+            game_url: String::new(),
+            opening_tags: vec![],
+            source: "lichess".to_string(),
+        }
+    }
+
+    async fn tactics_service(puzzles: &[&str]) -> TacticsService {
+        let mut db = PuzzleDatabase::open(&Url::parse("sqlite::memory:").unwrap(), SrsConfig::default())
+            .await.unwrap();
+        db.add_puzzles(&puzzles.iter().map(|id| puzzle(id)).collect()).await.unwrap();
+
+        TacticsService::new(AppConfig::default(), db)
+    }
+
+    #[tokio::test]
+    async fn test_puzzle_by_id() {
+        let mut service = tactics_service(&["00001", "00002"]).await;
+
+        // Puzzles that don't exist aren't found.
+        let (found, card) = service.get_puzzle_by_id("99999").await.unwrap();
+        assert!(found.is_none());
+        assert!(card.is_none());
+
+        // Puzzles removed from the lichess puzzle database can still be loaded by id, as they may
+        // be in the user's cards or collections.
+        service.db.clear_imported_lichess_puzzles().await.unwrap();
+        service.db.add_puzzles(&vec![puzzle("00001")]).await.unwrap();
+        assert_eq!(service.db.mark_removed_lichess_puzzles().await.unwrap(), 1);
+
+        let (found, card) = service.get_puzzle_by_id("00002").await.unwrap();
+        assert_eq!(found.unwrap().puzzle_id, "00002");
+        assert!(card.is_none());
+
+        // Recording an attempt without a card adds it to the history but doesn't create a card.
+        let attempt = AttemptData { moves: Some("e6e7 b3c1 h6c1".to_string()), ..Default::default() };
+        service.skip_puzzle("local", &puzzle("00001"), &attempt).await.unwrap();
+        let (history, count) = service.get_puzzle_history("local", None, 0, 10).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(history[0].puzzle.puzzle_id, "00001");
+        assert!(service.get_puzzle_by_id("00001").await.unwrap().1.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_puzzle_notes() {
+        let mut service = tactics_service(&["00001"]).await;
+
+        // Notes can only be got or set for puzzles that exist.
+        assert!(service.get_puzzle_notes("local", "99999").await.unwrap().is_none());
+        assert!(service.set_puzzle_notes("local", "99999", "Notes", &[]).await.unwrap().is_none());
+
+        let notes = service.set_puzzle_notes("local", "00001", "Notes", &["fork".to_string()]).await
+            .unwrap().unwrap();
+        assert_eq!(service.get_puzzle_notes("local", "00001").await.unwrap(), Some(notes));
+    }
+}
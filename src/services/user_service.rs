@@ -1,9 +1,13 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, FixedOffset, Local};
 
+use crate::activity::{self, Attempt};
 use crate::app::AppConfig;
-use crate::db::{PuzzleDatabase, ReviewScoreBucket};
+use crate::db::{PuzzleDatabase, ReviewScoreBucket, UserSettings, Review, AttemptData};
 use crate::rating::{Rating, GameResult};
 use crate::srs::{Difficulty, self};
+use crate::weakness::{self, Weakness};
 use crate::time::LocalTimeProvider;
 
 use super::{ServiceResult, ServiceError};
@@ -18,6 +22,22 @@ pub struct Stats {
     pub next_review_due: Option<DateTime<FixedOffset>>,
 }
 
+/// The result of importing a user's lichess puzzle activity.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ActivityImportResult {
+    pub reviews_added: usize,
+    pub cards_added: usize,
+    /// The number of attempts at puzzles the user already had reviews or cards for, which are
+    /// left alone so that importing the same activity again doesn't duplicate them.
+    pub attempts_skipped: usize,
+    /// The number of attempts at puzzles that aren't in the puzzle database.
+    pub attempts_missing: usize,
+    /// The user's new rating, if it was set from the activity.
+    pub rating: Option<i64>,
+    /// The entries that couldn't be read, and why.
+    pub errors: Vec<String>,
+}
+
 /// Encapsulates any kind of application logic to do with users.
 #[derive(Clone)]
 pub struct UserService {
@@ -66,6 +86,33 @@ impl UserService {
         Ok(())
     }
 
+    /// Get a user's settings.
+    pub async fn get_user_settings(&self, user_id: &str) -> ServiceResult<UserSettings> {
+        Ok(self.db
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| ServiceError::InternalError(format!("No such user {user_id}")))?
+            .settings)
+    }
+
+    /// Update a user's settings.
+    pub async fn set_user_settings(&mut self, user_id: &str, settings: UserSettings)
+        -> ServiceResult<()>
+    {
+        let mut user = self.db
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| ServiceError::InternalError(format!("No such user {user_id}")))?;
+
+        user.settings = settings;
+
+        self.db
+            .update_user(&user)
+            .await?;
+
+        Ok(())
+    }
+
     /// Get the rating for a user.
     pub async fn get_user_rating(&self, user_id: &str) -> ServiceResult<Rating> {
         Ok(self.db
@@ -151,6 +198,18 @@ impl UserService {
            .await?)
     }
 
+    /// Get a user's weakest puzzle themes and openings, weakest first, by comparing how often
+    /// they fail puzzles with each one to how often they fail puzzles of the same ratings in
+    /// general.
+    pub async fn get_weaknesses(&self, user_id: &str) -> ServiceResult<Vec<Weakness>> {
+        const BUCKET_SIZE: i64 = 100;
+
+        let baseline = self.db.get_review_score_history(user_id, BUCKET_SIZE).await?;
+        let tag_buckets = self.db.get_review_tag_history(user_id, BUCKET_SIZE).await?;
+
+        Ok(weakness::find_weaknesses(&baseline, &tag_buckets))
+    }
+
     /// Update the rating for a user.
     pub async fn update_rating(&mut self, user_id: &str, difficulty: Difficulty, result: GameResult<i64>)
         -> ServiceResult<Rating>
@@ -181,6 +240,93 @@ impl UserService {
         }
     }
 
+    /// Import a user's puzzle history from a lichess puzzle activity export, adding a review for
+    /// each attempt and cards for the puzzles that were failed or recently attempted. If
+    /// `set_rating` is set, the user's rating is replaced with one estimated from the attempts.
+    pub async fn import_lichess_activity(&mut self, user_id: &str, contents: &str, set_rating: bool)
+        -> ServiceResult<ActivityImportResult>
+    {
+        Self::validate_user_id(user_id)?;
+
+        let activity = activity::parse_activity(contents);
+        log::info!("Importing {} puzzle attempts from lichess ({} couldn't be read)",
+            activity.attempts.len(), activity.errors.len());
+
+        let puzzle_ids: Vec<&str> = activity.attempts.iter()
+            .map(|attempt| attempt.puzzle_id.as_str())
+            .collect();
+        let puzzles: HashMap<String, _> = self.db.get_puzzles_by_ids(&puzzle_ids).await?
+            .into_iter()
+            .map(|puzzle| (puzzle.puzzle_id.clone(), puzzle))
+            .collect();
+        let reviewed_puzzle_ids = self.db.get_reviewed_puzzle_ids(user_id).await?;
+
+        let mut result = ActivityImportResult {
+            reviews_added: 0,
+            cards_added: 0,
+            attempts_skipped: 0,
+            attempts_missing: 0,
+            rating: None,
+            errors: activity.errors,
+        };
+
+        // Replay the attempts in order, estimating the user's rating from all of them, but only
+        // adding reviews for puzzles that the user hasn't already done here.
+        let mut rating = activity::STARTING_RATING;
+        let mut reviews = Vec::new();
+        let mut puzzle_attempts: HashMap<&str, Vec<Attempt>> = HashMap::new();
+
+        for attempt in &activity.attempts {
+            let Some(puzzle) = puzzles.get(&attempt.puzzle_id) else {
+                result.attempts_missing += 1;
+                continue;
+            };
+
+            rating.update(vec![GameResult {
+                rating: puzzle.rating,
+                deviation: puzzle.rating_deviation,
+                score: attempt.score(),
+            }]);
+
+            if reviewed_puzzle_ids.contains(&attempt.puzzle_id) {
+                result.attempts_skipped += 1;
+                continue;
+            }
+
+            reviews.push(Review {
+                user_id: user_id.to_string(),
+                puzzle_id: attempt.puzzle_id.clone(),
+                difficulty: attempt.difficulty(),
+                date: attempt.date,
+                user_rating: set_rating.then_some(rating.rating),
+                attempt: AttemptData::default(),
+            });
+            puzzle_attempts.entry(&attempt.puzzle_id).or_default().push(attempt.clone());
+        }
+
+        let now = Local::now().fixed_offset();
+        let cards: Vec<_> = puzzle_attempts.iter()
+            .filter_map(|(puzzle_id, attempts)| {
+                activity::card_from_attempts(puzzle_id, attempts, now, self.app_config.srs)
+            })
+            .collect();
+
+        self.db.add_imported_reviews(&reviews, &cards).await?;
+        result.reviews_added = reviews.len();
+        result.cards_added = cards.len();
+
+        if set_rating && result.attempts_missing < activity.attempts.len() {
+            log::info!("Setting user's rating to {} from their lichess activity", rating.rating);
+            let mut user = self.db.get_user_by_id(user_id).await?
+                .ok_or_else(|| format!("No such user with id {user_id}"))?;
+            user.rating = rating;
+            self.db.update_user(&user).await?;
+            result.rating = Some(rating.rating);
+        }
+
+        Ok(result)
+    }
+
     /// Validate a user id.
     fn validate_user_id(user_id: &str) -> ServiceResult<()> {
         // For now we only support a local user, so check that it's the local user's stats that are
@@ -0,0 +1,260 @@
+use crate::chess::{Position, Move, ChessError};
+
+/// The result of checking a move against a puzzle's solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveResult {
+    /// The move is the next move in the solution. `complete` is true if it's the last one.
+    Correct { complete: bool },
+    /// The move is legal but isn't the solution.
+    Wrong,
+}
+
+/// A puzzle's solution line, which can be used to check the moves the user plays. Like in the
+/// lichess puzzle database, the puzzle's FEN is the position before the opponent's move, so the
+/// first move in the line is the opponent's and the user plays every other move after that.
+#[derive(Debug, Clone)]
+pub struct Solution {
+    start: Position,
+    moves: Vec<Move>,
+}
+
+impl Solution {
+    /// Parse a puzzle's FEN and space separated UCI moves, checking that all of the moves are
+    /// legal.
+    pub fn new(fen: &str, moves: &str) -> Result<Self, ChessError> {
+        let start = Position::from_fen(fen)?;
+
+        let mut position = start.clone();
+        let solution_moves = moves
+            .split_whitespace()
+            .map(|uci| position.play_uci(uci))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // There needs to be at least the opponent's move and one of the user's.
+        if solution_moves.len() < 2 {
+            return Err(ChessError::InvalidMove(moves.to_string()));
+        }
+
+        Ok(Self { start, moves: solution_moves })
+    }
+
+    /// The number of moves in the solution, including the opponent's.
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// The solution move at the given ply.
+    pub fn move_at(&self, ply: usize) -> Option<Move> {
+        self.moves.get(ply).copied()
+    }
+
+    /// The position after the first `ply` moves of the solution have been played.
+    pub fn position_after(&self, ply: usize) -> Position {
+        let mut position = self.start.clone();
+        for m in self.moves.iter().take(ply) {
+            position.play(m).expect("solution moves are checked in Solution::new");
+        }
+        position
+    }
+
+    /// Check a move played by the user in the position after the first `ply` moves of the
+    /// solution. Moves that are illegal in that position are an error rather than a wrong move.
+    ///
+    /// Any move that delivers checkmate is correct and completes the puzzle, even if it isn't the
+    /// one in the solution. Lichess accepts these too, as mates are the only case in which its
+    /// puzzles can have more than one solution (e.g. puzzle ULF41, where the solution is Qe1# but
+    /// Qf1# is also mate).
+    pub fn check_move(&self, ply: usize, uci: &str) -> Result<MoveResult, ChessError> {
+        let m = Move::from_uci(uci)?;
+
+        let mut position = self.position_after(ply);
+        position.play(&m)?;
+
+        Ok(match self.moves.get(ply) {
+            Some(expected) if *expected == m => MoveResult::Correct { complete: ply + 1 >= self.len() },
+            _ if position.is_checkmate() => MoveResult::Correct { complete: true },
+            _ => MoveResult::Wrong,
+        })
+    }
+
+    /// The final position of a completed attempt, after the user's last move. This is usually the
+    /// end of the solution, but the attempt may have been completed by a different mating move,
+    /// possibly before the end of the solution, in which case it's the position after that.
+    pub fn final_position(&self, last_move: &str) -> Position {
+        let Ok(m) = Move::from_uci(last_move) else {
+            return self.position_after(self.len());
+        };
+
+        (1..self.len()).step_by(2)
+            .find_map(|ply| {
+                let mut position = self.position_after(ply);
+                position.play(&m).ok()?;
+                let complete = ply + 1 >= self.len() && self.moves[ply] == m;
+                (complete || position.is_checkmate()).then_some(position)
+            })
+            .unwrap_or_else(|| self.position_after(self.len()))
+    }
+
+    /// Check a whole attempt at the puzzle from the moves the user played, as space separated UCI
+    /// moves not including the opponent's replies. Returns whether they solved it, i.e. played
+    /// correct moves until the solution was complete without any wrong ones.
+    pub fn check_attempt(&self, moves: &str) -> Result<bool, ChessError> {
+        let mut ply = 1;
+        for uci in moves.split_whitespace() {
+            match self.check_move(ply, uci)? {
+                MoveResult::Correct { complete: true } => return Ok(true),
+                MoveResult::Correct { complete: false } => ply += 2,
+                MoveResult::Wrong => return Ok(false),
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Puzzle 00008 from the lichess puzzle database.
+    const FEN: &str = "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2R1/PqP2bPP/7K b - - 0 24";
+    const MOVES: &str = "f2g3 e6e7 b2b1 b3c1 b1c1 h6c1";
+
+    #[test]
+    fn test_solution() {
+        let solution = Solution::new(FEN, MOVES).unwrap();
+        assert_eq!(solution.len(), 6);
+        assert_eq!(solution.move_at(1).unwrap().to_string(), "e6e7");
+        assert_eq!(solution.position_after(1).to_fen(),
+            "r6k/pp2r2p/4Rp1Q/3p4/8/1N1P2b1/PqP3PP/7K w - - 0 25");
+
+        assert_eq!(solution.check_move(1, "e6e7"), Ok(MoveResult::Correct { complete: false }));
+        assert_eq!(solution.check_move(1, "h6h7"), Ok(MoveResult::Wrong));
+        assert_eq!(solution.check_move(5, "h6c1"), Ok(MoveResult::Correct { complete: true }));
+
+        // Illegal moves aren't counted as wrong.
+        assert!(matches!(solution.check_move(1, "e6e8"), Err(ChessError::IllegalMove(_))));
+        assert!(matches!(solution.check_move(1, "e6"), Err(ChessError::InvalidMove(_))));
+    }
+
+    #[test]
+    fn test_check_attempt() {
+        let solution = Solution::new(FEN, MOVES).unwrap();
+        assert_eq!(solution.check_attempt("e6e7 b3c1 h6c1"), Ok(true));
+
+        // Incomplete attempts and wrong moves don't solve it, even if they're followed by the
+        // right moves.
+        assert_eq!(solution.check_attempt(""), Ok(false));
+        assert_eq!(solution.check_attempt("e6e7 b3c1"), Ok(false));
+        assert_eq!(solution.check_attempt("h6h7 e6e7 b3c1 h6c1"), Ok(false));
+        assert_eq!(solution.check_attempt("e6e7 b3c1 e7e1"), Ok(false));
+
+        // Moves that aren't legal in the position are an error.
+        assert!(solution.check_attempt("e6e7 e6e8").is_err());
+    }
+
+    #[test]
+    fn test_alternative_mates() {
+        // Puzzles from the lichess puzzle database that end in mate, with the position before the
+        // mating move and a non-mating alternative.
+        let puzzles = [
+            // 000Zo: ...Re1+ Kf2 Rf1#.
+            ("4r3/1k6/pp3r2/1b2P2p/3R1p2/P1R2P2/1P4PP/6K1 w - - 0 35", "e5f6 e8e1 g1f2 e1f1", 3, "e1e2"),
+            // 000hf: Qe6+ Kf8 Qf7#.
+            ("r1bqk2r/pp1nbNp1/2p1p2p/8/2BP4/1PN3P1/P3QP1P/3R1RK1 b kq - 0 19", "e8f7 e2e6 f7f8 e6f7", 3, "e6e7"),
+            // 001Wz: Rd8+ Re8 Rxe8#.
+            ("4r1k1/5ppp/r1p5/p1n1RP2/8/2P2N1P/2P3P1/3R2K1 b - - 0 21", "e8e5 d1d8 e5e8 d8e8", 3, "d8d7"),
+            // 000qP: ...Rf7+ Rc1#.
+            ("8/7R/8/5p2/4bk1P/8/2r2K2/6R1 w - - 7 51", "f2f1 f4f3 h7f7 c2c1", 3, "c2c8"),
+        ];
+
+        for (fen, moves, ply, wrong) in puzzles {
+            let solution = Solution::new(fen, moves).unwrap();
+            let expected = solution.move_at(ply).unwrap().to_string();
+
+            assert!(solution.position_after(ply + 1).is_checkmate(), "{fen}");
+            assert_eq!(solution.check_move(ply, &expected), Ok(MoveResult::Correct { complete: true }), "{fen}");
+            assert_eq!(solution.check_move(ply, wrong), Ok(MoveResult::Wrong), "{fen}");
+        }
+
+        // Puzzle 001Wz with a second white rook on e1, so that Rexe8# is also mate.
+        let solution = Solution::new("4r1k1/5ppp/r1p5/p1n1RP2/8/2P2N1P/2P3P1/3RR1K1 b - - 0 21",
+            "e8e5 d1d8 e5e8 d8e8").unwrap();
+        assert_eq!(solution.check_move(3, "e1e8"), Ok(MoveResult::Correct { complete: true }));
+        assert_eq!(solution.check_move(3, "d8e8"), Ok(MoveResult::Correct { complete: true }));
+
+        // A mate before the end of the solution also completes it.
+        let solution = Solution::new("rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq - 0 2",
+            "g2g4 b8c6 a2a3 d8h4").unwrap();
+        assert_eq!(solution.check_move(1, "d8h4"), Ok(MoveResult::Correct { complete: true }));
+        assert_eq!(solution.check_move(1, "b8c6"), Ok(MoveResult::Correct { complete: false }));
+        assert_eq!(solution.check_move(1, "d8g5"), Ok(MoveResult::Wrong));
+        assert_eq!(solution.final_position("d8h4").to_fen(),
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+    }
+
+    #[test]
+    fn test_ulf41() {
+        // The case from puzzle ULF41, where the solution ends with Qe1# but Qf1# is also mate. The
+        // puzzle itself isn't in the test fixtures, so this is a smaller position with the same
+        // two mates: the black king is trapped on h1, and the queen on e2 mates on either square.
+        let solution = Solution::new("8/8/8/p7/8/5K2/4Q2p/7k b - - 0 1", "a5a4 e2e1").unwrap();
+        assert_eq!(solution.check_move(1, "e2e1"), Ok(MoveResult::Correct { complete: true }));
+        assert_eq!(solution.check_move(1, "e2f1"), Ok(MoveResult::Correct { complete: true }));
+
+        // Queen moves that don't mate are still wrong.
+        assert_eq!(solution.check_move(1, "e2f2"), Ok(MoveResult::Wrong));
+        assert_eq!(solution.check_move(1, "e2e4"), Ok(MoveResult::Wrong));
+        assert_eq!(solution.check_attempt("e2f2"), Ok(false));
+        assert_eq!(solution.check_attempt("e2f1"), Ok(true));
+
+        // The final position is after the mate that was played.
+        assert_eq!(solution.final_position("e2e1").to_fen(), solution.position_after(2).to_fen());
+        assert_eq!(solution.final_position("e2f1").to_fen(), "8/8/8/8/p7/5K2/7p/5Q1k b - - 1 2");
+    }
+
+    #[test]
+    fn test_dump_mates() {
+        // Unmodified puzzles from the lichess puzzle database that end in mate. Every mating move
+        // in the positions the user plays from is accepted by check_attempt, not just the one in
+        // the solution. None of the dump puzzles available offline (including the test fixture)
+        // has a second mate, so the count of mates found is checked too, to show what's covered.
+        let puzzles = [
+            ("000Zo", "4r3/1k6/pp3r2/1b2P2p/3R1p2/P1R2P2/1P4PP/6K1 w - - 0 35", "e5f6 e8e1 g1f2 e1f1"),
+            ("000hf", "r1bqk2r/pp1nbNp1/2p1p2p/8/2BP4/1PN3P1/P3QP1P/3R1RK1 b kq - 0 19", "e8f7 e2e6 f7f8 e6f7"),
+            ("000qP", "8/7R/8/5p2/4bk1P/8/2r2K2/6R1 w - - 7 51", "f2f1 f4f3 h7f7 c2c1"),
+            ("001Wz", "4r1k1/5ppp/r1p5/p1n1RP2/8/2P2N1P/2P3P1/3R2K1 b - - 0 21", "e8e5 d1d8 e5e8 d8e8"),
+            ("001om", "5r1k/pp4pp/5p2/1BbQp1r1/6K1/7P/1PP3P1/3R3R w - - 2 26", "g4h4 c5f2 g2g3 f2g3"),
+        ];
+
+        for (puzzle_id, fen, moves) in puzzles {
+            let solution = Solution::new(fen, moves).unwrap();
+            let user_moves = (1..solution.len()).step_by(2)
+                .map(|ply| solution.move_at(ply).unwrap().to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(solution.check_attempt(&user_moves.join(" ")), Ok(true), "{puzzle_id}");
+
+            let mut mates = 0;
+            for (i, ply) in (1..solution.len()).step_by(2).enumerate() {
+                let position = solution.position_after(ply);
+                for m in position.legal_moves() {
+                    let mut after = position.clone();
+                    after.play(&m).unwrap();
+                    if after.is_checkmate() {
+                        let attempt = format!("{} {m}", user_moves[..i].join(" "));
+                        assert_eq!(solution.check_attempt(&attempt), Ok(true), "{puzzle_id}: {attempt}");
+                        mates += 1;
+                    }
+                }
+            }
+            assert_eq!(mates, 1, "{puzzle_id}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_solution() {
+        assert!(Solution::new(FEN, "f2g3 e6e8").is_err());
+        assert!(Solution::new(FEN, "f2g3").is_err());
+        assert!(Solution::new("8/8/8 w - - 0 1", MOVES).is_err());
+    }
+}
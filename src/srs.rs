@@ -1,6 +1,6 @@
 use std::error::Error;
 use lazy_static::lazy_static;
-use chrono::{DateTime, FixedOffset, Duration, NaiveTime};
+use chrono::{DateTime, FixedOffset, Duration, NaiveTime, NaiveDate};
 use strum::IntoEnumIterator;
 use crate::time::TimeProvider;
 use strum_macros::{EnumString, EnumIter, Display};
@@ -49,6 +49,74 @@ impl SrsConfig {
         crate::util::next_time_after(TP::now_local(), self.day_end_hour)
             .fixed_offset()
     }
+
+    /// Get the date of the current day, which ends at the next `day_end_hour`. Before the day end
+    /// time, this is the previous calendar day.
+    pub fn current_day<TP: TimeProvider>(&self) -> NaiveDate {
+        (self.day_end_datetime::<TP>() - Duration::days(1)).date_naive()
+    }
+}
+
+/// Automatic grading config. When enabled, the client submits the details of the user's attempt
+/// instead of a difficulty, and the difficulty is derived from them on the server.
+#[derive(Debug, Copy, Clone)]
+pub struct AutoGradeConfig {
+    pub enabled: bool,
+
+    /// Solves taking less than this multiple of the user's median solve time are graded 'Easy'.
+    pub easy_time_factor: f64,
+
+    /// Solves taking more than this multiple of the user's median solve time are graded 'Hard'.
+    pub hard_time_factor: f64,
+
+    /// The rating range either side of the puzzle's rating to calculate the median solve time over.
+    pub rating_range: i64,
+
+    /// The minimum number of previous solves needed before the solve time is taken into account.
+    pub min_samples: i64,
+}
+
+impl AutoGradeConfig {
+    /// Derive the difficulty for an attempt at a puzzle. Attempts with mistakes or where a hint
+    /// was used are graded 'Again', like in the review ui. Otherwise, the solve time is compared
+    /// to the user's median solve time for puzzles at a similar rating, if it's known.
+    pub fn grade(&self, mistakes: i64, hint_used: bool, duration_ms: Option<i64>,
+        median_duration_ms: Option<i64>) -> Difficulty
+    {
+        if mistakes > 0 || hint_used {
+            return Difficulty::Again;
+        }
+
+        match (duration_ms, median_duration_ms) {
+            (Some(duration), Some(median)) if median > 0 => {
+                let relative_time = duration as f64 / median as f64;
+                if relative_time <= self.easy_time_factor {
+                    Difficulty::Easy
+                }
+                else if relative_time >= self.hard_time_factor {
+                    Difficulty::Hard
+                }
+                else {
+                    Difficulty::Good
+                }
+            },
+            // If we don't know how long the solve took or how long solves usually take, we can
+            // only say that it was solved.
+            _ => Difficulty::Good,
+        }
+    }
+}
+
+impl Default for AutoGradeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            easy_time_factor: 0.5,
+            hard_time_factor: 2.0,
+            rating_range: 100,
+            min_samples: 10,
+        }
+    }
 }
 
 impl Default for SrsConfig {
@@ -269,9 +337,9 @@ impl Card {
 #[cfg(test)]
 mod tests {
     use crate::app::AppConfig;
-    use crate::srs::SrsConfig;
+    use crate::srs::{SrsConfig, AutoGradeConfig, Difficulty};
     use crate::time::TestTimeProvider;
-    use chrono::{DateTime, Timelike, NaiveTime};
+    use chrono::{DateTime, Timelike, NaiveTime, NaiveDate};
 
     #[test]
     fn test_day_end_datetime() {
@@ -311,4 +379,61 @@ mod tests {
         assert_eq!(srs.day_end_datetime::<TestTimeProvider<2023, 10, 07, 04, 00, 00, 00, 00>>(),
             DateTime::parse_from_rfc3339("2023-10-08T04:00:00+00:00").unwrap());
     }
+
+    #[test]
+    fn test_current_day() {
+        let srs = SrsConfig {
+            day_end_hour: NaiveTime::from_hms_opt(4, 0, 0).expect("Failed to create day_end_hour"),
+            ..AppConfig::default().srs
+        };
+
+        let oct_6 = NaiveDate::from_ymd_opt(2023, 10, 6).unwrap();
+        let oct_7 = NaiveDate::from_ymd_opt(2023, 10, 7).unwrap();
+
+        // The day doesn't change at midnight, only at the day end time.
+        assert_eq!(srs.current_day::<TestTimeProvider<2023, 10, 6, 9, 26, 0, 0, 0>>(), oct_6);
+        assert_eq!(srs.current_day::<TestTimeProvider<2023, 10, 7, 0, 0, 1, 0, 0>>(), oct_6);
+        assert_eq!(srs.current_day::<TestTimeProvider<2023, 10, 7, 3, 59, 59, 0, 0>>(), oct_6);
+        assert_eq!(srs.current_day::<TestTimeProvider<2023, 10, 7, 4, 0, 0, 0, 0>>(), oct_7);
+    }
+
+    #[test]
+    fn test_auto_grade() {
+        let config = AutoGradeConfig {
+            easy_time_factor: 0.5,
+            hard_time_factor: 2.0,
+            ..Default::default()
+        };
+
+        // Mistakes and hints should always result in 'Again', however fast the solve was.
+        assert_eq!(config.grade(1, false, Some(1000), Some(10000)), Difficulty::Again);
+        assert_eq!(config.grade(0, true, Some(1000), Some(10000)), Difficulty::Again);
+
+        // Without a solve time or a median solve time, a clean solve is just 'Good'.
+        assert_eq!(config.grade(0, false, None, Some(10000)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(1000), None), Difficulty::Good);
+
+        // Otherwise it depends on the solve time relative to the median.
+        assert_eq!(config.grade(0, false, Some(5000), Some(10000)), Difficulty::Easy);
+        assert_eq!(config.grade(0, false, Some(10000), Some(10000)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(19999), Some(10000)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(20000), Some(10000)), Difficulty::Hard);
+    }
+
+    #[test]
+    fn test_auto_grade_boundaries() {
+        let config = AutoGradeConfig::default();
+
+        // Solving in exactly the median time is 'Good', and the thresholds are inclusive.
+        assert_eq!(config.grade(0, false, Some(10000), Some(10000)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(4999), Some(10000)), Difficulty::Easy);
+        assert_eq!(config.grade(0, false, Some(5000), Some(10000)), Difficulty::Easy);
+        assert_eq!(config.grade(0, false, Some(5001), Some(10000)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(19999), Some(10000)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(20001), Some(10000)), Difficulty::Hard);
+
+        // A median of zero can't be compared against.
+        assert_eq!(config.grade(0, false, Some(1), Some(0)), Difficulty::Good);
+        assert_eq!(config.grade(0, false, Some(0), Some(1)), Difficulty::Easy);
+    }
 }
@@ -1,12 +1,29 @@
-use chrono::{Duration, DateTime, FixedOffset, Local, NaiveTime};
+use chrono::{Duration, DateTime, FixedOffset, Local, NaiveTime, NaiveDate};
 
 /// Serialize a chrono::DateTime.
-pub fn _serialize_datetime<S: serde::Serializer>(dt: &DateTime<FixedOffset>, s: S)
+pub fn serialize_datetime<S: serde::Serializer>(dt: &DateTime<FixedOffset>, s: S)
     -> Result<S::Ok, S::Error>
 {
     s.serialize_str(&dt.to_rfc3339())
 }
 
+/// Serialize an optional chrono::DateTime.
+pub fn serialize_optional_datetime<S: serde::Serializer>(dt: &Option<DateTime<FixedOffset>>, s: S)
+    -> Result<S::Ok, S::Error>
+{
+    match dt {
+        Some(dt) => s.serialize_some(&dt.to_rfc3339()),
+        None => s.serialize_none(),
+    }
+}
+
+/// Serialize a chrono::NaiveDate.
+pub fn serialize_date<S: serde::Serializer>(date: &NaiveDate, s: S)
+    -> Result<S::Ok, S::Error>
+{
+    s.serialize_str(&date.format("%Y-%m-%d").to_string())
+}
+
 /// Serialize a chrono::Duration.
 pub fn _serialize_duration<S: serde::Serializer>(dt: &Duration, s: S)
     -> Result<S::Ok, S::Error>
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::db::{ReviewScoreBucket, TagReviewBucket, PuzzleTagKind, PuzzleFilter};
+use crate::srs::Difficulty;
+
+/// The number of imaginary reviews at the expected failure rate that each tag starts with, so that
+/// a tag doesn't look like a big weakness just because the user failed the only puzzle they've
+/// done with it.
+const PRIOR_REVIEWS: f64 = 5.0;
+
+/// A puzzle theme or opening, and how much more often the user fails puzzles with it than would be
+/// expected for puzzles of the same ratings.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Weakness {
+    pub kind: PuzzleTagKind,
+    pub tag: String,
+    pub review_count: i64,
+    pub failure_count: i64,
+    /// The number of failures expected from the user's failure rate for puzzles of the same
+    /// ratings, regardless of tag.
+    pub expected_failures: f64,
+    /// The excess failure rate, positive if the tag is a weakness.
+    pub score: f64,
+}
+
+impl Weakness {
+    /// Narrow a puzzle filter down to the puzzles with this weakness. Returns None if the filter
+    /// doesn't allow them, because it excludes the theme or only includes other themes or openings.
+    pub fn restrict_filter(&self, filter: &PuzzleFilter) -> Option<PuzzleFilter> {
+        let mut filter = filter.clone();
+        match self.kind {
+            PuzzleTagKind::Theme => {
+                if filter.exclude_themes.contains(&self.tag)
+                    || !(filter.include_themes.is_empty() || filter.include_themes.contains(&self.tag))
+                {
+                    return None;
+                }
+                filter.include_themes = vec![self.tag.clone()];
+            },
+            PuzzleTagKind::Opening => {
+                if !(filter.opening_tags.is_empty() || filter.opening_tags.contains(&self.tag)) {
+                    return None;
+                }
+                filter.opening_tags = vec![self.tag.clone()];
+            },
+        }
+        Some(filter)
+    }
+}
+
+/// Find the user's weaknesses from their review scores for each rating bucket (the baseline), and
+/// for each tag in each rating bucket. The buckets need to be the same size. The weaknesses are
+/// returned with the weakest first.
+pub fn find_weaknesses(baseline: &[ReviewScoreBucket], tag_buckets: &[TagReviewBucket])
+    -> Vec<Weakness>
+{
+    // Get the failure rate for each rating bucket.
+    let mut bucket_counts: HashMap<i64, (i64, i64)> = HashMap::new();
+    for bucket in baseline {
+        let (failures, reviews) = bucket_counts.entry(bucket.puzzle_rating_min).or_default();
+        if bucket.difficulty == Difficulty::Again {
+            *failures += bucket.review_count;
+        }
+        *reviews += bucket.review_count;
+    }
+
+    let failure_rate = |puzzle_rating_min: i64| {
+        match bucket_counts.get(&puzzle_rating_min) {
+            Some((failures, reviews)) if *reviews > 0 => *failures as f64 / *reviews as f64,
+            _ => 0.0,
+        }
+    };
+
+    // Total up the actual and expected failures for each tag across all the rating buckets.
+    let mut weaknesses: HashMap<(PuzzleTagKind, &str), Weakness> = HashMap::new();
+    for bucket in tag_buckets {
+        let weakness = weaknesses.entry((bucket.kind, &bucket.tag)).or_insert_with(|| Weakness {
+            kind: bucket.kind,
+            tag: bucket.tag.clone(),
+            review_count: 0,
+            failure_count: 0,
+            expected_failures: 0.0,
+            score: 0.0,
+        });
+
+        weakness.review_count += bucket.review_count;
+        weakness.failure_count += bucket.failure_count;
+        weakness.expected_failures += bucket.review_count as f64 * failure_rate(bucket.puzzle_rating_min);
+    }
+
+    let mut weaknesses: Vec<Weakness> = weaknesses.into_values()
+        .map(|mut weakness| {
+            weakness.score = (weakness.failure_count as f64 - weakness.expected_failures)
+                / (weakness.review_count as f64 + PRIOR_REVIEWS);
+            weakness
+        })
+        .collect();
+
+    weaknesses.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.tag.cmp(&b.tag)));
+
+    weaknesses
+}
+
+/// Choose one of the `count` weakest tags at random, weighted by how weak they are. Returns None
+/// if there aren't any actual weaknesses.
+pub fn choose_weakness<'a, R: Rng>(weaknesses: &'a [Weakness], count: usize, rng: &mut R)
+    -> Option<&'a Weakness>
+{
+    let candidates: Vec<&Weakness> = weaknesses.iter()
+        .filter(|weakness| weakness.score > 0.0)
+        .take(count)
+        .collect();
+
+    let total_score: f64 = candidates.iter().map(|weakness| weakness.score).sum();
+    if total_score <= 0.0 {
+        return None;
+    }
+
+    let mut choice = rng.gen_range(0.0..total_score);
+    for weakness in &candidates {
+        if choice < weakness.score {
+            return Some(weakness);
+        }
+        choice -= weakness.score;
+    }
+
+    candidates.last().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use crate::db::{ReviewScoreBucket, TagReviewBucket, PuzzleTagKind, PuzzleFilter};
+    use crate::srs::Difficulty;
+    use crate::weakness::{find_weaknesses, choose_weakness, Weakness};
+
+    fn score_bucket(puzzle_rating_min: i64, difficulty: Difficulty, review_count: i64)
+        -> ReviewScoreBucket
+    {
+        ReviewScoreBucket {
+            puzzle_rating_min,
+            puzzle_rating_max: puzzle_rating_min + 100,
+            difficulty,
+            review_count,
+        }
+    }
+
+    fn tag_bucket(kind: PuzzleTagKind, tag: &str, puzzle_rating_min: i64, review_count: i64,
+        failure_count: i64) -> TagReviewBucket
+    {
+        TagReviewBucket {
+            kind,
+            tag: tag.to_string(),
+            puzzle_rating_min,
+            review_count,
+            failure_count,
+        }
+    }
+
+    #[test]
+    fn test_find_weaknesses() {
+        // The user fails 10% of puzzles at 1000 and 50% of puzzles at 1500.
+        let baseline = [
+            score_bucket(1000, Difficulty::Again, 10),
+            score_bucket(1000, Difficulty::Good, 90),
+            score_bucket(1500, Difficulty::Again, 50),
+            score_bucket(1500, Difficulty::Easy, 50),
+        ];
+
+        let tag_buckets = [
+            // Forks are only failed as often as expected, even though it's 50% at 1500.
+            tag_bucket(PuzzleTagKind::Theme, "fork", 1000, 20, 2),
+            tag_bucket(PuzzleTagKind::Theme, "fork", 1500, 20, 10),
+            // Pins are failed 30% of the time at 1000, which is much more than expected.
+            tag_bucket(PuzzleTagKind::Theme, "pin", 1000, 20, 6),
+            // The user is better than usual at the Sicilian.
+            tag_bucket(PuzzleTagKind::Opening, "Sicilian_Defense", 1500, 20, 2),
+        ];
+
+        let weaknesses = find_weaknesses(&baseline, &tag_buckets);
+        let tags: Vec<&str> = weaknesses.iter().map(|weakness| weakness.tag.as_str()).collect();
+        assert_eq!(tags, ["pin", "fork", "Sicilian_Defense"]);
+
+        assert_eq!(weaknesses[0].kind, PuzzleTagKind::Theme);
+        assert_eq!(weaknesses[0].review_count, 20);
+        assert_eq!(weaknesses[0].failure_count, 6);
+        assert!((weaknesses[0].expected_failures - 2.0).abs() < 0.0001);
+        assert!((weaknesses[0].score - 4.0 / 25.0).abs() < 0.0001);
+
+        assert!((weaknesses[1].expected_failures - 12.0).abs() < 0.0001);
+        assert!(weaknesses[1].score.abs() < 0.0001);
+
+        assert_eq!(weaknesses[2].kind, PuzzleTagKind::Opening);
+        assert!(weaknesses[2].score < 0.0);
+
+        // Only the actual weakness should ever be chosen.
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            assert_eq!(choose_weakness(&weaknesses, 5, &mut rng).map(|w| w.tag.as_str()), Some("pin"));
+        }
+
+        // And nothing should be chosen if there aren't any.
+        assert!(choose_weakness(&weaknesses[1..], 5, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_restrict_filter() {
+        let weakness = |kind, tag: &str| Weakness {
+            kind,
+            tag: tag.to_string(),
+            review_count: 10,
+            failure_count: 5,
+            expected_failures: 2.0,
+            score: 0.2,
+        };
+        let tags = |tags: &[&str]| tags.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let pin = weakness(PuzzleTagKind::Theme, "pin");
+        let sicilian = weakness(PuzzleTagKind::Opening, "Sicilian_Defense");
+
+        // The rest of the filter is kept.
+        let filter = PuzzleFilter {
+            opening_tags: tags(&["Sicilian_Defense", "French_Defense"]),
+            exclude_themes: tags(&["long"]),
+            min_popularity: Some(90),
+            ..Default::default()
+        };
+        let restricted = pin.restrict_filter(&filter).unwrap();
+        assert_eq!(restricted, PuzzleFilter { include_themes: tags(&["pin"]), ..filter.clone() });
+        let restricted = sicilian.restrict_filter(&filter).unwrap();
+        assert_eq!(restricted, PuzzleFilter { opening_tags: tags(&["Sicilian_Defense"]), ..filter.clone() });
+
+        // Included themes are narrowed down to the weakness if it's one of them.
+        let filter = PuzzleFilter { include_themes: tags(&["pin", "fork"]), ..Default::default() };
+        assert_eq!(pin.restrict_filter(&filter).unwrap().include_themes, ["pin"]);
+
+        // Filters that don't allow the weakness can't be restricted to it.
+        let filter = PuzzleFilter { include_themes: tags(&["fork"]), ..Default::default() };
+        assert!(pin.restrict_filter(&filter).is_none());
+        let filter = PuzzleFilter { exclude_themes: tags(&["pin"]), ..Default::default() };
+        assert!(pin.restrict_filter(&filter).is_none());
+        let filter = PuzzleFilter { opening_tags: tags(&["French_Defense"]), ..Default::default() };
+        assert!(sicilian.restrict_filter(&filter).is_none());
+    }
+}